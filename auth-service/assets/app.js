@@ -32,6 +32,18 @@ signupLoginLink.addEventListener("click", (e) => {
 
 // -----------------------------------------------------
 
+// /authorize sends users here to log in first, along with where to go once they have
+const returnTo = new URLSearchParams(window.location.search).get("return_to");
+
+function finishLogin() {
+    // Only ever return to /authorize, so the parameter can't send users anywhere else
+    if (returnTo && returnTo.startsWith("/authorize?")) {
+        window.location.assign(returnTo);
+    } else {
+        alert("You have successfully logged in.");
+    }
+}
+
 const loginForm = document.getElementById("login-form");
 const loginButton = document.getElementById("login-form-submit");
 const loginErrAlter = document.getElementById("login-err-alert");
@@ -66,7 +78,7 @@ loginButton.addEventListener("click", (e) => {
             loginForm.email.value = "";
             loginForm.password.value = "";
             loginErrAlter.style.display = "none";
-            alert("You have successfully logged in.");
+            finishLogin();
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -145,7 +157,7 @@ TwoFAButton.addEventListener("click", (e) => {
             TwoFAForm.email_code.value = "";
             TwoFAForm.login_attempt_id.value = "";
             TwoFAErrAlter.style.display = "none";
-            alert("You have successfully logged in.");
+            finishLogin();
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
             signupSection.style.display = "none";
@@ -161,4 +173,126 @@ TwoFAButton.addEventListener("click", (e) => {
             });
         }
     });
-});
\ No newline at end of file
+});
+
+// -----------------------------------------------------
+
+const passwordResetRequestSection = document.getElementById("password-reset-request-section");
+const passwordResetSection = document.getElementById("password-reset-section");
+
+function showSection(section) {
+    [loginSection, twoFASection, signupSection, passwordResetRequestSection, passwordResetSection]
+        .forEach(s => s.style.display = s === section ? "block" : "none");
+}
+
+document.getElementById("password-reset-link").addEventListener("click", (e) => {
+    e.preventDefault();
+    showSection(passwordResetRequestSection);
+});
+
+document.getElementById("password-reset-request-login-link").addEventListener("click", (e) => {
+    e.preventDefault();
+    showSection(loginSection);
+});
+
+const passwordResetRequestForm = document.getElementById("password-reset-request-form");
+const passwordResetRequestButton = document.getElementById("password-reset-request-form-submit");
+const passwordResetRequestErrAlert = document.getElementById("password-reset-request-err-alert");
+
+passwordResetRequestButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = passwordResetRequestForm.email.value;
+
+    fetch('/password-reset/request', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(response => {
+        response.json().then(data => {
+            if (response.ok) {
+                passwordResetRequestForm.email.value = "";
+                passwordResetRequestErrAlert.style.display = "none";
+                alert(data.message);
+                showSection(loginSection);
+            } else {
+                passwordResetRequestErrAlert.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                passwordResetRequestErrAlert.style.display = "block";
+            }
+        });
+    });
+});
+
+const passwordResetForm = document.getElementById("password-reset-form");
+const passwordResetButton = document.getElementById("password-reset-form-submit");
+const passwordResetErrAlert = document.getElementById("password-reset-err-alert");
+
+// The emailed reset link points back here with the token in the query string
+const passwordResetToken = new URLSearchParams(window.location.search).get("password_reset_token");
+if (passwordResetToken) {
+    passwordResetForm.token.value = passwordResetToken;
+    showSection(passwordResetSection);
+}
+
+passwordResetButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const token = passwordResetForm.token.value;
+    const newPassword = passwordResetForm.password.value;
+
+    fetch('/password-reset/confirm', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token, newPassword }),
+    }).then(response => {
+        if (response.ok) {
+            passwordResetForm.token.value = "";
+            passwordResetForm.password.value = "";
+            passwordResetErrAlert.style.display = "none";
+            window.history.replaceState({}, "", "/");
+            alert("Your password has been reset. You can now log in.");
+            showSection(loginSection);
+        } else {
+            response.json().then(data => {
+                passwordResetErrAlert.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                passwordResetErrAlert.style.display = "block";
+            });
+        }
+    });
+});
+
+// The emailed magic link points back here; passing it on to the callback logs in or asks for 2FA
+const magicLinkParams = new URLSearchParams(window.location.search);
+const magicLinkToken = magicLinkParams.get("magic_link_token");
+if (magicLinkToken) {
+    window.history.replaceState({}, "", "/");
+
+    const callbackParams = new URLSearchParams({
+        token: magicLinkToken,
+        expires: magicLinkParams.get("expires") ?? "",
+        signature: magicLinkParams.get("signature") ?? "",
+    });
+
+    fetch(`/login/magic-link/callback?${callbackParams}`).then(response => {
+        if (response.status === 206) {
+            response.json().then(data => {
+                TwoFAForm.email.value = data.email;
+                TwoFAForm.login_attempt_id.value = data.loginAttemptId;
+                showSection(twoFASection);
+            });
+        } else if (response.status === 200) {
+            finishLogin();
+        } else {
+            // Malformed query strings are rejected before reaching the handler, without a JSON body
+            response.json().catch(() => ({ error: "Invalid login link" })).then(data => {
+                loginErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                loginErrAlter.style.display = "block";
+                showSection(loginSection);
+            });
+        }
+    });
+}
@@ -0,0 +1,63 @@
+// /authorize sends users here with the authorization request in the query string. The same
+// request is posted back with the user's decision, and checked again by the server.
+const authorizationRequest = Object.fromEntries(new URLSearchParams(window.location.search));
+
+const consentErrAlert = document.getElementById("consent-err-alert");
+const consentClientName = document.getElementById("consent-client-name");
+const consentScopes = document.getElementById("consent-scopes");
+
+const scopeDescriptions = {
+    openid: "Confirm who you are",
+    email: "See your email address",
+};
+
+function showError(error_msg) {
+    consentErrAlert.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+    consentErrAlert.style.display = "block";
+}
+
+// The client's name comes from the server, so a crafted link can't make it claim to be another
+fetch(`/clients/${encodeURIComponent(authorizationRequest.client_id || "")}`).then(response => {
+    response.json().then(data => {
+        if (response.ok) {
+            consentClientName.textContent = data.name;
+        } else {
+            showError(data.error);
+        }
+    });
+});
+
+(authorizationRequest.scope || "").split(" ").filter(scope => scope in scopeDescriptions).forEach(scope => {
+    const item = document.createElement("li");
+    item.textContent = scopeDescriptions[scope];
+    consentScopes.appendChild(item);
+});
+
+function submitConsent(approved) {
+    fetch('/authorize', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ ...authorizationRequest, approved }),
+    }).then(response => {
+        response.json().then(data => {
+            if (response.ok) {
+                consentErrAlert.style.display = "none";
+                window.location.assign(data.redirectUri);
+            } else {
+                showError(data.error);
+            }
+        });
+    });
+}
+
+document.getElementById("consent-allow").addEventListener("click", (e) => {
+    e.preventDefault();
+    submitConsent(true);
+});
+
+document.getElementById("consent-deny").addEventListener("click", (e) => {
+    e.preventDefault();
+    submitConsent(false);
+});
@@ -0,0 +1,5 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS verified BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Accounts created before email verification existed were never sent a link,
+-- so treat them as verified rather than locking them out.
+UPDATE users SET verified = TRUE;
@@ -0,0 +1,8 @@
+ALTER TABLE users DROP COLUMN IF EXISTS totp_secret;
+
+ALTER TABLE users ADD COLUMN requires_2fa BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- TOTP users fall back to emailed codes
+UPDATE users SET requires_2fa = two_fa_method <> 'none';
+
+ALTER TABLE users DROP COLUMN two_fa_method;
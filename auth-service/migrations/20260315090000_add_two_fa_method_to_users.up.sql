@@ -0,0 +1,10 @@
+ALTER TABLE users
+    ADD COLUMN two_fa_method TEXT NOT NULL DEFAULT 'none'
+    CHECK (two_fa_method IN ('none', 'email', 'totp'));
+
+UPDATE users SET two_fa_method = 'email' WHERE requires_2fa;
+
+ALTER TABLE users DROP COLUMN requires_2fa;
+
+-- AES-256-GCM encrypted TOTP secret: 12 byte nonce followed by the ciphertext
+ALTER TABLE users ADD COLUMN totp_secret BYTEA;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS recovery_codes(
+   email TEXT NOT NULL REFERENCES users(email) ON DELETE CASCADE,
+   code_hash TEXT NOT NULL,
+   used_at TIMESTAMPTZ,
+   PRIMARY KEY (email, code_hash)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS delete_after TIMESTAMPTZ;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deletion_cancel_token_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS users_delete_after_idx ON users(delete_after) WHERE delete_after IS NOT NULL;
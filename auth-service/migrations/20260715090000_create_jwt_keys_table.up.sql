@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS jwt_keys(
+   kid TEXT NOT NULL PRIMARY KEY,
+   algorithm TEXT NOT NULL,
+   private_key BYTEA NOT NULL,
+   created_at TIMESTAMPTZ NOT NULL
+);
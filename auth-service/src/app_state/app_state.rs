@@ -1,13 +1,27 @@
 use std::sync::Arc;
+use secrecy::Secret;
 use tokio::sync::RwLock;
 
-use crate::{domain::{UserStore, BannedTokenStore, TwoFACodeStore, EmailClient}};
+use crate::{domain::{UserStore, BannedTokenStore, TwoFACodeStore, RefreshTokenStore, SessionStore, PasswordResetTokenStore, EmailVerificationTokenStore, MagicLinkTokenStore, RecoveryCodeStore, PasskeyStore, PasskeyChallengeStore, OAuthClientStore, AuthorizationCodeStore, LoginThrottleStore, JwtKeyStore, EmailClient}, utils::{jwt_keyring::JwtKeyring, login_throttle::LoginThrottlePolicy, password_policy::PasswordPolicy}};
 
 // Using a type alias to improve readability!
 pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
 pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
 pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
+pub type RefreshTokenStoreType = Arc<RwLock<dyn RefreshTokenStore + Send + Sync>>;
+pub type SessionStoreType = Arc<RwLock<dyn SessionStore + Send + Sync>>;
+pub type PasswordResetTokenStoreType = Arc<RwLock<dyn PasswordResetTokenStore + Send + Sync>>;
+pub type EmailVerificationTokenStoreType = Arc<RwLock<dyn EmailVerificationTokenStore + Send + Sync>>;
+pub type MagicLinkTokenStoreType = Arc<RwLock<dyn MagicLinkTokenStore + Send + Sync>>;
+pub type RecoveryCodeStoreType = Arc<RwLock<dyn RecoveryCodeStore + Send + Sync>>;
+pub type PasskeyStoreType = Arc<RwLock<dyn PasskeyStore + Send + Sync>>;
+pub type PasskeyChallengeStoreType = Arc<RwLock<dyn PasskeyChallengeStore + Send + Sync>>;
+pub type OAuthClientStoreType = Arc<RwLock<dyn OAuthClientStore + Send + Sync>>;
+pub type AuthorizationCodeStoreType = Arc<RwLock<dyn AuthorizationCodeStore + Send + Sync>>;
+pub type LoginThrottleStoreType = Arc<RwLock<dyn LoginThrottleStore + Send + Sync>>;
+pub type JwtKeyStoreType = Arc<RwLock<dyn JwtKeyStore + Send + Sync>>;
 pub type EmailClientType = Arc<dyn EmailClient + Send + Sync>;
+pub type JwtKeyringType = Arc<RwLock<JwtKeyring>>;
 
 
 #[derive(Clone)]
@@ -15,11 +29,33 @@ pub struct AppState {
     pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub session_store: SessionStoreType,
+    pub password_reset_token_store: PasswordResetTokenStoreType,
+    pub email_verification_token_store: EmailVerificationTokenStoreType,
+    pub magic_link_token_store: MagicLinkTokenStoreType,
+    pub recovery_code_store: RecoveryCodeStoreType,
+    pub passkey_store: PasskeyStoreType,
+    pub passkey_challenge_store: PasskeyChallengeStoreType,
+    pub oauth_client_store: OAuthClientStoreType,
+    pub authorization_code_store: AuthorizationCodeStoreType,
+    pub login_throttle_store: LoginThrottleStoreType,
     pub email_client: EmailClientType,
+    pub jwt_keyring: JwtKeyringType,
+    pub jwt_key_store: JwtKeyStoreType,
+    // When set, login is refused until the user has verified their email address.
+    // Otherwise unverified users can log in and are flagged in their JWT claims.
+    pub require_verified_email: bool,
+    pub login_throttle_policy: LoginThrottlePolicy,
+    // Checked whenever a password is set, at signup, password change and reset
+    pub password_policy: PasswordPolicy,
+    // Bearer token for the /admin endpoints, which are disabled when it is not set
+    pub admin_api_token: Option<Secret<String>>,
 }
 
 impl AppState {
-    pub fn new(user_store: UserStoreType, banned_token_store: BannedTokenStoreType, two_fa_code_store: TwoFACodeStoreType, email_client: EmailClientType) -> Self {
-        Self { user_store, banned_token_store, two_fa_code_store, email_client }
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(user_store: UserStoreType, banned_token_store: BannedTokenStoreType, two_fa_code_store: TwoFACodeStoreType, refresh_token_store: RefreshTokenStoreType, session_store: SessionStoreType, password_reset_token_store: PasswordResetTokenStoreType, email_verification_token_store: EmailVerificationTokenStoreType, magic_link_token_store: MagicLinkTokenStoreType, recovery_code_store: RecoveryCodeStoreType, passkey_store: PasskeyStoreType, passkey_challenge_store: PasskeyChallengeStoreType, oauth_client_store: OAuthClientStoreType, authorization_code_store: AuthorizationCodeStoreType, login_throttle_store: LoginThrottleStoreType, email_client: EmailClientType, jwt_keyring: JwtKeyringType, jwt_key_store: JwtKeyStoreType, require_verified_email: bool, login_throttle_policy: LoginThrottlePolicy, password_policy: PasswordPolicy, admin_api_token: Option<Secret<String>>) -> Self {
+        Self { user_store, banned_token_store, two_fa_code_store, refresh_token_store, session_store, password_reset_token_store, email_verification_token_store, magic_link_token_store, recovery_code_store, passkey_store, passkey_challenge_store, oauth_client_store, authorization_code_store, login_throttle_store, email_client, jwt_keyring, jwt_key_store, require_verified_email, login_throttle_policy, password_policy, admin_api_token }
     }
 }
\ No newline at end of file
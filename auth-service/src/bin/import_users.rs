@@ -0,0 +1,52 @@
+// Bulk import of users from another system, keeping the password hashes they came with.
+// Their hashes are upgraded to Argon2id as each user logs in.
+//
+//     cargo run --bin import_users -- users.csv
+//
+// CSV files need an `email,hash,requires_2fa` header row. JSONL files (.jsonl) have one
+// object per line with the same keys. Users that already exist are skipped.
+use std::{fs::File, path::Path};
+
+use auth_service::{
+    get_postgres_pool,
+    services::data_stores::PostgresUserStore,
+    utils::{
+        constants::DATABASE_URL,
+        user_import::{import_users, ImportFormat},
+    },
+};
+use color_eyre::eyre::{eyre, Context, Result};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| eyre!("usage: import_users <users.csv | users.jsonl>"))?;
+    let format = ImportFormat::from_path(Path::new(&path))?;
+    let file = File::open(&path).wrap_err_with(|| format!("failed to open {}", path))?;
+
+    let pg_pool = get_postgres_pool(&DATABASE_URL)
+        .await
+        .wrap_err("failed to connect to Postgres")?;
+    sqlx::migrate!().run(&pg_pool).await?;
+    let mut user_store = PostgresUserStore::new(pg_pool);
+
+    let summary = import_users(file, format, &mut user_store).await?;
+
+    for (line, reason) in &summary.failed {
+        eprintln!("line {}: {}", line, reason);
+    }
+    println!(
+        "imported {} users, {} already existed, {} failed",
+        summary.imported,
+        summary.already_existed,
+        summary.failed.len()
+    );
+
+    if !summary.failed.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use subtle::ConstantTimeEq;
+
+// Secrets (codes, tokens, IDs, and hashes of them) must be compared with this rather than `==`.
+// It takes as long no matter how much of a guess is right, so response times give nothing away.
+pub fn constant_time_eq(a: impl AsRef<[u8]>, b: impl AsRef<[u8]>) -> bool {
+    bool::from(a.as_ref().ct_eq(b.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_secrets_match() {
+        assert!(constant_time_eq("123456", "123456"));
+        assert!(constant_time_eq("", ""));
+    }
+
+    #[test]
+    fn different_secrets_do_not_match() {
+        assert!(!constant_time_eq("123456", "123457"));
+        assert!(!constant_time_eq("123456", "12345"));
+        assert!(!constant_time_eq("123456", ""));
+    }
+}
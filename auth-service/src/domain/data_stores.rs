@@ -1,9 +1,13 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{eyre, Context, Report, Result};
-use rand::Rng;
+use jsonwebtoken::Algorithm;
+use rand::{distributions::Alphanumeric, Rng};
 use secrecy::Secret;
 use thiserror::Error;
 use uuid::Uuid;
-use crate::domain::{Email, Password};
+use crate::domain::{AuthorizationGrant, Email, ImportedPasswordHash, OAuthClient, PasskeyChallenge, PasskeyCredential, Password, Session, TotpSecret, TwoFAMethod};
 use super::User;
 
 #[async_trait::async_trait]
@@ -14,9 +18,54 @@ pub trait UserStore {
 
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
 
+    // Add a user migrated from another system, keeping the password hash it came with.
+    // The hash is replaced with a current Argon2id one the first time they log in.
+    async fn import_user(
+        &mut self,
+        email: Email,
+        password_hash: ImportedPasswordHash,
+        two_fa_method: TwoFAMethod,
+    ) -> Result<(), UserStoreError>;
+
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
 
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError>;
+
+    // Whether the password matches one of the user's last PASSWORD_HISTORY_DEPTH passwords,
+    // counting the current one
+    async fn is_password_reused(&self, email: &Email, password: &Password) -> Result<bool, UserStoreError>;
+
+    // The replaced password goes into the user's password history, and a password that matches
+    // one of their last PASSWORD_HISTORY_DEPTH passwords is refused with PasswordReused
+    async fn update_password(&mut self, email: &Email, password: Password) -> Result<(), UserStoreError>;
+
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError>;
+
+    async fn set_totp_secret(&mut self, email: &Email, secret: TotpSecret) -> Result<(), UserStoreError>;
+
+    // Record the time step of a TOTP code the user just got right. A step at or before the
+    // last one recorded is refused with TotpCodeReused, so each code can only be used once.
+    async fn record_totp_step(&mut self, email: &Email, step: u64) -> Result<(), UserStoreError>;
+
+    async fn set_two_fa_method(&mut self, email: &Email, method: TwoFAMethod) -> Result<(), UserStoreError>;
+
+    async fn set_disabled(&mut self, email: &Email, disabled: bool) -> Result<(), UserStoreError>;
+
+    // Mark the account for deletion once `grace_period` has passed. Presenting `cancel_token`
+    // to `cancel_deletion` before then keeps the account.
+    async fn schedule_deletion(
+        &mut self,
+        email: &Email,
+        cancel_token: AccountDeletionToken,
+        grace_period: Duration,
+    ) -> Result<(), UserStoreError>;
+
+    // Returns the user whose deletion was cancelled, or UserNotFound if no pending deletion
+    // matches the token
+    async fn cancel_deletion(&mut self, cancel_token: &AccountDeletionToken) -> Result<Email, UserStoreError>;
+
+    // Permanently remove every account whose grace period has ended, returning their emails
+    async fn delete_expired_users(&mut self) -> Result<Vec<Email>, UserStoreError>;
 }
 
 // Add a BannedTokenStore trait to auth-service/src/domain/data_stores.rs 
@@ -25,16 +74,245 @@ pub trait UserStore {
 
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn store_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError>;
+    // Tokens are banned by their `jti` claim, which identifies them without being a credential
+    async fn store_token(&mut self, jti: String) -> Result<(), BannedTokenStoreError>;
+
+    async fn is_token_banned(&self, jti: &str) -> Result<bool, BannedTokenStoreError>;
+
+    // Every JWT records the user's token generation at the time it was issued.
+    // Bumping the generation invalidates all of the user's outstanding tokens at once.
+    async fn get_token_generation(&self, email: &Email) -> Result<u64, BannedTokenStoreError>;
 
-    async fn is_token_banned(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError>;
+    async fn revoke_user_tokens(&mut self, email: &Email) -> Result<(), BannedTokenStoreError>;
 
     fn as_ref(&self) -> &dyn BannedTokenStore;
 }
 
-// This trait represents the interface all concrete 2FA code stores should implement
+// This trait represents the interface all concrete refresh token stores should implement.
+// Refresh tokens are grouped into families: each rotation replaces the family's current token,
+// and presenting a token that has already been rotated out revokes the whole family.
+#[async_trait::async_trait]
+pub trait RefreshTokenStore {
+    // Each family belongs to one session, which every token rotated from it carries on
+    async fn add_token(
+        &mut self,
+        email: Email,
+        session_id: String,
+        token: RefreshToken,
+    ) -> Result<(), RefreshTokenStoreError>;
+
+    // Returns the user and session the family belongs to
+    async fn rotate_token(
+        &mut self,
+        token: &RefreshToken,
+        new_token: RefreshToken,
+    ) -> Result<(Email, String), RefreshTokenStoreError>;
+
+    async fn revoke_family(&mut self, token: &RefreshToken) -> Result<(), RefreshTokenStoreError>;
+
+    async fn revoke_user_families(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError>;
+}
+
+// This trait represents the interface all concrete session stores should implement.
+// A session stays in the store until it is revoked or goes unused for as long as a refresh token
+// lasts, and a token whose session is no longer in the store is rejected.
+#[async_trait::async_trait]
+pub trait SessionStore {
+    async fn add_session(&mut self, email: &Email, session: Session) -> Result<(), SessionStoreError>;
+
+    async fn get_sessions(&self, email: &Email) -> Result<Vec<Session>, SessionStoreError>;
+
+    // Record that the session was just used, failing if it has ended
+    async fn touch_session(&mut self, session_id: &str) -> Result<(), SessionStoreError>;
+
+    // Only the session's own user can end it, so another user's session ID is not found
+    async fn revoke_session(&mut self, email: &Email, session_id: &str) -> Result<(), SessionStoreError>;
+
+    async fn revoke_user_sessions(&mut self, email: &Email) -> Result<(), SessionStoreError>;
+}
+
+// This trait represents the interface all concrete password reset token stores should implement.
+// Tokens are single-use: consuming a token removes it from the store.
+#[async_trait::async_trait]
+pub trait PasswordResetTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: PasswordResetToken,
+    ) -> Result<(), PasswordResetTokenStoreError>;
+
+    // Look up whose token it is without using it up
+    async fn get_email(&self, token: &PasswordResetToken) -> Result<Email, PasswordResetTokenStoreError>;
+
+    // Count a refused attempt to reset the password with the token. The token keeps its
+    // original expiry, and is removed once it has been refused PASSWORD_RESET_MAX_ATTEMPTS times.
+    async fn record_failed_attempt(&mut self, token: &PasswordResetToken) -> Result<(), PasswordResetTokenStoreError>;
+
+    async fn consume_token(
+        &mut self,
+        token: &PasswordResetToken,
+    ) -> Result<Email, PasswordResetTokenStoreError>;
+}
+
+// This trait represents the interface all concrete email verification token stores should implement.
+// Like password reset tokens, verification tokens are single-use.
+#[async_trait::async_trait]
+pub trait EmailVerificationTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: EmailVerificationToken,
+    ) -> Result<(), EmailVerificationTokenStoreError>;
+
+    async fn consume_token(
+        &mut self,
+        token: &EmailVerificationToken,
+    ) -> Result<Email, EmailVerificationTokenStoreError>;
+}
+
+// This trait represents the interface all concrete magic link token stores should implement.
+// Each emailed login link can only be followed once, so consuming a token removes it.
+#[async_trait::async_trait]
+pub trait MagicLinkTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: MagicLinkToken,
+    ) -> Result<(), MagicLinkTokenStoreError>;
+
+    async fn consume_token(
+        &mut self,
+        token: &MagicLinkToken,
+    ) -> Result<Email, MagicLinkTokenStoreError>;
+}
+
+// This trait represents the interface all concrete recovery code stores should implement.
+// A user has at most one set of recovery codes; storing a new set replaces the old one.
+#[async_trait::async_trait]
+pub trait RecoveryCodeStore {
+    async fn replace_codes(
+        &mut self,
+        email: &Email,
+        codes: Vec<RecoveryCode>,
+    ) -> Result<(), RecoveryCodeStoreError>;
+
+    async fn consume_code(
+        &mut self,
+        email: &Email,
+        code: &RecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError>;
+}
+
+// This trait represents the interface all concrete passkey stores should implement.
+// A user can register any number of passkeys, but each credential ID belongs to one user.
+#[async_trait::async_trait]
+pub trait PasskeyStore {
+    async fn add_credential(
+        &mut self,
+        email: &Email,
+        credential: PasskeyCredential,
+    ) -> Result<(), PasskeyStoreError>;
+
+    async fn get_credentials(&self, email: &Email) -> Result<Vec<PasskeyCredential>, PasskeyStoreError>;
+
+    async fn update_sign_count(
+        &mut self,
+        email: &Email,
+        credential_id: &str,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError>;
+}
+
+// This trait represents the interface all concrete passkey challenge stores should implement.
+// Challenges are keyed by a ceremony ID handed to whoever started the ceremony, so starting one
+// can't disturb another that is in flight for the same user. A challenge can only be answered once.
+#[async_trait::async_trait]
+pub trait PasskeyChallengeStore {
+    async fn add_challenge(
+        &mut self,
+        ceremony_id: PasskeyCeremonyId,
+        email: Email,
+        challenge: PasskeyChallenge,
+    ) -> Result<(), PasskeyChallengeStoreError>;
+
+    // Returns the user the ceremony was started for along with its challenge
+    async fn take_challenge(
+        &mut self,
+        ceremony_id: &PasskeyCeremonyId,
+    ) -> Result<(Email, PasskeyChallenge), PasskeyChallengeStoreError>;
+}
+
+// This trait represents the interface all concrete OAuth client stores should implement.
+// Client secrets are only ever checked, never read back, so stores are free to keep just a hash.
+#[async_trait::async_trait]
+pub trait OAuthClientStore {
+    async fn add_client(&mut self, client: OAuthClient, secret: ClientSecret) -> Result<(), OAuthClientStoreError>;
+
+    async fn authenticate_client(
+        &self,
+        client_id: &str,
+        secret: &ClientSecret,
+    ) -> Result<OAuthClient, OAuthClientStoreError>;
+
+    async fn get_client(&self, client_id: &str) -> Result<OAuthClient, OAuthClientStoreError>;
+}
+
+// This trait represents the interface all concrete authorization code stores should implement.
+// Each code can only be redeemed once, so consuming a code removes it.
+#[async_trait::async_trait]
+pub trait AuthorizationCodeStore {
+    async fn add_code(
+        &mut self,
+        code: AuthorizationCode,
+        grant: AuthorizationGrant,
+    ) -> Result<(), AuthorizationCodeStoreError>;
+
+    async fn consume_code(
+        &mut self,
+        code: &AuthorizationCode,
+    ) -> Result<AuthorizationGrant, AuthorizationCodeStoreError>;
+}
+
+// This trait represents the interface all concrete JWT key stores should implement.
+// Rotated signing keys are kept here so they survive restarts and every instance of the
+// service signs and verifies with the same keys.
+#[async_trait::async_trait]
+pub trait JwtKeyStore {
+    async fn add_key(&mut self, key: StoredJwtKey) -> Result<(), JwtKeyStoreError>;
+
+    // Oldest first, so the last key is the one new tokens are signed with
+    async fn get_keys(&self) -> Result<Vec<StoredJwtKey>, JwtKeyStoreError>;
+
+    // Drop every key that a newer key had already replaced by `cutoff`
+    async fn remove_keys_replaced_before(&mut self, cutoff: DateTime<Utc>) -> Result<(), JwtKeyStoreError>;
+}
+
+// This trait represents the interface all concrete login throttle stores should implement.
+// It only does the bookkeeping; the limits themselves are decided by the caller.
+#[async_trait::async_trait]
+pub trait LoginThrottleStore {
+    // Count an attempt against `key`, returning how many it has had within the trailing `window`
+    async fn record_attempt(&mut self, key: &str, window: Duration) -> Result<u32, LoginThrottleStoreError>;
+
+    // Count a failure against `key`, returning how many it has had since the last reset.
+    // The count is forgotten once `ttl` passes without another failure.
+    async fn record_failure(&mut self, key: &str, ttl: Duration) -> Result<u32, LoginThrottleStoreError>;
+
+    async fn reset_failures(&mut self, key: &str) -> Result<(), LoginThrottleStoreError>;
+
+    async fn lock(&mut self, key: &str, duration: Duration) -> Result<(), LoginThrottleStoreError>;
+
+    // How much longer `key` stays locked, if it is locked at all
+    async fn get_lock(&self, key: &str) -> Result<Option<Duration>, LoginThrottleStoreError>;
+}
+
+// This trait represents the interface all concrete 2FA code stores should implement.
+// Codes are kept per login attempt rather than per user, so someone logging in on two devices
+// at once can finish on both. Each attempt expires after TWO_FA_CODE_TTL_SECONDS on its own.
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
+    // A user can have at most MAX_PENDING_TWO_FA_CHALLENGES attempts waiting on 2FA.
+    // Starting another drops their oldest.
     async fn add_code(
         &mut self,
         email: Email,
@@ -42,12 +320,50 @@ pub trait TwoFACodeStore {
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError>;
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(&mut self, login_attempt_id: &LoginAttemptId) -> Result<(), TwoFACodeStoreError>;
+
+    // Whether the code is the one sent for this attempt. Stores need not keep the code itself,
+    // only something to check it against. An attempt that belongs to another user is not found.
+    async fn verify_code(
+        &self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<bool, TwoFACodeStoreError>;
 
-    async fn get_code(
+    // Count a wrong code against the attempt, returning how many it has had
+    async fn record_failed_attempt(&mut self, login_attempt_id: &LoginAttemptId) -> Result<u32, TwoFACodeStoreError>;
+
+    async fn get_attempt(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<PendingTwoFAAttempt, TwoFACodeStoreError>;
+
+    // Swap a pending attempt's code for a fresh one that is about to be re-sent. The attempt
+    // gets a full lifetime again, but keeps the wrong codes it has already had.
+    async fn replace_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError>;
+}
+
+// A login attempt waiting on 2FA: when its code was last sent, and how many times it has been re-sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingTwoFAAttempt {
+    pub last_sent_at: DateTime<Utc>,
+    pub resends: u32,
+}
+
+// A rotated JWT signing key along with what it takes to rebuild it
+#[derive(Debug, Clone)]
+pub struct StoredJwtKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub private_key: Secret<String>,
+    pub created_at: DateTime<Utc>,
 }
 
 // Updated!
@@ -70,6 +386,269 @@ impl PartialEq for TwoFACodeStoreError {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct RefreshToken(Secret<String>);
+
+impl RefreshToken {
+    pub fn parse(token: String) -> Result<Self> {
+        if is_opaque_token(&token) {
+            Ok(Self(Secret::new(token)))
+        } else {
+            Err(eyre!("Invalid refresh token"))
+        }
+    }
+}
+
+impl Default for RefreshToken {
+    fn default() -> Self {
+        RefreshToken(Secret::new(generate_opaque_token()))
+    }
+}
+
+impl AsRef<Secret<String>> for RefreshToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordResetToken(Secret<String>);
+
+impl PasswordResetToken {
+    pub fn parse(token: String) -> Result<Self> {
+        if is_opaque_token(&token) {
+            Ok(Self(Secret::new(token)))
+        } else {
+            Err(eyre!("Invalid password reset token"))
+        }
+    }
+}
+
+impl Default for PasswordResetToken {
+    fn default() -> Self {
+        PasswordResetToken(Secret::new(generate_opaque_token()))
+    }
+}
+
+impl AsRef<Secret<String>> for PasswordResetToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailVerificationToken(Secret<String>);
+
+impl EmailVerificationToken {
+    pub fn parse(token: String) -> Result<Self> {
+        if is_opaque_token(&token) {
+            Ok(Self(Secret::new(token)))
+        } else {
+            Err(eyre!("Invalid email verification token"))
+        }
+    }
+}
+
+impl Default for EmailVerificationToken {
+    fn default() -> Self {
+        EmailVerificationToken(Secret::new(generate_opaque_token()))
+    }
+}
+
+impl AsRef<Secret<String>> for EmailVerificationToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MagicLinkToken(Secret<String>);
+
+impl MagicLinkToken {
+    pub fn parse(token: String) -> Result<Self> {
+        if is_opaque_token(&token) {
+            Ok(Self(Secret::new(token)))
+        } else {
+            Err(eyre!("Invalid magic link token"))
+        }
+    }
+}
+
+impl Default for MagicLinkToken {
+    fn default() -> Self {
+        MagicLinkToken(Secret::new(generate_opaque_token()))
+    }
+}
+
+impl AsRef<Secret<String>> for MagicLinkToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthorizationCode(Secret<String>);
+
+impl AuthorizationCode {
+    pub fn parse(code: String) -> Result<Self> {
+        if is_opaque_token(&code) {
+            Ok(Self(Secret::new(code)))
+        } else {
+            Err(eyre!("Invalid authorization code"))
+        }
+    }
+}
+
+impl Default for AuthorizationCode {
+    fn default() -> Self {
+        AuthorizationCode(Secret::new(generate_opaque_token()))
+    }
+}
+
+impl AsRef<Secret<String>> for AuthorizationCode {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PasskeyCeremonyId(Secret<String>);
+
+impl PasskeyCeremonyId {
+    pub fn parse(id: String) -> Result<Self> {
+        if is_opaque_token(&id) {
+            Ok(Self(Secret::new(id)))
+        } else {
+            Err(eyre!("Invalid passkey ceremony id"))
+        }
+    }
+}
+
+impl Default for PasskeyCeremonyId {
+    fn default() -> Self {
+        PasskeyCeremonyId(Secret::new(generate_opaque_token()))
+    }
+}
+
+impl AsRef<Secret<String>> for PasskeyCeremonyId {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientSecret(Secret<String>);
+
+impl ClientSecret {
+    pub fn parse(secret: String) -> Result<Self> {
+        if is_opaque_token(&secret) {
+            Ok(Self(Secret::new(secret)))
+        } else {
+            Err(eyre!("Invalid client secret"))
+        }
+    }
+}
+
+impl Default for ClientSecret {
+    fn default() -> Self {
+        ClientSecret(Secret::new(generate_opaque_token()))
+    }
+}
+
+impl AsRef<Secret<String>> for ClientSecret {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+// Recovery codes are typed in by hand, so they use a short lowercase format like `k3f9a-x2m7q`
+#[derive(Debug, Clone)]
+pub struct RecoveryCode(Secret<String>);
+
+const RECOVERY_CODE_GROUP_LENGTH: usize = 5;
+const RECOVERY_CODE_CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+impl RecoveryCode {
+    pub fn parse(code: String) -> Result<Self> {
+        // Accept the code with or without the dash and in any case, as people copy them differently
+        let normalized: String = code
+            .trim()
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        if normalized.len() == 2 * RECOVERY_CODE_GROUP_LENGTH
+            && normalized.bytes().all(|b| RECOVERY_CODE_CHARSET.contains(&b))
+        {
+            let (first, second) = normalized.split_at(RECOVERY_CODE_GROUP_LENGTH);
+            Ok(Self(Secret::new(format!("{}-{}", first, second))))
+        } else {
+            Err(eyre!("Invalid recovery code"))
+        }
+    }
+}
+
+impl Default for RecoveryCode {
+    fn default() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut group = || -> String {
+            (0..RECOVERY_CODE_GROUP_LENGTH)
+                .map(|_| RECOVERY_CODE_CHARSET[rng.gen_range(0..RECOVERY_CODE_CHARSET.len())] as char)
+                .collect()
+        };
+        let first = group();
+        let second = group();
+        RecoveryCode(Secret::new(format!("{}-{}", first, second)))
+    }
+}
+
+impl AsRef<Secret<String>> for RecoveryCode {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+const OPAQUE_TOKEN_LENGTH: usize = 64;
+
+// Opaque tokens carry no data, so a long random alphanumeric string is all we need
+fn generate_opaque_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(OPAQUE_TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+fn is_opaque_token(token: &str) -> bool {
+    token.len() == OPAQUE_TOKEN_LENGTH && token.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountDeletionToken(Secret<String>);
+
+impl AccountDeletionToken {
+    pub fn parse(token: String) -> Result<Self> {
+        if is_opaque_token(&token) {
+            Ok(Self(Secret::new(token)))
+        } else {
+            Err(eyre!("Invalid account deletion token"))
+        }
+    }
+}
+
+impl Default for AccountDeletionToken {
+    fn default() -> Self {
+        AccountDeletionToken(Secret::new(generate_opaque_token()))
+    }
+}
+
+impl AsRef<Secret<String>> for AccountDeletionToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LoginAttemptId(Secret<String>);
 
@@ -98,9 +677,8 @@ pub struct TwoFACode(Secret<String>);
 
 impl TwoFACode {
     pub fn parse(code: String) -> Result<Self> { // Updated!
-        let code_as_u32 = code.parse::<u32>().wrap_err("Invalid 2FA code")?; // Updated!
-
-        if (100_000..=999_999).contains(&code_as_u32) {
+        // TOTP codes can start with a zero, so any six digits are accepted
+        if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
             Ok(Self(Secret::new(code)))
         } else {
             Err(eyre!("Invalid 2FA code")) // Updated!
@@ -133,6 +711,10 @@ pub enum UserStoreError {
     UserNotFound,
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Password was used recently")]
+    PasswordReused,
+    #[error("TOTP code was already used")]
+    TotpCodeReused,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -144,13 +726,237 @@ impl PartialEq for UserStoreError {
             (Self::UserAlreadyExists, Self::UserAlreadyExists)
                 | (Self::UserNotFound, Self::UserNotFound)
                 | (Self::InvalidCredentials, Self::InvalidCredentials)
+                | (Self::PasswordReused, Self::PasswordReused)
+                | (Self::TotpCodeReused, Self::TotpCodeReused)
                 | (Self::UnexpectedError(_), Self::UnexpectedError(_))
         )
     }
 }
 
+#[derive(Debug, Error)]
+pub enum LoginThrottleStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for LoginThrottleStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!((self, other), (Self::UnexpectedError(_), Self::UnexpectedError(_)))
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum BannedTokenStoreError {
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
+
+#[derive(Debug, Error)]
+pub enum RefreshTokenStoreError {
+    #[error("Refresh token not found")]
+    TokenNotFound,
+    #[error("Refresh token reused")]
+    TokenReused,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for RefreshTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::TokenNotFound, Self::TokenNotFound)
+                | (Self::TokenReused, Self::TokenReused)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PasswordResetTokenStoreError {
+    #[error("Password reset token not found")]
+    TokenNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for PasswordResetTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::TokenNotFound, Self::TokenNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EmailVerificationTokenStoreError {
+    #[error("Email verification token not found")]
+    TokenNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for EmailVerificationTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::TokenNotFound, Self::TokenNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MagicLinkTokenStoreError {
+    #[error("Magic link token not found")]
+    TokenNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for MagicLinkTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::TokenNotFound, Self::TokenNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RecoveryCodeStoreError {
+    #[error("Recovery code not found")]
+    CodeNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for RecoveryCodeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::CodeNotFound, Self::CodeNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PasskeyStoreError {
+    #[error("Passkey already registered")]
+    CredentialAlreadyExists,
+    #[error("Passkey not found")]
+    CredentialNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for PasskeyStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::CredentialAlreadyExists, Self::CredentialAlreadyExists)
+                | (Self::CredentialNotFound, Self::CredentialNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PasskeyChallengeStoreError {
+    #[error("Passkey challenge not found")]
+    ChallengeNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for PasskeyChallengeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::ChallengeNotFound, Self::ChallengeNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OAuthClientStoreError {
+    #[error("Client already exists")]
+    ClientAlreadyExists,
+    #[error("Invalid client credentials")]
+    InvalidCredentials,
+    #[error("Client not found")]
+    ClientNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for OAuthClientStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::ClientAlreadyExists, Self::ClientAlreadyExists)
+                | (Self::InvalidCredentials, Self::InvalidCredentials)
+                | (Self::ClientNotFound, Self::ClientNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AuthorizationCodeStoreError {
+    #[error("Authorization code not found")]
+    CodeNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for AuthorizationCodeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::CodeNotFound, Self::CodeNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SessionStoreError {
+    #[error("Session not found")]
+    SessionNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for SessionStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::SessionNotFound, Self::SessionNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum JwtKeyStoreError {
+    #[error("Key already exists")]
+    KeyAlreadyExists,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for JwtKeyStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::KeyAlreadyExists, Self::KeyAlreadyExists)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
@@ -1,18 +1,44 @@
+use std::time::Duration;
+
 use color_eyre::eyre::Report;
 use thiserror::Error;
 
+use super::{OAuthErrorCode, PasswordPolicyViolation};
+
 #[derive(Debug, Error)]
 pub enum AuthAPIError {
-    #[error("User already exists")]
-    UserAlreadyExists,
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Password does not meet the password policy")]
+    PasswordPolicyViolation(Vec<PasswordPolicyViolation>),
     #[error("Incorrect credentials")]
     IncorrectCredentials,
     #[error("Missing token")]
     MissingToken,
     #[error("Invalid token")]
     InvalidToken,
+    #[error("2FA not enabled")]
+    TwoFANotEnabled,
+    #[error("TOTP already enabled")]
+    TotpAlreadyEnabled,
+    #[error("Email not verified")]
+    EmailNotVerified,
+    #[error("Session not found")]
+    SessionNotFound,
+    #[error("User not found")]
+    UserNotFound,
+    #[error("Account disabled")]
+    AccountDisabled,
+    #[error("Account pending deletion")]
+    AccountPendingDeletion,
+    #[error("Too many requests")]
+    TooManyRequests { retry_after: Duration },
+    #[error("2FA code sent too recently")]
+    TwoFAResendCooldown { retry_after: Duration },
+    #[error("2FA code resend limit reached")]
+    TooManyTwoFAResends,
+    #[error("{}", .0.as_ref())]
+    OAuthError(OAuthErrorCode),
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
\ No newline at end of file
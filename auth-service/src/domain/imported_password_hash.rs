@@ -0,0 +1,86 @@
+use argon2::PasswordHash;
+use color_eyre::eyre::{eyre, Result};
+use secrecy::{ExposeSecret, Secret};
+
+// PHC algorithm identifiers we can verify at login
+const SUPPORTED_PHC_ALGORITHMS: [&str; 6] = [
+    "argon2id",
+    "argon2i",
+    "argon2d",
+    "pbkdf2-sha256",
+    "pbkdf2-sha512",
+    "scrypt",
+];
+
+// A password hash brought over from another system. Accepts PHC strings of the algorithms
+// above, and bcrypt hashes, which have their own `$2b$` format instead.
+#[derive(Debug, Clone)]
+pub struct ImportedPasswordHash(Secret<String>);
+
+impl ImportedPasswordHash {
+    pub fn parse(s: Secret<String>) -> Result<Self> {
+        let hash = s.expose_secret();
+        if is_bcrypt_hash(hash) {
+            return Ok(Self(s));
+        }
+
+        let parsed = PasswordHash::new(hash).map_err(|e| eyre!("not a PHC password hash: {}", e))?;
+        if !SUPPORTED_PHC_ALGORITHMS.contains(&parsed.algorithm.as_str()) {
+            return Err(eyre!("unsupported password hash algorithm {}", parsed.algorithm));
+        }
+        if parsed.hash.is_none() {
+            return Err(eyre!("password hash has no hash value"));
+        }
+
+        Ok(Self(s))
+    }
+}
+
+impl AsRef<Secret<String>> for ImportedPasswordHash {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+pub(crate) fn is_bcrypt_hash(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+        && hash.len() == 60
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<ImportedPasswordHash> {
+        ImportedPasswordHash::parse(Secret::new(s.to_owned()))
+    }
+
+    #[test]
+    fn supported_formats_are_accepted() {
+        for hash in [
+            "$2b$04$jHK5W0uNx6cgr4p1RhR6G.rPx.yJLX8aFTTW/V4KDOu8HSNbRM0Ha",
+            "$pbkdf2-sha256$i=1000,l=32$c2FsdHNhbHQ$jJwjWz/YhMQRX9qsCPjbQwWqFHe1qBtESvc5B8FVFmQ",
+            "$scrypt$ln=4,r=8,p=1$c2FsdHNhbHQ$Qc1qNO8d1V5Y4RyGiYdtDwMPt+J0Mz8UHzqlYaXA5rQ",
+            "$argon2id$v=19$m=15000,t=2,p=1$c2FsdHNhbHQ$Fiywcd2rjjUAy+d40LE2tN1WAuwxn1J48+zzvA6Zzk0",
+        ] {
+            assert!(parse(hash).is_ok(), "{} was rejected", hash);
+        }
+    }
+
+    #[test]
+    fn unsupported_algorithm_is_rejected() {
+        assert!(parse("$md5$c2FsdHNhbHQ$Fiywcd2rjjUAy+d40LE2tN1WAuwxn1J48").is_err());
+    }
+
+    #[test]
+    fn plaintext_is_rejected() {
+        assert!(parse("password123").is_err());
+    }
+
+    #[test]
+    fn truncated_bcrypt_hash_is_rejected() {
+        assert!(parse("$2b$04$jHK5W0uNx6cgr4p1RhR6G.rPx").is_err());
+    }
+}
@@ -4,10 +4,22 @@ mod data_stores;
 mod email;
 mod password;
 mod email_client;
+mod totp_secret;
+mod passkey;
+mod oauth_client;
+mod session;
+mod constant_time;
+mod imported_password_hash;
 
 pub use user::*;
 pub use error::*;
 pub use data_stores::*;
 pub use email::*;
 pub use email_client::*;
-pub use password::*;
\ No newline at end of file
+pub use password::*;
+pub use totp_secret::*;
+pub use passkey::*;
+pub use oauth_client::*;
+pub use session::*;
+pub use constant_time::*;
+pub use imported_password_hash::*;
\ No newline at end of file
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// A service registered to call us with client credentials, such as a resource server
+// introspecting the tokens it is handed, or an app logging its users in through /authorize
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuthClient {
+    pub client_id: String,
+    pub name: String,
+    // Where /authorize may send users back to. Clients without any can't use it at all.
+    pub redirect_uris: Vec<String>,
+}
+
+impl OAuthClient {
+    pub fn new(name: String, redirect_uris: Vec<String>) -> Self {
+        Self {
+            client_id: Uuid::new_v4().to_string(),
+            name,
+            redirect_uris,
+        }
+    }
+
+    // Redirect URIs are compared exactly, as RFC 6749 section 3.1.2 recommends, so a client
+    // can't be made to send its codes to a path or query it didn't register
+    pub fn allows_redirect_uri(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris.iter().any(|registered| registered == redirect_uri)
+    }
+}
+
+// What a user agreed to at /authorize, held against the authorization code until the client
+// redeems it at /token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthorizationGrant {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub email: String,
+    // Space separated, as in the request
+    pub scope: String,
+    // The base64url SHA-256 of the client's PKCE code verifier (RFC 7636, S256 only)
+    pub code_challenge: String,
+    // Echoed into the ID token, so the client can tie it to the request it made
+    pub nonce: Option<String>,
+    // The device consent was given from, which the session started at /token is recorded against
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+// Error codes from RFC 6749 sections 4.1.2.1 and 5.2. OAuth client libraries act on these
+// codes, so they are sent as the error itself rather than as a message of our own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OAuthErrorCode {
+    InvalidRequest,
+    InvalidClient,
+    InvalidGrant,
+    UnauthorizedClient,
+    UnsupportedGrantType,
+    UnsupportedResponseType,
+    InvalidScope,
+    AccessDenied,
+}
+
+impl AsRef<str> for OAuthErrorCode {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::InvalidRequest => "invalid_request",
+            Self::InvalidClient => "invalid_client",
+            Self::InvalidGrant => "invalid_grant",
+            Self::UnauthorizedClient => "unauthorized_client",
+            Self::UnsupportedGrantType => "unsupported_grant_type",
+            Self::UnsupportedResponseType => "unsupported_response_type",
+            Self::InvalidScope => "invalid_scope",
+            Self::AccessDenied => "access_denied",
+        }
+    }
+}
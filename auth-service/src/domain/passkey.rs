@@ -0,0 +1,46 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+// WebAuthn recommends challenges of at least 16 random bytes
+const PASSKEY_CHALLENGE_LENGTH: usize = 32;
+
+// A passkey registered to a user. The public key is kept as an uncompressed SEC1 P-256 point,
+// which is the only key type we ask authenticators for (ES256).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasskeyCredential {
+    // Base64url encoded credential ID, as browsers send it back in `PublicKeyCredential.id`
+    pub credential_id: String,
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+// The ceremony a challenge was issued for, so a challenge handed out for one
+// can't be answered with a response meant for another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasskeyCeremony {
+    Registration,
+    // Passwordless login, where the passkey is the only credential presented
+    Login,
+    // Completing a 2FA login that already passed the password check
+    SecondFactor,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyChallenge {
+    pub ceremony: PasskeyCeremony,
+    // Base64url encoded random bytes, compared against the challenge echoed in clientDataJSON
+    pub challenge: String,
+}
+
+impl PasskeyChallenge {
+    pub fn new(ceremony: PasskeyCeremony) -> Self {
+        let mut bytes = [0u8; PASSKEY_CHALLENGE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self {
+            ceremony,
+            challenge: URL_SAFE_NO_PAD.encode(bytes),
+        }
+    }
+}
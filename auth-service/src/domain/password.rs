@@ -32,6 +32,33 @@ impl PartialEq for Password { // New!
     }
 }
 
+// Why a new password was turned down by the password policy. Clients get these as
+// machine-readable codes, so they can tell the user exactly what to fix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordPolicyViolation {
+    TooShort,
+    TooLong,
+    TooFewCharacterClasses,
+    ContainsEmail,
+    TooWeak,
+    Breached,
+    Reused,
+}
+
+impl AsRef<str> for PasswordPolicyViolation {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::TooShort => "too_short",
+            Self::TooLong => "too_long",
+            Self::TooFewCharacterClasses => "too_few_character_classes",
+            Self::ContainsEmail => "contains_email",
+            Self::TooWeak => "too_weak",
+            Self::Breached => "breached",
+            Self::Reused => "reused",
+        }
+    }
+}
+
 // Unit tests for `Password` implementation
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,28 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// One login on one device. Every JWT and refresh token handed out for it carries its ID,
+// so ending the session cuts the device off without touching the user's other logins.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    // Unix timestamps, like the times in our JWT claims
+    pub created_at: i64,
+    pub last_seen_at: i64,
+}
+
+impl Session {
+    pub fn new(user_agent: Option<String>, ip_address: Option<String>) -> Self {
+        let now = Utc::now().timestamp();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_agent,
+            ip_address,
+            created_at: now,
+            last_seen_at: now,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use base32::Alphabet;
+use color_eyre::eyre::{eyre, Result};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+
+// RFC 4226 recommends a shared secret of at least 160 bits
+const TOTP_SECRET_LENGTH: usize = 20;
+
+const BASE32_ALPHABET: Alphabet = Alphabet::Rfc4648 { padding: false };
+
+// Shared TOTP secret, held in the unpadded base32 form authenticator apps expect
+#[derive(Debug, Clone)]
+pub struct TotpSecret(Secret<String>);
+
+impl TotpSecret {
+    pub fn parse(s: Secret<String>) -> Result<TotpSecret> {
+        match base32::decode(BASE32_ALPHABET, s.expose_secret()) {
+            Some(bytes) if bytes.len() >= TOTP_SECRET_LENGTH => Ok(Self(s)),
+            _ => Err(eyre!("Invalid TOTP secret")),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Parsing guarantees the secret is valid base32
+        base32::decode(BASE32_ALPHABET, self.0.expose_secret()).unwrap_or_default()
+    }
+}
+
+impl Default for TotpSecret {
+    fn default() -> Self {
+        let mut bytes = [0u8; TOTP_SECRET_LENGTH];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        TotpSecret(Secret::new(base32::encode(BASE32_ALPHABET, &bytes)))
+    }
+}
+
+impl AsRef<Secret<String>> for TotpSecret {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+impl PartialEq for TotpSecret {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_secret_can_be_parsed() {
+        let secret = TotpSecret::default();
+        let parsed = TotpSecret::parse(secret.as_ref().clone()).unwrap();
+        assert_eq!(parsed, secret);
+        assert_eq!(parsed.to_bytes().len(), TOTP_SECRET_LENGTH);
+    }
+
+    #[test]
+    fn invalid_secrets_are_rejected() {
+        for s in ["", "not base32!", "GEZDGNBV"] {
+            assert!(TotpSecret::parse(Secret::new(s.to_owned())).is_err(), "accepted {:?}", s);
+        }
+    }
+}
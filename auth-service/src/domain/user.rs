@@ -1,18 +1,82 @@
-use crate::domain::{Email, Password};
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Email, Password, TotpSecret};
 
 #[derive(Clone,Debug, PartialEq)]
 pub struct User{
     pub email: Email,
     pub password: Password,
-    pub requires_2fa: bool,
+    pub two_fa_method: TwoFAMethod,
+    pub verified: bool,
+    // Set once the user starts TOTP enrollment. It is only used for login
+    // after the enrollment is confirmed and `two_fa_method` becomes `Totp`.
+    pub totp_secret: Option<TotpSecret>,
+    // Disabled by an admin. Disabled users can't log in or refresh their tokens.
+    pub disabled: bool,
+    // The user asked for their account to be deleted. Until the grace period ends and it is
+    // removed for good, they can't log in, but can still cancel through the emailed link.
+    pub pending_deletion: bool,
 }
 
 impl User {
-    pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
+    pub fn new(email: Email, password: Password, two_fa_method: TwoFAMethod) -> Self {
         Self {
             email,
             password,
-            requires_2fa,
+            two_fa_method,
+            // New accounts start out unverified until the emailed link is followed
+            verified: false,
+            totp_secret: None,
+            disabled: false,
+            pending_deletion: false,
+        }
+    }
+}
+
+// The second factor a user has to provide at login, if any
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAMethod {
+    None,
+    Email,
+    Totp,
+}
+
+impl TwoFAMethod {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "email" => Ok(Self::Email),
+            "totp" => Ok(Self::Totp),
+            _ => Err(eyre!("{} is not a valid 2FA method.", s)),
         }
     }
-}
\ No newline at end of file
+}
+
+impl AsRef<str> for TwoFAMethod {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::None => "none",
+            Self::Email => "email",
+            Self::Totp => "totp",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_fa_method_round_trips_through_str() {
+        for method in [TwoFAMethod::None, TwoFAMethod::Email, TwoFAMethod::Totp] {
+            assert_eq!(TwoFAMethod::parse(method.as_ref()).unwrap(), method);
+        }
+    }
+
+    #[test]
+    fn unknown_two_fa_method_is_rejected() {
+        assert!(TwoFAMethod::parse("sms").is_err());
+    }
+}
@@ -1,18 +1,21 @@
-use std::error::Error;
+use std::{error::Error, net::SocketAddr};
 use axum::{
-    http::{Method, StatusCode},
+    http::{header::RETRY_AFTER, Method, StatusCode},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{delete, get, post},
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
+    middleware::AddExtension,
     serve::Serve,
     Json, Router,
 };
-use domain::AuthAPIError;
+use domain::{AuthAPIError, OAuthErrorCode};
+use jsonwebtoken::Algorithm;
 use redis::{Client, RedisResult};
 use secrecy::{Secret, ExposeSecret};
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, postgres::PgPoolOptions};
 use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
-use routes::{signup, login, logout, verify_2fa, verify_token};
+use routes::{cancel_account_deletion, delete_account, change_password, disable_user, enable_user, logout_all, list_sessions, revoke_session, rotate_jwt_keys, register_oauth_client, introspect, jwks, authorize, authorize_consent, get_oauth_client, token, userinfo, openid_configuration, signup, login, request_magic_link, magic_link_callback, logout, refresh, resend_2fa, request_password_reset, enroll_totp, confirm_totp, regenerate_recovery_codes, verify_recovery_code, start_passkey_registration, finish_passkey_registration, start_passkey_login, finish_passkey_login, confirm_password_reset, verify_2fa, verify_email, verify_token};
 use app_state::AppState;
 
 use crate::utils::tracing::{make_span_with_request_id, on_request, on_response};
@@ -27,7 +30,7 @@ pub mod utils;
 
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<IntoMakeServiceWithConnectInfo<Router, SocketAddr>, AddExtension<Router, ConnectInfo<SocketAddr>>>,
     // address is exposed as a public field
     // so we have access to it in tests.
     pub address: String,
@@ -41,19 +44,64 @@ impl Application {
         ];
 
         let cors = CorsLayer::new()
-            // Allow GET and POST requests
-            .allow_methods([Method::GET, Method::POST])
+            // Allow GET, POST and DELETE requests
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
             // Allow cookies to be included in requests
             .allow_credentials(true)
             .allow_origin(allowed_origins);
 
-        let router = Router::new()
+        // Clients verify ID tokens themselves, which is only safe with a key we can publish.
+        // With HS256 that would mean handing them JWT_SECRET, so OAuth stays switched off.
+        let signing_algorithm = app_state.jwt_keyring.read().await.signing_key().algorithm;
+        let oauth_enabled = matches!(signing_algorithm, Algorithm::RS256 | Algorithm::EdDSA);
+
+        let mut router = Router::new()
             .nest_service("/", ServeDir::new("assets"))
             .route("/signup", post(signup))
             .route("/login", post(login))
+            .route("/login/magic-link", post(request_magic_link))
+            .route("/login/magic-link/callback", get(magic_link_callback))
             .route("/verify-2fa", post(verify_2fa))
+            .route("/resend-2fa", post(resend_2fa))
+            .route("/2fa/totp/enroll", post(enroll_totp))
+            .route("/2fa/totp/confirm", post(confirm_totp))
+            .route("/2fa/recovery-codes", post(regenerate_recovery_codes))
+            .route("/verify-recovery-code", post(verify_recovery_code))
+            .route("/passkeys/register/start", post(start_passkey_registration))
+            .route("/passkeys/register/finish", post(finish_passkey_registration))
+            .route("/passkeys/login/start", post(start_passkey_login))
+            .route("/passkeys/login/finish", post(finish_passkey_login))
             .route("/logout", post(logout))
+            .route("/logout-all", post(logout_all))
+            .route("/refresh", post(refresh))
+            .route("/sessions", get(list_sessions))
+            .route("/sessions/:id", delete(revoke_session))
+            .route("/change-password", post(change_password))
+            .route("/account", delete(delete_account))
+            .route("/account/cancel-deletion", get(cancel_account_deletion))
+            .route("/password-reset/request", post(request_password_reset))
+            .route("/password-reset/confirm", post(confirm_password_reset))
+            .route("/verify-email", get(verify_email))
             .route("/verify-token", post(verify_token))
+            .route("/introspect", post(introspect))
+            .route("/.well-known/jwks.json", get(jwks))
+            .route("/admin/jwt-keys/rotate", post(rotate_jwt_keys))
+            .route("/admin/clients", post(register_oauth_client))
+            .route("/admin/users/disable", post(disable_user))
+            .route("/admin/users/enable", post(enable_user));
+
+        if oauth_enabled {
+            router = router
+                .route("/authorize", get(authorize).post(authorize_consent))
+                .route("/clients/:client_id", get(get_oauth_client))
+                .route("/token", post(token))
+                .route("/userinfo", get(userinfo))
+                .route("/.well-known/openid-configuration", get(openid_configuration));
+        } else {
+            tracing::warn!("OAuth and OpenID Connect endpoints are disabled, set JWT_ALGORITHM to RS256 or EdDSA to enable them");
+        }
+
+        let router = router
             .with_state(app_state)
             .layer(cors)
             .layer( // New!
@@ -68,7 +116,8 @@ impl Application {
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Sessions record the address each login came from
+        let server = axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>());
 
         Ok(Application { server, address })
     }
@@ -82,24 +131,53 @@ impl Application {
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    // Machine-readable details, for errors that have more than one cause
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
 }
 
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
         log_error_chain(&self); // New!
         let (status, error_message) = match self {
-            AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, self.to_string()),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, self.to_string()),
+            AuthAPIError::PasswordPolicyViolation(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             AuthAPIError::IncorrectCredentials => (StatusCode::UNAUTHORIZED, self.to_string()),
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, self.to_string()),
             AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, self.to_string()),
+            AuthAPIError::TwoFANotEnabled => (StatusCode::BAD_REQUEST, self.to_string()),
+            AuthAPIError::TotpAlreadyEnabled => (StatusCode::CONFLICT, self.to_string()),
+            AuthAPIError::EmailNotVerified => (StatusCode::FORBIDDEN, self.to_string()),
+            AuthAPIError::SessionNotFound => (StatusCode::NOT_FOUND, self.to_string()),
+            AuthAPIError::UserNotFound => (StatusCode::NOT_FOUND, self.to_string()),
+            AuthAPIError::AccountDisabled => (StatusCode::FORBIDDEN, self.to_string()),
+            AuthAPIError::AccountPendingDeletion => (StatusCode::FORBIDDEN, self.to_string()),
+            AuthAPIError::TooManyRequests { .. } => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            AuthAPIError::TwoFAResendCooldown { .. } => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            AuthAPIError::TooManyTwoFAResends => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            // RFC 6749 section 5.2: a client that failed to authenticate gets a 401
+            AuthAPIError::OAuthError(OAuthErrorCode::InvalidClient) => (StatusCode::UNAUTHORIZED, self.to_string()),
+            AuthAPIError::OAuthError(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             AuthAPIError::UnexpectedError(_) => { // Updated!
                 (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
             }
         };
+        let reasons = match &self {
+            AuthAPIError::PasswordPolicyViolation(violations) => violations
+                .iter()
+                .map(|violation| violation.as_ref().to_owned())
+                .collect(),
+            _ => Vec::new(),
+        };
         let body = Json(ErrorResponse {
             error: error_message.to_string(),
+            reasons,
         });
+        // Tell throttled clients how long to back off for, in whole seconds rounded up
+        if let AuthAPIError::TooManyRequests { retry_after } | AuthAPIError::TwoFAResendCooldown { retry_after } = self {
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            return (status, [(RETRY_AFTER, seconds.to_string())], body).into_response();
+        }
         (status, body).into_response()
     }
 }
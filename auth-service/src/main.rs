@@ -1,9 +1,9 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use reqwest::Client;
 use secrecy::Secret;
 use sqlx::PgPool;
 use tokio::sync::RwLock;
-use auth_service::{Application, app_state::AppState, domain::Email, get_postgres_pool, get_redis_client, services::data_stores::{PostgresUserStore, PostmarkEmailClient, RedisBannedTokenStore, RedisTwoFACodeStore}, utils::{constants::{DATABASE_URL, POSTMARK_AUTH_TOKEN, REDIS_HOST_NAME, prod}, 
+use auth_service::{Application, app_state::AppState, domain::Email, get_postgres_pool, get_redis_client, services::data_stores::{PostgresJwtKeyStore, PostgresOAuthClientStore, PostgresPasskeyStore, PostgresRecoveryCodeStore, PostgresUserStore, PostmarkEmailClient, RedisAuthorizationCodeStore, RedisBannedTokenStore, RedisEmailVerificationTokenStore, RedisMagicLinkTokenStore, RedisPasskeyChallengeStore, RedisPasswordResetTokenStore, RedisRefreshTokenStore, RedisLoginThrottleStore, RedisSessionStore, RedisTwoFACodeStore}, utils::{account_deletion::delete_expired_accounts_periodically, constants::{ACCOUNT_DELETION_SWEEP_INTERVAL, ADMIN_API_TOKEN, DATABASE_URL, JWT_INITIAL_KEY, JWT_KEY_ROTATION_INTERVAL_SECONDS, JWT_KEYRING_SYNC_INTERVAL, POSTMARK_AUTH_TOKEN, REDIS_HOST_NAME, REQUIRE_VERIFIED_EMAIL, prod}, jwt_keyring::{rotate_jwt_key_periodically, sync_jwt_keyring, sync_jwt_keyring_periodically, JwtKeyring}, login_throttle::LoginThrottlePolicy, password_policy::PasswordPolicy,
     tracing::init_tracing
 }};
 
@@ -12,11 +12,33 @@ async fn main() {
     init_tracing().expect("Failed to initialize tracing"); // Updated!
     color_eyre::install().expect("Failed to install color_eyre"); // New!
     let pg_pool = configure_postgresql().await;
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool.clone())));
+    let recovery_code_store = Arc::new(RwLock::new(PostgresRecoveryCodeStore::new(pg_pool.clone())));
+    let passkey_store = Arc::new(RwLock::new(PostgresPasskeyStore::new(pg_pool.clone())));
+    let oauth_client_store = Arc::new(RwLock::new(PostgresOAuthClientStore::new(pg_pool.clone())));
+    let jwt_key_store = Arc::new(RwLock::new(PostgresJwtKeyStore::new(pg_pool)));
     let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
     let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(Arc::new(RwLock::new(configure_redis())))));
+    let refresh_token_store = Arc::new(RwLock::new(RedisRefreshTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
+    let session_store = Arc::new(RwLock::new(RedisSessionStore::new(Arc::new(RwLock::new(configure_redis())))));
+    let password_reset_token_store = Arc::new(RwLock::new(RedisPasswordResetTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
+    let email_verification_token_store = Arc::new(RwLock::new(RedisEmailVerificationTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
+    let magic_link_token_store = Arc::new(RwLock::new(RedisMagicLinkTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
+    let passkey_challenge_store = Arc::new(RwLock::new(RedisPasskeyChallengeStore::new(Arc::new(RwLock::new(configure_redis())))));
+    let authorization_code_store = Arc::new(RwLock::new(RedisAuthorizationCodeStore::new(Arc::new(RwLock::new(configure_redis())))));
+    let login_throttle_store = Arc::new(RwLock::new(RedisLoginThrottleStore::new(Arc::new(RwLock::new(configure_redis())))));
     let email_client = Arc::new(configure_postmark_email_client()); // Updated!
-    let app_state = AppState { user_store, banned_token_store, two_fa_code_store, email_client };
+    let jwt_keyring = Arc::new(RwLock::new(JwtKeyring::new(JWT_INITIAL_KEY.clone())));
+    // Pick up keys rotated before a restart or by other instances, and keep doing so
+    sync_jwt_keyring(jwt_keyring.clone(), jwt_key_store.clone())
+        .await
+        .expect("Failed to load rotated JWT keys");
+    tokio::spawn(sync_jwt_keyring_periodically(jwt_keyring.clone(), jwt_key_store.clone(), JWT_KEYRING_SYNC_INTERVAL));
+    if *JWT_KEY_ROTATION_INTERVAL_SECONDS > 0 {
+        tokio::spawn(rotate_jwt_key_periodically(jwt_keyring.clone(), jwt_key_store.clone(), Duration::from_secs(*JWT_KEY_ROTATION_INTERVAL_SECONDS)));
+    }
+    tokio::spawn(delete_expired_accounts_periodically(user_store.clone(), ACCOUNT_DELETION_SWEEP_INTERVAL));
+    let app_state = AppState { user_store, banned_token_store, two_fa_code_store, refresh_token_store, session_store, password_reset_token_store, email_verification_token_store, magic_link_token_store, recovery_code_store, passkey_store, passkey_challenge_store, oauth_client_store, authorization_code_store, login_throttle_store, email_client, jwt_keyring, jwt_key_store, require_verified_email: *REQUIRE_VERIFIED_EMAIL, login_throttle_policy: LoginThrottlePolicy::default(), password_policy: PasswordPolicy::default(), admin_api_token: ADMIN_API_TOKEN.clone() };
 
     let app = Application::build(app_state, prod::APP_ADDRESS)
         .await
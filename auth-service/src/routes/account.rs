@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AccountDeletionToken, AuthAPIError, Email, LoginAttemptId, Password, TwoFACode, TwoFAMethod, User, UserStoreError},
+    routes::{check_two_fa_code, start_two_fa, TwoFactorAuthResponse},
+    utils::{
+        auth::{get_authenticated_user, revoke_all_user_tokens},
+        constants::{ACCOUNT_DELETION_GRACE_PERIOD_SECONDS, AUTH_SERVICE_URL, JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+    },
+};
+
+// Schedules the signed-in user's account for deletion. Like logging in, this takes the
+// password and, for users with 2FA, a second round trip with the code.
+#[tracing::instrument(name = "Delete account", skip_all)]
+pub async fn delete_account(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ReauthenticationRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let user = match get_authenticated_user(&jar, &state).await {
+        Ok(user) => user,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    match reauthenticate(&user, request.password, request.login_attempt_id, request.two_fa_code, &state).await {
+        Ok(None) => (),
+        Ok(Some(response)) => {
+            return (jar, Ok((StatusCode::PARTIAL_CONTENT, Json(DeleteAccountResponse::TwoFactorAuth(response)))))
+        }
+        Err(e) => return (jar, Err(e)),
+    }
+
+    let cancel_token = AccountDeletionToken::default();
+    let grace_period = Duration::from_secs(*ACCOUNT_DELETION_GRACE_PERIOD_SECONDS);
+    if let Err(e) = state
+        .user_store
+        .write()
+        .await
+        .schedule_deletion(&user.email, cancel_token.clone(), grace_period)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+
+    if let Err(e) = revoke_all_user_tokens(&user.email, &state).await {
+        return (jar, Err(AuthAPIError::UnexpectedError(e)));
+    }
+
+    if let Err(e) = send_cancel_deletion_email(&state, &user.email, &cancel_token).await {
+        return (jar, Err(e));
+    }
+
+    let jar = jar
+        .remove(Cookie::build(JWT_COOKIE_NAME).path("/"))
+        .remove(Cookie::build(REFRESH_COOKIE_NAME).path("/"));
+
+    let response = DeleteAccountResponse::Scheduled(AccountDeletionScheduledResponse {
+        message: "Account scheduled for deletion. Check your email to cancel.".to_owned(),
+    });
+
+    (jar, Ok((StatusCode::ACCEPTED, Json(response))))
+}
+
+// Make the signed-in user prove it is them again before a sensitive change, as when logging
+// in: with their password and, for users with 2FA, a second round trip with the code. Returns
+// the response asking for the code while that round trip is still to come.
+#[tracing::instrument(name = "Reauthenticate", skip_all)]
+pub(crate) async fn reauthenticate(
+    user: &User,
+    password: Secret<String>,
+    login_attempt_id: Option<String>,
+    two_fa_code: Option<String>,
+    state: &AppState,
+) -> Result<Option<TwoFactorAuthResponse>, AuthAPIError> {
+    let password = Password::parse(password).map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    match state.user_store.read().await.validate_user(&user.email, &password).await {
+        Ok(_) => (),
+        Err(UserStoreError::InvalidCredentials) => return Err(AuthAPIError::IncorrectCredentials),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    if user.two_fa_method == TwoFAMethod::None {
+        return Ok(None);
+    }
+
+    match (login_attempt_id, two_fa_code) {
+        (Some(login_attempt_id), Some(two_fa_code)) => {
+            let login_attempt_id = LoginAttemptId::parse(login_attempt_id).map_err(|_| AuthAPIError::InvalidCredentials)?;
+            let two_fa_code = TwoFACode::parse(two_fa_code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+            check_two_fa_code(user, &login_attempt_id, &two_fa_code, state).await?;
+            Ok(None)
+        }
+        // The password was right, so send the user off to fetch their code
+        _ => start_two_fa(user, state).await.map(Some),
+    }
+}
+
+#[tracing::instrument(name = "Cancel account deletion", skip_all)]
+pub async fn cancel_account_deletion(
+    State(state): State<AppState>,
+    Query(params): Query<CancelAccountDeletionParams>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = AccountDeletionToken::parse(params.token.expose_secret().to_owned())
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    match state.user_store.write().await.cancel_deletion(&token).await {
+        Ok(_) => (),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(CancelAccountDeletionResponse {
+        message: "Account deletion cancelled. You can log in again.".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Send cancel account deletion email", skip_all)]
+async fn send_cancel_deletion_email(
+    state: &AppState,
+    email: &Email,
+    token: &AccountDeletionToken,
+) -> Result<(), AuthAPIError> {
+    let link = format!(
+        "{}/account/cancel-deletion?token={}",
+        AUTH_SERVICE_URL.as_str(),
+        token.as_ref().expose_secret()
+    );
+    let body = format!(
+        "Your account is scheduled for deletion. If you did not ask for this or have changed your mind, follow this link before the account is removed: {}.",
+        link
+    );
+
+    state
+        .email_client
+        .send_email(email, "Your account is scheduled for deletion", &body)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
+// The body of requests that need the user to reauthenticate first
+#[derive(Deserialize)]
+pub struct ReauthenticationRequest {
+    pub password: Secret<String>,
+    // Only needed on the second call for users with 2FA
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: Option<String>,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum DeleteAccountResponse {
+    Scheduled(AccountDeletionScheduledResponse),
+    TwoFactorAuth(TwoFactorAuthResponse),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AccountDeletionScheduledResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct CancelAccountDeletionParams {
+    pub token: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct CancelAccountDeletionResponse {
+    pub message: String,
+}
@@ -0,0 +1,158 @@
+use axum::{extract::State, http::{header, HeaderMap, StatusCode}, response::IntoResponse, Json};
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{constant_time_eq, AuthAPIError, ClientSecret, Email, OAuthClient, UserStoreError},
+    utils::{auth::revoke_all_user_tokens, jwt_keyring::rotate_jwt_key},
+};
+
+// Sign new tokens with a freshly generated key. Tokens signed with the old key stay valid
+// until they expire, and the new key shows up in /.well-known/jwks.json straight away.
+// Other instances pick the key up within JWT_KEYRING_SYNC_INTERVAL.
+#[tracing::instrument(name = "Rotate JWT key endpoint", skip_all)]
+pub async fn rotate_jwt_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&headers, &state)?;
+
+    let kid = rotate_jwt_key(state.jwt_keyring.clone(), state.jwt_key_store.clone())
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok((StatusCode::OK, Json(RotateJwtKeysResponse { kid })))
+}
+
+// Register a service that authenticates with client credentials, such as a resource server
+// calling /introspect, or an app logging users in through /authorize, which also needs the
+// redirect URIs it may be sent back to. The secret is only ever returned here.
+#[tracing::instrument(name = "Register OAuth client endpoint", skip_all)]
+pub async fn register_oauth_client(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<RegisterOAuthClientRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&headers, &state)?;
+
+    let name = request.name.trim().to_owned();
+    if name.is_empty() {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    if !request.redirect_uris.iter().all(|uri| is_valid_redirect_uri(uri)) {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    let client = OAuthClient::new(name, request.redirect_uris);
+    let secret = ClientSecret::default();
+    state
+        .oauth_client_store
+        .write()
+        .await
+        .add_client(client.clone(), secret.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(RegisterOAuthClientResponse {
+        client_id: client.client_id,
+        client_secret: secret.as_ref().expose_secret().to_owned(),
+        name: client.name,
+        redirect_uris: client.redirect_uris,
+    });
+    Ok((StatusCode::CREATED, response))
+}
+
+// Lock a user out: they are signed out everywhere and can't log in again until re-enabled
+#[tracing::instrument(name = "Disable user endpoint", skip_all)]
+pub async fn disable_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<UserRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&headers, &state)?;
+
+    let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    set_user_disabled(&email, true, &state).await?;
+
+    revoke_all_user_tokens(&email, &state)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(name = "Enable user endpoint", skip_all)]
+pub async fn enable_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<UserRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&headers, &state)?;
+
+    let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    set_user_disabled(&email, false, &state).await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn set_user_disabled(email: &Email, disabled: bool, state: &AppState) -> Result<(), AuthAPIError> {
+    match state.user_store.write().await.set_disabled(email, disabled).await {
+        Ok(_) => Ok(()),
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::UserNotFound),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// RFC 6749 section 3.1.2: redirect URIs are absolute and carry no fragment
+fn is_valid_redirect_uri(uri: &str) -> bool {
+    match Url::parse(uri) {
+        Ok(url) => matches!(url.scheme(), "http" | "https") && url.has_host() && url.fragment().is_none(),
+        Err(_) => false,
+    }
+}
+
+// Admin endpoints take the configured ADMIN_API_TOKEN as a bearer token
+fn authorize_admin(headers: &HeaderMap, state: &AppState) -> Result<(), AuthAPIError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthAPIError::MissingToken)?;
+
+    let expected = state.admin_api_token.as_ref().ok_or(AuthAPIError::InvalidToken)?;
+    if !constant_time_eq(token, expected.expose_secret()) {
+        return Err(AuthAPIError::InvalidToken);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RotateJwtKeysResponse {
+    pub kid: String,
+}
+
+#[derive(Deserialize)]
+pub struct UserRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterOAuthClientRequest {
+    pub name: String,
+    #[serde(default, rename = "redirectUris")]
+    pub redirect_uris: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RegisterOAuthClientResponse {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    #[serde(rename = "clientSecret")]
+    pub client_secret: String,
+    pub name: String,
+    #[serde(rename = "redirectUris")]
+    pub redirect_uris: Vec<String>,
+}
@@ -0,0 +1,82 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Password, PasswordPolicyViolation, UserStoreError},
+    routes::create_jwt_cookie,
+    utils::{
+        auth::{get_authenticated_user, revoke_all_user_tokens},
+        device::DeviceInfo,
+    },
+};
+
+#[tracing::instrument(name = "Change password", skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    device: DeviceInfo,
+    Json(request): Json<ChangePasswordRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let user = match get_authenticated_user(&jar, &state).await {
+        Ok(user) => user,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    if let Err(e) = state.password_policy.enforce(&request.new_password, Some(&user.email)).await {
+        return (jar, Err(e));
+    }
+    let new_password = match Password::parse(request.new_password) {
+        Ok(password) => password,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    // Whoever holds the cookie still has to know the password before they can change it
+    let current_password = match Password::parse(request.current_password) {
+        Ok(password) => password,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+    match state.user_store.read().await.validate_user(&user.email, &current_password).await {
+        Ok(_) => (),
+        Err(UserStoreError::InvalidCredentials) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    match state.user_store.write().await.update_password(&user.email, new_password).await {
+        Ok(_) => (),
+        Err(UserStoreError::PasswordReused) => {
+            return (jar, Err(AuthAPIError::PasswordPolicyViolation(vec![PasswordPolicyViolation::Reused])))
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    // Every device signed in with the old password is signed out, including this request's
+    // own token, which is then replaced with one from a fresh session
+    if let Err(e) = revoke_all_user_tokens(&user.email, &state).await {
+        return (jar, Err(AuthAPIError::UnexpectedError(e)));
+    }
+
+    let (jar, result) = create_jwt_cookie(&user, &state, jar, device).await;
+    let response = result.map(|_| {
+        Json(ChangePasswordResponse {
+            message: "Password changed successfully!".to_owned(),
+        })
+    });
+
+    (jar, response.map(|body| (StatusCode::OK, body)))
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ChangePasswordResponse {
+    pub message: String,
+}
@@ -0,0 +1,132 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Form, Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, ClientSecret, OAuthClient, OAuthClientStoreError},
+    utils::auth::validate_token,
+};
+
+// RFC 7662 token introspection for resource servers. Callers authenticate with the client
+// credentials they were registered with, either as HTTP Basic auth or in the form body.
+// A token that fails validation for any reason, including being banned, is simply inactive.
+#[tracing::instrument(name = "Introspect endpoint", skip_all)]
+pub async fn introspect(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(request): Form<IntrospectRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticate_client(&headers, request.client_id.as_deref(), request.client_secret.as_ref(), &state).await?;
+
+    let response = match validate_token(&request.token, state.banned_token_store.clone(), state.session_store.clone(), state.jwt_keyring.clone()).await {
+        Ok(claims) => IntrospectResponse {
+            active: true,
+            username: Some(claims.sub.clone()),
+            sub: Some(claims.sub),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            jti: Some(claims.jti),
+            // Tokens issued to OAuth clients carry the scope they were granted, ours carry roles
+            scope: Some(claims.scope.unwrap_or_else(|| claims.roles.join(" "))).filter(|scope| !scope.is_empty()),
+            client_id: claims.client_id,
+            token_type: Some(TOKEN_TYPE.to_owned()),
+        },
+        Err(_) => IntrospectResponse::default(),
+    };
+
+    // Introspection results must not outlive a revocation in some intermediate cache
+    Ok((
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(response),
+    ))
+}
+
+pub(crate) const TOKEN_TYPE: &str = "Bearer";
+
+// Authenticate a registered client calling us with its client credentials
+pub(crate) async fn authenticate_client(
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&Secret<String>>,
+    state: &AppState,
+) -> Result<OAuthClient, AuthAPIError> {
+    let (client_id, client_secret) = client_credentials(headers, client_id, client_secret)?;
+    let client_secret = ClientSecret::parse(client_secret.expose_secret().to_owned()).map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    match state
+        .oauth_client_store
+        .read()
+        .await
+        .authenticate_client(&client_id, &client_secret)
+        .await
+    {
+        Ok(client) => Ok(client),
+        Err(OAuthClientStoreError::InvalidCredentials) => Err(AuthAPIError::IncorrectCredentials),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// RFC 6749 section 2.3.1: Basic auth is preferred, with the form body as a fallback
+fn client_credentials(
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&Secret<String>>,
+) -> Result<(String, Secret<String>), AuthAPIError> {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "));
+
+    if let Some(encoded) = basic {
+        let decoded = STANDARD
+            .decode(encoded)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or(AuthAPIError::IncorrectCredentials)?;
+        let (client_id, client_secret) = decoded.split_once(':').ok_or(AuthAPIError::IncorrectCredentials)?;
+        return Ok((client_id.to_owned(), Secret::new(client_secret.to_owned())));
+    }
+
+    match (client_id, client_secret) {
+        (Some(client_id), Some(client_secret)) => Ok((client_id.to_owned(), client_secret.clone())),
+        _ => Err(AuthAPIError::IncorrectCredentials),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct IntrospectRequest {
+    pub token: String,
+    // Accepted as RFC 7662 allows it, but we only ever issue one kind of token
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<Secret<String>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct IntrospectResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+}
@@ -0,0 +1,17 @@
+use axum::{extract::State, http::{header, StatusCode}, response::IntoResponse, Json};
+
+use crate::app_state::AppState;
+
+// Lets other services fetch the public keys they need to verify our JWTs themselves,
+// instead of calling /verify-token for every request. Keys replaced by a rotation are
+// listed until they retire, so a token signed just before the rotation still verifies.
+#[tracing::instrument(name = "JWKS endpoint", skip_all)]
+pub async fn jwks(State(state): State<AppState>) -> impl IntoResponse {
+    let jwks = state.jwt_keyring.read().await.jwks();
+
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(jwks),
+    )
+}
@@ -2,18 +2,24 @@ use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use secrecy::{Secret, ExposeSecret};
 use serde::{Deserialize, Serialize};
-use crate::domain::{LoginAttemptId, TwoFACode};
+use crate::domain::{LoginAttemptId, TwoFACode, TwoFAMethod, User};
 
 use crate::{
     app_state::AppState, 
     domain::{AuthAPIError,Email, Password},
-    utils::auth::generate_auth_cookie,
+    utils::{
+        auth::{ensure_can_log_in, generate_auth_cookie, generate_refresh_cookie, start_session},
+        device::{DeviceInfo, PeerAddress},
+        login_throttle::{record_login_failure, record_login_success, throttle_login},
+    },
 };
 
 #[tracing::instrument(name = "Login", skip_all)]
 pub async fn login(
     State(state): State<AppState>,
     jar: CookieJar, // New!
+    device: DeviceInfo,
+    PeerAddress(client_ip): PeerAddress,
     Json(request): Json<LoginRequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
     let email = match Email::parse(Secret::new(request.email.clone())) {
@@ -24,22 +30,54 @@ pub async fn login(
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
         Ok(password) => password,
     };    
+
+    if let Err(e) = throttle_login(&state, client_ip, &email).await {
+        return (jar, Err(e));
+    }
+
     let user_store = state.user_store.read().await;
     
-    match user_store.validate_user(&email, &password).await {
-        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
-        Ok(_) => (),
-    };
+    if user_store.validate_user(&email, &password).await.is_err() {
+        if let Err(e) = record_login_failure(&state, &email, None).await {
+            return (jar, Err(e));
+        }
+        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    }
 
     let user = match user_store.get_user(&email).await {
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
         Ok(user) => user,
     };
 
+    complete_login(&user, &state, jar, device).await
+}
+
+// Everything a login does once it knows who the user is, however they proved it:
+// turning away accounts that can't log in, then either asking for 2FA or handing out tokens
+#[tracing::instrument(name = "Complete login", skip_all)]
+pub(crate) async fn complete_login(
+    user: &User,
+    state: &AppState,
+    jar: CookieJar,
+    device: DeviceInfo,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    if let Err(e) = ensure_can_log_in(user) {
+        return (jar, Err(e));
+    }
+
+    // Unverified users are either turned away here or let through with
+    // `email_verified: false` in their JWT claims, depending on configuration
+    if !user.verified && state.require_verified_email {
+        return (jar, Err(AuthAPIError::EmailNotVerified));
+    }
+
     // Handle request based on user's 2FA configuration
-    match user.requires_2fa {
-        true => handle_2fa(&email,&state,jar).await,
-        false => handle_no_2fa(&user.email, jar).await,
+    match user.two_fa_method {
+        TwoFAMethod::Email | TwoFAMethod::Totp => handle_2fa(user, state, jar).await,
+        TwoFAMethod::None => handle_no_2fa(user, state, jar, device).await,
     }
 }
 
@@ -64,59 +102,99 @@ pub struct TwoFactorAuthResponse {
     pub message: String,
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
+    // Tells the client whether to wait for an email or ask for an authenticator app code
+    #[serde(rename = "2FAMethod")]
+    pub two_fa_method: TwoFAMethod,
+    // Only set when the user never typed their email, e.g. after following a magic link,
+    // since /verify-2fa needs it along with the code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
 }
 
 #[tracing::instrument(name = "Handle 2FA", skip_all)]
 async fn handle_2fa(
-    email: &Email, // New!
+    user: &User,
     state: &AppState, // New!
     jar: CookieJar,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    // First, we must generate a new random login attempt ID and 2FA code
+    match start_two_fa(user, state).await {
+        Ok(response) => (jar, Ok((StatusCode::PARTIAL_CONTENT, Json(LoginResponse::TwoFactorAuth(response))))),
+        Err(e) => (jar, Err(e)),
+    }
+}
+
+// Ask the user for their second factor, emailing them a code if that is their method.
+// The returned login attempt ID has to be sent back along with the code.
+#[tracing::instrument(name = "Start 2FA", skip_all)]
+pub(crate) async fn start_two_fa(user: &User, state: &AppState) -> Result<TwoFactorAuthResponse, AuthAPIError> {
+    let email = &user.email;
+
+    // First, we must generate a new random login attempt ID and 2FA code.
+    // TOTP users never see the generated code, but the login attempt ID still has
+    // to be stored so verify_2fa can tie the TOTP code to this login.
     let login_attempt_id = LoginAttemptId::default();
     let two_fa_code = TwoFACode::default();
 
     // Updated!
-    if let Err(e) = state
+    state
         .two_fa_code_store
         .write()
         .await
         .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
         .await
-    {
-        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    if user.two_fa_method == TwoFAMethod::Email {
+        send_two_fa_code(email, &two_fa_code, state).await?;
     }
-    
-    let body = format!("Here is your 2FA code: {}, don't share it with anyone.", two_fa_code.as_ref().expose_secret());
-    match state.email_client.send_email(email, "2FA Code", &body).await {
-        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
-        Ok(_) => (),
-    };
 
     // Finally, we need to return the login attempt ID to the client
-    let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
+    Ok(TwoFactorAuthResponse {
         message: "2FA required".to_owned(),
         login_attempt_id: login_attempt_id.as_ref().expose_secret().to_owned(),
-    }));
+        two_fa_method: user.two_fa_method,
+        email: None,
+    })
+}
 
-    (jar, Ok((StatusCode::PARTIAL_CONTENT, response)))
+#[tracing::instrument(name = "Send 2FA code", skip_all)]
+pub(crate) async fn send_two_fa_code(email: &Email, two_fa_code: &TwoFACode, state: &AppState) -> Result<(), AuthAPIError> {
+    let body = format!("Here is your 2FA code: {}, don't share it with anyone.", two_fa_code.as_ref().expose_secret());
+    state
+        .email_client
+        .send_email(email, "2FA Code", &body)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
 }
 
 #[tracing::instrument(name = "Handle No 2FA", skip_all)]
 async fn handle_no_2fa(
-    email: &Email,
+    user: &User,
+    state: &AppState,
     jar: CookieJar,
+    device: DeviceInfo,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    let auth_cookie = match generate_auth_cookie(email) {
+    if let Err(e) = record_login_success(state, &user.email).await {
+        return (jar, Err(e));
+    }
+    let session_id = match start_session(&user.email, device, state.session_store.clone()).await {
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        Ok(session_id) => session_id,
+    };
+    let auth_cookie = match generate_auth_cookie(user, &session_id, state.banned_token_store.clone(), state.jwt_keyring.clone()).await {
         Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))), // Updated!
         Ok(cookie) => cookie,
     };
-    let updated_jar = jar.add(auth_cookie);
+    let refresh_cookie = match generate_refresh_cookie(&user.email, &session_id, state.refresh_token_store.clone()).await {
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        Ok(cookie) => cookie,
+    };
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
     (updated_jar, Ok((StatusCode::OK, Json(LoginResponse::RegularAuth))))
 }
\ No newline at end of file
@@ -4,37 +4,76 @@ use secrecy::Secret;
 
 use crate::{
     app_state::{AppState}, 
-    domain::{AuthAPIError},
-    utils::{auth::validate_token, constants::JWT_COOKIE_NAME},
+    domain::{AuthAPIError, Email, RefreshToken, RefreshTokenStoreError, SessionStoreError},
+    utils::{auth::{get_authenticated_claims, revoke_all_user_tokens}, constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME}},
 };
 
 #[tracing::instrument(name = "Logout endpoint", skip_all)]
 pub async fn logout(
     State(state): State<AppState>,
     jar: CookieJar) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
-    // Retrieve JWT cookie from the `CookieJar`
-    // Return AuthAPIError::MissingToken is the cookie is not found
-
-    let cookie = match jar.get(JWT_COOKIE_NAME) {
-        Some(cookie) => cookie,
-        None => return (jar, Err(AuthAPIError::MissingToken)),
+    // Validate the JWT cookie, returning MissingToken if there is none and InvalidToken if it
+    // doesn't check out
+    let claims = match get_authenticated_claims(&jar, &state).await {
+        Ok(claims) => claims,
+        Err(e) => return (jar, Err(e)),
     };
-
-    let token = cookie.value().to_string();
-
-    match validate_token(&token, state.banned_token_store.clone()).await {
-        Ok(_claims) => (),
-        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
-    }
     
-    // Store the token in the banned token store    
+    // Ban the token by its ID in the banned token store
     let mut banned_token_store = state.banned_token_store.write().await;
-    match banned_token_store.store_token(Secret::new(token)).await {
+    match banned_token_store.store_token(claims.jti).await {
         Ok(_) => (),
         Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
     }    
+    drop(banned_token_store);
+
+    // End the session, so it no longer shows up in the user's list of signed-in devices
+    let email = match Email::parse(Secret::new(claims.sub)) {
+        Ok(email) => email,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+    match state.session_store.write().await.revoke_session(&email, &claims.sid).await {
+        Ok(_) | Err(SessionStoreError::SessionNotFound) => (),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
 
     let jar = jar.remove(JWT_COOKIE_NAME);    
 
+    // Revoke the refresh token family too, so the session cannot be revived through /refresh
+    let refresh_token = jar
+        .get(REFRESH_COOKIE_NAME)
+        .and_then(|cookie| RefreshToken::parse(cookie.value().to_owned()).ok());
+
+    if let Some(refresh_token) = refresh_token {
+        match state.refresh_token_store.write().await.revoke_family(&refresh_token).await {
+            Ok(_) | Err(RefreshTokenStoreError::TokenNotFound) => (),
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+        }
+    }
+
+    let jar = jar.remove(REFRESH_COOKIE_NAME);
+
+    (jar, Ok(StatusCode::OK))
+}
+// Signs the user out on every device, e.g. after a suspected compromise
+#[tracing::instrument(name = "Logout all endpoint", skip_all)]
+pub async fn logout_all(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let claims = match get_authenticated_claims(&jar, &state).await {
+        Ok(claims) => claims,
+        Err(e) => return (jar, Err(e)),
+    };
+    let email = match Email::parse(Secret::new(claims.sub)) {
+        Ok(email) => email,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    if let Err(e) = revoke_all_user_tokens(&email, &state).await {
+        return (jar, Err(AuthAPIError::UnexpectedError(e)));
+    }
+
+    let jar = jar.remove(JWT_COOKIE_NAME).remove(REFRESH_COOKIE_NAME);
     (jar, Ok(StatusCode::OK))
-}
\ No newline at end of file
+}
@@ -0,0 +1,150 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, MagicLinkToken, MagicLinkTokenStoreError, UserStoreError},
+    routes::{complete_login, LoginResponse, TwoFactorAuthResponse},
+    utils::{
+        auth::{ensure_can_log_in, MAGIC_LINK_TOKEN_TTL_SECONDS},
+        constants::AUTH_SERVICE_URL,
+        device::DeviceInfo,
+        magic_link::{sign_magic_link, verify_magic_link},
+    },
+};
+
+#[tracing::instrument(name = "Request magic link", skip_all)]
+pub async fn request_magic_link(
+    State(state): State<AppState>,
+    Json(request): Json<MagicLinkRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Like password reset, neither the response nor how long it takes may reveal whether an
+    // account exists for this email, so the link is sent after responding
+    tokio::spawn(
+        async move {
+            if let Err(e) = send_magic_link_email(&email, &state).await {
+                tracing::error!("failed to send magic link email: {:?}", e);
+            }
+        }
+        .in_current_span(),
+    );
+
+    let response = Json(MagicLinkResponse {
+        message: "If an account exists for this email, a login link has been sent.".to_owned(),
+    });
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Send magic link email", skip_all)]
+async fn send_magic_link_email(email: &Email, state: &AppState) -> Result<(), AuthAPIError> {
+    let user = match state.user_store.read().await.get_user(email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Ok(()),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    // The link couldn't be used to log in, so there is no point sending it
+    if ensure_can_log_in(&user).is_err() {
+        return Ok(());
+    }
+
+    let token = MagicLinkToken::default();
+    let expires_at = Utc::now().timestamp() + MAGIC_LINK_TOKEN_TTL_SECONDS;
+
+    state
+        .magic_link_token_store
+        .write()
+        .await
+        .add_token(email.clone(), token.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let link = format!(
+        "{}/?magic_link_token={}&expires={}&signature={}",
+        AUTH_SERVICE_URL.as_str(),
+        token.as_ref().expose_secret(),
+        expires_at,
+        sign_magic_link(&token, expires_at)
+    );
+    let body = format!(
+        "Use this link to log in: {}. It expires in {} minutes and can only be used once. If you did not ask to log in, you can ignore this email.",
+        link,
+        MAGIC_LINK_TOKEN_TTL_SECONDS / 60
+    );
+
+    state
+        .email_client
+        .send_email(email, "Your Login Link", &body)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
+// Following the link stands in for entering the password. Everything after that, including
+// asking for 2FA, is the same as for a password login.
+#[tracing::instrument(name = "Magic link callback", skip_all)]
+pub async fn magic_link_callback(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    device: DeviceInfo,
+    Query(params): Query<MagicLinkCallbackParams>,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    let token = match MagicLinkToken::parse(params.token.expose_secret().to_owned()) {
+        Ok(token) => token,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+    if !verify_magic_link(&token, params.expires, params.signature.expose_secret()) {
+        return (jar, Err(AuthAPIError::InvalidToken));
+    }
+
+    let email = match state.magic_link_token_store.write().await.consume_token(&token).await {
+        Ok(email) => email,
+        Err(MagicLinkTokenStoreError::TokenNotFound) => return (jar, Err(AuthAPIError::InvalidToken)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return (jar, Err(AuthAPIError::InvalidToken)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    // The page the link opens doesn't know whose link it was, but needs the email for /verify-2fa
+    let (jar, result) = complete_login(&user, &state, jar, device).await;
+    let result = result.map(|(status, Json(response))| match response {
+        LoginResponse::TwoFactorAuth(response) => (
+            status,
+            Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
+                email: Some(user.email.as_ref().expose_secret().to_owned()),
+                ..response
+            })),
+        ),
+        response => (status, Json(response)),
+    });
+    (jar, result)
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkRequest {
+    pub email: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct MagicLinkResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkCallbackParams {
+    pub token: Secret<String>,
+    pub expires: i64,
+    pub signature: Secret<String>,
+}
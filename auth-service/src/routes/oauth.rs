@@ -0,0 +1,426 @@
+use axum::{
+    extract::{Path, Query, RawQuery, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect},
+    Form, Json,
+};
+use axum_extra::extract::CookieJar;
+use jsonwebtoken::Algorithm;
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, AuthorizationCode, AuthorizationCodeStoreError, AuthorizationGrant, Email, OAuthClient,
+        OAuthClientStoreError, OAuthErrorCode, UserStoreError,
+    },
+    routes::{authenticate_client, TOKEN_TYPE},
+    utils::{
+        auth::{
+            ensure_can_log_in, generate_oauth_tokens, get_authenticated_claims, get_authenticated_user, start_session,
+            validate_token, TOKEN_TTL_SECONDS,
+        },
+        constants::{AUTH_SERVICE_URL, JWT_ISSUER},
+        device::DeviceInfo,
+        oauth::{
+            has_scope, is_supported_scope, is_valid_code_challenge, verify_pkce, EMAIL_SCOPE, OPENID_SCOPE,
+            PKCE_METHOD, SUPPORTED_SCOPES,
+        },
+    },
+};
+
+const AUTHORIZATION_CODE_RESPONSE_TYPE: &str = "code";
+const AUTHORIZATION_CODE_GRANT_TYPE: &str = "authorization_code";
+
+// Start of the authorization code flow (RFC 6749 section 4.1). Once the client and redirect URI
+// check out, anything else wrong with the request is reported back to the client on its redirect
+// URI. Valid requests go on to the consent page, which posts the user's decision back to us.
+// Users who aren't logged in are sent to log in first, and brought back here afterwards.
+#[tracing::instrument(name = "Authorize endpoint", skip_all)]
+pub async fn authorize(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    RawQuery(query): RawQuery,
+    Query(request): Query<AuthorizationRequest>,
+) -> Result<Redirect, AuthAPIError> {
+    get_registered_client(&request, &state).await?;
+
+    if let Err(error) = check_authorization_request(&request) {
+        return Ok(Redirect::to(&authorization_redirect(&request, &[("error", error.as_ref())])?));
+    }
+
+    let query = query.unwrap_or_default();
+    if get_authenticated_claims(&jar, &state).await.is_err() {
+        let return_to = format!("/authorize?{}", query);
+        return Ok(Redirect::to(&format!("/?return_to={}", urlencoding::encode(&return_to))));
+    }
+
+    Ok(Redirect::to(&format!("/consent.html?{}", query)))
+}
+
+// The consent page posts the user's decision here, along with the authorization request it was
+// shown. The request is checked again, as nothing stops it being posted without the page. The
+// page submits with fetch, so it is told where to send the browser next instead of redirected.
+#[tracing::instrument(name = "Authorize consent endpoint", skip_all)]
+pub async fn authorize_consent(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    device: DeviceInfo,
+    Json(consent): Json<ConsentRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let request = consent.request;
+    let client = get_registered_client(&request, &state).await?;
+
+    let user = get_authenticated_user(&jar, &state).await?;
+    ensure_can_log_in(&user)?;
+    if !user.verified && state.require_verified_email {
+        return Err(AuthAPIError::EmailNotVerified);
+    }
+
+    let checked = match check_authorization_request(&request) {
+        Ok(checked) => checked,
+        Err(error) => {
+            let redirect_uri = authorization_redirect(&request, &[("error", error.as_ref())])?;
+            return Ok((StatusCode::OK, Json(ConsentResponse { redirect_uri })));
+        }
+    };
+
+    if !consent.approved {
+        let redirect_uri = authorization_redirect(&request, &[("error", OAuthErrorCode::AccessDenied.as_ref())])?;
+        return Ok((StatusCode::OK, Json(ConsentResponse { redirect_uri })));
+    }
+
+    let code = AuthorizationCode::default();
+    let grant = AuthorizationGrant {
+        client_id: client.client_id,
+        redirect_uri: request.redirect_uri.clone(),
+        email: user.email.as_ref().expose_secret().to_owned(),
+        scope: checked.scope.to_owned(),
+        code_challenge: checked.code_challenge.to_owned(),
+        nonce: request.nonce.clone(),
+        user_agent: device.user_agent,
+        ip_address: device.ip_address,
+    };
+
+    state
+        .authorization_code_store
+        .write()
+        .await
+        .add_code(code.clone(), grant)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let redirect_uri = authorization_redirect(&request, &[("code", code.as_ref().expose_secret().as_str())])?;
+    Ok((StatusCode::OK, Json(ConsentResponse { redirect_uri })))
+}
+
+// What the consent page shows about the client asking for access
+#[tracing::instrument(name = "Get OAuth client endpoint", skip_all)]
+pub async fn get_oauth_client(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let client = match state.oauth_client_store.read().await.get_client(&client_id).await {
+        Ok(client) => client,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(AuthAPIError::OAuthError(OAuthErrorCode::InvalidRequest)),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(OAuthClientResponse {
+            client_id: client.client_id,
+            name: client.name,
+        }),
+    ))
+}
+
+// The client redeems its authorization code for tokens (RFC 6749 section 4.1.3), proving with
+// the PKCE code verifier that it is the one that started the flow. Every session it is handed
+// tokens for shows up in the user's session list like any other login.
+#[tracing::instrument(name = "Token endpoint", skip_all)]
+pub async fn token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(request): Form<TokenRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let client = authenticate_client(&headers, request.client_id.as_deref(), request.client_secret.as_ref(), &state)
+        .await
+        .map_err(|e| match e {
+            AuthAPIError::IncorrectCredentials => AuthAPIError::OAuthError(OAuthErrorCode::InvalidClient),
+            e => e,
+        })?;
+
+    if request.grant_type != AUTHORIZATION_CODE_GRANT_TYPE {
+        return Err(AuthAPIError::OAuthError(OAuthErrorCode::UnsupportedGrantType));
+    }
+
+    let (Some(code), Some(redirect_uri), Some(code_verifier)) = (&request.code, &request.redirect_uri, &request.code_verifier) else {
+        return Err(AuthAPIError::OAuthError(OAuthErrorCode::InvalidRequest));
+    };
+
+    let code = AuthorizationCode::parse(code.expose_secret().to_owned())
+        .map_err(|_| AuthAPIError::OAuthError(OAuthErrorCode::InvalidGrant))?;
+
+    // The code is spent even if the checks below fail, so it can't be tried again
+    let grant = match state.authorization_code_store.write().await.consume_code(&code).await {
+        Ok(grant) => grant,
+        Err(AuthorizationCodeStoreError::CodeNotFound) => return Err(AuthAPIError::OAuthError(OAuthErrorCode::InvalidGrant)),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    if grant.client_id != client.client_id
+        || grant.redirect_uri != *redirect_uri
+        || !verify_pkce(code_verifier.expose_secret(), &grant.code_challenge)
+    {
+        return Err(AuthAPIError::OAuthError(OAuthErrorCode::InvalidGrant));
+    }
+
+    let email = Email::parse(Secret::new(grant.email.clone())).map_err(AuthAPIError::UnexpectedError)?;
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::OAuthError(OAuthErrorCode::InvalidGrant)),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    // The account may have been disabled since the user gave their consent
+    if ensure_can_log_in(&user).is_err() {
+        return Err(AuthAPIError::OAuthError(OAuthErrorCode::InvalidGrant));
+    }
+
+    let device = DeviceInfo {
+        user_agent: grant.user_agent.clone(),
+        ip_address: grant.ip_address.clone(),
+    };
+    let session_id = start_session(&email, device, state.session_store.clone())
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let tokens = generate_oauth_tokens(&user, &session_id, &grant, state.banned_token_store.clone(), state.jwt_keyring.clone())
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(TokenResponse {
+        access_token: tokens.access_token,
+        token_type: TOKEN_TYPE.to_owned(),
+        expires_in: TOKEN_TTL_SECONDS,
+        id_token: tokens.id_token,
+        scope: grant.scope,
+    });
+
+    // RFC 6749 section 5.1: responses carrying tokens must not be cached
+    Ok((
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "no-store"), (header::PRAGMA, "no-cache")],
+        response,
+    ))
+}
+
+// OpenID Connect Core section 5.3. Takes an access token issued at /token as a bearer token,
+// and only tells the client what the user agreed to share with it.
+#[tracing::instrument(name = "Userinfo endpoint", skip_all)]
+pub async fn userinfo(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthAPIError::MissingToken)?;
+
+    let claims = validate_token(token, state.banned_token_store.clone(), state.session_store.clone(), state.jwt_keyring.clone())
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // Our own tokens carry no scope, so they are turned away here along with non-OpenID ones
+    let scope = claims.scope.as_deref().unwrap_or_default();
+    if !has_scope(scope, OPENID_SCOPE) {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    let email = Email::parse(Secret::new(claims.sub.clone())).map_err(|_| AuthAPIError::InvalidToken)?;
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let share_email = has_scope(scope, EMAIL_SCOPE);
+    let response = UserInfoResponse {
+        sub: claims.sub,
+        email: share_email.then(|| user.email.as_ref().expose_secret().to_owned()),
+        email_verified: share_email.then_some(user.verified),
+    };
+
+    Ok((StatusCode::OK, [(header::CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+// OpenID Connect Discovery 1.0, so client libraries can configure themselves from our issuer URL
+#[tracing::instrument(name = "OpenID configuration endpoint", skip_all)]
+pub async fn openid_configuration(State(state): State<AppState>) -> impl IntoResponse {
+    let algorithm = state.jwt_keyring.read().await.signing_key().algorithm;
+    let base_url = AUTH_SERVICE_URL.as_str();
+
+    let configuration = OpenIdConfiguration {
+        issuer: JWT_ISSUER.to_owned(),
+        authorization_endpoint: format!("{}/authorize", base_url),
+        token_endpoint: format!("{}/token", base_url),
+        userinfo_endpoint: format!("{}/userinfo", base_url),
+        jwks_uri: format!("{}/.well-known/jwks.json", base_url),
+        scopes_supported: SUPPORTED_SCOPES.iter().map(|scope| scope.to_string()).collect(),
+        response_types_supported: vec![AUTHORIZATION_CODE_RESPONSE_TYPE.to_owned()],
+        grant_types_supported: vec![AUTHORIZATION_CODE_GRANT_TYPE.to_owned()],
+        subject_types_supported: vec!["public".to_owned()],
+        id_token_signing_alg_values_supported: vec![algorithm],
+        token_endpoint_auth_methods_supported: vec!["client_secret_basic".to_owned(), "client_secret_post".to_owned()],
+        code_challenge_methods_supported: vec![PKCE_METHOD.to_owned()],
+        claims_supported: ["iss", "sub", "aud", "exp", "iat", "nonce", "email", "email_verified"]
+            .iter()
+            .map(|claim| claim.to_string())
+            .collect(),
+    };
+
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(configuration),
+    )
+}
+
+// Until the client and redirect URI are known to be good, errors can't be sent to the redirect
+// URI (RFC 6749 section 4.1.2.1), so they are returned to the browser instead
+async fn get_registered_client(request: &AuthorizationRequest, state: &AppState) -> Result<OAuthClient, AuthAPIError> {
+    let client = match state.oauth_client_store.read().await.get_client(&request.client_id).await {
+        Ok(client) => client,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(AuthAPIError::OAuthError(OAuthErrorCode::InvalidRequest)),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    if !client.allows_redirect_uri(&request.redirect_uri) {
+        return Err(AuthAPIError::OAuthError(OAuthErrorCode::InvalidRequest));
+    }
+
+    Ok(client)
+}
+
+// The parts of a valid authorization request that end up in the grant
+struct CheckedAuthorizationRequest<'a> {
+    scope: &'a str,
+    code_challenge: &'a str,
+}
+
+// Every authorization must use PKCE, even from clients that can keep a secret, as it also stops
+// a code leaked through the redirect from being redeemed by anyone else
+fn check_authorization_request(request: &AuthorizationRequest) -> Result<CheckedAuthorizationRequest<'_>, OAuthErrorCode> {
+    if request.response_type.as_deref() != Some(AUTHORIZATION_CODE_RESPONSE_TYPE) {
+        return Err(OAuthErrorCode::UnsupportedResponseType);
+    }
+
+    let scope = request.scope.as_deref().ok_or(OAuthErrorCode::InvalidRequest)?;
+    if !is_supported_scope(scope) {
+        return Err(OAuthErrorCode::InvalidScope);
+    }
+
+    let code_challenge = request.code_challenge.as_deref().ok_or(OAuthErrorCode::InvalidRequest)?;
+    if request.code_challenge_method.as_deref() != Some(PKCE_METHOD) || !is_valid_code_challenge(code_challenge) {
+        return Err(OAuthErrorCode::InvalidRequest);
+    }
+
+    Ok(CheckedAuthorizationRequest { scope, code_challenge })
+}
+
+// Send the browser back to the client with the given parameters, and the client's state
+fn authorization_redirect(request: &AuthorizationRequest, params: &[(&str, &str)]) -> Result<String, AuthAPIError> {
+    let mut url = Url::parse(&request.redirect_uri).map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.extend_pairs(params);
+        if let Some(state) = &request.state {
+            query.append_pair("state", state);
+        }
+    }
+    Ok(url.into())
+}
+
+#[derive(Deserialize)]
+pub struct AuthorizationRequest {
+    pub client_id: String,
+    pub redirect_uri: String,
+    // The rest are checked by hand, so problems with them can be reported to the client
+    pub response_type: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ConsentRequest {
+    #[serde(flatten)]
+    pub request: AuthorizationRequest,
+    pub approved: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConsentResponse {
+    #[serde(rename = "redirectUri")]
+    pub redirect_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct OAuthClientResponse {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<Secret<String>>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<Secret<String>>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<Secret<String>>,
+}
+
+// Field names follow RFC 6749 section 5.1 rather than our usual camelCase
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+    pub scope: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct UserInfoResponse {
+    pub sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct OpenIdConfiguration {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<Algorithm>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+}
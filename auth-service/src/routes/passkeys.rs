@@ -0,0 +1,467 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, LoginAttemptId, PasskeyCeremony, PasskeyCeremonyId, PasskeyChallenge,
+        PasskeyChallengeStoreError, PasskeyCredential, PasskeyStoreError, User,
+    },
+    routes::create_jwt_cookie,
+    utils::{
+        auth::get_authenticated_user,
+        constants::{WEBAUTHN_ORIGIN, WEBAUTHN_RP_ID, WEBAUTHN_RP_NAME},
+        device::{DeviceInfo, PeerAddress},
+        login_throttle::{record_login_failure, record_login_success, throttle_login, throttle_two_fa},
+        webauthn::{
+            decoy_credential_id, verify_assertion, verify_registration, PasskeyAssertion, RelyingParty,
+            COSE_ALGORITHM_ES256, PASSKEY_CHALLENGE_TTL_SECONDS,
+        },
+    },
+};
+
+const PUBLIC_KEY_CREDENTIAL_TYPE: &str = "public-key";
+
+// Hands a logged-in user the options for `navigator.credentials.create()`
+#[tracing::instrument(name = "Start passkey registration", skip_all)]
+pub async fn start_passkey_registration(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user = get_authenticated_user(&jar, &state).await?;
+
+    let existing_credentials = state
+        .passkey_store
+        .read()
+        .await
+        .get_credentials(&user.email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let ceremony_id = PasskeyCeremonyId::default();
+    let challenge = PasskeyChallenge::new(PasskeyCeremony::Registration);
+    state
+        .passkey_challenge_store
+        .write()
+        .await
+        .add_challenge(ceremony_id.clone(), user.email.clone(), challenge.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let email = user.email.as_ref().expose_secret().to_owned();
+    let response = Json(PasskeyRegistrationOptions {
+        ceremony_id: ceremony_id.as_ref().expose_secret().to_owned(),
+        challenge: challenge.challenge,
+        rp: RelyingPartyEntity {
+            id: WEBAUTHN_RP_ID.to_owned(),
+            name: WEBAUTHN_RP_NAME.to_owned(),
+        },
+        user: UserEntity {
+            // The user handle is stored on the authenticator, so it shouldn't carry the email itself
+            id: URL_SAFE_NO_PAD.encode(Sha256::digest(email.as_bytes())),
+            name: email.clone(),
+            display_name: email,
+        },
+        pub_key_cred_params: vec![CredentialParameters {
+            credential_type: PUBLIC_KEY_CREDENTIAL_TYPE.to_owned(),
+            alg: COSE_ALGORITHM_ES256,
+        }],
+        timeout: PASSKEY_CHALLENGE_TTL_SECONDS * 1000,
+        attestation: "none".to_owned(),
+        // Stops the browser from registering the same authenticator twice
+        exclude_credentials: credential_descriptors(&existing_credentials),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Finish passkey registration", skip_all)]
+pub async fn finish_passkey_registration(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<PasskeyRegistrationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let ceremony_id = PasskeyCeremonyId::parse(request.ceremony_id).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let client_data_json = decode_field(&request.response.client_data_json)?;
+    let attestation_object = decode_field(&request.response.attestation_object)?;
+
+    let user = get_authenticated_user(&jar, &state).await?;
+
+    let challenge = match state.passkey_challenge_store.write().await.take_challenge(&ceremony_id).await {
+        Ok((email, challenge)) if email == user.email && challenge.ceremony == PasskeyCeremony::Registration => challenge,
+        Ok(_) | Err(PasskeyChallengeStoreError::ChallengeNotFound) => return Err(AuthAPIError::InvalidCredentials),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let credential = verify_registration(&relying_party(), &challenge, &client_data_json, &attestation_object)
+        .map_err(|e| {
+            tracing::warn!("Passkey registration rejected: {:?}", e);
+            AuthAPIError::IncorrectCredentials
+        })?;
+
+    match state.passkey_store.write().await.add_credential(&user.email, credential).await {
+        Ok(_) => (),
+        Err(PasskeyStoreError::CredentialAlreadyExists) => return Err(AuthAPIError::InvalidCredentials),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(PasskeyRegistrationResponse {
+        message: "Passkey registered successfully!".to_owned(),
+    });
+
+    Ok((StatusCode::CREATED, response))
+}
+
+// Hands out the options for `navigator.credentials.get()`. With a login attempt ID the passkey
+// completes a password login as its second factor; without one it is the only credential
+// presented, so the authenticator must also verify the user (PIN or biometrics).
+// Emails without an account or a passkey get the same kind of answer as those with one.
+#[tracing::instrument(name = "Start passkey login", skip_all)]
+pub async fn start_passkey_login(
+    State(state): State<AppState>,
+    Json(request): Json<PasskeyLoginStartRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let ceremony = match request.login_attempt_id {
+        Some(id) => {
+            LoginAttemptId::parse(id).map_err(|_| AuthAPIError::InvalidCredentials)?;
+            PasskeyCeremony::SecondFactor
+        }
+        None => PasskeyCeremony::Login,
+    };
+
+    let credentials = state
+        .passkey_store
+        .read()
+        .await
+        .get_credentials(&email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let allow_credentials = if credentials.is_empty() {
+        vec![CredentialDescriptor {
+            credential_type: PUBLIC_KEY_CREDENTIAL_TYPE.to_owned(),
+            id: decoy_credential_id(&email),
+        }]
+    } else {
+        credential_descriptors(&credentials)
+    };
+
+    // A challenge is stored either way, so nothing about the response tells the two cases apart.
+    // Answering one for a user without passkeys fails at the finish like a wrong passkey would.
+    let ceremony_id = PasskeyCeremonyId::default();
+    let challenge = PasskeyChallenge::new(ceremony);
+    state
+        .passkey_challenge_store
+        .write()
+        .await
+        .add_challenge(ceremony_id.clone(), email, challenge.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let user_verification = match ceremony {
+        PasskeyCeremony::SecondFactor => "preferred",
+        _ => "required",
+    };
+
+    let response = Json(PasskeyLoginOptions {
+        ceremony_id: ceremony_id.as_ref().expose_secret().to_owned(),
+        challenge: challenge.challenge,
+        rp_id: WEBAUTHN_RP_ID.to_owned(),
+        allow_credentials,
+        timeout: PASSKEY_CHALLENGE_TTL_SECONDS * 1000,
+        user_verification: user_verification.to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Finish passkey login", skip_all)]
+pub async fn finish_passkey_login(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    device: DeviceInfo,
+    PeerAddress(client_ip): PeerAddress,
+    Json(request): Json<PasskeyLoginFinishRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let email = match Email::parse(Secret::new(request.email)) {
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+        Ok(email) => email,
+    };
+
+    let login_attempt_id = match request.login_attempt_id.map(LoginAttemptId::parse).transpose() {
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+        Ok(id) => id,
+    };
+
+    let ceremony_id = match PasskeyCeremonyId::parse(request.ceremony_id) {
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+        Ok(id) => id,
+    };
+
+    let assertion = match decode_assertion(&request.response) {
+        Err(e) => return (jar, Err(e)),
+        Ok(assertion) => assertion,
+    };
+
+    // A passkey stands in for a password or a 2FA code, so it is throttled like one
+    let throttled = match &login_attempt_id {
+        Some(login_attempt_id) => throttle_two_fa(&state, client_ip, &email, login_attempt_id).await,
+        None => throttle_login(&state, client_ip, &email).await,
+    };
+    if let Err(e) = throttled {
+        return (jar, Err(e));
+    }
+
+    let user = match check_passkey_assertion(
+        &email,
+        login_attempt_id.as_ref(),
+        &ceremony_id,
+        &request.id,
+        &assertion,
+        &state,
+    )
+    .await
+    {
+        Ok(user) => user,
+        Err(AuthAPIError::IncorrectCredentials) => {
+            if let Err(e) = record_login_failure(&state, &email, login_attempt_id.as_ref()).await {
+                return (jar, Err(e));
+            }
+            return (jar, Err(AuthAPIError::IncorrectCredentials));
+        }
+        Err(e) => return (jar, Err(e)),
+    };
+
+    // Passwordless logins skip /login, so its email verification check happens here
+    if login_attempt_id.is_none() && !user.verified && state.require_verified_email {
+        return (jar, Err(AuthAPIError::EmailNotVerified));
+    }
+
+    if let Err(e) = record_login_success(&state, &email).await {
+        return (jar, Err(e));
+    }
+
+    create_jwt_cookie(&user, &state, jar, device).await
+}
+
+// Check a passkey's answer to the challenge of the ceremony it claims to finish, returning the
+// user it logs in. When it completes a 2FA login, the pending login attempt is used up too.
+#[tracing::instrument(name = "Check passkey assertion", skip_all)]
+async fn check_passkey_assertion(
+    email: &Email,
+    login_attempt_id: Option<&LoginAttemptId>,
+    ceremony_id: &PasskeyCeremonyId,
+    credential_id: &str,
+    assertion: &PasskeyAssertion,
+    state: &AppState,
+) -> Result<User, AuthAPIError> {
+    let user = state
+        .user_store
+        .read()
+        .await
+        .get_user(email)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    let challenge = match state.passkey_challenge_store.write().await.take_challenge(ceremony_id).await {
+        Ok((ceremony_email, challenge)) if &ceremony_email == email => challenge,
+        Ok(_) | Err(PasskeyChallengeStoreError::ChallengeNotFound) => return Err(AuthAPIError::IncorrectCredentials),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let require_user_verification = match (challenge.ceremony, login_attempt_id) {
+        (PasskeyCeremony::Login, None) => true,
+        (PasskeyCeremony::SecondFactor, Some(_)) => false,
+        _ => return Err(AuthAPIError::IncorrectCredentials),
+    };
+
+    let credential = state
+        .passkey_store
+        .read()
+        .await
+        .get_credentials(email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .into_iter()
+        .find(|credential| credential.credential_id == credential_id)
+        .ok_or(AuthAPIError::IncorrectCredentials)?;
+
+    let sign_count = verify_assertion(&relying_party(), &challenge, &credential, assertion, require_user_verification)
+        .map_err(|e| {
+            tracing::warn!("Passkey assertion rejected: {:?}", e);
+            AuthAPIError::IncorrectCredentials
+        })?;
+
+    state
+        .passkey_store
+        .write()
+        .await
+        .update_sign_count(email, &credential.credential_id, sign_count)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // The password was already checked by /login, so only the pending 2FA attempt is left
+    if let Some(login_attempt_id) = login_attempt_id {
+        let mut two_fa_code_store = state.two_fa_code_store.write().await;
+
+        two_fa_code_store
+            .get_attempt(email, login_attempt_id)
+            .await
+            .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+        two_fa_code_store
+            .remove_code(login_attempt_id)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    }
+
+    Ok(user)
+}
+
+fn relying_party() -> RelyingParty<'static> {
+    RelyingParty {
+        id: &WEBAUTHN_RP_ID,
+        origin: &WEBAUTHN_ORIGIN,
+    }
+}
+
+fn credential_descriptors(credentials: &[PasskeyCredential]) -> Vec<CredentialDescriptor> {
+    credentials
+        .iter()
+        .map(|credential| CredentialDescriptor {
+            credential_type: PUBLIC_KEY_CREDENTIAL_TYPE.to_owned(),
+            id: credential.credential_id.clone(),
+        })
+        .collect()
+}
+
+fn decode_field(value: &str) -> Result<Vec<u8>, AuthAPIError> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| AuthAPIError::InvalidCredentials)
+}
+
+fn decode_assertion(response: &AssertionResponse) -> Result<PasskeyAssertion, AuthAPIError> {
+    Ok(PasskeyAssertion {
+        client_data_json: decode_field(&response.client_data_json)?,
+        authenticator_data: decode_field(&response.authenticator_data)?,
+        signature: decode_field(&response.signature)?,
+    })
+}
+
+// The request bodies mirror the JSON form of a `PublicKeyCredential` (binary fields
+// base64url encoded), so the browser's result can be posted as is
+
+#[derive(Deserialize)]
+pub struct PasskeyRegistrationRequest {
+    #[serde(rename = "ceremonyId")]
+    pub ceremony_id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyLoginStartRequest {
+    pub email: String,
+    #[serde(rename = "loginAttemptId", default)]
+    pub login_attempt_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyLoginFinishRequest {
+    pub email: String,
+    #[serde(rename = "loginAttemptId", default)]
+    pub login_attempt_id: Option<String>,
+    #[serde(rename = "ceremonyId")]
+    pub ceremony_id: String,
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct PasskeyRegistrationResponse {
+    pub message: String,
+}
+
+// Mirrors `PublicKeyCredentialCreationOptions`, with binary fields base64url encoded.
+// The ceremony ID isn't part of WebAuthn; it has to be sent back with the browser's response.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasskeyRegistrationOptions {
+    #[serde(rename = "ceremonyId")]
+    pub ceremony_id: String,
+    pub challenge: String,
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    #[serde(rename = "pubKeyCredParams")]
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    pub timeout: u64,
+    pub attestation: String,
+    #[serde(rename = "excludeCredentials")]
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+}
+
+// Mirrors `PublicKeyCredentialRequestOptions`, with binary fields base64url encoded.
+// As with registration, the ceremony ID has to be sent back with the browser's response.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasskeyLoginOptions {
+    #[serde(rename = "ceremonyId")]
+    pub ceremony_id: String,
+    pub challenge: String,
+    #[serde(rename = "rpId")]
+    pub rp_id: String,
+    #[serde(rename = "allowCredentials")]
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub timeout: u64,
+    #[serde(rename = "userVerification")]
+    pub user_verification: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserEntity {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub alg: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub id: String,
+}
@@ -0,0 +1,157 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, Password, PasswordResetToken, PasswordResetTokenStoreError,
+        UserStoreError,
+    },
+    utils::{auth::revoke_all_user_tokens, constants::AUTH_SERVICE_URL},
+};
+
+#[tracing::instrument(name = "Request password reset", skip_all)]
+pub async fn request_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // The response must not reveal whether an account exists for this email, so everything
+    // that depends on it happens after responding. Unknown emails then take as long as known ones.
+    tokio::spawn(
+        async move {
+            if let Err(e) = send_password_reset_email(&email, &state).await {
+                tracing::error!("failed to send password reset email: {:?}", e);
+            }
+        }
+        .in_current_span(),
+    );
+
+    let response = Json(PasswordResetResponse {
+        message: "If an account exists for this email, a password reset link has been sent.".to_owned(),
+    });
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Send password reset email", skip_all)]
+async fn send_password_reset_email(email: &Email, state: &AppState) -> Result<(), AuthAPIError> {
+    match state.user_store.read().await.get_user(email).await {
+        Ok(_) => (),
+        Err(UserStoreError::UserNotFound) => return Ok(()),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let token = PasswordResetToken::default();
+
+    state
+        .password_reset_token_store
+        .write()
+        .await
+        .add_token(email.clone(), token.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let link = format!(
+        "{}/?password_reset_token={}",
+        AUTH_SERVICE_URL.as_str(),
+        token.as_ref().expose_secret()
+    );
+    let body = format!(
+        "Use this link to reset your password: {}. It can only be used once. If you did not request a password reset, you can ignore this email.",
+        link
+    );
+
+    state
+        .email_client
+        .send_email(email, "Password Reset", &body)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
+#[tracing::instrument(name = "Confirm password reset", skip_all)]
+pub async fn confirm_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetConfirmRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = PasswordResetToken::parse(request.token.expose_secret().to_owned())
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // Checks that don't depend on whose link it is come first, and don't count against it
+    state.password_policy.enforce(&request.new_password, None).await?;
+    let password = Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // The token is only looked at until the password is known to be acceptable, so a refused
+    // password doesn't burn the emailed link or restart its expiry
+    let email = match state.password_reset_token_store.read().await.get_email(&token).await {
+        Ok(email) => email,
+        Err(PasswordResetTokenStoreError::TokenNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    // Whether the password contains the email, or is one the user had before, can only be told
+    // now. Each refusal counts against the link, so it can't be used to try guesses against the
+    // user's old passwords indefinitely. For the same reason a reused password isn't reported
+    // as such here, unlike at /change-password.
+    if let Err(e) = state.password_policy.enforce(password.as_ref(), Some(&email)).await {
+        return Err(refuse_password(&state, &token, e).await);
+    }
+    match state.user_store.read().await.is_password_reused(&email, &password).await {
+        Ok(false) => (),
+        Ok(true) => return Err(refuse_password(&state, &token, AuthAPIError::InvalidCredentials).await),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    match state.password_reset_token_store.write().await.consume_token(&token).await {
+        Ok(_) => (),
+        Err(PasswordResetTokenStoreError::TokenNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    match state.user_store.write().await.update_password(&email, password).await {
+        Ok(_) => (),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        // Only if the password was changed in the meantime; the link has been used up by now
+        Err(UserStoreError::PasswordReused) => return Err(AuthAPIError::InvalidCredentials),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    // Anyone holding an old session must sign in again with the new password
+    revoke_all_user_tokens(&email, &state)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(PasswordResetResponse {
+        message: "Password reset successfully!".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Counts a refused password against the token, returning the refusal
+async fn refuse_password(state: &AppState, token: &PasswordResetToken, error: AuthAPIError) -> AuthAPIError {
+    match state.password_reset_token_store.write().await.record_failed_attempt(token).await {
+        Ok(_) | Err(PasswordResetTokenStoreError::TokenNotFound) => error,
+        Err(e) => AuthAPIError::UnexpectedError(e.into()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: Secret<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetConfirmRequest {
+    pub token: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct PasswordResetResponse {
+    pub message: String,
+}
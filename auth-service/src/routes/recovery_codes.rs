@@ -0,0 +1,86 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, RecoveryCode, TwoFAMethod},
+    routes::{reauthenticate, ReauthenticationRequest, TwoFactorAuthResponse},
+    utils::auth::get_authenticated_user,
+};
+
+// Number of recovery codes handed out in each set
+const RECOVERY_CODE_COUNT: usize = 10;
+
+// Replaces the user's recovery codes. Each one gets past 2FA, so the user has to
+// reauthenticate with their password and second factor first.
+#[tracing::instrument(name = "Regenerate recovery codes", skip_all)]
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ReauthenticationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user = get_authenticated_user(&jar, &state).await?;
+
+    if user.two_fa_method == TwoFAMethod::None {
+        return Err(AuthAPIError::TwoFANotEnabled);
+    }
+
+    if let Some(response) =
+        reauthenticate(&user, request.password, request.login_attempt_id, request.two_fa_code, &state).await?
+    {
+        return Ok((StatusCode::PARTIAL_CONTENT, Json(RegenerateRecoveryCodesResponse::TwoFactorAuth(response))));
+    }
+
+    let recovery_codes = issue_recovery_codes(&user.email, &state).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(RegenerateRecoveryCodesResponse::Regenerated(RecoveryCodesResponse { recovery_codes })),
+    ))
+}
+
+// Generate a new set of recovery codes for the user, invalidating any previous set.
+// Only hashes are stored, so the returned codes are the one chance to show them to the user.
+#[tracing::instrument(name = "Issue recovery codes", skip_all)]
+pub(crate) async fn issue_recovery_codes(
+    email: &Email,
+    state: &AppState,
+) -> Result<Vec<String>, AuthAPIError> {
+    let codes = generate_recovery_codes();
+
+    let plaintext_codes = codes
+        .iter()
+        .map(|code| code.as_ref().expose_secret().to_owned())
+        .collect();
+
+    state
+        .recovery_code_store
+        .write()
+        .await
+        .replace_codes(email, codes)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(plaintext_codes)
+}
+
+pub(crate) fn generate_recovery_codes() -> Vec<RecoveryCode> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| RecoveryCode::default())
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum RegenerateRecoveryCodesResponse {
+    Regenerated(RecoveryCodesResponse),
+    TwoFactorAuth(TwoFactorAuthResponse),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct RecoveryCodesResponse {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
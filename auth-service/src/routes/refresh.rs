@@ -0,0 +1,74 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::CookieJar;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, RefreshToken, RefreshTokenStoreError, SessionStoreError, UserStoreError},
+    utils::{auth::{create_refresh_cookie, ensure_can_log_in, generate_auth_cookie}, constants::REFRESH_COOKIE_NAME},
+};
+
+#[tracing::instrument(name = "Refresh endpoint", skip_all)]
+pub async fn refresh(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let cookie = match jar.get(REFRESH_COOKIE_NAME) {
+        Some(cookie) => cookie,
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let token = match RefreshToken::parse(cookie.value().to_owned()) {
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+        Ok(token) => token,
+    };
+
+    // Every refresh hands out a new refresh token and retires the one that was presented
+    let new_token = RefreshToken::default();
+
+    let (email, session_id) = match state
+        .refresh_token_store
+        .write()
+        .await
+        .rotate_token(&token, new_token.clone())
+        .await
+    {
+        Ok(result) => result,
+        Err(RefreshTokenStoreError::TokenReused) => {
+            tracing::warn!("Refresh token reused, token family revoked");
+            return (jar, Err(AuthAPIError::InvalidToken));
+        }
+        Err(RefreshTokenStoreError::TokenNotFound) => return (jar, Err(AuthAPIError::InvalidToken)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    // A signed-out session can't be revived, so drop the family along with it
+    match state.session_store.write().await.touch_session(&session_id).await {
+        Ok(_) => (),
+        Err(SessionStoreError::SessionNotFound) => {
+            if let Err(e) = state.refresh_token_store.write().await.revoke_family(&new_token).await {
+                return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+            }
+            return (jar, Err(AuthAPIError::InvalidToken));
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    // Look the user up again so the new JWT reflects their current account state
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return (jar, Err(AuthAPIError::InvalidToken)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    if let Err(e) = ensure_can_log_in(&user) {
+        return (jar, Err(e));
+    }
+
+    let auth_cookie = match generate_auth_cookie(&user, &session_id, state.banned_token_store.clone(), state.jwt_keyring.clone()).await {
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        Ok(cookie) => cookie,
+    };
+
+    let updated_jar = jar.add(auth_cookie).add(create_refresh_cookie(new_token));
+    (updated_jar, Ok(StatusCode::OK))
+}
@@ -0,0 +1,80 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::Utc;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, LoginAttemptId, TwoFACode, TwoFACodeStoreError, TwoFAMethod, UserStoreError},
+    routes::send_two_fa_code,
+    utils::{device::PeerAddress, login_throttle::throttle_two_fa},
+};
+
+// Emails a fresh code for a login attempt that is still waiting on 2FA, for when the
+// first email was delayed or lost. The attempt itself, and its ID, stay the same.
+#[tracing::instrument(name = "Resend 2FA endpoint", skip_all)]
+pub async fn resend_2fa(
+    State(state): State<AppState>,
+    PeerAddress(client_ip): PeerAddress,
+    Json(request): Json<Resend2FARequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let login_attempt_id =
+        LoginAttemptId::parse(request.login_attempt_id).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Every resend sends an email, so it counts as an attempt like any other
+    throttle_two_fa(&state, client_ip, &email, &login_attempt_id).await?;
+
+    // Every case without a pending emailed attempt gets the same answer, so the response
+    // doesn't tell whether the account exists or which 2FA method it uses
+    match state.two_fa_code_store.read().await.get_attempt(&email, &login_attempt_id).await {
+        Ok(_) => (),
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => return Err(AuthAPIError::IncorrectCredentials),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    // TOTP codes come from the user's authenticator app, so there is nothing to resend
+    match state.user_store.read().await.get_user(&email).await {
+        Ok(user) if user.two_fa_method == TwoFAMethod::Email => (),
+        Ok(_) | Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::IncorrectCredentials),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let two_fa_code = TwoFACode::default();
+    {
+        let mut two_fa_code_store = state.two_fa_code_store.write().await;
+
+        let attempt = match two_fa_code_store.get_attempt(&email, &login_attempt_id).await {
+            Ok(attempt) => attempt,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => return Err(AuthAPIError::IncorrectCredentials),
+            Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+        };
+        state.login_throttle_policy.check_two_fa_resend(&attempt, Utc::now())?;
+
+        two_fa_code_store
+            .replace_code(&email, &login_attempt_id, two_fa_code.clone())
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    }
+
+    send_two_fa_code(&email, &two_fa_code, &state).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(Resend2FAResponse {
+            message: "2FA code resent".to_owned(),
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct Resend2FARequest {
+    email: String,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Resend2FAResponse {
+    pub message: String,
+}
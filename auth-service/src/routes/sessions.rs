@@ -0,0 +1,111 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, Session, SessionStoreError},
+    utils::{
+        auth::get_authenticated_claims,
+        constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+    },
+};
+
+// Lists every device the user is signed in on, most recently active first
+#[tracing::instrument(name = "List sessions", skip_all)]
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let claims = get_authenticated_claims(&jar, &state).await?;
+    let email = Email::parse(Secret::new(claims.sub)).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let mut sessions = state
+        .session_store
+        .read()
+        .await
+        .get_sessions(&email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen_at));
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| SessionResponse::new(session, &claims.sid))
+        .collect();
+
+    Ok((StatusCode::OK, Json(SessionsResponse { sessions })))
+}
+
+// Signs the user out on one device. Its tokens stop working straight away.
+#[tracing::instrument(name = "Revoke session", skip_all)]
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(session_id): Path<String>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let claims = match get_authenticated_claims(&jar, &state).await {
+        Ok(claims) => claims,
+        Err(e) => return (jar, Err(e)),
+    };
+    let email = match Email::parse(Secret::new(claims.sub)) {
+        Ok(email) => email,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    match state.session_store.write().await.revoke_session(&email, &session_id).await {
+        Ok(_) => (),
+        Err(SessionStoreError::SessionNotFound) => return (jar, Err(AuthAPIError::SessionNotFound)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    // Signing out the current device works like logging out. The cookies were set for the
+    // whole site, so the removals need that path too rather than this route's own.
+    let jar = if session_id == claims.sid {
+        jar.remove(Cookie::build(JWT_COOKIE_NAME).path("/"))
+            .remove(Cookie::build(REFRESH_COOKIE_NAME).path("/"))
+    } else {
+        jar
+    };
+
+    (jar, Ok(StatusCode::OK))
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SessionsResponse {
+    pub sessions: Vec<SessionResponse>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SessionResponse {
+    pub id: String,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: i64,
+    // Whether this is the session making the request
+    pub current: bool,
+}
+
+impl SessionResponse {
+    fn new(session: Session, current_session_id: &str) -> Self {
+        Self {
+            current: session.id == current_session_id,
+            id: session.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
-use crate::{app_state::AppState, domain::{AuthAPIError,User,Email, Password}};
+use crate::{app_state::AppState, routes::{generate_recovery_codes, issue_recovery_codes}, domain::{AuthAPIError,User,Email, EmailVerificationToken, Password, TwoFAMethod, UserStoreError}, utils::constants::AUTH_SERVICE_URL};
 
 #[tracing::instrument(name = "Signup", skip_all)] // Updated
 pub async fn signup(
@@ -14,28 +14,99 @@ pub async fn signup(
     // Update signup route to replace validation logic with calls to Email::parse and Password::parse
 
     let email = Email::parse(Secret::new(email.clone())).map_err(|_| AuthAPIError::InvalidCredentials)?;
-    let password = Password::parse(password.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    state.password_policy.enforce(&password, Some(&email)).await?;
+    let password = Password::parse(password).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let user = User::new(email, password, request.requires_2fa);
+    // Signup can only opt into emailed codes; TOTP is enrolled separately once logged in
+    let two_fa_method = match request.requires_2fa {
+        true => TwoFAMethod::Email,
+        false => TwoFAMethod::None,
+    };
 
-    let mut user_store = state.user_store.write().await;
+    let user = User::new(email, password, two_fa_method);
 
-    if let Ok(_) = user_store.get_user(&user.email).await {
-        return Err(AuthAPIError::UserAlreadyExists);
-    }
+    let add_result = state.user_store.write().await.add_user(user.clone()).await;
 
-    if let Err(e) = user_store.add_user(user.clone()).await {
-        println!("Error adding user: {:?}", e);
-        return Err(AuthAPIError::UnexpectedError(e.into())); // Updated!
-    }
+    // Signing up with a taken email looks exactly like a fresh signup, so the endpoint can't be
+    // used to find out who has an account. The owner is told by email instead.
+    let recovery_codes = match add_result {
+        Ok(()) => {
+            send_verification_email(&user.email, &state).await?;
+
+            // Users who turn on 2FA get their recovery codes straight away
+            match user.two_fa_method {
+                TwoFAMethod::None => None,
+                _ => Some(issue_recovery_codes(&user.email, &state).await?),
+            }
+        }
+        Err(UserStoreError::UserAlreadyExists) => {
+            send_account_exists_email(&user.email, &state).await?;
+
+            // These are never stored, they only keep the response shape identical
+            match user.two_fa_method {
+                TwoFAMethod::None => None,
+                _ => Some(
+                    generate_recovery_codes()
+                        .iter()
+                        .map(|code| code.as_ref().expose_secret().to_owned())
+                        .collect(),
+                ),
+            }
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
 
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
+        recovery_codes,
     });
 
     Ok((StatusCode::CREATED, response))
 }
 
+#[tracing::instrument(name = "Send verification email", skip_all)]
+async fn send_verification_email(email: &Email, state: &AppState) -> Result<(), AuthAPIError> {
+    let token = EmailVerificationToken::default();
+
+    state
+        .email_verification_token_store
+        .write()
+        .await
+        .add_token(email.clone(), token.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let link = format!(
+        "{}/verify-email?token={}",
+        AUTH_SERVICE_URL.as_str(),
+        token.as_ref().expose_secret()
+    );
+    let body = format!(
+        "Welcome! Please confirm your email address by following this link: {}. If you did not sign up, you can ignore this email.",
+        link
+    );
+
+    state
+        .email_client
+        .send_email(email, "Verify your email address", &body)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
+#[tracing::instrument(name = "Send account exists email", skip_all)]
+async fn send_account_exists_email(email: &Email, state: &AppState) -> Result<(), AuthAPIError> {
+    let body = format!(
+        "Someone tried to sign up with this email address, but it already has an account. You can log in at {}, or reset your password if you have forgotten it. If this wasn't you, you can ignore this email.",
+        AUTH_SERVICE_URL.as_str()
+    );
+
+    state
+        .email_client
+        .send_email(email, "Sign up attempt for your account", &body)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
 #[derive(Deserialize)]
 pub struct SignupRequest {
     pub email: Secret<String>,
@@ -47,4 +118,6 @@ pub struct SignupRequest {
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct SignupResponse {
     pub message: String,
+    #[serde(rename = "recoveryCodes", default, skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
 }
\ No newline at end of file
@@ -0,0 +1,130 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, TotpSecret, TwoFACode, TwoFAMethod},
+    routes::{consume_totp_step, issue_recovery_codes, reauthenticate, ReauthenticationRequest, TwoFactorAuthResponse},
+    utils::{
+        auth::get_authenticated_user,
+        constants::TOTP_DRIFT_STEPS,
+        totp::{get_otpauth_uri, verify_code},
+    },
+};
+
+// Hands out a new TOTP secret. Whoever has it can produce the user's second factor, so the
+// user has to reauthenticate first, like when deleting their account.
+#[tracing::instrument(name = "Enroll TOTP", skip_all)]
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ReauthenticationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user = get_authenticated_user(&jar, &state).await?;
+
+    if user.two_fa_method == TwoFAMethod::Totp {
+        return Err(AuthAPIError::TotpAlreadyEnabled);
+    }
+
+    if let Some(response) =
+        reauthenticate(&user, request.password, request.login_attempt_id, request.two_fa_code, &state).await?
+    {
+        return Ok((StatusCode::PARTIAL_CONTENT, Json(EnrollTotpResponse::TwoFactorAuth(response))));
+    }
+
+    // The secret is stored straight away, but it only replaces the user's current
+    // 2FA method once they prove their authenticator app produces matching codes
+    let secret = TotpSecret::default();
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_totp_secret(&user.email, secret.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(EnrollTotpResponse::Enrolled(TotpEnrollmentResponse {
+        otpauth_uri: get_otpauth_uri(&secret, &user.email),
+        secret: secret.as_ref().expose_secret().to_owned(),
+    }));
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Confirm TOTP", skip_all)]
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ConfirmTotpRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let code = TwoFACode::parse(request.two_fa_code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user = get_authenticated_user(&jar, &state).await?;
+
+    if user.two_fa_method == TwoFAMethod::Totp {
+        return Err(AuthAPIError::TotpAlreadyEnabled);
+    }
+
+    // Confirming without enrolling first is a malformed request
+    let secret = user.totp_secret.ok_or(AuthAPIError::InvalidCredentials)?;
+
+    let is_valid = match verify_code(&secret, &code, Utc::now().timestamp() as u64, *TOTP_DRIFT_STEPS)
+        .map_err(AuthAPIError::UnexpectedError)?
+    {
+        // The code confirming enrollment can't then be used to log in
+        Some(step) => consume_totp_step(&user.email, step, &state).await?,
+        None => false,
+    };
+    if !is_valid {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_two_fa_method(&user.email, TwoFAMethod::Totp)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // TOTP gets a fresh set of recovery codes, replacing any issued for emailed codes
+    let recovery_codes = issue_recovery_codes(&user.email, &state).await?;
+
+    let response = Json(ConfirmTotpResponse {
+        message: "TOTP enabled successfully!".to_owned(),
+        recovery_codes,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum EnrollTotpResponse {
+    Enrolled(TotpEnrollmentResponse),
+    TwoFactorAuth(TwoFactorAuthResponse),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct TotpEnrollmentResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmTotpRequest {
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ConfirmTotpResponse {
+    pub message: String,
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
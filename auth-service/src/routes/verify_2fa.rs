@@ -2,12 +2,15 @@ use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use secrecy::{Secret, ExposeSecret};
 use serde::Deserialize;
-use crate::{app_state::AppState, domain::{AuthAPIError, Email, LoginAttemptId, TwoFACode}, utils::auth::generate_auth_cookie};
+use chrono::Utc;
+use crate::{app_state::AppState, domain::{AuthAPIError, Email, LoginAttemptId, RecoveryCode, RecoveryCodeStoreError, TwoFACode, TwoFAMethod, User, UserStoreError}, utils::{auth::{ensure_can_log_in, generate_auth_cookie, generate_refresh_cookie, start_session}, constants::{MAX_TWO_FA_CODE_ATTEMPTS, TOTP_DRIFT_STEPS}, device::{DeviceInfo, PeerAddress}, login_throttle::{record_login_failure, record_login_success, throttle_two_fa}, totp::verify_code}};
 
 #[tracing::instrument(name = "Verify 2FA endpoint", skip_all)]
 pub async fn verify_2fa(
     State(state): State<AppState>, // New!
     jar: CookieJar, // New!
+    device: DeviceInfo,
+    PeerAddress(client_ip): PeerAddress,
     Json(request): Json<Verify2FARequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
     let email = match Email::parse(Secret::new(request.email.clone())) {
@@ -25,24 +28,103 @@ pub async fn verify_2fa(
         Ok(code) => code,
     };
 
-    let mut two_fa_code_store = state.two_fa_code_store.write().await;
+    // Without this the 6-digit code could be brute-forced well within its lifetime
+    if let Err(e) = throttle_two_fa(&state, client_ip, &email, &login_attempt_id).await {
+        return (jar, Err(e));
+    }
 
-    let code_tuple = match two_fa_code_store.get_code(&email).await {
+    let user = match state.user_store.read().await.get_user(&email).await {
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
-        Ok(code_tuple) => code_tuple,
+        Ok(user) => user,
     };
 
-    if code_tuple.0.as_ref().expose_secret() != login_attempt_id.as_ref().expose_secret() || 
-       code_tuple.1.as_ref().expose_secret() != two_fa_code.as_ref().expose_secret() {
-        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    match check_two_fa_code(&user, &login_attempt_id, &two_fa_code, &state).await {
+        Ok(_) => (),
+        Err(AuthAPIError::IncorrectCredentials) => {
+            if let Err(e) = record_login_failure(&state, &email, Some(&login_attempt_id)).await {
+                return (jar, Err(e));
+            }
+            return (jar, Err(AuthAPIError::IncorrectCredentials));
+        }
+        Err(e) => return (jar, Err(e)),
     }
 
-    match two_fa_code_store.remove_code(&email).await {
-        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
-        Ok(_) => (),
-    };  
+    if let Err(e) = record_login_success(&state, &email).await {
+        return (jar, Err(e));
+    }
+
+    create_jwt_cookie(&user, &state, jar, device).await
+}
 
-    create_jwt_cookie(&email, jar).await
+// Check the code the user answered a 2FA challenge from `start_two_fa` with.
+// A correct code uses up the challenge.
+#[tracing::instrument(name = "Check 2FA code", skip_all)]
+pub(crate) async fn check_two_fa_code(
+    user: &User,
+    login_attempt_id: &LoginAttemptId,
+    two_fa_code: &TwoFACode,
+    state: &AppState,
+) -> Result<(), AuthAPIError> {
+    // TOTP users answer with a code from their authenticator app instead of the stored one
+    let code_is_valid = match (user.two_fa_method, &user.totp_secret) {
+        (TwoFAMethod::Totp, Some(secret)) => {
+            state
+                .two_fa_code_store
+                .read()
+                .await
+                .get_attempt(&user.email, login_attempt_id)
+                .await
+                .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+            match verify_code(secret, two_fa_code, Utc::now().timestamp() as u64, *TOTP_DRIFT_STEPS)
+                .map_err(AuthAPIError::UnexpectedError)?
+            {
+                Some(step) => consume_totp_step(&user.email, step, state).await?,
+                None => false,
+            }
+        }
+        _ => state
+            .two_fa_code_store
+            .read()
+            .await
+            .verify_code(&user.email, login_attempt_id, two_fa_code)
+            .await
+            .map_err(|_| AuthAPIError::IncorrectCredentials)?,
+    };
+
+    let mut two_fa_code_store = state.two_fa_code_store.write().await;
+
+    if !code_is_valid {
+        // Each login attempt only gets a few guesses before the user has to start over
+        let failed_attempts = two_fa_code_store
+            .record_failed_attempt(login_attempt_id)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        if failed_attempts >= MAX_TWO_FA_CODE_ATTEMPTS {
+            two_fa_code_store
+                .remove_code(login_attempt_id)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        }
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    two_fa_code_store
+        .remove_code(login_attempt_id)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)
+}
+
+// Accept a TOTP code's time step, unless a code from that step or a later one has already
+// been used. Otherwise a code someone has seen could be replayed for as long as the drift
+// window lets it through.
+// The user store is locked here, so callers must not be holding the 2FA code store's lock,
+// which login takes while holding the user store's.
+pub(crate) async fn consume_totp_step(email: &Email, step: u64, state: &AppState) -> Result<bool, AuthAPIError> {
+    match state.user_store.write().await.record_totp_step(email, step).await {
+        Ok(()) => Ok(true),
+        Err(UserStoreError::TotpCodeReused) => Ok(false),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
 }
 
 #[derive(Deserialize)]
@@ -54,18 +136,125 @@ pub struct Verify2FARequest {
     pub two_fa_code: String,
 }
 
-#[tracing::instrument(name = "Create JWT cookie", skip_all)]
-async fn create_jwt_cookie(
+// Lets a user who has lost access to their second factor finish logging in
+// with one of their single-use recovery codes instead
+#[tracing::instrument(name = "Verify recovery code endpoint", skip_all)]
+pub async fn verify_recovery_code(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    device: DeviceInfo,
+    PeerAddress(client_ip): PeerAddress,
+    Json(request): Json<VerifyRecoveryCodeRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let email = match Email::parse(Secret::new(request.email.clone())) {
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+        Ok(email) => email,
+    };
+
+    let login_attempt_id = match LoginAttemptId::parse(request.login_attempt_id) {
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+        Ok(id) => id,
+    };
+
+    let recovery_code = match RecoveryCode::parse(request.recovery_code.expose_secret().to_owned()) {
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+        Ok(code) => code,
+    };
+
+    // Recovery codes stand in for 2FA codes, so guessing them is throttled the same way
+    if let Err(e) = throttle_two_fa(&state, client_ip, &email, &login_attempt_id).await {
+        return (jar, Err(e));
+    }
+
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+        Ok(user) => user,
+    };
+
+    match check_recovery_code(&email, &login_attempt_id, &recovery_code, &state).await {
+        Ok(_) => tracing::info!("Recovery code used to complete login"),
+        Err(AuthAPIError::IncorrectCredentials) => {
+            if let Err(e) = record_login_failure(&state, &email, Some(&login_attempt_id)).await {
+                return (jar, Err(e));
+            }
+            return (jar, Err(AuthAPIError::IncorrectCredentials));
+        }
+        Err(e) => return (jar, Err(e)),
+    }
+
+    if let Err(e) = record_login_success(&state, &email).await {
+        return (jar, Err(e));
+    }
+
+    create_jwt_cookie(&user, &state, jar, device).await
+}
+
+// Use up one of the user's recovery codes to answer the 2FA challenge for a login attempt.
+// A correct code uses up the challenge too.
+#[tracing::instrument(name = "Check recovery code", skip_all)]
+async fn check_recovery_code(
     email: &Email,
+    login_attempt_id: &LoginAttemptId,
+    recovery_code: &RecoveryCode,
+    state: &AppState,
+) -> Result<(), AuthAPIError> {
+    let mut two_fa_code_store = state.two_fa_code_store.write().await;
+
+    // Only the attempt itself matters here, as the recovery code stands in for its 2FA code
+    two_fa_code_store
+        .get_attempt(email, login_attempt_id)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    match state.recovery_code_store.write().await.consume_code(email, recovery_code).await {
+        Ok(_) => (),
+        Err(RecoveryCodeStoreError::CodeNotFound) => return Err(AuthAPIError::IncorrectCredentials),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    two_fa_code_store
+        .remove_code(login_attempt_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+#[derive(Deserialize)]
+pub struct VerifyRecoveryCodeRequest {
+    email: String,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: String,
+    #[serde(rename = "recoveryCode")]
+    pub recovery_code: Secret<String>,
+}
+
+// Start a session for the device the user just logged in from, and hand it its tokens
+#[tracing::instrument(name = "Create JWT cookie", skip_all)]
+pub(crate) async fn create_jwt_cookie(
+    user: &User,
+    state: &AppState,
     jar: CookieJar,
+    device: DeviceInfo,
 ) -> (
     CookieJar,
     Result<impl IntoResponse, AuthAPIError>,
 ) {
-    let auth_cookie = match generate_auth_cookie(email) {
+    // Checked here as well as at login, since every login flow ends up here
+    if let Err(e) = ensure_can_log_in(user) {
+        return (jar, Err(e));
+    }
+
+    let session_id = match start_session(&user.email, device, state.session_store.clone()).await {
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        Ok(session_id) => session_id,
+    };
+    let auth_cookie = match generate_auth_cookie(user, &session_id, state.banned_token_store.clone(), state.jwt_keyring.clone()).await {
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        Ok(cookie) => cookie,
+    };
+    let refresh_cookie = match generate_refresh_cookie(&user.email, &session_id, state.refresh_token_store.clone()).await {
         Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
         Ok(cookie) => cookie,
     };
-    let updated_jar = jar.add(auth_cookie);
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
     (updated_jar, Ok(StatusCode::OK.into_response()))
 }
\ No newline at end of file
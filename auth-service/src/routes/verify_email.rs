@@ -0,0 +1,45 @@
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, EmailVerificationToken, EmailVerificationTokenStoreError, UserStoreError},
+};
+
+#[tracing::instrument(name = "Verify email", skip_all)]
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Query(params): Query<VerifyEmailParams>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = EmailVerificationToken::parse(params.token.expose_secret().to_owned())
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let email = match state.email_verification_token_store.write().await.consume_token(&token).await {
+        Ok(email) => email,
+        Err(EmailVerificationTokenStoreError::TokenNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    match state.user_store.write().await.mark_email_verified(&email).await {
+        Ok(_) => (),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(VerifyEmailResponse {
+        message: "Email verified successfully!".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailParams {
+    pub token: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct VerifyEmailResponse {
+    pub message: String,
+}
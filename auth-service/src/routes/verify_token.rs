@@ -9,7 +9,10 @@ pub async fn verify_token(
     let token = request.token;
 
     // Check token validity and treat authentication failures as InvalidToken
-    match validate_token(&token, state.banned_token_store.clone()).await {
+    match validate_token(&token, state.banned_token_store.clone(), state.session_store.clone(), state.jwt_keyring.clone()).await {
+        // Access tokens issued to OAuth clients are not credentials for our own apps, which
+        // only check their jwt cookie through this endpoint (see get_authenticated_claims)
+        Ok(claims) if claims.client_id.is_some() => Err(AuthAPIError::InvalidToken),
         Ok(_claims) => {
             let response = Json(VerifytokenResponse { valid: true });
             Ok((StatusCode::OK, response))
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use secrecy::ExposeSecret;
+
+use crate::{
+    domain::{AuthorizationCode, AuthorizationCodeStore, AuthorizationCodeStoreError, AuthorizationGrant},
+    utils::auth::AUTHORIZATION_CODE_TTL_SECONDS,
+};
+
+#[derive(Default)]
+pub struct HashmapAuthorizationCodeStore {
+    // Maps each code to the grant it stands for and the timestamp at which it expires
+    codes: HashMap<String, (AuthorizationGrant, i64)>,
+}
+
+#[async_trait::async_trait]
+impl AuthorizationCodeStore for HashmapAuthorizationCodeStore {
+    async fn add_code(
+        &mut self,
+        code: AuthorizationCode,
+        grant: AuthorizationGrant,
+    ) -> Result<(), AuthorizationCodeStoreError> {
+        let expires_at = Utc::now().timestamp() + AUTHORIZATION_CODE_TTL_SECONDS;
+        self.codes.insert(code.as_ref().expose_secret().to_owned(), (grant, expires_at));
+        Ok(())
+    }
+
+    async fn consume_code(
+        &mut self,
+        code: &AuthorizationCode,
+    ) -> Result<AuthorizationGrant, AuthorizationCodeStoreError> {
+        match self.codes.remove(code.as_ref().expose_secret()) {
+            Some((grant, expires_at)) if expires_at > Utc::now().timestamp() => Ok(grant),
+            _ => Err(AuthorizationCodeStoreError::CodeNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_grant() -> AuthorizationGrant {
+        AuthorizationGrant {
+            client_id: "web-app".to_owned(),
+            redirect_uri: "https://app.example.com/callback".to_owned(),
+            email: "test@example.com".to_owned(),
+            scope: "openid email".to_owned(),
+            code_challenge: "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuNd-bw-cM".to_owned(),
+            nonce: Some("n-0S6_WzA2Mj".to_owned()),
+            user_agent: None,
+            ip_address: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consume_code() {
+        let mut store = HashmapAuthorizationCodeStore::default();
+        let code = AuthorizationCode::default();
+
+        store.add_code(code.clone(), test_grant()).await.unwrap();
+        assert_eq!(store.consume_code(&code).await, Ok(test_grant()));
+    }
+
+    #[tokio::test]
+    async fn test_code_can_only_be_consumed_once() {
+        let mut store = HashmapAuthorizationCodeStore::default();
+        let code = AuthorizationCode::default();
+
+        store.add_code(code.clone(), test_grant()).await.unwrap();
+        store.consume_code(&code).await.unwrap();
+        assert_eq!(store.consume_code(&code).await, Err(AuthorizationCodeStoreError::CodeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_code_is_rejected() {
+        let mut store = HashmapAuthorizationCodeStore::default();
+        let code = AuthorizationCode::default();
+
+        store.codes.insert(
+            code.as_ref().expose_secret().to_owned(),
+            (test_grant(), Utc::now().timestamp() - 1),
+        );
+        assert_eq!(store.consume_code(&code).await, Err(AuthorizationCodeStoreError::CodeNotFound));
+    }
+}
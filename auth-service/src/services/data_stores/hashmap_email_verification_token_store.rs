@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use secrecy::ExposeSecret;
+
+use crate::{
+    domain::{Email, EmailVerificationToken, EmailVerificationTokenStore, EmailVerificationTokenStoreError},
+    utils::auth::EMAIL_VERIFICATION_TOKEN_TTL_SECONDS,
+};
+
+#[derive(Default)]
+pub struct HashmapEmailVerificationTokenStore {
+    // Maps each token to its owner and the timestamp at which it expires
+    tokens: HashMap<String, (Email, i64)>,
+}
+
+#[async_trait::async_trait]
+impl EmailVerificationTokenStore for HashmapEmailVerificationTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: EmailVerificationToken,
+    ) -> Result<(), EmailVerificationTokenStoreError> {
+        let expires_at = Utc::now().timestamp() + EMAIL_VERIFICATION_TOKEN_TTL_SECONDS;
+        self.tokens.insert(token.as_ref().expose_secret().to_owned(), (email, expires_at));
+        Ok(())
+    }
+
+    async fn consume_token(
+        &mut self,
+        token: &EmailVerificationToken,
+    ) -> Result<Email, EmailVerificationTokenStoreError> {
+        match self.tokens.remove(token.as_ref().expose_secret()) {
+            Some((email, expires_at)) if expires_at > Utc::now().timestamp() => Ok(email),
+            _ => Err(EmailVerificationTokenStoreError::TokenNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_consume_token() {
+        let mut store = HashmapEmailVerificationTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = EmailVerificationToken::default();
+
+        store.add_token(email.clone(), token.clone()).await.unwrap();
+        assert_eq!(store.consume_token(&token).await, Ok(email));
+    }
+
+    #[tokio::test]
+    async fn test_token_can_only_be_consumed_once() {
+        let mut store = HashmapEmailVerificationTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = EmailVerificationToken::default();
+
+        store.add_token(email, token.clone()).await.unwrap();
+        store.consume_token(&token).await.unwrap();
+        assert_eq!(store.consume_token(&token).await, Err(EmailVerificationTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_rejected() {
+        let mut store = HashmapEmailVerificationTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = EmailVerificationToken::default();
+
+        store.tokens.insert(
+            token.as_ref().expose_secret().to_owned(),
+            (email, Utc::now().timestamp() - 1),
+        );
+        assert_eq!(store.consume_token(&token).await, Err(EmailVerificationTokenStoreError::TokenNotFound));
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::domain::{JwtKeyStore, JwtKeyStoreError, StoredJwtKey};
+
+#[derive(Default)]
+pub struct HashmapJwtKeyStore {
+    // Rotated keys by key ID
+    keys: HashMap<String, StoredJwtKey>,
+}
+
+#[async_trait::async_trait]
+impl JwtKeyStore for HashmapJwtKeyStore {
+    async fn add_key(&mut self, key: StoredJwtKey) -> Result<(), JwtKeyStoreError> {
+        if self.keys.contains_key(&key.kid) {
+            return Err(JwtKeyStoreError::KeyAlreadyExists);
+        }
+        self.keys.insert(key.kid.clone(), key);
+        Ok(())
+    }
+
+    async fn get_keys(&self) -> Result<Vec<StoredJwtKey>, JwtKeyStoreError> {
+        let mut keys: Vec<_> = self.keys.values().cloned().collect();
+        keys.sort_by_key(|key| key.created_at);
+        Ok(keys)
+    }
+
+    async fn remove_keys_replaced_before(&mut self, cutoff: DateTime<Utc>) -> Result<(), JwtKeyStoreError> {
+        let created: Vec<_> = self.keys.values().map(|key| key.created_at).collect();
+        self.keys.retain(|_, key| {
+            !created
+                .iter()
+                .any(|&newer| newer > key.created_at && newer <= cutoff)
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::Algorithm;
+    use secrecy::Secret;
+
+    use super::*;
+
+    fn stored_key(kid: &str, created_at: DateTime<Utc>) -> StoredJwtKey {
+        StoredJwtKey {
+            kid: kid.to_owned(),
+            algorithm: Algorithm::HS256,
+            private_key: Secret::new("secret".to_owned()),
+            created_at,
+        }
+    }
+
+    fn kids(keys: Vec<StoredJwtKey>) -> Vec<String> {
+        keys.into_iter().map(|key| key.kid).collect()
+    }
+
+    #[tokio::test]
+    async fn test_add_key() {
+        let mut store = HashmapJwtKeyStore::default();
+        let key = stored_key("key-1", Utc::now());
+
+        assert_eq!(store.add_key(key.clone()).await, Ok(()));
+        assert_eq!(store.add_key(key).await, Err(JwtKeyStoreError::KeyAlreadyExists));
+    }
+
+    #[tokio::test]
+    async fn test_get_keys_oldest_first() {
+        let mut store = HashmapJwtKeyStore::default();
+        let now = Utc::now();
+        store.add_key(stored_key("newer", now)).await.unwrap();
+        store.add_key(stored_key("older", now - chrono::Duration::minutes(5))).await.unwrap();
+
+        assert_eq!(kids(store.get_keys().await.unwrap()), vec!["older", "newer"]);
+    }
+
+    #[tokio::test]
+    async fn test_remove_keys_replaced_before() {
+        let mut store = HashmapJwtKeyStore::default();
+        let now = Utc::now();
+        store.add_key(stored_key("first", now - chrono::Duration::hours(3))).await.unwrap();
+        store.add_key(stored_key("second", now - chrono::Duration::hours(2))).await.unwrap();
+        store.add_key(stored_key("third", now)).await.unwrap();
+
+        // Only "first" had been replaced by then; "second" was replaced later and the newest never was
+        store.remove_keys_replaced_before(now - chrono::Duration::hours(1)).await.unwrap();
+        assert_eq!(kids(store.get_keys().await.unwrap()), vec!["second", "third"]);
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::domain::{LoginThrottleStore, LoginThrottleStoreError};
+
+#[derive(Default)]
+pub struct HashmapLoginThrottleStore {
+    attempts: HashMap<String, VecDeque<Instant>>,
+    // Failure count and when it is forgotten
+    failures: HashMap<String, (u32, Instant)>,
+    locks: HashMap<String, Instant>,
+}
+
+#[async_trait::async_trait]
+impl LoginThrottleStore for HashmapLoginThrottleStore {
+    async fn record_attempt(&mut self, key: &str, window: Duration) -> Result<u32, LoginThrottleStoreError> {
+        let now = Instant::now();
+        let attempts = self.attempts.entry(key.to_owned()).or_default();
+        while attempts.front().is_some_and(|attempt| now.duration_since(*attempt) >= window) {
+            attempts.pop_front();
+        }
+        attempts.push_back(now);
+        Ok(attempts.len() as u32)
+    }
+
+    async fn record_failure(&mut self, key: &str, ttl: Duration) -> Result<u32, LoginThrottleStoreError> {
+        let now = Instant::now();
+        let (count, expires_at) = self.failures.entry(key.to_owned()).or_insert((0, now));
+        if *expires_at <= now {
+            *count = 0;
+        }
+        *count += 1;
+        *expires_at = now + ttl;
+        Ok(*count)
+    }
+
+    async fn reset_failures(&mut self, key: &str) -> Result<(), LoginThrottleStoreError> {
+        self.failures.remove(key);
+        Ok(())
+    }
+
+    async fn lock(&mut self, key: &str, duration: Duration) -> Result<(), LoginThrottleStoreError> {
+        self.locks.insert(key.to_owned(), Instant::now() + duration);
+        Ok(())
+    }
+
+    async fn get_lock(&self, key: &str) -> Result<Option<Duration>, LoginThrottleStoreError> {
+        let now = Instant::now();
+        Ok(self
+            .locks
+            .get(key)
+            .filter(|locked_until| **locked_until > now)
+            .map(|locked_until| *locked_until - now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_attempt_counts_within_window() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let window = Duration::from_millis(50);
+
+        assert_eq!(store.record_attempt("ip:127.0.0.1", window).await, Ok(1));
+        assert_eq!(store.record_attempt("ip:127.0.0.1", window).await, Ok(2));
+        assert_eq!(store.record_attempt("ip:10.0.0.1", window).await, Ok(1));
+
+        // Older attempts slide out of the window
+        tokio::time::sleep(window).await;
+        assert_eq!(store.record_attempt("ip:127.0.0.1", window).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_record_and_reset_failures() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(store.record_failure("account:test@example.com", ttl).await, Ok(1));
+        assert_eq!(store.record_failure("account:test@example.com", ttl).await, Ok(2));
+        assert_eq!(store.reset_failures("account:test@example.com").await, Ok(()));
+        assert_eq!(store.record_failure("account:test@example.com", ttl).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_forgotten_after_ttl() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let ttl = Duration::from_millis(50);
+
+        assert_eq!(store.record_failure("account:test@example.com", ttl).await, Ok(1));
+        tokio::time::sleep(ttl).await;
+        assert_eq!(store.record_failure("account:test@example.com", ttl).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_lock() {
+        let mut store = HashmapLoginThrottleStore::default();
+
+        assert_eq!(store.get_lock("account:test@example.com").await, Ok(None));
+
+        store.lock("account:test@example.com", Duration::from_millis(50)).await.unwrap();
+        let remaining = store.get_lock("account:test@example.com").await.unwrap();
+        assert!(remaining.is_some_and(|remaining| remaining <= Duration::from_millis(50)));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(store.get_lock("account:test@example.com").await, Ok(None));
+    }
+}
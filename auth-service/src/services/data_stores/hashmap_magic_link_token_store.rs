@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use secrecy::ExposeSecret;
+
+use crate::{
+    domain::{Email, MagicLinkToken, MagicLinkTokenStore, MagicLinkTokenStoreError},
+    utils::auth::MAGIC_LINK_TOKEN_TTL_SECONDS,
+};
+
+#[derive(Default)]
+pub struct HashmapMagicLinkTokenStore {
+    // Maps each token to its owner and the timestamp at which it expires
+    tokens: HashMap<String, (Email, i64)>,
+}
+
+#[async_trait::async_trait]
+impl MagicLinkTokenStore for HashmapMagicLinkTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: MagicLinkToken,
+    ) -> Result<(), MagicLinkTokenStoreError> {
+        let expires_at = Utc::now().timestamp() + MAGIC_LINK_TOKEN_TTL_SECONDS;
+        self.tokens.insert(token.as_ref().expose_secret().to_owned(), (email, expires_at));
+        Ok(())
+    }
+
+    async fn consume_token(
+        &mut self,
+        token: &MagicLinkToken,
+    ) -> Result<Email, MagicLinkTokenStoreError> {
+        match self.tokens.remove(token.as_ref().expose_secret()) {
+            Some((email, expires_at)) if expires_at > Utc::now().timestamp() => Ok(email),
+            _ => Err(MagicLinkTokenStoreError::TokenNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_consume_token() {
+        let mut store = HashmapMagicLinkTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = MagicLinkToken::default();
+
+        store.add_token(email.clone(), token.clone()).await.unwrap();
+        assert_eq!(store.consume_token(&token).await, Ok(email));
+    }
+
+    #[tokio::test]
+    async fn test_token_can_only_be_consumed_once() {
+        let mut store = HashmapMagicLinkTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = MagicLinkToken::default();
+
+        store.add_token(email, token.clone()).await.unwrap();
+        store.consume_token(&token).await.unwrap();
+        assert_eq!(store.consume_token(&token).await, Err(MagicLinkTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_rejected() {
+        let mut store = HashmapMagicLinkTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = MagicLinkToken::default();
+
+        store.tokens.insert(
+            token.as_ref().expose_secret().to_owned(),
+            (email, Utc::now().timestamp() - 1),
+        );
+        assert_eq!(store.consume_token(&token).await, Err(MagicLinkTokenStoreError::TokenNotFound));
+    }
+}
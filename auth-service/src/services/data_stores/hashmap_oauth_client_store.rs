@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use secrecy::ExposeSecret;
+
+use crate::domain::{constant_time_eq, ClientSecret, OAuthClient, OAuthClientStore, OAuthClientStoreError};
+
+#[derive(Default)]
+pub struct HashmapOAuthClientStore {
+    // Registered clients with their secrets, by client ID
+    clients: HashMap<String, (OAuthClient, ClientSecret)>,
+}
+
+#[async_trait::async_trait]
+impl OAuthClientStore for HashmapOAuthClientStore {
+    async fn add_client(&mut self, client: OAuthClient, secret: ClientSecret) -> Result<(), OAuthClientStoreError> {
+        if self.clients.contains_key(&client.client_id) {
+            return Err(OAuthClientStoreError::ClientAlreadyExists);
+        }
+        self.clients.insert(client.client_id.clone(), (client, secret));
+        Ok(())
+    }
+
+    async fn authenticate_client(
+        &self,
+        client_id: &str,
+        secret: &ClientSecret,
+    ) -> Result<OAuthClient, OAuthClientStoreError> {
+        let (client, stored_secret) = self
+            .clients
+            .get(client_id)
+            .ok_or(OAuthClientStoreError::InvalidCredentials)?;
+
+        if !constant_time_eq(stored_secret.as_ref().expose_secret(), secret.as_ref().expose_secret()) {
+            return Err(OAuthClientStoreError::InvalidCredentials);
+        }
+
+        Ok(client.clone())
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<OAuthClient, OAuthClientStoreError> {
+        self.clients
+            .get(client_id)
+            .map(|(client, _)| client.clone())
+            .ok_or(OAuthClientStoreError::ClientNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_client() {
+        let mut store = HashmapOAuthClientStore::default();
+        let client = OAuthClient::new("resource-server".to_owned(), Vec::new());
+
+        assert_eq!(store.add_client(client.clone(), ClientSecret::default()).await, Ok(()));
+        assert_eq!(
+            store.add_client(client, ClientSecret::default()).await,
+            Err(OAuthClientStoreError::ClientAlreadyExists)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_client() {
+        let mut store = HashmapOAuthClientStore::default();
+        let client = OAuthClient::new("resource-server".to_owned(), Vec::new());
+        let secret = ClientSecret::default();
+        store.add_client(client.clone(), secret.clone()).await.unwrap();
+
+        assert_eq!(store.authenticate_client(&client.client_id, &secret).await, Ok(client.clone()));
+        assert_eq!(
+            store.authenticate_client(&client.client_id, &ClientSecret::default()).await,
+            Err(OAuthClientStoreError::InvalidCredentials)
+        );
+        assert_eq!(
+            store.authenticate_client("unknown", &secret).await,
+            Err(OAuthClientStoreError::InvalidCredentials)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_client() {
+        let mut store = HashmapOAuthClientStore::default();
+        let client = OAuthClient::new("web-app".to_owned(), vec!["https://app.example.com/callback".to_owned()]);
+        store.add_client(client.clone(), ClientSecret::default()).await.unwrap();
+
+        assert_eq!(store.get_client(&client.client_id).await, Ok(client));
+        assert_eq!(store.get_client("unknown").await, Err(OAuthClientStoreError::ClientNotFound));
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use secrecy::ExposeSecret;
+
+use crate::domain::{Email, PasskeyCeremonyId, PasskeyChallenge, PasskeyChallengeStore, PasskeyChallengeStoreError};
+
+#[derive(Default)]
+pub struct HashmapPasskeyChallengeStore {
+    challenges: HashMap<String, (Email, PasskeyChallenge)>,
+}
+
+#[async_trait::async_trait]
+impl PasskeyChallengeStore for HashmapPasskeyChallengeStore {
+    async fn add_challenge(
+        &mut self,
+        ceremony_id: PasskeyCeremonyId,
+        email: Email,
+        challenge: PasskeyChallenge,
+    ) -> Result<(), PasskeyChallengeStoreError> {
+        self.challenges
+            .insert(ceremony_id.as_ref().expose_secret().to_owned(), (email, challenge));
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &mut self,
+        ceremony_id: &PasskeyCeremonyId,
+    ) -> Result<(Email, PasskeyChallenge), PasskeyChallengeStoreError> {
+        self.challenges
+            .remove(ceremony_id.as_ref().expose_secret())
+            .ok_or(PasskeyChallengeStoreError::ChallengeNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use crate::domain::PasskeyCeremony;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_take_challenge() {
+        let mut store = HashmapPasskeyChallengeStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let ceremony_id = PasskeyCeremonyId::default();
+        let challenge = PasskeyChallenge::new(PasskeyCeremony::Login);
+
+        store.add_challenge(ceremony_id.clone(), email.clone(), challenge.clone()).await.unwrap();
+        assert_eq!(store.take_challenge(&ceremony_id).await, Ok((email, challenge)));
+        assert_eq!(store.take_challenge(&ceremony_id).await, Err(PasskeyChallengeStoreError::ChallengeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_ceremonies_for_same_user_are_kept_apart() {
+        let mut store = HashmapPasskeyChallengeStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let first_id = PasskeyCeremonyId::default();
+        let second_id = PasskeyCeremonyId::default();
+        let first_challenge = PasskeyChallenge::new(PasskeyCeremony::Login);
+        let second_challenge = PasskeyChallenge::new(PasskeyCeremony::Login);
+
+        // Someone else starting a ceremony for the same user doesn't replace the first one
+        store.add_challenge(first_id.clone(), email.clone(), first_challenge.clone()).await.unwrap();
+        store.add_challenge(second_id.clone(), email.clone(), second_challenge.clone()).await.unwrap();
+        assert_eq!(store.take_challenge(&first_id).await, Ok((email.clone(), first_challenge)));
+        assert_eq!(store.take_challenge(&second_id).await, Ok((email, second_challenge)));
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use crate::domain::{Email, PasskeyCredential, PasskeyStore, PasskeyStoreError};
+
+#[derive(Default)]
+pub struct HashmapPasskeyStore {
+    credentials: HashMap<Email, Vec<PasskeyCredential>>,
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for HashmapPasskeyStore {
+    async fn add_credential(
+        &mut self,
+        email: &Email,
+        credential: PasskeyCredential,
+    ) -> Result<(), PasskeyStoreError> {
+        let already_exists = self
+            .credentials
+            .values()
+            .flatten()
+            .any(|existing| existing.credential_id == credential.credential_id);
+        if already_exists {
+            return Err(PasskeyStoreError::CredentialAlreadyExists);
+        }
+
+        self.credentials.entry(email.clone()).or_default().push(credential);
+        Ok(())
+    }
+
+    async fn get_credentials(&self, email: &Email) -> Result<Vec<PasskeyCredential>, PasskeyStoreError> {
+        Ok(self.credentials.get(email).cloned().unwrap_or_default())
+    }
+
+    async fn update_sign_count(
+        &mut self,
+        email: &Email,
+        credential_id: &str,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError> {
+        let credential = self
+            .credentials
+            .get_mut(email)
+            .and_then(|credentials| {
+                credentials
+                    .iter_mut()
+                    .find(|credential| credential.credential_id == credential_id)
+            });
+
+        match credential {
+            Some(credential) => {
+                credential.sign_count = sign_count;
+                Ok(())
+            }
+            None => Err(PasskeyStoreError::CredentialNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use super::*;
+
+    fn credential(credential_id: &str) -> PasskeyCredential {
+        PasskeyCredential {
+            credential_id: credential_id.to_owned(),
+            public_key: vec![4; 65],
+            sign_count: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_credential() {
+        let mut store = HashmapPasskeyStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let other_email = Email::parse(Secret::new("other@example.com".to_string())).unwrap();
+
+        assert_eq!(store.add_credential(&email, credential("first")).await, Ok(()));
+        assert_eq!(store.add_credential(&email, credential("second")).await, Ok(()));
+        assert_eq!(
+            store.add_credential(&other_email, credential("first")).await,
+            Err(PasskeyStoreError::CredentialAlreadyExists)
+        );
+
+        assert_eq!(store.get_credentials(&email).await.unwrap().len(), 2);
+        assert_eq!(store.get_credentials(&other_email).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_update_sign_count() {
+        let mut store = HashmapPasskeyStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let other_email = Email::parse(Secret::new("other@example.com".to_string())).unwrap();
+
+        store.add_credential(&email, credential("first")).await.unwrap();
+        assert_eq!(store.update_sign_count(&email, "first", 7).await, Ok(()));
+        assert_eq!(store.get_credentials(&email).await.unwrap()[0].sign_count, 7);
+
+        assert_eq!(
+            store.update_sign_count(&other_email, "first", 8).await,
+            Err(PasskeyStoreError::CredentialNotFound)
+        );
+    }
+}
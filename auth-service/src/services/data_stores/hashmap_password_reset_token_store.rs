@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use secrecy::ExposeSecret;
+
+use crate::{
+    domain::{Email, PasswordResetToken, PasswordResetTokenStore, PasswordResetTokenStoreError},
+    utils::auth::{PASSWORD_RESET_MAX_ATTEMPTS, PASSWORD_RESET_TOKEN_TTL_SECONDS},
+};
+
+#[derive(Default)]
+pub struct HashmapPasswordResetTokenStore {
+    // Maps each token to its owner and the timestamp at which it expires
+    tokens: HashMap<String, (Email, i64)>,
+    // How many times each token has been refused
+    failed_attempts: HashMap<String, u32>,
+}
+
+#[async_trait::async_trait]
+impl PasswordResetTokenStore for HashmapPasswordResetTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: PasswordResetToken,
+    ) -> Result<(), PasswordResetTokenStoreError> {
+        let expires_at = Utc::now().timestamp() + PASSWORD_RESET_TOKEN_TTL_SECONDS;
+        self.tokens.insert(token.as_ref().expose_secret().to_owned(), (email, expires_at));
+        Ok(())
+    }
+
+    async fn get_email(&self, token: &PasswordResetToken) -> Result<Email, PasswordResetTokenStoreError> {
+        match self.tokens.get(token.as_ref().expose_secret()) {
+            Some((email, expires_at)) if *expires_at > Utc::now().timestamp() => Ok(email.clone()),
+            _ => Err(PasswordResetTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn record_failed_attempt(&mut self, token: &PasswordResetToken) -> Result<(), PasswordResetTokenStoreError> {
+        let key = token.as_ref().expose_secret();
+        if !self.tokens.contains_key(key) {
+            return Err(PasswordResetTokenStoreError::TokenNotFound);
+        }
+
+        let failed_attempts = self.failed_attempts.entry(key.to_owned()).or_default();
+        *failed_attempts += 1;
+        if *failed_attempts >= PASSWORD_RESET_MAX_ATTEMPTS {
+            self.tokens.remove(key);
+            self.failed_attempts.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn consume_token(
+        &mut self,
+        token: &PasswordResetToken,
+    ) -> Result<Email, PasswordResetTokenStoreError> {
+        self.failed_attempts.remove(token.as_ref().expose_secret());
+        match self.tokens.remove(token.as_ref().expose_secret()) {
+            Some((email, expires_at)) if expires_at > Utc::now().timestamp() => Ok(email),
+            _ => Err(PasswordResetTokenStoreError::TokenNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_consume_token() {
+        let mut store = HashmapPasswordResetTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = PasswordResetToken::default();
+
+        store.add_token(email.clone(), token.clone()).await.unwrap();
+        assert_eq!(store.consume_token(&token).await, Ok(email));
+    }
+
+    #[tokio::test]
+    async fn test_token_can_only_be_consumed_once() {
+        let mut store = HashmapPasswordResetTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = PasswordResetToken::default();
+
+        store.add_token(email, token.clone()).await.unwrap();
+        store.consume_token(&token).await.unwrap();
+        assert_eq!(store.consume_token(&token).await, Err(PasswordResetTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_get_email_does_not_consume_token() {
+        let mut store = HashmapPasswordResetTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = PasswordResetToken::default();
+
+        store.add_token(email.clone(), token.clone()).await.unwrap();
+        assert_eq!(store.get_email(&token).await, Ok(email.clone()));
+        assert_eq!(store.consume_token(&token).await, Ok(email));
+    }
+
+    #[tokio::test]
+    async fn test_token_is_removed_after_too_many_failed_attempts() {
+        let mut store = HashmapPasswordResetTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = PasswordResetToken::default();
+
+        store.add_token(email.clone(), token.clone()).await.unwrap();
+        let (_, expires_at) = store.tokens[token.as_ref().expose_secret()].clone();
+        for _ in 1..PASSWORD_RESET_MAX_ATTEMPTS {
+            store.record_failed_attempt(&token).await.unwrap();
+        }
+        // Refused attempts don't extend the link's lifetime
+        assert_eq!(store.tokens[token.as_ref().expose_secret()].1, expires_at);
+        assert_eq!(store.get_email(&token).await, Ok(email));
+
+        store.record_failed_attempt(&token).await.unwrap();
+        assert_eq!(store.get_email(&token).await, Err(PasswordResetTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_rejected() {
+        let mut store = HashmapPasswordResetTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = PasswordResetToken::default();
+
+        store.tokens.insert(
+            token.as_ref().expose_secret().to_owned(),
+            (email, Utc::now().timestamp() - 1),
+        );
+        assert_eq!(store.consume_token(&token).await, Err(PasswordResetTokenStoreError::TokenNotFound));
+    }
+}
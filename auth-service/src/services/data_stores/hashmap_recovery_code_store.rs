@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use secrecy::ExposeSecret;
+
+use crate::domain::{constant_time_eq, Email, RecoveryCode, RecoveryCodeStore, RecoveryCodeStoreError};
+
+#[derive(Default)]
+pub struct HashmapRecoveryCodeStore {
+    // Unused recovery codes per user
+    codes: HashMap<Email, Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl RecoveryCodeStore for HashmapRecoveryCodeStore {
+    async fn replace_codes(
+        &mut self,
+        email: &Email,
+        codes: Vec<RecoveryCode>,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        let codes = codes
+            .iter()
+            .map(|code| code.as_ref().expose_secret().to_owned())
+            .collect();
+        self.codes.insert(email.clone(), codes);
+        Ok(())
+    }
+
+    async fn consume_code(
+        &mut self,
+        email: &Email,
+        code: &RecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        match self.codes.get_mut(email) {
+            Some(codes) => {
+                let position = codes
+                    .iter()
+                    .position(|stored| constant_time_eq(stored, code.as_ref().expose_secret()))
+                    .ok_or(RecoveryCodeStoreError::CodeNotFound)?;
+                codes.swap_remove(position);
+                Ok(())
+            }
+            None => Err(RecoveryCodeStoreError::CodeNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_consume_code() {
+        let mut store = HashmapRecoveryCodeStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let code = RecoveryCode::default();
+
+        store.replace_codes(&email, vec![code.clone(), RecoveryCode::default()]).await.unwrap();
+        assert_eq!(store.consume_code(&email, &code).await, Ok(()));
+        assert_eq!(store.consume_code(&email, &code).await, Err(RecoveryCodeStoreError::CodeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_replace_codes_invalidates_old_codes() {
+        let mut store = HashmapRecoveryCodeStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let old_code = RecoveryCode::default();
+        let new_code = RecoveryCode::default();
+
+        store.replace_codes(&email, vec![old_code.clone()]).await.unwrap();
+        store.replace_codes(&email, vec![new_code.clone()]).await.unwrap();
+        assert_eq!(store.consume_code(&email, &old_code).await, Err(RecoveryCodeStoreError::CodeNotFound));
+        assert_eq!(store.consume_code(&email, &new_code).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_code_is_scoped_to_user() {
+        let mut store = HashmapRecoveryCodeStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let other_email = Email::parse(Secret::new("other@example.com".to_string())).unwrap();
+        let code = RecoveryCode::default();
+
+        store.replace_codes(&email, vec![code.clone()]).await.unwrap();
+        assert_eq!(store.consume_code(&other_email, &code).await, Err(RecoveryCodeStoreError::CodeNotFound));
+    }
+
+    #[test]
+    fn test_parse_normalizes_code() {
+        let code = RecoveryCode::parse(" K3F9AX2M7Q ".to_owned()).unwrap();
+        assert_eq!(code.as_ref().expose_secret(), "k3f9a-x2m7q");
+
+        let generated = RecoveryCode::default();
+        let parsed = RecoveryCode::parse(generated.as_ref().expose_secret().to_owned()).unwrap();
+        assert_eq!(parsed.as_ref().expose_secret(), generated.as_ref().expose_secret());
+
+        assert!(RecoveryCode::parse("k3f9a-x2m7".to_owned()).is_err());
+        assert!(RecoveryCode::parse("k3f9a-x2m7!".to_owned()).is_err());
+    }
+}
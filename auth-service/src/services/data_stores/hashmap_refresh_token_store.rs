@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+
+use crate::domain::{Email, RefreshToken, RefreshTokenStore, RefreshTokenStoreError};
+
+#[derive(Default)]
+pub struct HashmapRefreshTokenStore {
+    // Every refresh token ever issued, mapped to the ID of its family.
+    // Rotated-out tokens are kept so that replaying them can be detected.
+    tokens: HashMap<String, Uuid>,
+    families: HashMap<Uuid, RefreshTokenFamily>,
+}
+
+struct RefreshTokenFamily {
+    email: Email,
+    session_id: String,
+    current_token: String,
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for HashmapRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        session_id: String,
+        token: RefreshToken,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let family_id = Uuid::new_v4();
+        let current_token = token.as_ref().expose_secret().to_owned();
+
+        self.tokens.insert(current_token.clone(), family_id);
+        self.families.insert(family_id, RefreshTokenFamily { email, session_id, current_token });
+        Ok(())
+    }
+
+    async fn rotate_token(
+        &mut self,
+        token: &RefreshToken,
+        new_token: RefreshToken,
+    ) -> Result<(Email, String), RefreshTokenStoreError> {
+        let family_id = match self.tokens.get(token.as_ref().expose_secret()) {
+            Some(family_id) => *family_id,
+            None => return Err(RefreshTokenStoreError::TokenNotFound),
+        };
+
+        let family = match self.families.get_mut(&family_id) {
+            Some(family) => family,
+            None => return Err(RefreshTokenStoreError::TokenNotFound),
+        };
+
+        if &family.current_token != token.as_ref().expose_secret() {
+            self.families.remove(&family_id);
+            return Err(RefreshTokenStoreError::TokenReused);
+        }
+
+        family.current_token = new_token.as_ref().expose_secret().to_owned();
+        let result = (family.email.clone(), family.session_id.clone());
+        self.tokens.insert(family.current_token.clone(), family_id);
+        Ok(result)
+    }
+
+    async fn revoke_family(&mut self, token: &RefreshToken) -> Result<(), RefreshTokenStoreError> {
+        match self.tokens.get(token.as_ref().expose_secret()) {
+            Some(family_id) => {
+                self.families.remove(family_id);
+                Ok(())
+            }
+            None => Err(RefreshTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn revoke_user_families(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        self.families.retain(|_, family| &family.email != email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use super::*;
+
+    fn email() -> Email {
+        Email::parse(Secret::new("test@example.com".to_string())).unwrap()
+    }
+
+    const SESSION_ID: &str = "session";
+
+    fn session() -> (Email, String) {
+        (email(), SESSION_ID.to_owned())
+    }
+
+    #[tokio::test]
+    async fn test_rotate_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        store.add_token(email(), SESSION_ID.to_owned(), token.clone()).await.unwrap();
+
+        let new_token = RefreshToken::default();
+        assert_eq!(store.rotate_token(&token, new_token.clone()).await, Ok(session()));
+        assert_eq!(store.rotate_token(&new_token, RefreshToken::default()).await, Ok(session()));
+    }
+
+    #[tokio::test]
+    async fn test_rotate_unknown_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let result = store.rotate_token(&RefreshToken::default(), RefreshToken::default()).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_reused_token_revokes_family() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        store.add_token(email(), SESSION_ID.to_owned(), token.clone()).await.unwrap();
+
+        let new_token = RefreshToken::default();
+        store.rotate_token(&token, new_token.clone()).await.unwrap();
+
+        let result = store.rotate_token(&token, RefreshToken::default()).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenReused));
+
+        let result = store.rotate_token(&new_token, RefreshToken::default()).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        store.add_token(email(), SESSION_ID.to_owned(), token.clone()).await.unwrap();
+
+        assert!(store.revoke_family(&token).await.is_ok());
+        let result = store.rotate_token(&token, RefreshToken::default()).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_user_families() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let first_token = RefreshToken::default();
+        let second_token = RefreshToken::default();
+        store.add_token(email(), SESSION_ID.to_owned(), first_token.clone()).await.unwrap();
+        store.add_token(email(), SESSION_ID.to_owned(), second_token.clone()).await.unwrap();
+
+        let other_email = Email::parse(Secret::new("other@example.com".to_string())).unwrap();
+        let other_token = RefreshToken::default();
+        store.add_token(other_email.clone(), SESSION_ID.to_owned(), other_token.clone()).await.unwrap();
+
+        assert!(store.revoke_user_families(&email()).await.is_ok());
+        for token in [first_token, second_token] {
+            let result = store.rotate_token(&token, RefreshToken::default()).await;
+            assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+        }
+        assert_eq!(store.rotate_token(&other_token, RefreshToken::default()).await, Ok((other_email, SESSION_ID.to_owned())));
+    }
+}
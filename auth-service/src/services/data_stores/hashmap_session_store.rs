@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use crate::domain::{Email, Session, SessionStore, SessionStoreError};
+
+#[derive(Default)]
+pub struct HashmapSessionStore {
+    sessions: HashMap<String, (Email, Session)>,
+}
+
+#[async_trait::async_trait]
+impl SessionStore for HashmapSessionStore {
+    async fn add_session(&mut self, email: &Email, session: Session) -> Result<(), SessionStoreError> {
+        self.sessions.insert(session.id.clone(), (email.clone(), session));
+        Ok(())
+    }
+
+    async fn get_sessions(&self, email: &Email) -> Result<Vec<Session>, SessionStoreError> {
+        Ok(self
+            .sessions
+            .values()
+            .filter(|(owner, _)| owner == email)
+            .map(|(_, session)| session.clone())
+            .collect())
+    }
+
+    async fn touch_session(&mut self, session_id: &str) -> Result<(), SessionStoreError> {
+        match self.sessions.get_mut(session_id) {
+            Some((_, session)) => {
+                session.last_seen_at = Utc::now().timestamp();
+                Ok(())
+            }
+            None => Err(SessionStoreError::SessionNotFound),
+        }
+    }
+
+    async fn revoke_session(&mut self, email: &Email, session_id: &str) -> Result<(), SessionStoreError> {
+        match self.sessions.get(session_id) {
+            Some((owner, _)) if owner == email => {
+                self.sessions.remove(session_id);
+                Ok(())
+            }
+            _ => Err(SessionStoreError::SessionNotFound),
+        }
+    }
+
+    async fn revoke_user_sessions(&mut self, email: &Email) -> Result<(), SessionStoreError> {
+        self.sessions.retain(|_, (owner, _)| owner != email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use super::*;
+
+    fn email(address: &str) -> Email {
+        Email::parse(Secret::new(address.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_sessions_only_returns_own_sessions() {
+        let mut store = HashmapSessionStore::default();
+        let session = Session::new(Some("Firefox".to_owned()), None);
+        store.add_session(&email("test@example.com"), session.clone()).await.unwrap();
+        store.add_session(&email("other@example.com"), Session::new(None, None)).await.unwrap();
+
+        assert_eq!(store.get_sessions(&email("test@example.com")).await, Ok(vec![session]));
+    }
+
+    #[tokio::test]
+    async fn test_touch_session() {
+        let mut store = HashmapSessionStore::default();
+        let mut session = Session::new(None, None);
+        session.last_seen_at = 0;
+        store.add_session(&email("test@example.com"), session.clone()).await.unwrap();
+
+        assert_eq!(store.touch_session(&session.id).await, Ok(()));
+        let sessions = store.get_sessions(&email("test@example.com")).await.unwrap();
+        assert!(sessions[0].last_seen_at > 0);
+
+        assert_eq!(store.touch_session("unknown").await, Err(SessionStoreError::SessionNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_session() {
+        let mut store = HashmapSessionStore::default();
+        let session = Session::new(None, None);
+        store.add_session(&email("test@example.com"), session.clone()).await.unwrap();
+
+        assert_eq!(
+            store.revoke_session(&email("other@example.com"), &session.id).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+        assert_eq!(store.revoke_session(&email("test@example.com"), &session.id).await, Ok(()));
+        assert_eq!(store.touch_session(&session.id).await, Err(SessionStoreError::SessionNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_user_sessions() {
+        let mut store = HashmapSessionStore::default();
+        let other_session = Session::new(None, None);
+        store.add_session(&email("test@example.com"), Session::new(None, None)).await.unwrap();
+        store.add_session(&email("test@example.com"), Session::new(None, None)).await.unwrap();
+        store.add_session(&email("other@example.com"), other_session.clone()).await.unwrap();
+
+        assert_eq!(store.revoke_user_sessions(&email("test@example.com")).await, Ok(()));
+        assert_eq!(store.get_sessions(&email("test@example.com")).await, Ok(vec![]));
+        assert_eq!(store.get_sessions(&email("other@example.com")).await, Ok(vec![other_session]));
+    }
+}
@@ -1,12 +1,46 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
-use color_eyre::eyre;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
 
-use crate::domain::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError, Email};
+use crate::{
+    domain::{constant_time_eq, Email, LoginAttemptId, PendingTwoFAAttempt, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    utils::constants::{MAX_PENDING_TWO_FA_CHALLENGES, TWO_FA_CODE_TTL_SECONDS},
+};
 
+struct PendingCode {
+    email: Email,
+    code: TwoFACode,
+    failed_attempts: u32,
+    resends: u32,
+    last_sent_at: DateTime<Utc>,
+    created_at: Instant,
+    // Orders a user's attempts, even ones created within the same instant
+    sequence: u64,
+}
+
+impl PendingCode {
+    fn is_expired(&self) -> bool {
+        self.created_at.elapsed() >= Duration::from_secs(TWO_FA_CODE_TTL_SECONDS)
+    }
+}
+
+// Keyed by the login attempt ID
 #[derive(Default)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: HashMap<String, PendingCode>,
+    next_sequence: u64,
+}
+
+impl HashmapTwoFACodeStore {
+    fn get_pending_code(&self, email: &Email, login_attempt_id: &LoginAttemptId) -> Option<&PendingCode> {
+        self.codes
+            .get(login_attempt_id.as_ref().expose_secret())
+            .filter(|pending| !pending.is_expired() && &pending.email == email)
+    }
 }
 
 #[async_trait::async_trait]
@@ -17,32 +51,100 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        match self.get_code(&email).await {
-            Ok(_) => Err(TwoFACodeStoreError::UnexpectedError(eyre::eyre!("Code already exists"))),
-            Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {
-                self.codes.insert(email, (login_attempt_id, code));
-                Ok(())
-            }
-            Err(e) => Err(e),
+        self.codes.retain(|_, pending| !pending.is_expired());
+
+        // Make room for the new attempt by dropping the user's oldest ones
+        let mut pending_ids: Vec<(u64, String)> = self
+            .codes
+            .iter()
+            .filter(|(_, pending)| pending.email == email)
+            .map(|(id, pending)| (pending.sequence, id.clone()))
+            .collect();
+        pending_ids.sort();
+        let excess = (pending_ids.len() + 1).saturating_sub(MAX_PENDING_TWO_FA_CHALLENGES);
+        for (_, id) in pending_ids.into_iter().take(excess) {
+            self.codes.remove(&id);
         }
+
+        self.codes.insert(
+            login_attempt_id.as_ref().expose_secret().to_owned(),
+            PendingCode {
+                email,
+                code,
+                failed_attempts: 0,
+                resends: 0,
+                last_sent_at: Utc::now(),
+                created_at: Instant::now(),
+                sequence: self.next_sequence,
+            },
+        );
+        self.next_sequence += 1;
+        Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        match self.codes.remove(email) {
+    async fn remove_code(&mut self, login_attempt_id: &LoginAttemptId) -> Result<(), TwoFACodeStoreError> {
+        match self.codes.remove(login_attempt_id.as_ref().expose_secret()) {
             Some(_) => Ok(()),
             None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
 
-    async fn get_code(
+    async fn verify_code(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        match self.codes.get(email) {
-            Some((login_attempt_id, code)) => Ok((login_attempt_id.clone(), code.clone())),
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<bool, TwoFACodeStoreError> {
+        match self.get_pending_code(email, login_attempt_id) {
+            Some(pending) => Ok(constant_time_eq(
+                pending.code.as_ref().expose_secret(),
+                code.as_ref().expose_secret(),
+            )),
             None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
+
+    async fn record_failed_attempt(&mut self, login_attempt_id: &LoginAttemptId) -> Result<u32, TwoFACodeStoreError> {
+        match self.codes.get_mut(login_attempt_id.as_ref().expose_secret()) {
+            Some(pending) if !pending.is_expired() => {
+                pending.failed_attempts += 1;
+                Ok(pending.failed_attempts)
+            }
+            _ => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
+
+    async fn get_attempt(
+        &self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<PendingTwoFAAttempt, TwoFACodeStoreError> {
+        match self.get_pending_code(email, login_attempt_id) {
+            Some(pending) => Ok(PendingTwoFAAttempt {
+                last_sent_at: pending.last_sent_at,
+                resends: pending.resends,
+            }),
+            None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
+
+    async fn replace_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        match self.codes.get_mut(login_attempt_id.as_ref().expose_secret()) {
+            Some(pending) if !pending.is_expired() && &pending.email == email => {
+                pending.code = code;
+                pending.resends += 1;
+                pending.last_sent_at = Utc::now();
+                pending.created_at = Instant::now();
+                Ok(())
+            }
+            _ => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -50,6 +152,16 @@ mod tests {
     use secrecy::Secret;
     use super::*;
 
+    fn email(address: &str) -> Email {
+        Email::parse(Secret::new(address.to_string())).unwrap()
+    }
+
+    // A code that is certainly not the given one
+    fn wrong_code(code: &TwoFACode) -> TwoFACode {
+        let wrong = if code.as_ref().expose_secret() == "000000" { "111111" } else { "000000" };
+        TwoFACode::parse(wrong.to_owned()).unwrap()
+    }
+
     #[tokio::test]
     async fn test_add_code() {
         let mut store = HashmapTwoFACodeStore::default();
@@ -67,24 +179,122 @@ mod tests {
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
-        store.add_code(email.clone(), login_attempt_id, code).await.unwrap();
-        assert!(store.remove_code(&email).await.is_ok());
+        store.add_code(email.clone(), login_attempt_id.clone(), code.clone()).await.unwrap();
+        assert!(store.remove_code(&login_attempt_id).await.is_ok());
+        assert_eq!(
+            store.verify_code(&email, &login_attempt_id, &code).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
     }
 
     #[tokio::test]
-    async fn test_get_code() {
+    async fn test_verify_code() {
         let mut store = HashmapTwoFACodeStore::default();
         let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
-        store.add_code(email.clone(), login_attempt_id, code).await.unwrap();
-        assert!(store.get_code(&email).await.is_ok());
+        store.add_code(email.clone(), login_attempt_id.clone(), code.clone()).await.unwrap();
+        assert_eq!(store.verify_code(&email, &login_attempt_id, &code).await, Ok(true));
+        assert_eq!(store.verify_code(&email, &login_attempt_id, &wrong_code(&code)).await, Ok(false));
     }
-}
 
+    #[tokio::test]
+    async fn test_verify_code_of_another_user() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::default();
+
+        store.add_code(email("test@example.com"), login_attempt_id.clone(), code.clone()).await.unwrap();
+        assert_eq!(
+            store.verify_code(&email("other@example.com"), &login_attempt_id, &code).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_login_attempts() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let first_attempt = LoginAttemptId::default();
+        let second_attempt = LoginAttemptId::default();
+        let first_code = TwoFACode::default();
+        let second_code = wrong_code(&first_code);
+
+        store.add_code(email("test@example.com"), first_attempt.clone(), first_code.clone()).await.unwrap();
+        store.add_code(email("test@example.com"), second_attempt.clone(), second_code.clone()).await.unwrap();
+
+        assert_eq!(store.verify_code(&email("test@example.com"), &first_attempt, &first_code).await, Ok(true));
+        assert_eq!(store.verify_code(&email("test@example.com"), &second_attempt, &second_code).await, Ok(true));
+        // A code only works for the attempt it was sent for
+        assert_eq!(store.verify_code(&email("test@example.com"), &first_attempt, &second_code).await, Ok(false));
+    }
+
+    #[tokio::test]
+    async fn test_oldest_attempt_is_dropped_past_limit() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let attempts: Vec<LoginAttemptId> = (0..=MAX_PENDING_TWO_FA_CHALLENGES).map(|_| LoginAttemptId::default()).collect();
+
+        for attempt in &attempts {
+            store.add_code(email("test@example.com"), attempt.clone(), TwoFACode::default()).await.unwrap();
+        }
+        // Another user's attempts don't count towards the limit
+        store.add_code(email("other@example.com"), LoginAttemptId::default(), TwoFACode::default()).await.unwrap();
 
+        assert_eq!(
+            store.get_attempt(&email("test@example.com"), &attempts[0]).await.err(),
+            Some(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+        for attempt in &attempts[1..] {
+            assert!(store.get_attempt(&email("test@example.com"), attempt).await.is_ok());
+        }
+    }
 
+    #[tokio::test]
+    async fn test_record_failed_attempt() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
 
+        assert_eq!(
+            store.record_failed_attempt(&login_attempt_id).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
 
+        store.add_code(email("test@example.com"), login_attempt_id.clone(), TwoFACode::default()).await.unwrap();
+        assert_eq!(store.record_failed_attempt(&login_attempt_id).await, Ok(1));
+        assert_eq!(store.record_failed_attempt(&login_attempt_id).await, Ok(2));
+    }
 
+    #[tokio::test]
+    async fn test_replace_code() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
+        let old_code = TwoFACode::default();
+        let new_code = wrong_code(&old_code);
+
+        assert_eq!(
+            store.replace_code(&email("test@example.com"), &login_attempt_id, new_code.clone()).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+
+        store.add_code(email("test@example.com"), login_attempt_id.clone(), old_code.clone()).await.unwrap();
+        store.record_failed_attempt(&login_attempt_id).await.unwrap();
+        let attempt = store.get_attempt(&email("test@example.com"), &login_attempt_id).await.unwrap();
+        assert_eq!(attempt.resends, 0);
+
+        // Another user can't swap the code out
+        assert_eq!(
+            store.replace_code(&email("other@example.com"), &login_attempt_id, new_code.clone()).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+
+        store.replace_code(&email("test@example.com"), &login_attempt_id, new_code.clone()).await.unwrap();
+        assert_eq!(store.verify_code(&email("test@example.com"), &login_attempt_id, &new_code).await, Ok(true));
+        assert_eq!(store.verify_code(&email("test@example.com"), &login_attempt_id, &old_code).await, Ok(false));
+
+        let new_attempt = store.get_attempt(&email("test@example.com"), &login_attempt_id).await.unwrap();
+        assert_eq!(new_attempt.resends, 1);
+        assert!(new_attempt.last_sent_at >= attempt.last_sent_at);
+        // Wrong codes still count after a resend
+        assert_eq!(store.record_failed_attempt(&login_attempt_id).await, Ok(2));
+    }
+}
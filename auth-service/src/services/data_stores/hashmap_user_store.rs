@@ -1,11 +1,37 @@
-use std::collections::HashMap;
-use crate::domain::{User, UserStore, UserStoreError, Email, Password};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::{
+    domain::{constant_time_eq, AccountDeletionToken, ImportedPasswordHash, User, UserStore, UserStoreError, Email, Password, TotpSecret, TwoFAMethod},
+    utils::{
+        constants::PASSWORD_HISTORY_DEPTH,
+        password_hash::{compute_password_hash, verify_password_hash},
+    },
+};
 
 // a `HashMap`` of email `String`s mapped to `User` objects.
 // Derive the `Default` trait for `HashmapUserStore`.
 #[derive(Default, Debug)]
 pub struct HashmapUserStore {
     users: HashMap<Email, User>,
+    pending_deletions: HashMap<Email, PendingDeletion>,
+    // Users whose stored password is still the hash they were imported with
+    imported: HashSet<Email>,
+    // Hashes of each user's replaced passwords, newest first
+    password_history: HashMap<Email, Vec<Secret<String>>>,
+    // Time step of the last TOTP code each user got right
+    last_totp_steps: HashMap<Email, u64>,
+}
+
+#[derive(Debug)]
+struct PendingDeletion {
+    cancel_token: String,
+    delete_at: DateTime<Utc>,
 }
 
 #[async_trait::async_trait]
@@ -21,6 +47,22 @@ impl UserStore for HashmapUserStore {
         }
     }
 
+    async fn import_user(
+        &mut self,
+        email: Email,
+        password_hash: ImportedPasswordHash,
+        two_fa_method: TwoFAMethod,
+    ) -> Result<(), UserStoreError> {
+        let password = Password::parse(password_hash.as_ref().clone())
+            .map_err(UserStoreError::UnexpectedError)?;
+        let mut user = User::new(email.clone(), password, two_fa_method);
+        user.verified = true;
+
+        self.add_user(user).await?;
+        self.imported.insert(email);
+        Ok(())
+    }
+
 
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
         match self.users.get(email) {
@@ -31,6 +73,11 @@ impl UserStore for HashmapUserStore {
 
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
         match self.get_user(email).await {
+            Ok(user) if self.imported.contains(email) => {
+                verify_password_hash(user.password.as_ref().clone(), password.as_ref().clone())
+                    .await
+                    .map_err(|_| UserStoreError::InvalidCredentials)
+            }
             Ok(user) => {
                 if &user.password == password {
                     Ok(())
@@ -41,6 +88,163 @@ impl UserStore for HashmapUserStore {
             Err(_) => Err(UserStoreError::UserNotFound),
         }
     }
+
+    async fn is_password_reused(&self, email: &Email, password: &Password) -> Result<bool, UserStoreError> {
+        if *PASSWORD_HISTORY_DEPTH == 0 {
+            return Ok(false);
+        }
+        self.get_user(email).await?;
+
+        if self.validate_user(email, password).await.is_ok() {
+            return Ok(true);
+        }
+        let kept_history = PASSWORD_HISTORY_DEPTH.saturating_sub(1);
+        for previous_hash in self.password_history.get(email).into_iter().flatten().take(kept_history) {
+            if verify_password_hash(previous_hash.clone(), password.as_ref().clone()).await.is_ok() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    async fn update_password(&mut self, email: &Email, password: Password) -> Result<(), UserStoreError> {
+        let user = self.get_user(email).await?;
+        let kept_history = PASSWORD_HISTORY_DEPTH.saturating_sub(1);
+
+        if self.is_password_reused(email, &password).await? {
+            return Err(UserStoreError::PasswordReused);
+        }
+
+        // Passwords are kept as they were given here, so they are hashed on their way into the history
+        let replaced_hash = match kept_history {
+            0 => None,
+            _ if self.imported.contains(email) => Some(user.password.as_ref().clone()),
+            _ => Some(
+                compute_password_hash(user.password.as_ref().clone())
+                    .await
+                    .map_err(UserStoreError::UnexpectedError)?,
+            ),
+        };
+        let history = self.password_history.entry(email.clone()).or_default();
+        if let Some(replaced_hash) = replaced_hash {
+            history.insert(0, replaced_hash);
+        }
+        history.truncate(kept_history);
+
+        if let Some(user) = self.users.get_mut(email) {
+            user.password = password;
+        }
+        self.imported.remove(email);
+        Ok(())
+    }
+
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.verified = true;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn set_totp_secret(&mut self, email: &Email, secret: TotpSecret) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.totp_secret = Some(secret);
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn record_totp_step(&mut self, email: &Email, step: u64) -> Result<(), UserStoreError> {
+        if !self.users.contains_key(email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        match self.last_totp_steps.get(email) {
+            Some(last_step) if step <= *last_step => Err(UserStoreError::TotpCodeReused),
+            _ => {
+                self.last_totp_steps.insert(email.clone(), step);
+                Ok(())
+            }
+        }
+    }
+
+    async fn set_two_fa_method(&mut self, email: &Email, method: TwoFAMethod) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.two_fa_method = method;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn set_disabled(&mut self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.disabled = disabled;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn schedule_deletion(
+        &mut self,
+        email: &Email,
+        cancel_token: AccountDeletionToken,
+        grace_period: Duration,
+    ) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(email).ok_or(UserStoreError::UserNotFound)?;
+        let grace_period = chrono::Duration::from_std(grace_period)
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        user.pending_deletion = true;
+        self.pending_deletions.insert(
+            email.clone(),
+            PendingDeletion {
+                cancel_token: cancel_token.as_ref().expose_secret().to_owned(),
+                delete_at: Utc::now() + grace_period,
+            },
+        );
+        Ok(())
+    }
+
+    async fn cancel_deletion(&mut self, cancel_token: &AccountDeletionToken) -> Result<Email, UserStoreError> {
+        let email = self
+            .pending_deletions
+            .iter()
+            .find(|(_, pending)| constant_time_eq(&pending.cancel_token, cancel_token.as_ref().expose_secret()))
+            .map(|(email, _)| email.clone())
+            .ok_or(UserStoreError::UserNotFound)?;
+
+        self.pending_deletions.remove(&email);
+        if let Some(user) = self.users.get_mut(&email) {
+            user.pending_deletion = false;
+        }
+        Ok(email)
+    }
+
+    async fn delete_expired_users(&mut self) -> Result<Vec<Email>, UserStoreError> {
+        let now = Utc::now();
+        let expired: Vec<Email> = self
+            .pending_deletions
+            .iter()
+            .filter(|(_, pending)| pending.delete_at <= now)
+            .map(|(email, _)| email.clone())
+            .collect();
+
+        for email in &expired {
+            self.pending_deletions.remove(email);
+            self.users.remove(email);
+            self.imported.remove(email);
+            self.password_history.remove(email);
+            self.last_totp_steps.remove(email);
+        }
+        Ok(expired)
+    }
 }
 
 // TODO: Add unit tests for your `HashmapUserStore` implementation
@@ -55,7 +259,11 @@ mod tests {
         let user = User {
             email: Email::parse(Secret::new("test@gmail.com".to_string())).unwrap(),
             password:Password::parse(Secret::new("password".to_string())).unwrap(),
-            requires_2fa: false,
+            two_fa_method: TwoFAMethod::None,
+            verified: false,
+            disabled: false,
+            pending_deletion: false,
+            totp_secret: None,
         };
         assert_eq!(store.add_user(user.clone()).await, Ok(()));
         assert_eq!(store.add_user(user).await, Err(UserStoreError::UserAlreadyExists));
@@ -67,7 +275,11 @@ mod tests {
         let user = User {
             email: Email::parse(Secret::new("test@gmail.com".to_string())).unwrap(),
             password:Password::parse(Secret::new("password".to_string())).unwrap(),
-            requires_2fa: false,
+            two_fa_method: TwoFAMethod::None,
+            verified: false,
+            disabled: false,
+            pending_deletion: false,
+            totp_secret: None,
         };
         assert_eq!(store.get_user(&Email::parse(Secret::new("test@gmail.com".to_string())).unwrap()).await, Err(UserStoreError::UserNotFound));
         assert_eq!(store.add_user(user.clone()).await, Ok(()));
@@ -80,11 +292,212 @@ mod tests {
         let user = User {
             email: Email::parse(Secret::new("test@gmail.com".to_string())).unwrap(),
             password:Password::parse(Secret::new("password".to_string())).unwrap(),
-            requires_2fa: false,
+            two_fa_method: TwoFAMethod::None,
+            verified: false,
+            disabled: false,
+            pending_deletion: false,
+            totp_secret: None,
         };
         assert_eq!(store.validate_user(&Email::parse(Secret::new("test@gmail.com".to_string())).unwrap(), &Password::parse(Secret::new("password".to_string())).unwrap()).await, Err(UserStoreError::UserNotFound));
         store.add_user(user.clone()).await.unwrap();
         assert_eq!(store.validate_user(&Email::parse(Secret::new("test@gmail.com".to_string())).unwrap(), &Password::parse(Secret::new("password".to_string())).unwrap()).await, Ok(()));
         assert_eq!(store.validate_user(&Email::parse(Secret::new("test@gmail.com".to_string())).unwrap(), &Password::parse(Secret::new("wrongpassword".to_string())).unwrap()).await, Err(UserStoreError::InvalidCredentials));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_import_user() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@gmail.com".to_string())).unwrap();
+        let hash = ImportedPasswordHash::parse(Secret::new(bcrypt::hash("password", 4).unwrap())).unwrap();
+
+        assert_eq!(store.import_user(email.clone(), hash.clone(), TwoFAMethod::Email).await, Ok(()));
+        assert_eq!(store.import_user(email.clone(), hash, TwoFAMethod::Email).await, Err(UserStoreError::UserAlreadyExists));
+
+        let user = store.get_user(&email).await.unwrap();
+        assert_eq!(user.two_fa_method, TwoFAMethod::Email);
+        assert!(user.verified);
+        assert_eq!(store.validate_user(&email, &Password::parse(Secret::new("password".to_string())).unwrap()).await, Ok(()));
+        assert_eq!(store.validate_user(&email, &Password::parse(Secret::new("wrongpassword".to_string())).unwrap()).await, Err(UserStoreError::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@gmail.com".to_string())).unwrap();
+        let new_password = Password::parse(Secret::new("newpassword".to_string())).unwrap();
+        assert_eq!(store.update_password(&email, new_password.clone()).await, Err(UserStoreError::UserNotFound));
+
+        let user = User {
+            email: email.clone(),
+            password: Password::parse(Secret::new("password".to_string())).unwrap(),
+            two_fa_method: TwoFAMethod::None,
+            verified: false,
+            disabled: false,
+            pending_deletion: false,
+            totp_secret: None,
+        };
+        store.add_user(user).await.unwrap();
+        assert_eq!(store.update_password(&email, new_password.clone()).await, Ok(()));
+        assert_eq!(store.validate_user(&email, &new_password).await, Ok(()));
+        assert_eq!(store.validate_user(&email, &Password::parse(Secret::new("password".to_string())).unwrap()).await, Err(UserStoreError::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn test_update_password_rejects_recent_passwords() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@gmail.com".to_string())).unwrap();
+        let password = |s: &str| Password::parse(Secret::new(s.to_string())).unwrap();
+        let user = User {
+            email: email.clone(),
+            password: password("password0"),
+            two_fa_method: TwoFAMethod::None,
+            verified: false,
+            disabled: false,
+            pending_deletion: false,
+            totp_secret: None,
+        };
+        store.add_user(user).await.unwrap();
+
+        assert_eq!(store.is_password_reused(&email, &password("password0")).await, Ok(true));
+        assert_eq!(store.is_password_reused(&email, &password("password1")).await, Ok(false));
+        assert_eq!(store.update_password(&email, password("password0")).await, Err(UserStoreError::PasswordReused));
+        for i in 1..=*PASSWORD_HISTORY_DEPTH {
+            assert_eq!(store.update_password(&email, password(&format!("password{}", i))).await, Ok(()));
+        }
+        for i in 2..=*PASSWORD_HISTORY_DEPTH {
+            assert_eq!(store.update_password(&email, password(&format!("password{}", i))).await, Err(UserStoreError::PasswordReused));
+        }
+
+        // The first password has been pruned from the history, so it can be used again
+        assert_eq!(store.password_history[&email].len(), *PASSWORD_HISTORY_DEPTH - 1);
+        assert_eq!(store.update_password(&email, password("password0")).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_mark_email_verified() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@gmail.com".to_string())).unwrap();
+        assert_eq!(store.mark_email_verified(&email).await, Err(UserStoreError::UserNotFound));
+
+        let user = User::new(
+            email.clone(),
+            Password::parse(Secret::new("password".to_string())).unwrap(),
+            TwoFAMethod::None,
+        );
+        store.add_user(user).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().verified);
+
+        assert_eq!(store.mark_email_verified(&email).await, Ok(()));
+        assert!(store.get_user(&email).await.unwrap().verified);
+    }
+
+    #[tokio::test]
+    async fn test_enable_totp() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@gmail.com".to_string())).unwrap();
+        let secret = TotpSecret::default();
+        assert_eq!(store.set_totp_secret(&email, secret.clone()).await, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.set_two_fa_method(&email, TwoFAMethod::Totp).await, Err(UserStoreError::UserNotFound));
+
+        let user = User::new(
+            email.clone(),
+            Password::parse(Secret::new("password".to_string())).unwrap(),
+            TwoFAMethod::Email,
+        );
+        store.add_user(user).await.unwrap();
+
+        assert_eq!(store.set_totp_secret(&email, secret.clone()).await, Ok(()));
+        assert_eq!(store.set_two_fa_method(&email, TwoFAMethod::Totp).await, Ok(()));
+
+        let user = store.get_user(&email).await.unwrap();
+        assert_eq!(user.totp_secret, Some(secret));
+        assert_eq!(user.two_fa_method, TwoFAMethod::Totp);
+    }
+
+    #[tokio::test]
+    async fn test_record_totp_step() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        assert_eq!(store.record_totp_step(&email, 100).await, Err(UserStoreError::UserNotFound));
+
+        let user = User::new(
+            email.clone(),
+            Password::parse(Secret::new("password".to_string())).unwrap(),
+            TwoFAMethod::Totp,
+        );
+        store.add_user(user).await.unwrap();
+
+        assert_eq!(store.record_totp_step(&email, 100).await, Ok(()));
+        assert_eq!(store.record_totp_step(&email, 100).await, Err(UserStoreError::TotpCodeReused));
+        assert_eq!(store.record_totp_step(&email, 99).await, Err(UserStoreError::TotpCodeReused));
+        assert_eq!(store.record_totp_step(&email, 101).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_set_disabled() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        assert_eq!(store.set_disabled(&email, true).await, Err(UserStoreError::UserNotFound));
+
+        let user = User::new(
+            email.clone(),
+            Password::parse(Secret::new("password".to_string())).unwrap(),
+            TwoFAMethod::None,
+        );
+        store.add_user(user).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().disabled);
+
+        assert_eq!(store.set_disabled(&email, true).await, Ok(()));
+        assert!(store.get_user(&email).await.unwrap().disabled);
+        assert_eq!(store.set_disabled(&email, false).await, Ok(()));
+        assert!(!store.get_user(&email).await.unwrap().disabled);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_deletion() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let token = AccountDeletionToken::default();
+        let grace_period = Duration::from_secs(3600);
+        assert_eq!(
+            store.schedule_deletion(&email, token.clone(), grace_period).await,
+            Err(UserStoreError::UserNotFound)
+        );
+
+        let user = User::new(
+            email.clone(),
+            Password::parse(Secret::new("password".to_string())).unwrap(),
+            TwoFAMethod::None,
+        );
+        store.add_user(user).await.unwrap();
+
+        assert_eq!(store.schedule_deletion(&email, token.clone(), grace_period).await, Ok(()));
+        assert!(store.get_user(&email).await.unwrap().pending_deletion);
+        assert_eq!(store.cancel_deletion(&AccountDeletionToken::default()).await, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.cancel_deletion(&token).await, Ok(email.clone()));
+        assert!(!store.get_user(&email).await.unwrap().pending_deletion);
+        assert_eq!(store.cancel_deletion(&token).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_delete_expired_users() {
+        let mut store = HashmapUserStore::default();
+        let expired_email = Email::parse(Secret::new("expired@example.com".to_string())).unwrap();
+        let pending_email = Email::parse(Secret::new("pending@example.com".to_string())).unwrap();
+        for email in [&expired_email, &pending_email] {
+            let user = User::new(
+                email.clone(),
+                Password::parse(Secret::new("password".to_string())).unwrap(),
+                TwoFAMethod::None,
+            );
+            store.add_user(user).await.unwrap();
+        }
+
+        store.schedule_deletion(&expired_email, AccountDeletionToken::default(), Duration::ZERO).await.unwrap();
+        store.schedule_deletion(&pending_email, AccountDeletionToken::default(), Duration::from_secs(3600)).await.unwrap();
+
+        assert_eq!(store.delete_expired_users().await, Ok(vec![expired_email.clone()]));
+        assert_eq!(store.get_user(&expired_email).await, Err(UserStoreError::UserNotFound));
+        assert!(store.get_user(&pending_email).await.unwrap().pending_deletion);
+    }
+}
@@ -3,33 +3,42 @@
 // The struct should be defined in the auth-service/src/services directory. 
 // Make sure to add unit tests!
 
-use std::{collections::HashSet};
+use std::{collections::{HashMap, HashSet}};
 use color_eyre::eyre;
-use secrecy::{ExposeSecret, Secret};
 
-use crate::domain::{BannedTokenStore, BannedTokenStoreError};
+use crate::domain::{BannedTokenStore, BannedTokenStoreError, Email};
 
 #[derive(Default, Debug)]
 pub struct HashsetBannedTokenStore {
+    // IDs (`jti`) of banned tokens
     tokens: HashSet<String>,
+    token_generations: HashMap<Email, u64>,
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn store_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
-        let token_str = token.expose_secret();
-        match self.tokens.get(token_str) {
+    async fn store_token(&mut self, jti: String) -> Result<(), BannedTokenStoreError> {
+        match self.tokens.get(&jti) {
             Some(_) => return Err(BannedTokenStoreError::UnexpectedError(eyre::eyre!("Token already exists"))), 
             None => {
-                self.tokens.insert(token_str.to_owned());
+                self.tokens.insert(jti);
             },
         }
         Ok(())
     }
 
-    async fn is_token_banned(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
+    async fn is_token_banned(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
         // For an in-memory HashSet store this operation cannot fail, so return Ok
-        Ok(self.tokens.contains(token.expose_secret()))
+        Ok(self.tokens.contains(jti))
+    }
+
+    async fn get_token_generation(&self, email: &Email) -> Result<u64, BannedTokenStoreError> {
+        Ok(self.token_generations.get(email).copied().unwrap_or_default())
+    }
+
+    async fn revoke_user_tokens(&mut self, email: &Email) -> Result<(), BannedTokenStoreError> {
+        *self.token_generations.entry(email.clone()).or_default() += 1;
+        Ok(())
     }
 
     fn as_ref(&self) -> &dyn BannedTokenStore {
@@ -42,12 +51,24 @@ async fn test_banned_token_store() {
     let mut store = HashsetBannedTokenStore::default();
 
     // Test storing a token
-    let token = Secret::new("test_token".to_string());
-    assert!(store.store_token(token.clone()).await.is_ok());
+    let jti = "test_jti".to_string();
+    assert!(store.store_token(jti.clone()).await.is_ok());
 
     // Test storing the same token again
-    assert!(store.store_token(token.clone()).await.is_err());
+    assert!(store.store_token(jti.clone()).await.is_err());
 
     // Test checking if the token is banned
-    assert!(store.is_token_banned(&token).await.unwrap());
+    assert!(store.is_token_banned(&jti).await.unwrap());
+    assert!(!store.is_token_banned("other_jti").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_revoke_user_tokens() {
+    let mut store = HashsetBannedTokenStore::default();
+    let email = Email::parse(secrecy::Secret::new("test@example.com".to_string())).unwrap();
+
+    assert_eq!(store.get_token_generation(&email).await.unwrap(), 0);
+
+    store.revoke_user_tokens(&email).await.unwrap();
+    assert_eq!(store.get_token_generation(&email).await.unwrap(), 1);
 }
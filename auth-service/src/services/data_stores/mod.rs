@@ -1,17 +1,65 @@
 mod hashmap_user_store;
 mod hashset_banned_token_store;
 mod hashmap_two_fa_code_store;
+mod hashmap_refresh_token_store;
+mod hashmap_password_reset_token_store;
+mod hashmap_email_verification_token_store;
+mod hashmap_magic_link_token_store;
+mod hashmap_recovery_code_store;
+mod hashmap_passkey_store;
+mod hashmap_passkey_challenge_store;
+mod hashmap_oauth_client_store;
+mod hashmap_authorization_code_store;
+mod hashmap_session_store;
+mod hashmap_login_throttle_store;
+mod hashmap_jwt_key_store;
 mod mock_email_client;
 mod postgres_user_store;
+mod postgres_recovery_code_store;
+mod postgres_passkey_store;
+mod postgres_oauth_client_store;
+mod postgres_jwt_key_store;
 mod redis_banned_token_store;
 mod redis_two_fa_code_store;
+mod redis_refresh_token_store;
+mod redis_password_reset_token_store;
+mod redis_email_verification_token_store;
+mod redis_magic_link_token_store;
+mod redis_authorization_code_store;
+mod redis_passkey_challenge_store;
+mod redis_session_store;
+mod redis_login_throttle_store;
 mod postmark_email_client;
 
 pub use hashmap_user_store::*;
 pub use hashset_banned_token_store::*;
 pub use hashmap_two_fa_code_store::*;
+pub use hashmap_refresh_token_store::*;
+pub use hashmap_password_reset_token_store::*;
+pub use hashmap_email_verification_token_store::*;
+pub use hashmap_magic_link_token_store::*;
+pub use hashmap_recovery_code_store::*;
+pub use hashmap_passkey_store::*;
+pub use hashmap_passkey_challenge_store::*;
+pub use hashmap_oauth_client_store::*;
+pub use hashmap_authorization_code_store::*;
+pub use hashmap_session_store::*;
+pub use hashmap_login_throttle_store::*;
+pub use hashmap_jwt_key_store::*;
 pub use mock_email_client::*;
 pub use postmark_email_client::*;
 pub use redis_banned_token_store::*;
 pub use postgres_user_store::*;
+pub use postgres_recovery_code_store::*;
+pub use postgres_passkey_store::*;
+pub use postgres_oauth_client_store::*;
+pub use postgres_jwt_key_store::*;
 pub use redis_two_fa_code_store::*;
+pub use redis_refresh_token_store::*;
+pub use redis_password_reset_token_store::*;
+pub use redis_email_verification_token_store::*;
+pub use redis_magic_link_token_store::*;
+pub use redis_authorization_code_store::*;
+pub use redis_passkey_challenge_store::*;
+pub use redis_session_store::*;
+pub use redis_login_throttle_store::*;
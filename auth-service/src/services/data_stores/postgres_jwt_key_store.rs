@@ -0,0 +1,133 @@
+use std::str::FromStr;
+
+use aes_gcm::{aead::Aead, AeadCore, Aes256Gcm, KeyInit};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Context, Result};
+use jsonwebtoken::Algorithm;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::{
+    domain::{JwtKeyStore, JwtKeyStoreError, StoredJwtKey},
+    utils::constants::JWT_KEY_ENCRYPTION_KEY,
+};
+
+pub struct PostgresJwtKeyStore {
+    pool: PgPool,
+}
+
+impl PostgresJwtKeyStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl JwtKeyStore for PostgresJwtKeyStore {
+    #[tracing::instrument(name = "Adding JWT key to PostgreSQL", skip_all)]
+    async fn add_key(&mut self, key: StoredJwtKey) -> Result<(), JwtKeyStoreError> {
+        let private_key = encrypt_private_key(&key.private_key).map_err(JwtKeyStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO jwt_keys (kid, algorithm, private_key, created_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (kid) DO NOTHING
+            "#,
+            key.kid,
+            format!("{:?}", key.algorithm),
+            private_key,
+            key.created_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| JwtKeyStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(JwtKeyStoreError::KeyAlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving JWT keys from PostgreSQL", skip_all)]
+    async fn get_keys(&self) -> Result<Vec<StoredJwtKey>, JwtKeyStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT kid, algorithm, private_key, created_at
+            FROM jwt_keys
+            ORDER BY created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| JwtKeyStoreError::UnexpectedError(e.into()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(StoredJwtKey {
+                    kid: row.kid,
+                    algorithm: Algorithm::from_str(&row.algorithm)
+                        .map_err(|e| JwtKeyStoreError::UnexpectedError(e.into()))?,
+                    private_key: decrypt_private_key(&row.private_key).map_err(JwtKeyStoreError::UnexpectedError)?,
+                    created_at: row.created_at,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Removing replaced JWT keys from PostgreSQL", skip_all)]
+    async fn remove_keys_replaced_before(&mut self, cutoff: DateTime<Utc>) -> Result<(), JwtKeyStoreError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM jwt_keys
+            WHERE EXISTS (
+                SELECT 1 FROM jwt_keys AS newer
+                WHERE newer.created_at > jwt_keys.created_at AND newer.created_at <= $1
+            )
+            "#,
+            cutoff,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| JwtKeyStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+}
+
+// Like TOTP secrets, private keys have to be recoverable, so they are encrypted rather than
+// hashed. The stored value is the random nonce followed by the ciphertext.
+#[tracing::instrument(name = "Encrypting JWT private key", skip_all)]
+fn encrypt_private_key(private_key: &Secret<String>) -> Result<Vec<u8>> {
+    let cipher = get_jwt_key_cipher();
+    let nonce = Aes256Gcm::generate_nonce(&mut rand::thread_rng());
+    let ciphertext = cipher
+        .encrypt(&nonce, private_key.expose_secret().as_bytes())
+        .map_err(|_| eyre!("failed to encrypt JWT private key"))?;
+
+    Ok([&nonce[..], &ciphertext].concat())
+}
+
+#[tracing::instrument(name = "Decrypting JWT private key", skip_all)]
+fn decrypt_private_key(encrypted: &[u8]) -> Result<Secret<String>> {
+    const NONCE_LENGTH: usize = 12;
+    if encrypted.len() <= NONCE_LENGTH {
+        return Err(eyre!("encrypted JWT private key is too short"));
+    }
+
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+    let plaintext = get_jwt_key_cipher()
+        .decrypt(nonce.into(), ciphertext)
+        .map_err(|_| eyre!("failed to decrypt JWT private key"))?;
+    let private_key = String::from_utf8(plaintext).wrap_err("decrypted JWT private key is not valid UTF-8")?;
+
+    Ok(Secret::new(private_key))
+}
+
+// JWT_KEY_ENCRYPTION_KEY is an arbitrary string like TOTP_ENCRYPTION_KEY, so derive a 256-bit key from it
+fn get_jwt_key_cipher() -> Aes256Gcm {
+    let key = Sha256::digest(JWT_KEY_ENCRYPTION_KEY.expose_secret().as_bytes());
+    Aes256Gcm::new(&key)
+}
@@ -0,0 +1,102 @@
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::domain::{constant_time_eq, ClientSecret, OAuthClient, OAuthClientStore, OAuthClientStoreError};
+
+pub struct PostgresOAuthClientStore {
+    pool: PgPool,
+}
+
+impl PostgresOAuthClientStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthClientStore for PostgresOAuthClientStore {
+    #[tracing::instrument(name = "Adding OAuth client to PostgreSQL", skip_all)]
+    async fn add_client(&mut self, client: OAuthClient, secret: ClientSecret) -> Result<(), OAuthClientStoreError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO oauth_clients (client_id, name, secret_hash, redirect_uris)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (client_id) DO NOTHING
+            "#,
+            client.client_id,
+            client.name,
+            compute_client_secret_hash(&secret),
+            &client.redirect_uris,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| OAuthClientStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(OAuthClientStoreError::ClientAlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Authenticating OAuth client in PostgreSQL", skip_all)]
+    async fn authenticate_client(
+        &self,
+        client_id: &str,
+        secret: &ClientSecret,
+    ) -> Result<OAuthClient, OAuthClientStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT client_id, name, secret_hash, redirect_uris
+            FROM oauth_clients
+            WHERE client_id = $1
+            "#,
+            client_id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| OAuthClientStoreError::UnexpectedError(e.into()))?
+        .ok_or(OAuthClientStoreError::InvalidCredentials)?;
+
+        let secret_hash = compute_client_secret_hash(secret);
+        if !constant_time_eq(row.secret_hash, secret_hash) {
+            return Err(OAuthClientStoreError::InvalidCredentials);
+        }
+
+        Ok(OAuthClient {
+            client_id: row.client_id,
+            name: row.name,
+            redirect_uris: row.redirect_uris,
+        })
+    }
+
+    #[tracing::instrument(name = "Retrieving OAuth client from PostgreSQL", skip_all)]
+    async fn get_client(&self, client_id: &str) -> Result<OAuthClient, OAuthClientStoreError> {
+        sqlx::query!(
+            r#"
+            SELECT client_id, name, redirect_uris
+            FROM oauth_clients
+            WHERE client_id = $1
+            "#,
+            client_id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| OAuthClientStoreError::UnexpectedError(e.into()))?
+        .map(|row| OAuthClient {
+            client_id: row.client_id,
+            name: row.name,
+            redirect_uris: row.redirect_uris,
+        })
+        .ok_or(OAuthClientStoreError::ClientNotFound)
+    }
+}
+
+// Client secrets are long random strings, so like recovery codes a fast unsalted hash is enough
+fn compute_client_secret_hash(secret: &ClientSecret) -> String {
+    Sha256::digest(secret.as_ref().expose_secret().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
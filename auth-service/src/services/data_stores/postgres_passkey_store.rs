@@ -0,0 +1,100 @@
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+use crate::domain::{Email, PasskeyCredential, PasskeyStore, PasskeyStoreError};
+
+pub struct PostgresPasskeyStore {
+    pool: PgPool,
+}
+
+impl PostgresPasskeyStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for PostgresPasskeyStore {
+    #[tracing::instrument(name = "Adding passkey to PostgreSQL", skip_all)]
+    async fn add_credential(
+        &mut self,
+        email: &Email,
+        credential: PasskeyCredential,
+    ) -> Result<(), PasskeyStoreError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO passkey_credentials (credential_id, email, public_key, sign_count)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (credential_id) DO NOTHING
+            "#,
+            credential.credential_id,
+            email.as_ref().expose_secret(),
+            credential.public_key,
+            i64::from(credential.sign_count),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(PasskeyStoreError::CredentialAlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving passkeys from PostgreSQL", skip_all)]
+    async fn get_credentials(&self, email: &Email) -> Result<Vec<PasskeyCredential>, PasskeyStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT credential_id, public_key, sign_count
+            FROM passkey_credentials
+            WHERE email = $1
+            ORDER BY created_at
+            "#,
+            email.as_ref().expose_secret(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(PasskeyCredential {
+                    credential_id: row.credential_id,
+                    public_key: row.public_key,
+                    sign_count: u32::try_from(row.sign_count)
+                        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Updating passkey sign count in PostgreSQL", skip_all)]
+    async fn update_sign_count(
+        &mut self,
+        email: &Email,
+        credential_id: &str,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE passkey_credentials
+            SET sign_count = $3, last_used_at = NOW()
+            WHERE email = $1 AND credential_id = $2
+            "#,
+            email.as_ref().expose_secret(),
+            credential_id,
+            i64::from(sign_count),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(PasskeyStoreError::CredentialNotFound);
+        }
+
+        Ok(())
+    }
+}
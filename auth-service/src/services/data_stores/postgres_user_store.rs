@@ -1,13 +1,20 @@
-use color_eyre::eyre::{Context, Result};
+use std::time::Duration;
+
+use aes_gcm::{aead::Aead, AeadCore, Aes256Gcm, KeyInit};
+use color_eyre::eyre::{eyre, Context, Result};
 use secrecy::{ExposeSecret, Secret};
-use argon2::{
-    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
-    PasswordVerifier, Version,
-};
 
+use sha2::{Digest, Sha256};
 use sqlx::PgPool;
+use tracing::Instrument;
 
-use crate::domain::{UserStore, UserStoreError,Email, Password, User,};
+use crate::{
+    domain::{AccountDeletionToken, ImportedPasswordHash, UserStore, UserStoreError,Email, Password, TotpSecret, TwoFAMethod, User,},
+    utils::{
+        constants::{PASSWORD_HISTORY_DEPTH, TOTP_ENCRYPTION_KEY},
+        password_hash::{compute_password_hash, needs_rehash, verify_password_hash, DUMMY_PASSWORD_HASH},
+    },
+};
 
 pub struct PostgresUserStore {
     pool: PgPool,
@@ -29,16 +36,53 @@ impl UserStore for PostgresUserStore {
 
         sqlx::query!(
             r#"
-            INSERT INTO users (email, password_hash, requires_2fa)
-            VALUES ($1, $2, $3)
+            INSERT INTO users (email, password_hash, two_fa_method, verified)
+            VALUES ($1, $2, $3, $4)
             "#,
             user.email.as_ref().expose_secret(),
             &password_hash.expose_secret(),
-            user.requires_2fa,
+            user.two_fa_method.as_ref(),
+            user.verified,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Importing user into PostgreSQL", skip_all)]
+    async fn import_user(
+        &mut self,
+        email: Email,
+        password_hash: ImportedPasswordHash,
+        two_fa_method: TwoFAMethod,
+    ) -> Result<(), UserStoreError> {
+        // Imported accounts were already in use on the old system, so they aren't asked to
+        // verify their email address again
+        sqlx::query!(
+            r#"
+            INSERT INTO users (email, password_hash, two_fa_method, verified)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            email.as_ref().expose_secret(),
+            password_hash.as_ref().expose_secret(),
+            two_fa_method.as_ref(),
+            true,
         )
         .execute(&self.pool)
         .await
-        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?; // Updated!
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
 
         Ok(())
     }
@@ -47,7 +91,8 @@ impl UserStore for PostgresUserStore {
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
         sqlx::query!(
             r#"
-            SELECT email, password_hash, requires_2fa
+            SELECT email, password_hash, two_fa_method, verified, totp_secret, disabled,
+                delete_after IS NOT NULL AS "pending_deletion!"
             FROM users
             WHERE email = $1
             "#,
@@ -61,7 +106,16 @@ impl UserStore for PostgresUserStore {
                 email: Email::parse(Secret::new(row.email)).map_err(UserStoreError::UnexpectedError)?,
                 password: Password::parse(Secret::new(row.password_hash))
                     .map_err(UserStoreError::UnexpectedError)?,
-                requires_2fa: row.requires_2fa,
+                two_fa_method: TwoFAMethod::parse(&row.two_fa_method)
+                    .map_err(UserStoreError::UnexpectedError)?,
+                verified: row.verified,
+                totp_secret: row
+                    .totp_secret
+                    .map(|encrypted| decrypt_totp_secret(&encrypted))
+                    .transpose()
+                    .map_err(UserStoreError::UnexpectedError)?,
+                disabled: row.disabled,
+                pending_deletion: row.pending_deletion,
             })
         })
         .ok_or(UserStoreError::UserNotFound)?
@@ -69,60 +123,410 @@ impl UserStore for PostgresUserStore {
 
     #[tracing::instrument(name = "Validating user credentials in PostgreSQL", skip_all)] // New!
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
-        let user = self.get_user(email).await?;
+        let user = match self.get_user(email).await {
+            Ok(user) => user,
+            Err(UserStoreError::UserNotFound) => {
+                // Spend the same Argon2 work as for a wrong password, so the response time
+                // doesn't reveal whether the account exists
+                let _ = verify_password_hash(
+                    DUMMY_PASSWORD_HASH.clone(),
+                    password.as_ref().to_owned(),
+                )
+                .await;
+                return Err(UserStoreError::UserNotFound);
+            }
+            Err(e) => return Err(e),
+        };
 
-        match verify_password_hash(
+        if verify_password_hash(
             user.password.as_ref().to_owned(), // Updated!
             password.as_ref().to_owned(),      // Updated!
         )
         .await
+        .is_err()
         {
-            Ok(_) => Ok(()),
-            Err(_) => Err(UserStoreError::InvalidCredentials),
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        // Hashes made with older settings are upgraded while we have the password to hand.
+        // It's done in the background so the login doesn't wait on a second hash.
+        if needs_rehash(user.password.as_ref().expose_secret()) {
+            let pool = self.pool.clone();
+            let email = email.clone();
+            let old_hash = user.password.as_ref().to_owned();
+            let password = password.as_ref().to_owned();
+            tokio::spawn(
+                async move {
+                    if let Err(e) = upgrade_password_hash(&pool, &email, old_hash, password).await {
+                        tracing::warn!("failed to upgrade password hash: {:?}", e);
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Checking password history in PostgreSQL", skip_all)]
+    async fn is_password_reused(&self, email: &Email, password: &Password) -> Result<bool, UserStoreError> {
+        if *PASSWORD_HISTORY_DEPTH == 0 {
+            return Ok(false);
+        }
+
+        let user = self.get_user(email).await?;
+        let previous_hashes = sqlx::query!(
+            r#"
+            SELECT password_hash
+            FROM password_history
+            WHERE email = $1
+            ORDER BY id DESC
+            LIMIT $2
+            "#,
+            email.as_ref().expose_secret(),
+            PASSWORD_HISTORY_DEPTH.saturating_sub(1) as i64,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(|row| Secret::new(row.password_hash));
+
+        for previous_hash in std::iter::once(user.password.as_ref().to_owned()).chain(previous_hashes) {
+            if verify_password_hash(previous_hash, password.as_ref().to_owned()).await.is_ok() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    #[tracing::instrument(name = "Updating user password in PostgreSQL", skip_all)]
+    async fn update_password(&mut self, email: &Email, password: Password) -> Result<(), UserStoreError> {
+        // The current password counts towards the history depth, so only the rest is kept in
+        // password_history
+        let kept_history = PASSWORD_HISTORY_DEPTH.saturating_sub(1) as i64;
+
+        if self.is_password_reused(email, &password).await? {
+            return Err(UserStoreError::PasswordReused);
+        }
+
+        let password_hash = compute_password_hash(password.as_ref().to_owned())
+            .await
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        // Copied over in the same statement so a hash that is upgraded in the meantime isn't lost
+        if kept_history > 0 {
+            sqlx::query!(
+                r#"
+                INSERT INTO password_history (email, password_hash)
+                SELECT email, password_hash FROM users
+                WHERE email = $1
+                "#,
+                email.as_ref().expose_secret(),
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        }
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_hash = $1
+            WHERE email = $2
+            "#,
+            &password_hash.expose_secret(),
+            email.as_ref().expose_secret(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        // Also clears out what a larger PASSWORD_HISTORY_DEPTH kept before it was lowered
+        sqlx::query!(
+            r#"
+            DELETE FROM password_history
+            WHERE email = $1 AND id NOT IN (
+                SELECT id FROM password_history
+                WHERE email = $1
+                ORDER BY id DESC
+                LIMIT $2
+            )
+            "#,
+            email.as_ref().expose_secret(),
+            kept_history,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Marking user email as verified in PostgreSQL", skip_all)]
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET verified = TRUE
+            WHERE email = $1
+            "#,
+            email.as_ref().expose_secret(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Storing TOTP secret in PostgreSQL", skip_all)]
+    async fn set_totp_secret(&mut self, email: &Email, secret: TotpSecret) -> Result<(), UserStoreError> {
+        let encrypted_secret = encrypt_totp_secret(&secret).map_err(UserStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_secret = $1
+            WHERE email = $2
+            "#,
+            encrypted_secret,
+            email.as_ref().expose_secret(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Recording TOTP step in PostgreSQL", skip_all)]
+    async fn record_totp_step(&mut self, email: &Email, step: u64) -> Result<(), UserStoreError> {
+        let step = i64::try_from(step).map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        // Checked and updated in one statement, so two requests racing with the same code
+        // can't both get through
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET last_totp_step = $1
+            WHERE email = $2 AND (last_totp_step IS NULL OR last_totp_step < $1)
+            "#,
+            step,
+            email.as_ref().expose_secret(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        // Only users who have enrolled TOTP get this far, so nothing updated means a replay
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::TotpCodeReused);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Updating user 2FA method in PostgreSQL", skip_all)]
+    async fn set_two_fa_method(&mut self, email: &Email, method: TwoFAMethod) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET two_fa_method = $1
+            WHERE email = $2
+            "#,
+            method.as_ref(),
+            email.as_ref().expose_secret(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Updating user disabled flag in PostgreSQL", skip_all)]
+    async fn set_disabled(&mut self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET disabled = $1
+            WHERE email = $2
+            "#,
+            disabled,
+            email.as_ref().expose_secret(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Scheduling user deletion in PostgreSQL", skip_all)]
+    async fn schedule_deletion(
+        &mut self,
+        email: &Email,
+        cancel_token: AccountDeletionToken,
+        grace_period: Duration,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET delete_after = NOW() + make_interval(secs => $1), deletion_cancel_token_hash = $2
+            WHERE email = $3
+            "#,
+            grace_period.as_secs_f64(),
+            compute_deletion_token_hash(&cancel_token),
+            email.as_ref().expose_secret(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
         }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Cancelling user deletion in PostgreSQL", skip_all)]
+    async fn cancel_deletion(&mut self, cancel_token: &AccountDeletionToken) -> Result<Email, UserStoreError> {
+        let row = sqlx::query!(
+            r#"
+            UPDATE users
+            SET delete_after = NULL, deletion_cancel_token_hash = NULL
+            WHERE deletion_cancel_token_hash = $1
+            RETURNING email
+            "#,
+            compute_deletion_token_hash(cancel_token),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        Email::parse(Secret::new(row.email)).map_err(UserStoreError::UnexpectedError)
+    }
+
+    // The user's recovery codes, passkeys and password history go with them, through ON DELETE CASCADE
+    #[tracing::instrument(name = "Deleting expired users from PostgreSQL", skip_all)]
+    async fn delete_expired_users(&mut self) -> Result<Vec<Email>, UserStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            DELETE FROM users
+            WHERE delete_after <= NOW()
+            RETURNING email
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        rows.into_iter()
+            .map(|row| Email::parse(Secret::new(row.email)).map_err(UserStoreError::UnexpectedError))
+            .collect()
     }
 }
 
-#[tracing::instrument(name = "Verify password hash", skip_all)]
-async fn verify_password_hash(
-    expected_password_hash: Secret<String>, // Updated!
-    password_candidate: Secret<String>, // Updated!
-) -> Result<()> { // Changed!
-    let current_span: tracing::Span = tracing::Span::current();
-    let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
-            let expected_password_hash: PasswordHash<'_> =
-                PasswordHash::new(&expected_password_hash.expose_secret())?;
-
-            Argon2::default()
-                .verify_password(password_candidate.expose_secret().as_bytes(), &expected_password_hash)
-                .wrap_err("failed to verify password hash")
-        })
-    })
-    .await;
+// Cancel tokens are long random strings, so like client secrets a fast unsalted hash is enough
+fn compute_deletion_token_hash(token: &AccountDeletionToken) -> String {
+    Sha256::digest(token.as_ref().expose_secret().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
 
-    result?
+// TOTP secrets have to be recoverable to check codes, so unlike passwords they are
+// encrypted rather than hashed. The stored value is the random nonce followed by the ciphertext.
+#[tracing::instrument(name = "Encrypting TOTP secret", skip_all)]
+fn encrypt_totp_secret(secret: &TotpSecret) -> Result<Vec<u8>> {
+    let cipher = get_totp_cipher();
+    let nonce = Aes256Gcm::generate_nonce(&mut rand::thread_rng());
+    let ciphertext = cipher
+        .encrypt(&nonce, secret.as_ref().expose_secret().as_bytes())
+        .map_err(|_| eyre!("failed to encrypt TOTP secret"))?;
+
+    Ok([&nonce[..], &ciphertext].concat())
 }
 
-#[tracing::instrument(name = "Computing password hash", skip_all)]
-async fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> { // Updated!
-    let current_span: tracing::Span = tracing::Span::current();
-
-    let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
-            let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
-            let password_hash = Argon2::new(
-                Algorithm::Argon2id,
-                Version::V0x13,
-                Params::new(15000, 2, 1, None)?,
-            )
-            .hash_password(password.expose_secret().as_bytes(), &salt)?
-            .to_string();
+#[tracing::instrument(name = "Decrypting TOTP secret", skip_all)]
+fn decrypt_totp_secret(encrypted: &[u8]) -> Result<TotpSecret> {
+    const NONCE_LENGTH: usize = 12;
+    if encrypted.len() <= NONCE_LENGTH {
+        return Err(eyre!("encrypted TOTP secret is too short"));
+    }
 
-            Ok(Secret::new(password_hash)) // Updated!
-        })
-    })
-    .await;
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+    let plaintext = get_totp_cipher()
+        .decrypt(nonce.into(), ciphertext)
+        .map_err(|_| eyre!("failed to decrypt TOTP secret"))?;
+    let secret = String::from_utf8(plaintext).wrap_err("decrypted TOTP secret is not valid UTF-8")?;
+
+    TotpSecret::parse(Secret::new(secret))
+}
+
+// TOTP_ENCRYPTION_KEY is an arbitrary string like JWT_SECRET, so derive a 256-bit key from it
+fn get_totp_cipher() -> Aes256Gcm {
+    let key = Sha256::digest(TOTP_ENCRYPTION_KEY.expose_secret().as_bytes());
+    Aes256Gcm::new(&key)
+}
+
+#[tracing::instrument(name = "Upgrading password hash in PostgreSQL", skip_all)]
+async fn upgrade_password_hash(
+    pool: &PgPool,
+    email: &Email,
+    old_hash: Secret<String>,
+    password: Secret<String>,
+) -> Result<()> {
+    let new_hash = compute_password_hash(password).await?;
+
+    // Only swap out the hash that was checked, so a password changed in the meantime is kept
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE email = $2 AND password_hash = $3
+        "#,
+        new_hash.expose_secret(),
+        email.as_ref().expose_secret(),
+        old_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await?;
 
-    result?
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::ExposeSecret;
+use tokio::sync::RwLock;
+
+use crate::{
+    domain::{AuthorizationCode, AuthorizationCodeStore, AuthorizationCodeStoreError, AuthorizationGrant},
+    utils::auth::AUTHORIZATION_CODE_TTL_SECONDS,
+};
+
+pub struct RedisAuthorizationCodeStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisAuthorizationCodeStore {
+    #[tracing::instrument(name = "Creating Redis authorization code store", skip_all)]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthorizationCodeStore for RedisAuthorizationCodeStore {
+    #[tracing::instrument(name = "Adding authorization code", skip_all)]
+    async fn add_code(
+        &mut self,
+        code: AuthorizationCode,
+        grant: AuthorizationGrant,
+    ) -> Result<(), AuthorizationCodeStoreError> {
+        let key = get_key(&code);
+        let serialized_grant = serde_json::to_string(&grant)
+            .wrap_err("failed to serialize authorization grant")
+            .map_err(AuthorizationCodeStoreError::UnexpectedError)?;
+
+        let ttl: u64 = AUTHORIZATION_CODE_TTL_SECONDS
+            .try_into()
+            .wrap_err("failed to cast AUTHORIZATION_CODE_TTL_SECONDS to u64")
+            .map_err(AuthorizationCodeStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(&key, serialized_grant, ttl)
+            .wrap_err("failed to set authorization code in Redis")
+            .map_err(AuthorizationCodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Consuming authorization code", skip_all)]
+    async fn consume_code(
+        &mut self,
+        code: &AuthorizationCode,
+    ) -> Result<AuthorizationGrant, AuthorizationCodeStoreError> {
+        let key = get_key(code);
+
+        // GETDEL reads and removes the code in one step, so it can never be redeemed twice
+        let serialized_grant: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(&key)
+            .wrap_err("failed to consume authorization code in Redis")
+            .map_err(AuthorizationCodeStoreError::UnexpectedError)?;
+
+        match serialized_grant {
+            Some(serialized_grant) => serde_json::from_str(&serialized_grant)
+                .wrap_err("failed to deserialize authorization grant")
+                .map_err(AuthorizationCodeStoreError::UnexpectedError),
+            None => Err(AuthorizationCodeStoreError::CodeNotFound),
+        }
+    }
+}
+
+const AUTHORIZATION_CODE_PREFIX: &str = "authorization_code:";
+
+#[tracing::instrument(name = "Generating authorization code key", skip_all)]
+fn get_key(code: &AuthorizationCode) -> String {
+    format!("{}{}", AUTHORIZATION_CODE_PREFIX, code.as_ref().expose_secret())
+}
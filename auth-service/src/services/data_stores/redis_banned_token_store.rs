@@ -1,12 +1,12 @@
 use std::sync::Arc;
 use color_eyre::eyre::{Context, Result};
 use redis::{Commands, Connection};
-use secrecy::{ExposeSecret, Secret};
+use secrecy::ExposeSecret;
 use tokio::sync::RwLock;
 
 use crate::{
-    domain::{BannedTokenStore, BannedTokenStoreError},
-    utils::auth::TOKEN_TTL_SECONDS,
+    domain::{BannedTokenStore, BannedTokenStoreError, Email},
+    utils::{auth::TOKEN_TTL_SECONDS, constants::JWT_LEEWAY_SECONDS},
 };
 
 pub struct RedisBannedTokenStore {
@@ -24,13 +24,15 @@ impl RedisBannedTokenStore {
 impl BannedTokenStore for RedisBannedTokenStore {
 
     #[tracing::instrument(name = "Storing banned token", skip_all)]
-    async fn store_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
-        let key = get_key(token.expose_secret());
+    async fn store_token(&mut self, jti: String) -> Result<(), BannedTokenStoreError> {
+        let key = get_key(&jti);
 
+        // A token is accepted for up to the leeway past its expiry, so it stays banned that long too
         let ttl: u64 = TOKEN_TTL_SECONDS
             .try_into()
             .wrap_err("failed to cast TOKEN_TTL_SECONDS to u64") // New!
             .map_err(BannedTokenStoreError::UnexpectedError)?; // Updated!
+        let ttl = ttl + *JWT_LEEWAY_SECONDS;
 
         let _: () = self
             .conn
@@ -44,9 +46,9 @@ impl BannedTokenStore for RedisBannedTokenStore {
     }
 
     #[tracing::instrument(name = "Checking if token is banned", skip_all)]
-    async fn is_token_banned(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {        
+    async fn is_token_banned(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
         // Check if the token exists by calling the exists method on the Redis connection
-        let key = get_key(token.expose_secret());
+        let key = get_key(jti);
         
         let is_banned: bool = self
             .conn
@@ -59,6 +61,38 @@ impl BannedTokenStore for RedisBannedTokenStore {
         Ok(is_banned)
     }
 
+    #[tracing::instrument(name = "Getting user token generation", skip_all)]
+    async fn get_token_generation(&self, email: &Email) -> Result<u64, BannedTokenStoreError> {
+        let key = get_token_generation_key(email);
+
+        let generation: Option<u64> = self
+            .conn
+            .write()
+            .await
+            .get(&key)
+            .wrap_err("failed to get token generation from Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(generation.unwrap_or_default())
+    }
+
+    // The generation key has no TTL: if it expired, tokens minted before the last
+    // revocation would carry a generation that looks current again.
+    #[tracing::instrument(name = "Revoking user tokens", skip_all)]
+    async fn revoke_user_tokens(&mut self, email: &Email) -> Result<(), BannedTokenStoreError> {
+        let key = get_token_generation_key(email);
+
+        let _: u64 = self
+            .conn
+            .write()
+            .await
+            .incr(&key, 1)
+            .wrap_err("failed to increment token generation in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
     fn as_ref(&self) -> &dyn BannedTokenStore {
         self
     }
@@ -68,6 +102,13 @@ impl BannedTokenStore for RedisBannedTokenStore {
 const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
 
 #[tracing::instrument(name = "Generating banned token key", skip_all)]
-fn get_key(token: &str) -> String {
-    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token)
-}
\ No newline at end of file
+fn get_key(jti: &str) -> String {
+    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, jti)
+}
+
+const TOKEN_GENERATION_KEY_PREFIX: &str = "token_generation:";
+
+#[tracing::instrument(name = "Generating token generation key", skip_all)]
+fn get_token_generation_key(email: &Email) -> String {
+    format!("{}{}", TOKEN_GENERATION_KEY_PREFIX, email.as_ref().expose_secret())
+}
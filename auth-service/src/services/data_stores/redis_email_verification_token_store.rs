@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::{ExposeSecret, Secret};
+use tokio::sync::RwLock;
+
+use crate::{
+    domain::{Email, EmailVerificationToken, EmailVerificationTokenStore, EmailVerificationTokenStoreError},
+    utils::auth::EMAIL_VERIFICATION_TOKEN_TTL_SECONDS,
+};
+
+pub struct RedisEmailVerificationTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisEmailVerificationTokenStore {
+    #[tracing::instrument(name = "Creating Redis email verification token store", skip_all)]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailVerificationTokenStore for RedisEmailVerificationTokenStore {
+    #[tracing::instrument(name = "Adding email verification token", skip_all)]
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: EmailVerificationToken,
+    ) -> Result<(), EmailVerificationTokenStoreError> {
+        let key = get_key(&token);
+
+        let ttl: u64 = EMAIL_VERIFICATION_TOKEN_TTL_SECONDS
+            .try_into()
+            .wrap_err("failed to cast EMAIL_VERIFICATION_TOKEN_TTL_SECONDS to u64")
+            .map_err(EmailVerificationTokenStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(&key, email.as_ref().expose_secret(), ttl)
+            .wrap_err("failed to set email verification token in Redis")
+            .map_err(EmailVerificationTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Consuming email verification token", skip_all)]
+    async fn consume_token(
+        &mut self,
+        token: &EmailVerificationToken,
+    ) -> Result<Email, EmailVerificationTokenStoreError> {
+        let key = get_key(token);
+
+        // GETDEL reads and removes the token in one step, so it can never be used twice
+        let email: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(&key)
+            .wrap_err("failed to consume email verification token in Redis")
+            .map_err(EmailVerificationTokenStoreError::UnexpectedError)?;
+
+        match email {
+            Some(email) => Email::parse(Secret::new(email))
+                .map_err(EmailVerificationTokenStoreError::UnexpectedError),
+            None => Err(EmailVerificationTokenStoreError::TokenNotFound),
+        }
+    }
+}
+
+const EMAIL_VERIFICATION_TOKEN_PREFIX: &str = "email_verification_token:";
+
+#[tracing::instrument(name = "Generating email verification token key", skip_all)]
+fn get_key(token: &EmailVerificationToken) -> String {
+    format!("{}{}", EMAIL_VERIFICATION_TOKEN_PREFIX, token.as_ref().expose_secret())
+}
@@ -0,0 +1,140 @@
+use std::{sync::Arc, time::Duration};
+use chrono::Utc;
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    domain::{LoginThrottleStore, LoginThrottleStoreError},
+    services::data_stores::HashmapLoginThrottleStore,
+};
+
+// Login throttling keeps working while Redis is unreachable: the counts fall back to this
+// process's memory, which is per instance and lost on restart but better than no limit at all
+pub struct RedisLoginThrottleStore {
+    conn: Arc<RwLock<Connection>>,
+    fallback: HashmapLoginThrottleStore,
+}
+
+impl RedisLoginThrottleStore {
+    #[tracing::instrument(name = "Creating Redis login throttle store", skip_all)]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self {
+            conn,
+            fallback: HashmapLoginThrottleStore::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginThrottleStore for RedisLoginThrottleStore {
+    #[tracing::instrument(name = "Recording login attempt", skip_all)]
+    async fn record_attempt(&mut self, key: &str, window: Duration) -> Result<u32, LoginThrottleStoreError> {
+        let redis_key = get_attempts_key(key);
+        let now = Utc::now().timestamp_millis();
+        let window_start = now - window.as_millis() as i64;
+        // Members must be unique, or two attempts in the same millisecond would count as one
+        let member = format!("{}:{}", now, Uuid::new_v4());
+
+        // A sorted set of attempt timestamps: drop the ones that have slid out of the window,
+        // add this one, and count what is left
+        let result: redis::RedisResult<(u32,)> = redis::pipe()
+            .atomic()
+            .zrembyscore(&redis_key, "-inf", window_start)
+            .ignore()
+            .zadd(&redis_key, member, now)
+            .ignore()
+            .zcard(&redis_key)
+            .pexpire(&redis_key, window.as_millis() as i64)
+            .ignore()
+            .query(&mut *self.conn.write().await);
+
+        match result.wrap_err("failed to record login attempt in Redis") {
+            Ok((count,)) => Ok(count),
+            Err(e) => {
+                tracing::warn!("falling back to in-memory login throttling: {:?}", e);
+                self.fallback.record_attempt(key, window).await
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "Recording login failure", skip_all)]
+    async fn record_failure(&mut self, key: &str, ttl: Duration) -> Result<u32, LoginThrottleStoreError> {
+        let redis_key = get_failures_key(key);
+
+        let result: redis::RedisResult<(u32,)> = redis::pipe()
+            .atomic()
+            .incr(&redis_key, 1)
+            .pexpire(&redis_key, ttl.as_millis() as i64)
+            .ignore()
+            .query(&mut *self.conn.write().await);
+
+        match result.wrap_err("failed to record login failure in Redis") {
+            Ok((count,)) => Ok(count),
+            Err(e) => {
+                tracing::warn!("falling back to in-memory login throttling: {:?}", e);
+                self.fallback.record_failure(key, ttl).await
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "Resetting login failures", skip_all)]
+    async fn reset_failures(&mut self, key: &str) -> Result<(), LoginThrottleStoreError> {
+        let result: redis::RedisResult<()> = self.conn.write().await.del(get_failures_key(key));
+
+        if let Err(e) = result.wrap_err("failed to reset login failures in Redis") {
+            tracing::warn!("falling back to in-memory login throttling: {:?}", e);
+        }
+        // Failures may have been counted in memory while Redis was down
+        self.fallback.reset_failures(key).await
+    }
+
+    #[tracing::instrument(name = "Locking login", skip_all)]
+    async fn lock(&mut self, key: &str, duration: Duration) -> Result<(), LoginThrottleStoreError> {
+        let result: redis::RedisResult<()> = self
+            .conn
+            .write()
+            .await
+            .pset_ex(get_lock_key(key), 1, duration.as_millis() as u64);
+
+        match result.wrap_err("failed to lock login in Redis") {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("falling back to in-memory login throttling: {:?}", e);
+                self.fallback.lock(key, duration).await
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "Getting login lock", skip_all)]
+    async fn get_lock(&self, key: &str) -> Result<Option<Duration>, LoginThrottleStoreError> {
+        // PTTL is negative when the key does not exist or has no expiry
+        let result: redis::RedisResult<i64> = self.conn.write().await.pttl(get_lock_key(key));
+
+        match result.wrap_err("failed to get login lock from Redis") {
+            Ok(remaining) if remaining > 0 => Ok(Some(Duration::from_millis(remaining as u64))),
+            Ok(_) => self.fallback.get_lock(key).await,
+            Err(e) => {
+                tracing::warn!("falling back to in-memory login throttling: {:?}", e);
+                self.fallback.get_lock(key).await
+            }
+        }
+    }
+}
+
+const LOGIN_ATTEMPTS_PREFIX: &str = "login_attempts:";
+const LOGIN_FAILURES_PREFIX: &str = "login_failures:";
+const LOGIN_LOCK_PREFIX: &str = "login_lock:";
+
+fn get_attempts_key(key: &str) -> String {
+    format!("{}{}", LOGIN_ATTEMPTS_PREFIX, key)
+}
+
+fn get_failures_key(key: &str) -> String {
+    format!("{}{}", LOGIN_FAILURES_PREFIX, key)
+}
+
+fn get_lock_key(key: &str) -> String {
+    format!("{}{}", LOGIN_LOCK_PREFIX, key)
+}
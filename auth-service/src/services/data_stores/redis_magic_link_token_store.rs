@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::{ExposeSecret, Secret};
+use tokio::sync::RwLock;
+
+use crate::{
+    domain::{Email, MagicLinkToken, MagicLinkTokenStore, MagicLinkTokenStoreError},
+    utils::auth::MAGIC_LINK_TOKEN_TTL_SECONDS,
+};
+
+pub struct RedisMagicLinkTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisMagicLinkTokenStore {
+    #[tracing::instrument(name = "Creating Redis magic link token store", skip_all)]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl MagicLinkTokenStore for RedisMagicLinkTokenStore {
+    #[tracing::instrument(name = "Adding magic link token", skip_all)]
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: MagicLinkToken,
+    ) -> Result<(), MagicLinkTokenStoreError> {
+        let key = get_key(&token);
+
+        let ttl: u64 = MAGIC_LINK_TOKEN_TTL_SECONDS
+            .try_into()
+            .wrap_err("failed to cast MAGIC_LINK_TOKEN_TTL_SECONDS to u64")
+            .map_err(MagicLinkTokenStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(&key, email.as_ref().expose_secret(), ttl)
+            .wrap_err("failed to set magic link token in Redis")
+            .map_err(MagicLinkTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Consuming magic link token", skip_all)]
+    async fn consume_token(
+        &mut self,
+        token: &MagicLinkToken,
+    ) -> Result<Email, MagicLinkTokenStoreError> {
+        let key = get_key(token);
+
+        // GETDEL reads and removes the token in one step, so it can never be used twice
+        let email: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(&key)
+            .wrap_err("failed to consume magic link token in Redis")
+            .map_err(MagicLinkTokenStoreError::UnexpectedError)?;
+
+        match email {
+            Some(email) => Email::parse(Secret::new(email))
+                .map_err(MagicLinkTokenStoreError::UnexpectedError),
+            None => Err(MagicLinkTokenStoreError::TokenNotFound),
+        }
+    }
+}
+
+const MAGIC_LINK_TOKEN_PREFIX: &str = "magic_link_token:";
+
+#[tracing::instrument(name = "Generating magic link token key", skip_all)]
+fn get_key(token: &MagicLinkToken) -> String {
+    format!("{}{}", MAGIC_LINK_TOKEN_PREFIX, token.as_ref().expose_secret())
+}
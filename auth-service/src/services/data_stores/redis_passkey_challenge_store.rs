@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    domain::{Email, PasskeyCeremonyId, PasskeyChallenge, PasskeyChallengeStore, PasskeyChallengeStoreError},
+    utils::webauthn::PASSKEY_CHALLENGE_TTL_SECONDS,
+};
+
+pub struct RedisPasskeyChallengeStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisPasskeyChallengeStore {
+    #[tracing::instrument(name = "Creating Redis passkey challenge store", skip_all)]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasskeyChallengeStore for RedisPasskeyChallengeStore {
+    #[tracing::instrument(name = "Adding passkey challenge", skip_all)]
+    async fn add_challenge(
+        &mut self,
+        ceremony_id: PasskeyCeremonyId,
+        email: Email,
+        challenge: PasskeyChallenge,
+    ) -> Result<(), PasskeyChallengeStoreError> {
+        let key = get_key(&ceremony_id);
+        let record = PasskeyChallengeRecord {
+            email: email.as_ref().expose_secret().to_owned(),
+            challenge,
+        };
+        let serialized_record = serde_json::to_string(&record)
+            .wrap_err("failed to serialize passkey challenge")
+            .map_err(PasskeyChallengeStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(&key, serialized_record, PASSKEY_CHALLENGE_TTL_SECONDS)
+            .wrap_err("failed to set passkey challenge in Redis")
+            .map_err(PasskeyChallengeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Taking passkey challenge", skip_all)]
+    async fn take_challenge(
+        &mut self,
+        ceremony_id: &PasskeyCeremonyId,
+    ) -> Result<(Email, PasskeyChallenge), PasskeyChallengeStoreError> {
+        let key = get_key(ceremony_id);
+
+        // GETDEL reads and removes the challenge in one step, so it can never be answered twice
+        let serialized_record: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(&key)
+            .wrap_err("failed to take passkey challenge from Redis")
+            .map_err(PasskeyChallengeStoreError::UnexpectedError)?;
+
+        let record: PasskeyChallengeRecord = match serialized_record {
+            Some(serialized_record) => serde_json::from_str(&serialized_record)
+                .wrap_err("failed to deserialize passkey challenge")
+                .map_err(PasskeyChallengeStoreError::UnexpectedError)?,
+            None => return Err(PasskeyChallengeStoreError::ChallengeNotFound),
+        };
+
+        let email = Email::parse(Secret::new(record.email))
+            .wrap_err("failed to parse email of passkey ceremony")
+            .map_err(PasskeyChallengeStoreError::UnexpectedError)?;
+
+        Ok((email, record.challenge))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PasskeyChallengeRecord {
+    email: String,
+    challenge: PasskeyChallenge,
+}
+
+const PASSKEY_CHALLENGE_PREFIX: &str = "passkey_challenge:";
+
+#[tracing::instrument(name = "Generating passkey challenge key", skip_all)]
+fn get_key(ceremony_id: &PasskeyCeremonyId) -> String {
+    format!("{}{}", PASSKEY_CHALLENGE_PREFIX, ceremony_id.as_ref().expose_secret())
+}
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::{ExposeSecret, Secret};
+use tokio::sync::RwLock;
+
+use crate::{
+    domain::{Email, PasswordResetToken, PasswordResetTokenStore, PasswordResetTokenStoreError},
+    utils::auth::{PASSWORD_RESET_MAX_ATTEMPTS, PASSWORD_RESET_TOKEN_TTL_SECONDS},
+};
+
+pub struct RedisPasswordResetTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisPasswordResetTokenStore {
+    #[tracing::instrument(name = "Creating Redis password reset token store", skip_all)]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasswordResetTokenStore for RedisPasswordResetTokenStore {
+    #[tracing::instrument(name = "Adding password reset token", skip_all)]
+    async fn add_token(
+        &mut self,
+        email: Email,
+        token: PasswordResetToken,
+    ) -> Result<(), PasswordResetTokenStoreError> {
+        let key = get_key(&token);
+
+        let ttl: u64 = PASSWORD_RESET_TOKEN_TTL_SECONDS
+            .try_into()
+            .wrap_err("failed to cast PASSWORD_RESET_TOKEN_TTL_SECONDS to u64")
+            .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(&key, email.as_ref().expose_secret(), ttl)
+            .wrap_err("failed to set password reset token in Redis")
+            .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving password reset token", skip_all)]
+    async fn get_email(&self, token: &PasswordResetToken) -> Result<Email, PasswordResetTokenStoreError> {
+        let email: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get(get_key(token))
+            .wrap_err("failed to get password reset token from Redis")
+            .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+
+        match email {
+            Some(email) => Email::parse(Secret::new(email))
+                .map_err(PasswordResetTokenStoreError::UnexpectedError),
+            None => Err(PasswordResetTokenStoreError::TokenNotFound),
+        }
+    }
+
+    #[tracing::instrument(name = "Recording failed password reset attempt", skip_all)]
+    async fn record_failed_attempt(&mut self, token: &PasswordResetToken) -> Result<(), PasswordResetTokenStoreError> {
+        let key = get_key(token);
+        let attempts_key = get_attempts_key(token);
+        let mut conn = self.conn.write().await;
+
+        // The counter expires along with the token, which keeps the expiry it was created with
+        let ttl_ms: i64 = conn
+            .pttl(&key)
+            .wrap_err("failed to get password reset token TTL from Redis")
+            .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+        if ttl_ms <= 0 {
+            return Err(PasswordResetTokenStoreError::TokenNotFound);
+        }
+
+        let (failed_attempts,): (u32,) = redis::pipe()
+            .atomic()
+            .incr(&attempts_key, 1)
+            .pexpire(&attempts_key, ttl_ms)
+            .ignore()
+            .query(&mut *conn)
+            .wrap_err("failed to record failed password reset attempt in Redis")
+            .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+
+        if failed_attempts >= PASSWORD_RESET_MAX_ATTEMPTS {
+            let _: () = conn
+                .del(vec![key, attempts_key])
+                .wrap_err("failed to delete password reset token from Redis")
+                .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Consuming password reset token", skip_all)]
+    async fn consume_token(
+        &mut self,
+        token: &PasswordResetToken,
+    ) -> Result<Email, PasswordResetTokenStoreError> {
+        let key = get_key(token);
+
+        // GETDEL reads and removes the token in one step, so it can never be used twice
+        let email: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(&key)
+            .wrap_err("failed to consume password reset token in Redis")
+            .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+
+        match email {
+            Some(email) => Email::parse(Secret::new(email))
+                .map_err(PasswordResetTokenStoreError::UnexpectedError),
+            None => Err(PasswordResetTokenStoreError::TokenNotFound),
+        }
+    }
+}
+
+const PASSWORD_RESET_TOKEN_PREFIX: &str = "password_reset_token:";
+const PASSWORD_RESET_ATTEMPTS_PREFIX: &str = "password_reset_attempts:";
+
+#[tracing::instrument(name = "Generating password reset token key", skip_all)]
+fn get_key(token: &PasswordResetToken) -> String {
+    format!("{}{}", PASSWORD_RESET_TOKEN_PREFIX, token.as_ref().expose_secret())
+}
+
+#[tracing::instrument(name = "Generating password reset attempts key", skip_all)]
+fn get_attempts_key(token: &PasswordResetToken) -> String {
+    format!("{}{}", PASSWORD_RESET_ATTEMPTS_PREFIX, token.as_ref().expose_secret())
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+use color_eyre::eyre::{Context, Result};
+use redis::{Commands, Connection};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    domain::{Email, RefreshToken, RefreshTokenStore, RefreshTokenStoreError},
+    utils::auth::REFRESH_TOKEN_TTL_SECONDS,
+};
+
+pub struct RedisRefreshTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisRefreshTokenStore {
+    #[tracing::instrument(name = "Creating Redis refresh token store", skip_all)]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+
+    #[tracing::instrument(name = "Getting refresh token family ID", skip_all)]
+    fn get_family_id(conn: &mut Connection, token: &RefreshToken) -> Result<Option<String>, RefreshTokenStoreError> {
+        conn.get(get_token_key(token.as_ref().expose_secret()))
+            .wrap_err("failed to get refresh token from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Storing refresh token family", skip_all)]
+    fn set_family(
+        conn: &mut Connection,
+        family_id: &str,
+        family: &RefreshTokenFamily,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let ttl = get_ttl()?;
+
+        let serialized_family = serde_json::to_string(family)
+            .wrap_err("failed to serialize refresh token family")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .set_ex(get_token_key(&family.current_token), family_id, ttl)
+            .wrap_err("failed to set refresh token in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .set_ex(get_family_key(family_id), serialized_family, ttl)
+            .wrap_err("failed to set refresh token family in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    #[tracing::instrument(name = "Adding refresh token", skip_all)]
+    async fn add_token(
+        &mut self,
+        email: Email,
+        session_id: String,
+        token: RefreshToken,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let family_id = Uuid::new_v4().to_string();
+        let family = RefreshTokenFamily {
+            email: email.as_ref().expose_secret().to_owned(),
+            session_id,
+            current_token: token.as_ref().expose_secret().to_owned(),
+        };
+
+        let mut conn = self.conn.write().await;
+        Self::set_family(&mut conn, &family_id, &family)?;
+
+        // Index the family under its user so all of a user's families can be revoked at once
+        let user_families_key = get_user_families_key(&email);
+        let _: () = conn
+            .sadd(&user_families_key, &family_id)
+            .wrap_err("failed to add refresh token family to user index in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+        let _: () = conn
+            .expire(&user_families_key, REFRESH_TOKEN_TTL_SECONDS)
+            .wrap_err("failed to set expiry on user refresh token families in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Rotating refresh token", skip_all)]
+    async fn rotate_token(
+        &mut self,
+        token: &RefreshToken,
+        new_token: RefreshToken,
+    ) -> Result<(Email, String), RefreshTokenStoreError> {
+        let mut conn = self.conn.write().await;
+
+        let family_id = Self::get_family_id(&mut conn, token)?
+            .ok_or(RefreshTokenStoreError::TokenNotFound)?;
+
+        let serialized_family: Option<String> = conn
+            .get(get_family_key(&family_id))
+            .wrap_err("failed to get refresh token family from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        let mut family: RefreshTokenFamily = match serialized_family {
+            Some(serialized_family) => serde_json::from_str(&serialized_family)
+                .wrap_err("failed to deserialize refresh token family")
+                .map_err(RefreshTokenStoreError::UnexpectedError)?,
+            None => return Err(RefreshTokenStoreError::TokenNotFound),
+        };
+
+        if &family.current_token != token.as_ref().expose_secret() {
+            let _: () = conn
+                .del(get_family_key(&family_id))
+                .wrap_err("failed to delete refresh token family from Redis")
+                .map_err(RefreshTokenStoreError::UnexpectedError)?;
+            return Err(RefreshTokenStoreError::TokenReused);
+        }
+
+        family.current_token = new_token.as_ref().expose_secret().to_owned();
+        Self::set_family(&mut conn, &family_id, &family)?;
+
+        let email = Email::parse(Secret::new(family.email)).map_err(RefreshTokenStoreError::UnexpectedError)?;
+        Ok((email, family.session_id))
+    }
+
+    #[tracing::instrument(name = "Revoking refresh token family", skip_all)]
+    async fn revoke_family(&mut self, token: &RefreshToken) -> Result<(), RefreshTokenStoreError> {
+        let mut conn = self.conn.write().await;
+
+        let family_id = Self::get_family_id(&mut conn, token)?
+            .ok_or(RefreshTokenStoreError::TokenNotFound)?;
+
+        let _: () = conn
+            .del(get_family_key(&family_id))
+            .wrap_err("failed to delete refresh token family from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Revoking user refresh token families", skip_all)]
+    async fn revoke_user_families(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        let mut conn = self.conn.write().await;
+        let user_families_key = get_user_families_key(email);
+
+        let family_ids: Vec<String> = conn
+            .smembers(&user_families_key)
+            .wrap_err("failed to get user refresh token families from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        for family_id in family_ids {
+            let _: () = conn
+                .del(get_family_key(&family_id))
+                .wrap_err("failed to delete refresh token family from Redis")
+                .map_err(RefreshTokenStoreError::UnexpectedError)?;
+        }
+
+        let _: () = conn
+            .del(&user_families_key)
+            .wrap_err("failed to delete user refresh token families from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RefreshTokenFamily {
+    email: String,
+    // Families created before sessions were tracked have none, and can no longer be refreshed
+    #[serde(default)]
+    session_id: String,
+    current_token: String,
+}
+
+const REFRESH_TOKEN_PREFIX: &str = "refresh_token:";
+const REFRESH_TOKEN_FAMILY_PREFIX: &str = "refresh_token_family:";
+const USER_REFRESH_TOKEN_FAMILIES_PREFIX: &str = "user_refresh_token_families:";
+
+fn get_ttl() -> Result<u64, RefreshTokenStoreError> {
+    REFRESH_TOKEN_TTL_SECONDS
+        .try_into()
+        .wrap_err("failed to cast REFRESH_TOKEN_TTL_SECONDS to u64")
+        .map_err(RefreshTokenStoreError::UnexpectedError)
+}
+
+#[tracing::instrument(name = "Generating refresh token key", skip_all)]
+fn get_token_key(token: &str) -> String {
+    format!("{}{}", REFRESH_TOKEN_PREFIX, token)
+}
+
+#[tracing::instrument(name = "Generating refresh token family key", skip_all)]
+fn get_family_key(family_id: &str) -> String {
+    format!("{}{}", REFRESH_TOKEN_FAMILY_PREFIX, family_id)
+}
+
+#[tracing::instrument(name = "Generating user refresh token families key", skip_all)]
+fn get_user_families_key(email: &Email) -> String {
+    format!("{}{}", USER_REFRESH_TOKEN_FAMILIES_PREFIX, email.as_ref().expose_secret())
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+use chrono::Utc;
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    domain::{Email, Session, SessionStore, SessionStoreError},
+    utils::auth::REFRESH_TOKEN_TTL_SECONDS,
+};
+
+pub struct RedisSessionStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl RedisSessionStore {
+    #[tracing::instrument(name = "Creating Redis session store", skip_all)]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+
+    #[tracing::instrument(name = "Getting session record", skip_all)]
+    fn get_record(conn: &mut Connection, session_id: &str) -> Result<Option<SessionRecord>, SessionStoreError> {
+        let serialized_record: Option<String> = conn
+            .get(get_session_key(session_id))
+            .wrap_err("failed to get session from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        serialized_record
+            .map(|serialized_record| {
+                serde_json::from_str(&serialized_record)
+                    .wrap_err("failed to deserialize session")
+                    .map_err(SessionStoreError::UnexpectedError)
+            })
+            .transpose()
+    }
+
+    // A session lives as long as a refresh token issued for it could, counted from its last use
+    #[tracing::instrument(name = "Storing session record", skip_all)]
+    fn set_record(conn: &mut Connection, record: &SessionRecord) -> Result<(), SessionStoreError> {
+        let serialized_record = serde_json::to_string(record)
+            .wrap_err("failed to serialize session")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .set_ex(get_session_key(&record.session.id), serialized_record, get_ttl()?)
+            .wrap_err("failed to set session in Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisSessionStore {
+    #[tracing::instrument(name = "Adding session", skip_all)]
+    async fn add_session(&mut self, email: &Email, session: Session) -> Result<(), SessionStoreError> {
+        let record = SessionRecord {
+            email: email.as_ref().expose_secret().to_owned(),
+            session,
+        };
+
+        let mut conn = self.conn.write().await;
+        Self::set_record(&mut conn, &record)?;
+
+        // Index the session under its user so all of a user's sessions can be listed
+        let user_sessions_key = get_user_sessions_key(email);
+        let _: () = conn
+            .sadd(&user_sessions_key, &record.session.id)
+            .wrap_err("failed to add session to user index in Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+        let _: () = conn
+            .expire(&user_sessions_key, REFRESH_TOKEN_TTL_SECONDS)
+            .wrap_err("failed to set expiry on user sessions in Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Getting sessions", skip_all)]
+    async fn get_sessions(&self, email: &Email) -> Result<Vec<Session>, SessionStoreError> {
+        let mut conn = self.conn.write().await;
+        let user_sessions_key = get_user_sessions_key(email);
+
+        let session_ids: Vec<String> = conn
+            .smembers(&user_sessions_key)
+            .wrap_err("failed to get user sessions from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        let mut sessions = Vec::with_capacity(session_ids.len());
+        for session_id in session_ids {
+            match Self::get_record(&mut conn, &session_id)? {
+                Some(record) => sessions.push(record.session),
+                // The session expired, so drop it from the index too
+                None => {
+                    let _: () = conn
+                        .srem(&user_sessions_key, &session_id)
+                        .wrap_err("failed to remove expired session from user index in Redis")
+                        .map_err(SessionStoreError::UnexpectedError)?;
+                }
+            }
+        }
+
+        Ok(sessions)
+    }
+
+    #[tracing::instrument(name = "Touching session", skip_all)]
+    async fn touch_session(&mut self, session_id: &str) -> Result<(), SessionStoreError> {
+        let mut conn = self.conn.write().await;
+
+        let mut record = Self::get_record(&mut conn, session_id)?.ok_or(SessionStoreError::SessionNotFound)?;
+        record.session.last_seen_at = Utc::now().timestamp();
+        Self::set_record(&mut conn, &record)?;
+
+        let _: () = conn
+            .expire(get_user_sessions_key_for(&record.email), REFRESH_TOKEN_TTL_SECONDS)
+            .wrap_err("failed to set expiry on user sessions in Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Revoking session", skip_all)]
+    async fn revoke_session(&mut self, email: &Email, session_id: &str) -> Result<(), SessionStoreError> {
+        let mut conn = self.conn.write().await;
+
+        let record = Self::get_record(&mut conn, session_id)?.ok_or(SessionStoreError::SessionNotFound)?;
+        if &record.email != email.as_ref().expose_secret() {
+            return Err(SessionStoreError::SessionNotFound);
+        }
+
+        let _: () = conn
+            .del(get_session_key(session_id))
+            .wrap_err("failed to delete session from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+        let _: () = conn
+            .srem(get_user_sessions_key(email), session_id)
+            .wrap_err("failed to remove session from user index in Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Revoking user sessions", skip_all)]
+    async fn revoke_user_sessions(&mut self, email: &Email) -> Result<(), SessionStoreError> {
+        let mut conn = self.conn.write().await;
+        let user_sessions_key = get_user_sessions_key(email);
+
+        let session_ids: Vec<String> = conn
+            .smembers(&user_sessions_key)
+            .wrap_err("failed to get user sessions from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        for session_id in session_ids {
+            let _: () = conn
+                .del(get_session_key(&session_id))
+                .wrap_err("failed to delete session from Redis")
+                .map_err(SessionStoreError::UnexpectedError)?;
+        }
+
+        let _: () = conn
+            .del(&user_sessions_key)
+            .wrap_err("failed to delete user sessions from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SessionRecord {
+    email: String,
+    session: Session,
+}
+
+const SESSION_PREFIX: &str = "session:";
+const USER_SESSIONS_PREFIX: &str = "user_sessions:";
+
+fn get_ttl() -> Result<u64, SessionStoreError> {
+    REFRESH_TOKEN_TTL_SECONDS
+        .try_into()
+        .wrap_err("failed to cast REFRESH_TOKEN_TTL_SECONDS to u64")
+        .map_err(SessionStoreError::UnexpectedError)
+}
+
+#[tracing::instrument(name = "Generating session key", skip_all)]
+fn get_session_key(session_id: &str) -> String {
+    format!("{}{}", SESSION_PREFIX, session_id)
+}
+
+#[tracing::instrument(name = "Generating user sessions key", skip_all)]
+fn get_user_sessions_key(email: &Email) -> String {
+    get_user_sessions_key_for(email.as_ref().expose_secret())
+}
+
+fn get_user_sessions_key_for(email: &str) -> String {
+    format!("{}{}", USER_SESSIONS_PREFIX, email)
+}
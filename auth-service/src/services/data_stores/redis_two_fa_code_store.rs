@@ -1,12 +1,21 @@
 use std::sync::Arc;
-use color_eyre::eyre::Context;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Context};
+use hmac::{Hmac, Mac};
 use redis::{Commands, Connection};
 use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use tokio::sync::RwLock;
 
-use crate::domain::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError,Email,};
+use crate::{
+    domain::{constant_time_eq, Email, LoginAttemptId, PendingTwoFAAttempt, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    utils::constants::{MAX_PENDING_TWO_FA_CHALLENGES, TWO_FA_CODE_HASH_KEY, TWO_FA_CODE_TTL_SECONDS},
+};
 
+// Neither codes nor login attempt IDs are stored as they are, only keyed hashes of them, so
+// someone who can read Redis can neither see the codes nor tell which attempts they belong to.
+// Below, `attempt_hash` is always the hash of a login attempt ID.
 pub struct RedisTwoFACodeStore {
     conn: Arc<RwLock<Connection>>,
 }
@@ -16,6 +25,54 @@ impl RedisTwoFACodeStore {
     pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
         Self { conn }
     }
+
+    #[tracing::instrument(name = "Getting 2FA record", skip_all)]
+    fn get_record(conn: &mut Connection, attempt_hash: &str) -> Result<Option<TwoFARecord>, TwoFACodeStoreError> {
+        let serialized_record: Option<String> = conn
+            .get(get_code_key(attempt_hash))
+            .wrap_err("failed to get 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        serialized_record
+            .map(|serialized_record| {
+                serde_json::from_str(&serialized_record)
+                    .wrap_err("failed to deserialize 2FA record")
+                    .map_err(TwoFACodeStoreError::UnexpectedError)
+            })
+            .transpose()
+    }
+
+    // Writing the record also restarts its lifetime
+    #[tracing::instrument(name = "Setting 2FA record", skip_all)]
+    fn set_record(conn: &mut Connection, attempt_hash: &str, record: &TwoFARecord) -> Result<(), TwoFACodeStoreError> {
+        let serialized_record = serde_json::to_string(record)
+            .wrap_err("failed to serialize 2FA record")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        conn.set_ex(get_code_key(attempt_hash), serialized_record, TWO_FA_CODE_TTL_SECONDS)
+            .wrap_err("failed to set 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)
+    }
+
+    // The attempt's record, as long as it belongs to this user
+    fn get_user_record(conn: &mut Connection, email: &Email, attempt_hash: &str) -> Result<TwoFARecord, TwoFACodeStoreError> {
+        match Self::get_record(conn, attempt_hash)? {
+            Some(record) if &record.email == email.as_ref().expose_secret() => Ok(record),
+            _ => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
+
+    #[tracing::instrument(name = "Deleting 2FA record", skip_all)]
+    fn delete_record(conn: &mut Connection, email: &str, attempt_hash: &str) -> Result<(), TwoFACodeStoreError> {
+        let _: () = conn
+            .del(&[get_code_key(attempt_hash), get_failed_attempts_key(attempt_hash)])
+            .wrap_err("failed to delete 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let _: () = conn
+            .zrem(get_user_attempts_key(email), attempt_hash)
+            .wrap_err("failed to remove login attempt from user index in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
@@ -27,68 +84,207 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(&email);
-        let tuple = TwoFATuple(login_attempt_id.as_ref().expose_secret().to_string(), code.as_ref().expose_secret().to_string());
-        let serialized_tuple = serde_json::to_string(&tuple)
-            .wrap_err("failed to serialize 2FA tuple") // New!
+        let email = email.as_ref().expose_secret();
+        let attempt_hash = hash_login_attempt_id(&login_attempt_id)?;
+        let now = Utc::now().timestamp_millis();
+        let record = TwoFARecord {
+            email: email.to_owned(),
+            code_hash: hash_code(&login_attempt_id, &code)?,
+            resends: 0,
+            last_sent_at: now,
+        };
+
+        let mut conn = self.conn.write().await;
+        Self::set_record(&mut conn, &attempt_hash, &record)?;
+
+        // The user's pending attempts, oldest first, so the oldest can be dropped past the limit.
+        // Attempts that have expired by themselves are cleared out of the index first.
+        let user_attempts_key = get_user_attempts_key(email);
+        let expired_before = now - (TWO_FA_CODE_TTL_SECONDS * 1000) as i64;
+        let _: () = conn
+            .zrembyscore(&user_attempts_key, "-inf", expired_before)
+            .wrap_err("failed to clear expired login attempts in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let _: () = conn
+            .zadd(&user_attempts_key, &attempt_hash, now)
+            .wrap_err("failed to add login attempt to user index in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let _: () = conn
+            .expire(&user_attempts_key, TWO_FA_CODE_TTL_SECONDS as i64)
+            .wrap_err("failed to set expiry on user login attempts in Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-        let _: () = self
-            .conn
-            .write()
-            .await
-            .set_ex(&key, serialized_tuple, TEN_MINUTES_IN_SECONDS)
-            .wrap_err("failed to set 2FA code in Redis") // New! 
-            .map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+        let pending: usize = conn
+            .zcard(&user_attempts_key)
+            .wrap_err("failed to count login attempts in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        if pending > MAX_PENDING_TWO_FA_CHALLENGES {
+            let oldest: Vec<String> = conn
+                .zrange(&user_attempts_key, 0, (pending - MAX_PENDING_TWO_FA_CHALLENGES - 1) as isize)
+                .wrap_err("failed to get oldest login attempts from Redis")
+                .map_err(TwoFACodeStoreError::UnexpectedError)?;
+            for oldest_hash in oldest {
+                Self::delete_record(&mut conn, email, &oldest_hash)?;
+            }
+        }
 
         Ok(())
     }
 
     #[tracing::instrument(name = "Removing 2FA code", skip_all)]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(email);
-        let _: () = self
-            .conn
-            .write()
-            .await
-            .del(&key)
-            .wrap_err("failed to delete 2FA code from Redis") // New!
-            .map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+    async fn remove_code(&mut self, login_attempt_id: &LoginAttemptId) -> Result<(), TwoFACodeStoreError> {
+        let attempt_hash = hash_login_attempt_id(login_attempt_id)?;
+        let mut conn = self.conn.write().await;
 
-        Ok(())
+        let record = Self::get_record(&mut conn, &attempt_hash)?
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        Self::delete_record(&mut conn, &record.email, &attempt_hash)
     }
 
-    #[tracing::instrument(name = "Getting 2FA code", skip_all)]
-    async fn get_code(
+    #[tracing::instrument(name = "Verifying 2FA code", skip_all)]
+    async fn verify_code(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-       let key = get_key(email);
-       let mut conn = self.conn.write().await;
-       let result: Result<String, redis::RedisError> = conn.get(key);
-       match result {
-           Ok(serialized_tuple) => {
-               let tuple: TwoFATuple = serde_json::from_str(&serialized_tuple)
-                   .wrap_err("failed to deserialize 2FA tuple") // New!
-                   .map_err(TwoFACodeStoreError::UnexpectedError)?;
-               let login_attempt_id = LoginAttemptId::parse(tuple.0)
-                   .map_err(TwoFACodeStoreError::UnexpectedError)?;
-               let code = TwoFACode::parse(tuple.1)
-                   .map_err(TwoFACodeStoreError::UnexpectedError)?;
-               Ok((login_attempt_id, code))
-           }
-            Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
-       }
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<bool, TwoFACodeStoreError> {
+        let attempt_hash = hash_login_attempt_id(login_attempt_id)?;
+        let code_hash = hash_code(login_attempt_id, code)?;
+        let mut conn = self.conn.write().await;
+
+        let record = Self::get_user_record(&mut conn, email, &attempt_hash)?;
+        Ok(constant_time_eq(record.code_hash, code_hash))
+    }
+
+    #[tracing::instrument(name = "Recording failed 2FA attempt", skip_all)]
+    async fn record_failed_attempt(&mut self, login_attempt_id: &LoginAttemptId) -> Result<u32, TwoFACodeStoreError> {
+        let attempt_hash = hash_login_attempt_id(login_attempt_id)?;
+        let mut conn = self.conn.write().await;
+
+        if Self::get_record(&mut conn, &attempt_hash)?.is_none() {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
+
+        // The counter outlives its code by at most the code's lifetime, and is deleted with it
+        let failed_attempts_key = get_failed_attempts_key(&attempt_hash);
+        let failed_attempts: u32 = conn
+            .incr(&failed_attempts_key, 1)
+            .wrap_err("failed to count failed 2FA attempt in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let _: () = conn
+            .expire(&failed_attempts_key, TWO_FA_CODE_TTL_SECONDS as i64)
+            .wrap_err("failed to set expiry on failed 2FA attempts in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok(failed_attempts)
+    }
+
+    #[tracing::instrument(name = "Getting pending 2FA attempt", skip_all)]
+    async fn get_attempt(
+        &self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<PendingTwoFAAttempt, TwoFACodeStoreError> {
+        let attempt_hash = hash_login_attempt_id(login_attempt_id)?;
+        let mut conn = self.conn.write().await;
+
+        let record = Self::get_user_record(&mut conn, email, &attempt_hash)?;
+        let last_sent_at = DateTime::from_timestamp_millis(record.last_sent_at)
+            .ok_or_else(|| TwoFACodeStoreError::UnexpectedError(eyre!("invalid 2FA code send time")))?;
+        Ok(PendingTwoFAAttempt {
+            last_sent_at,
+            resends: record.resends,
+        })
+    }
+
+    #[tracing::instrument(name = "Replacing 2FA code", skip_all)]
+    async fn replace_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let attempt_hash = hash_login_attempt_id(login_attempt_id)?;
+        let code_hash = hash_code(login_attempt_id, &code)?;
+        let mut conn = self.conn.write().await;
+
+        let record = Self::get_user_record(&mut conn, email, &attempt_hash)?;
+        let now = Utc::now().timestamp_millis();
+        let record = TwoFARecord {
+            code_hash,
+            resends: record.resends + 1,
+            last_sent_at: now,
+            ..record
+        };
+        Self::set_record(&mut conn, &attempt_hash, &record)?;
+
+        // The failed attempts counter has to live as long as the code it counts for
+        let _: () = conn
+            .expire(get_failed_attempts_key(&attempt_hash), TWO_FA_CODE_TTL_SECONDS as i64)
+            .wrap_err("failed to set expiry on failed 2FA attempts in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        // Re-scored too, so the attempt isn't cleared out of the user's index while still alive
+        let _: () = conn
+            .zadd(get_user_attempts_key(&record.email), &attempt_hash, now)
+            .wrap_err("failed to update login attempt in user index in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let _: () = conn
+            .expire(get_user_attempts_key(&record.email), TWO_FA_CODE_TTL_SECONDS as i64)
+            .wrap_err("failed to set expiry on user login attempts in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok(())
     }
 }
 
 #[derive(Serialize, Deserialize)]
-struct TwoFATuple(pub String, pub String);
+struct TwoFARecord {
+    email: String,
+    code_hash: String,
+    resends: u32,
+    // Unix timestamp in milliseconds
+    last_sent_at: i64,
+}
 
-const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+const TWO_FA_FAILED_ATTEMPTS_PREFIX: &str = "two_fa_failed_attempts:";
+const USER_LOGIN_ATTEMPTS_PREFIX: &str = "user_login_attempts:";
 
 #[tracing::instrument(name = "Generating 2FA code key", skip_all)]
-fn get_key(email: &Email) -> String {
-    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref().expose_secret())
-}
\ No newline at end of file
+fn get_code_key(attempt_hash: &str) -> String {
+    format!("{}{}", TWO_FA_CODE_PREFIX, attempt_hash)
+}
+
+fn get_failed_attempts_key(attempt_hash: &str) -> String {
+    format!("{}{}", TWO_FA_FAILED_ATTEMPTS_PREFIX, attempt_hash)
+}
+
+fn get_user_attempts_key(email: &str) -> String {
+    format!("{}{}", USER_LOGIN_ATTEMPTS_PREFIX, email)
+}
+
+fn hash_login_attempt_id(login_attempt_id: &LoginAttemptId) -> Result<String, TwoFACodeStoreError> {
+    keyed_hash(&["login_attempt_id", login_attempt_id.as_ref().expose_secret()])
+}
+
+// Tied to the login attempt, so the same code sent for two attempts hashes differently
+fn hash_code(login_attempt_id: &LoginAttemptId, code: &TwoFACode) -> Result<String, TwoFACodeStoreError> {
+    keyed_hash(&["code", login_attempt_id.as_ref().expose_secret(), code.as_ref().expose_secret()])
+}
+
+// HMAC-SHA256 over the parts, each length-prefixed so that no two lists of parts hash alike
+fn keyed_hash(parts: &[&str]) -> Result<String, TwoFACodeStoreError> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(TWO_FA_CODE_HASH_KEY.expose_secret().as_bytes())
+        .wrap_err("failed to create 2FA code HMAC")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+    for part in parts {
+        mac.update(&(part.len() as u64).to_be_bytes());
+        mac.update(part.as_bytes());
+    }
+    Ok(mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
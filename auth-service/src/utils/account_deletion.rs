@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use crate::app_state::UserStoreType;
+
+// Remove accounts whose deletion grace period has run out, every `interval`
+// for as long as the service runs
+pub async fn delete_expired_accounts_periodically(user_store: UserStoreType, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match user_store.write().await.delete_expired_users().await {
+            Ok(deleted) if !deleted.is_empty() => {
+                tracing::info!(count = deleted.len(), "deleted accounts past their grace period");
+            }
+            Ok(_) => (),
+            Err(e) => tracing::error!("scheduled account deletion failed: {:?}", e),
+        }
+    }
+}
@@ -1,17 +1,45 @@
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
-use crate::{app_state::{BannedTokenStoreType}, domain::{Email}};
+use axum_extra::extract::CookieJar;
+use crate::{app_state::{AppState, BannedTokenStoreType, JwtKeyringType, RefreshTokenStoreType, SessionStoreType}, domain::{AuthAPIError, AuthorizationGrant, Email, RefreshToken, Session, User, UserStoreError}};
 use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
+use uuid::Uuid;
 
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+use super::{constants::{JWT_AUDIENCE, JWT_COOKIE_NAME, JWT_ISSUER, JWT_LEEWAY_SECONDS, REFRESH_COOKIE_NAME}, device::DeviceInfo, jwt_key::JwtKey, oauth::{has_scope, EMAIL_SCOPE, OPENID_SCOPE}};
 
-// Create cookie with a new JWT auth token
+// Record a new login from the given device, returning the session ID its tokens should carry
+#[tracing::instrument(name = "Starting session", skip_all)]
+pub async fn start_session(email: &Email, device: DeviceInfo, session_store: SessionStoreType) -> Result<String> {
+    let session = Session::new(device.user_agent, device.ip_address);
+    let session_id = session.id.clone();
+    session_store
+        .write()
+        .await
+        .add_session(email, session)
+        .await
+        .wrap_err("failed to store session")?;
+    Ok(session_id)
+}
+
+// Create cookie with a new JWT auth token stamped with the user's current token generation
 #[tracing::instrument(name = "Generating auth cookie", skip_all)]
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email)?;
+pub async fn generate_auth_cookie(
+    user: &User,
+    session_id: &str,
+    banned_token_store: BannedTokenStoreType,
+    jwt_keyring: JwtKeyringType,
+) -> Result<Cookie<'static>> {
+    let generation = banned_token_store
+        .read()
+        .await
+        .get_token_generation(&user.email)
+        .await
+        .wrap_err("failed to get token generation")?;
+    let signing_key = jwt_keyring.read().await.signing_key();
+    let token = generate_auth_token(user, session_id, generation, &signing_key)?;
     Ok(create_auth_cookie(token))
 }
 
@@ -26,16 +54,123 @@ fn create_auth_cookie(token: String) -> Cookie<'static> {
     cookie
 }
 
+// Create cookie with a new refresh token, starting a new token family for the session
+#[tracing::instrument(name = "Generating refresh cookie", skip_all)]
+pub async fn generate_refresh_cookie(
+    email: &Email,
+    session_id: &str,
+    refresh_token_store: RefreshTokenStoreType,
+) -> Result<Cookie<'static>> {
+    let token = RefreshToken::default();
+    refresh_token_store
+        .write()
+        .await
+        .add_token(email.clone(), session_id.to_owned(), token.clone())
+        .await
+        .wrap_err("failed to store refresh token")?;
+    Ok(create_refresh_cookie(token))
+}
+
+// Create cookie and set the value to the passed-in refresh token.
+// Unlike the JWT cookie it outlives the browser session, so it gets an explicit max age.
+#[tracing::instrument(name = "Creating refresh cookie", skip_all)]
+pub fn create_refresh_cookie(token: RefreshToken) -> Cookie<'static> {
+    let cookie = Cookie::build((REFRESH_COOKIE_NAME, token.as_ref().expose_secret().to_owned()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(REFRESH_TOKEN_TTL_SECONDS))
+        .build();
+    cookie
+}
+
+// The role every user's tokens carry
+pub const USER_ROLE: &str = "user";
+
 // This value determines how long the JWT auth token is valid for
 pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
 
+// This value determines how long a refresh token can go unused before it expires
+pub const REFRESH_TOKEN_TTL_SECONDS: i64 = 1_209_600; // 14 days
+
+// This value determines how long an emailed password reset link stays usable
+pub const PASSWORD_RESET_TOKEN_TTL_SECONDS: i64 = 1800; // 30 minutes
+
+// This value determines how many refused passwords an emailed password reset link survives
+pub const PASSWORD_RESET_MAX_ATTEMPTS: u32 = 5;
+
+// This value determines how long an emailed verification link stays usable
+pub const EMAIL_VERIFICATION_TOKEN_TTL_SECONDS: i64 = 86_400; // 24 hours
+
+// This value determines how long an emailed magic login link stays usable
+pub const MAGIC_LINK_TOKEN_TTL_SECONDS: i64 = 900; // 15 minutes
+
+// This value determines how long an OAuth client has to redeem an authorization code
+pub const AUTHORIZATION_CODE_TTL_SECONDS: i64 = 60; // 1 minute
+
 // Create JWT auth token
 #[tracing::instrument(name = "Generating auth token", skip_all)]
-fn generate_auth_token(email: &Email) -> Result<String> {
+fn generate_auth_token(user: &User, session_id: &str, generation: u64, key: &JwtKey) -> Result<String> {
+    create_token(&build_claims(user, session_id, generation)?, key)
+}
+
+// The tokens an OAuth client gets at /token
+pub struct OAuthTokens {
+    pub access_token: String,
+    // Only issued when the `openid` scope was granted
+    pub id_token: Option<String>,
+}
+
+// Create the tokens for a redeemed authorization code. The access token names the client and the
+// scope it was granted. /introspect and /userinfo accept it, but /verify-token and the
+// cookie-authenticated endpoints refuse it (see get_authenticated_claims). The ID token is
+// addressed to the client instead of to us, so it can't be replayed here as an access token.
+#[tracing::instrument(name = "Generating OAuth tokens", skip_all)]
+pub async fn generate_oauth_tokens(
+    user: &User,
+    session_id: &str,
+    grant: &AuthorizationGrant,
+    banned_token_store: BannedTokenStoreType,
+    jwt_keyring: JwtKeyringType,
+) -> Result<OAuthTokens> {
+    let generation = banned_token_store
+        .read()
+        .await
+        .get_token_generation(&user.email)
+        .await
+        .wrap_err("failed to get token generation")?;
+    let signing_key = jwt_keyring.read().await.signing_key();
+
+    let access_claims = Claims {
+        client_id: Some(grant.client_id.clone()),
+        scope: Some(grant.scope.clone()),
+        ..build_claims(user, session_id, generation)?
+    };
+    let access_token = create_token(&access_claims, &signing_key)?;
+
+    let id_token = if has_scope(&grant.scope, OPENID_SCOPE) {
+        let id_claims = Claims {
+            aud: grant.client_id.clone(),
+            client_id: Some(grant.client_id.clone()),
+            nonce: grant.nonce.clone(),
+            email: has_scope(&grant.scope, EMAIL_SCOPE).then(|| user.email.as_ref().expose_secret().to_owned()),
+            ..build_claims(user, session_id, generation)?
+        };
+        Some(create_token(&id_claims, &signing_key)?)
+    } else {
+        None
+    };
+
+    Ok(OAuthTokens { access_token, id_token })
+}
+
+// Claims for a token issued to the user right now, for our own audience
+fn build_claims(user: &User, session_id: &str, generation: u64) -> Result<Claims> {
     let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
         .wrap_err("failed to create 10 minute time delta")?;
 
-    let exp = Utc::now()
+    let now = Utc::now();
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(eyre!("failed to add 10 minutes to current time"))?
         .timestamp();
@@ -45,53 +180,199 @@ fn generate_auth_token(email: &Email) -> Result<String> {
         exp
     ))?;
 
-    let sub = email.as_ref().expose_secret().to_owned();
+    let sub = user.email.as_ref().expose_secret().to_owned();
 
-    let claims = Claims { sub, exp };
+    let iat: usize = now.timestamp().try_into().wrap_err("failed to cast iat time to usize")?;
 
-    create_token(&claims)
+    Ok(Claims {
+        sub,
+        exp,
+        iat,
+        nbf: iat,
+        jti: Uuid::new_v4().to_string(),
+        sid: session_id.to_owned(),
+        iss: JWT_ISSUER.to_owned(),
+        aud: JWT_AUDIENCE.to_owned(),
+        roles: vec![USER_ROLE.to_owned()],
+        generation,
+        email_verified: user.verified,
+        client_id: None,
+        scope: None,
+        nonce: None,
+        email: None,
+    })
 }
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
+// Check if JWT auth token is valid by decoding it using the keyring key named in its header,
+// then checking it was issued by us, for our audience, and hasn't been revoked since
 #[tracing::instrument(name = "Validating token", skip_all)]
 pub async fn validate_token(
     token: &str,
     banned_token_store: BannedTokenStoreType,
-) -> Result<Claims> {    
+    session_store: SessionStoreType,
+    jwt_keyring: JwtKeyringType,
+) -> Result<Claims> {
+    let header = decode_header(token).wrap_err("failed to decode token header")?;
+    let key = jwt_keyring
+        .read()
+        .await
+        .verification_key(header.kid.as_deref())
+        .ok_or(eyre!("token was signed with an unknown or retired key"))?;
 
-    match banned_token_store.read().await.is_token_banned(&Secret::new(token.to_string())).await {
-        Ok(value) => {
-            if value {
-                return Err(eyre!("token is banned"));
-            }
-        }
-        Err(e) => return Err(e.into()),
+    // Only the key's own algorithm is accepted, so a token can't pick a weaker one for itself
+    let mut validation = Validation::new(key.algorithm);
+    validation.set_issuer(&[JWT_ISSUER.as_str()]);
+    validation.set_audience(&[JWT_AUDIENCE.as_str()]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+    validation.validate_nbf = true;
+    validation.leeway = *JWT_LEEWAY_SECONDS;
+
+    let claims = decode::<Claims>(token, &key.decoding_key, &validation)
+        .map(|data| data.claims)
+        .wrap_err("failed to decode token")?;
+
+    // Checked after the signature, so only tokens we issued ever reach the store
+    if banned_token_store.read().await.is_token_banned(&claims.jti).await? {
+        return Err(eyre!("token is banned"));
+    }
+
+    // Tokens issued before the user's tokens were last revoked carry an older generation
+    let email = Email::parse(Secret::new(claims.sub.clone())).wrap_err("token subject is not a valid email")?;
+    let generation = banned_token_store.read().await.get_token_generation(&email).await?;
+    if claims.generation < generation {
+        return Err(eyre!("token has been revoked"));
+    }
+
+    // Signing out a device ends its session, which cuts off every token issued for it
+    session_store
+        .write()
+        .await
+        .touch_session(&claims.sid)
+        .await
+        .wrap_err("token's session has ended")?;
+
+    Ok(claims)
+}
+
+// Disabled accounts and accounts waiting to be deleted can't be issued new tokens
+pub fn ensure_can_log_in(user: &User) -> Result<(), AuthAPIError> {
+    if user.disabled {
+        return Err(AuthAPIError::AccountDisabled);
+    }
+    if user.pending_deletion {
+        return Err(AuthAPIError::AccountPendingDeletion);
     }
+    Ok(())
+}
+
+// Sign the user out everywhere: every JWT and refresh token issued so far stops working,
+// and all of their sessions end
+#[tracing::instrument(name = "Revoking all user tokens", skip_all)]
+pub async fn revoke_all_user_tokens(email: &Email, state: &AppState) -> Result<()> {
+    state
+        .banned_token_store
+        .write()
+        .await
+        .revoke_user_tokens(email)
+        .await
+        .wrap_err("failed to revoke user tokens")?;
+
+    state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_user_families(email)
+        .await
+        .wrap_err("failed to revoke user refresh tokens")?;
+
+    state
+        .session_store
+        .write()
+        .await
+        .revoke_user_sessions(email)
+        .await
+        .wrap_err("failed to revoke user sessions")?;
+
+    Ok(())
+}
+
+// Validate the JWT cookie, returning its claims
+#[tracing::instrument(name = "Get authenticated claims", skip_all)]
+pub async fn get_authenticated_claims(jar: &CookieJar, state: &AppState) -> Result<Claims, AuthAPIError> {
+    let cookie = jar.get(JWT_COOKIE_NAME).ok_or(AuthAPIError::MissingToken)?;
 
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
-        &Validation::default(),
+    let claims = validate_token(
+        cookie.value(),
+        state.banned_token_store.clone(),
+        state.session_store.clone(),
+        state.jwt_keyring.clone(),
     )
-    .map(|data| data.claims)
-    .wrap_err("failed to decode token")
+    .await
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // Access tokens issued to OAuth clients are for /userinfo and downstream services. Taken
+    // as a cookie they would let the client manage the user's account.
+    if claims.client_id.is_some() {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    Ok(claims)
 }
 
-// Create JWT auth token by encoding claims using the JWT secret
+// Resolve the user behind the JWT cookie
+#[tracing::instrument(name = "Get authenticated user", skip_all)]
+pub async fn get_authenticated_user(jar: &CookieJar, state: &AppState) -> Result<User, AuthAPIError> {
+    let claims = get_authenticated_claims(jar, state).await?;
+
+    let email = Email::parse(Secret::new(claims.sub)).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => Ok(user),
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::InvalidToken),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// Create JWT auth token by encoding claims using the given signing key
 #[tracing::instrument(name = "Creating token", skip_all)]
-fn create_token(claims: &Claims) -> Result<String> {
-    encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &EncodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
-    )
-    .wrap_err("failed to create token")
+fn create_token(claims: &Claims, key: &JwtKey) -> Result<String> {
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
+
+    encode(&header, &claims, &key.encoding_key).wrap_err("failed to create token")
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    pub iat: usize,
+    pub nbf: usize,
+    // Unique per token, so a single token can be banned without touching the user's others
+    pub jti: String,
+    // The session the token was issued for (see SessionStore)
+    pub sid: String,
+    pub iss: String,
+    pub aud: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub generation: u64,
+    // Unverified users can still be issued tokens (see REQUIRE_VERIFIED_EMAIL),
+    // so downstream services need to be able to tell them apart
+    #[serde(default)]
+    pub email_verified: bool,
+    // The OAuth client the token was issued to. Tokens from our own login flows have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    // What the client was granted, space separated. Only set on tokens issued to OAuth clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    // The remaining claims are only set on ID tokens (see generate_oauth_tokens)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
 }
 
 #[cfg(test)]
@@ -101,14 +382,66 @@ mod tests {
     use secrecy::Secret;
     use tokio::sync::RwLock;
 
-    use crate::{app_state::AppState, services::data_stores::{HashmapTwoFACodeStore, HashmapUserStore, HashsetBannedTokenStore, MockEmailClient}};
+    use crate::{app_state::AppState, domain::{BannedTokenStore, Password, RefreshTokenStore, SessionStore, TwoFAMethod}, services::data_stores::{HashmapAuthorizationCodeStore, HashmapEmailVerificationTokenStore, HashmapJwtKeyStore, HashmapLoginThrottleStore, HashmapMagicLinkTokenStore, HashmapOAuthClientStore, HashmapPasskeyChallengeStore, HashmapPasskeyStore, HashmapPasswordResetTokenStore, HashmapRecoveryCodeStore, HashmapRefreshTokenStore, HashmapSessionStore, HashmapTwoFACodeStore, HashmapUserStore, HashsetBannedTokenStore, MockEmailClient}};
+
+    use crate::utils::{constants::JWT_INITIAL_KEY, jwt_keyring::{rotate_jwt_key, JwtKeyring}, login_throttle::LoginThrottlePolicy, password_policy::PasswordPolicy};
 
     use super::*;
 
+    fn test_keyring() -> JwtKeyringType {
+        Arc::new(RwLock::new(JwtKeyring::new(JWT_INITIAL_KEY.clone())))
+    }
+
+    const TEST_SESSION_ID: &str = "test-session";
+
+    // A session store holding the session our test tokens are issued for
+    async fn test_session_store() -> SessionStoreType {
+        let mut session_store = HashmapSessionStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let session = Session { id: TEST_SESSION_ID.to_owned(), ..Session::new(None, None) };
+        session_store.add_session(&email, session).await.unwrap();
+        Arc::new(RwLock::new(session_store))
+    }
+
+    // Claims as we would issue them right now, for tests to tamper with
+    fn test_claims() -> Claims {
+        let now = Utc::now().timestamp() as usize;
+        Claims {
+            sub: "test@example.com".to_owned(),
+            exp: now + TOKEN_TTL_SECONDS as usize,
+            iat: now,
+            nbf: now,
+            jti: Uuid::new_v4().to_string(),
+            sid: TEST_SESSION_ID.to_owned(),
+            iss: JWT_ISSUER.to_owned(),
+            aud: JWT_AUDIENCE.to_owned(),
+            roles: vec![USER_ROLE.to_owned()],
+            generation: 0,
+            email_verified: false,
+            client_id: None,
+            scope: None,
+            nonce: None,
+            email: None,
+        }
+    }
+
+    fn sign(claims: &Claims) -> String {
+        create_token(claims, &JWT_INITIAL_KEY).unwrap()
+    }
+
+    fn test_user() -> User {
+        User::new(
+            Email::parse(Secret::new("test@example.com".to_owned())).unwrap(),
+            Password::parse(Secret::new("password123".to_owned())).unwrap(),
+            TwoFAMethod::None,
+        )
+    }
+
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let user = test_user();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let cookie = generate_auth_cookie(&user, TEST_SESSION_ID, banned_token_store, test_keyring()).await.unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -128,24 +461,140 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_generate_auth_token() {
+    async fn test_generate_refresh_cookie() {
         let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let refresh_token_store = Arc::new(RwLock::new(HashmapRefreshTokenStore::default()));
+        let cookie = generate_refresh_cookie(&email, TEST_SESSION_ID, refresh_token_store.clone()).await.unwrap();
+        assert_eq!(cookie.name(), REFRESH_COOKIE_NAME);
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.max_age(), Some(time::Duration::seconds(REFRESH_TOKEN_TTL_SECONDS)));
+
+        let token = RefreshToken::parse(cookie.value().to_owned()).unwrap();
+        let result = refresh_token_store.write().await.rotate_token(&token, RefreshToken::default()).await;
+        assert_eq!(result.unwrap(), (email, TEST_SESSION_ID.to_owned()));
+    }
+
+    #[tokio::test]
+    async fn test_generate_auth_token() {
+        let result = generate_auth_token(&test_user(), TEST_SESSION_ID, 0, &JWT_INITIAL_KEY).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
+    #[tokio::test]
+    async fn test_generate_auth_token_names_signing_key() {
+        let token = generate_auth_token(&test_user(), TEST_SESSION_ID, 0, &JWT_INITIAL_KEY).unwrap();
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.kid, Some(JWT_INITIAL_KEY.kid.clone()));
+        assert_eq!(header.alg, JWT_INITIAL_KEY.algorithm);
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_unknown_key_id() {
+        let mut header = Header::new(JWT_INITIAL_KEY.algorithm);
+        header.kid = Some("retired-key".to_owned());
+        let claims = test_claims();
+        let token = encode(&header, &claims, &JWT_INITIAL_KEY.encoding_key).unwrap();
+
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        assert!(validate_token(&token, banned_token_store, test_session_store().await, test_keyring()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_wrong_issuer() {
+        let claims = Claims { iss: "https://attacker.example.com".to_owned(), ..test_claims() };
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        assert!(validate_token(&sign(&claims), banned_token_store, test_session_store().await, test_keyring()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_wrong_audience() {
+        let claims = Claims { aud: "some-other-service".to_owned(), ..test_claims() };
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        assert!(validate_token(&sign(&claims), banned_token_store, test_session_store().await, test_keyring()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_allows_clock_skew_within_leeway() {
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let now = Utc::now().timestamp() as usize;
+        let leeway = *JWT_LEEWAY_SECONDS as usize;
+
+        // Issued by a clock running slightly ahead of ours
+        let claims = Claims { nbf: now + leeway / 2, ..test_claims() };
+        assert!(validate_token(&sign(&claims), banned_token_store.clone(), test_session_store().await, test_keyring()).await.is_ok());
+
+        let claims = Claims { nbf: now + leeway + 60, ..test_claims() };
+        assert!(validate_token(&sign(&claims), banned_token_store.clone(), test_session_store().await, test_keyring()).await.is_err());
+
+        let claims = Claims { exp: now - leeway - 60, ..test_claims() };
+        assert!(validate_token(&sign(&claims), banned_token_store, test_session_store().await, test_keyring()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_banned_jti() {
+        let claims = test_claims();
+        let token = sign(&claims);
+        let other_token = sign(&test_claims());
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        banned_token_store.write().await.store_token(claims.jti.clone()).await.unwrap();
+
+        assert!(validate_token(&token, banned_token_store.clone(), test_session_store().await, test_keyring()).await.is_err());
+        // Banning one token leaves the user's other tokens alone
+        assert!(validate_token(&other_token, banned_token_store, test_session_store().await, test_keyring()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_after_key_rotation() {
+        let user = test_user();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let jwt_keyring = test_keyring();
+        let old_cookie = generate_auth_cookie(&user, TEST_SESSION_ID, banned_token_store.clone(), jwt_keyring.clone()).await.unwrap();
+
+        let kid = rotate_jwt_key(jwt_keyring.clone(), Arc::new(RwLock::new(HashmapJwtKeyStore::default()))).await.unwrap();
+
+        // Tokens signed before the rotation stay valid until the old key retires
+        assert!(validate_token(old_cookie.value(), banned_token_store.clone(), test_session_store().await, jwt_keyring.clone()).await.is_ok());
+
+        let new_cookie = generate_auth_cookie(&user, TEST_SESSION_ID, banned_token_store.clone(), jwt_keyring.clone()).await.unwrap();
+        assert_eq!(decode_header(new_cookie.value()).unwrap().kid, Some(kid));
+        assert!(validate_token(new_cookie.value(), banned_token_store.clone(), test_session_store().await, jwt_keyring.clone()).await.is_ok());
+
+        // Once retired, the old key no longer verifies anything
+        jwt_keyring.write().await.rotate(JwtKey::generate(JWT_INITIAL_KEY.algorithm).unwrap(), chrono::Duration::zero());
+        assert!(validate_token(old_cookie.value(), banned_token_store.clone(), test_session_store().await, jwt_keyring.clone()).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let token = generate_auth_token(&email).unwrap();
+        let token = generate_auth_token(&test_user(), TEST_SESSION_ID, 0, &JWT_INITIAL_KEY).unwrap();
         let user_store = Arc::new(RwLock::new(HashmapUserStore::default()));
         let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
         let two_fa_code_store = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
+        let refresh_token_store = Arc::new(RwLock::new(HashmapRefreshTokenStore::default()));
+        let session_store = test_session_store().await;
+        let password_reset_token_store = Arc::new(RwLock::new(HashmapPasswordResetTokenStore::default()));
+        let email_verification_token_store = Arc::new(RwLock::new(HashmapEmailVerificationTokenStore::default()));
+        let magic_link_token_store = Arc::new(RwLock::new(HashmapMagicLinkTokenStore::default()));
+        let recovery_code_store = Arc::new(RwLock::new(HashmapRecoveryCodeStore::default()));
+        let passkey_store = Arc::new(RwLock::new(HashmapPasskeyStore::default()));
+        let passkey_challenge_store = Arc::new(RwLock::new(HashmapPasskeyChallengeStore::default()));
+        let oauth_client_store = Arc::new(RwLock::new(HashmapOAuthClientStore::default()));
+        let authorization_code_store = Arc::new(RwLock::new(HashmapAuthorizationCodeStore::default()));
+        let login_throttle_store = Arc::new(RwLock::new(HashmapLoginThrottleStore::default()));
+        let jwt_key_store = Arc::new(RwLock::new(HashmapJwtKeyStore::default()));
         let email_client = Arc::new(MockEmailClient);
-        let app_state = AppState { user_store, banned_token_store, two_fa_code_store, email_client };
+        let app_state = AppState { user_store, banned_token_store, two_fa_code_store, refresh_token_store, session_store, password_reset_token_store, email_verification_token_store, magic_link_token_store, recovery_code_store, passkey_store, passkey_challenge_store, oauth_client_store, authorization_code_store, login_throttle_store, email_client, jwt_keyring: test_keyring(), jwt_key_store, require_verified_email: false, login_throttle_policy: LoginThrottlePolicy::default(), password_policy: PasswordPolicy::default(), admin_api_token: None };
 
-        let result = validate_token(&token, app_state.banned_token_store.clone()).await.unwrap();
+        let result = validate_token(&token, app_state.banned_token_store.clone(), app_state.session_store.clone(), app_state.jwt_keyring.clone()).await.unwrap();
         assert_eq!(result.sub, "test@example.com");
+        assert!(!result.email_verified);
+        assert_eq!(result.iss, *JWT_ISSUER);
+        assert_eq!(result.aud, *JWT_AUDIENCE);
+        assert_eq!(result.roles, vec![USER_ROLE.to_owned()]);
+        assert_eq!(result.nbf, result.iat);
+        assert!(!result.jti.is_empty());
+        assert_eq!(result.sid, TEST_SESSION_ID);
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -161,10 +610,104 @@ mod tests {
         let user_store = Arc::new(RwLock::new(HashmapUserStore::default()));
         let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
         let two_fa_code_store = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
+        let refresh_token_store = Arc::new(RwLock::new(HashmapRefreshTokenStore::default()));
+        let session_store = test_session_store().await;
+        let password_reset_token_store = Arc::new(RwLock::new(HashmapPasswordResetTokenStore::default()));
+        let email_verification_token_store = Arc::new(RwLock::new(HashmapEmailVerificationTokenStore::default()));
+        let magic_link_token_store = Arc::new(RwLock::new(HashmapMagicLinkTokenStore::default()));
+        let recovery_code_store = Arc::new(RwLock::new(HashmapRecoveryCodeStore::default()));
+        let passkey_store = Arc::new(RwLock::new(HashmapPasskeyStore::default()));
+        let passkey_challenge_store = Arc::new(RwLock::new(HashmapPasskeyChallengeStore::default()));
+        let oauth_client_store = Arc::new(RwLock::new(HashmapOAuthClientStore::default()));
+        let authorization_code_store = Arc::new(RwLock::new(HashmapAuthorizationCodeStore::default()));
+        let login_throttle_store = Arc::new(RwLock::new(HashmapLoginThrottleStore::default()));
+        let jwt_key_store = Arc::new(RwLock::new(HashmapJwtKeyStore::default()));
         let email_client = Arc::new(MockEmailClient);
-        let app_state = AppState { user_store, banned_token_store, two_fa_code_store, email_client };
+        let app_state = AppState { user_store, banned_token_store, two_fa_code_store, refresh_token_store, session_store, password_reset_token_store, email_verification_token_store, magic_link_token_store, recovery_code_store, passkey_store, passkey_challenge_store, oauth_client_store, authorization_code_store, login_throttle_store, email_client, jwt_keyring: test_keyring(), jwt_key_store, require_verified_email: false, login_throttle_policy: LoginThrottlePolicy::default(), password_policy: PasswordPolicy::default(), admin_api_token: None };
 
-        let result = validate_token(&token, app_state.banned_token_store.clone()).await;
+        let result = validate_token(&token, app_state.banned_token_store.clone(), app_state.session_store.clone(), app_state.jwt_keyring.clone()).await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_validate_token_after_user_tokens_revoked() {
+        let user = test_user();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let jwt_keyring = test_keyring();
+        let old_cookie = generate_auth_cookie(&user, TEST_SESSION_ID, banned_token_store.clone(), jwt_keyring.clone()).await.unwrap();
+
+        banned_token_store.write().await.revoke_user_tokens(&user.email).await.unwrap();
+
+        let result = validate_token(old_cookie.value(), banned_token_store.clone(), test_session_store().await, jwt_keyring.clone()).await;
+        assert!(result.is_err());
+
+        let new_cookie = generate_auth_cookie(&user, TEST_SESSION_ID, banned_token_store.clone(), jwt_keyring.clone()).await.unwrap();
+        let result = validate_token(new_cookie.value(), banned_token_store.clone(), test_session_store().await, jwt_keyring).await.unwrap();
+        assert_eq!(result.generation, 1);
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_after_session_revoked() {
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let session_store = test_session_store().await;
+        let token = sign(&test_claims());
+        assert!(validate_token(&token, banned_token_store.clone(), session_store.clone(), test_keyring()).await.is_ok());
+
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        session_store.write().await.revoke_session(&email, TEST_SESSION_ID).await.unwrap();
+        assert!(validate_token(&token, banned_token_store, session_store, test_keyring()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_verified_email() {
+        let mut user = test_user();
+        user.verified = true;
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let token = generate_auth_token(&user, TEST_SESSION_ID, 0, &JWT_INITIAL_KEY).unwrap();
+
+        let result = validate_token(&token, banned_token_store, test_session_store().await, test_keyring()).await.unwrap();
+        assert!(result.email_verified);
+    }
+
+    fn test_grant(scope: &str) -> AuthorizationGrant {
+        AuthorizationGrant {
+            client_id: "web-app".to_owned(),
+            redirect_uri: "https://app.example.com/callback".to_owned(),
+            email: "test@example.com".to_owned(),
+            scope: scope.to_owned(),
+            code_challenge: "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuNd-bw-cM".to_owned(),
+            nonce: Some("n-0S6_WzA2Mj".to_owned()),
+            user_agent: None,
+            ip_address: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_oauth_tokens() {
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let jwt_keyring = test_keyring();
+        let tokens = generate_oauth_tokens(&test_user(), TEST_SESSION_ID, &test_grant("openid email"), banned_token_store.clone(), jwt_keyring.clone()).await.unwrap();
+
+        let claims = validate_token(&tokens.access_token, banned_token_store.clone(), test_session_store().await, jwt_keyring.clone()).await.unwrap();
+        assert_eq!(claims.client_id.as_deref(), Some("web-app"));
+        assert_eq!(claims.scope.as_deref(), Some("openid email"));
+
+        // The ID token is addressed to the client, so we don't accept it ourselves
+        let id_token = tokens.id_token.unwrap();
+        assert!(validate_token(&id_token, banned_token_store, test_session_store().await, jwt_keyring).await.is_err());
+
+        let mut validation = Validation::new(JWT_INITIAL_KEY.algorithm);
+        validation.set_audience(&["web-app"]);
+        let id_claims = decode::<Claims>(&id_token, &JWT_INITIAL_KEY.decoding_key, &validation).unwrap().claims;
+        assert_eq!(id_claims.iss, *JWT_ISSUER);
+        assert_eq!(id_claims.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+        assert_eq!(id_claims.email.as_deref(), Some("test@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_oauth_tokens_without_openid_scope() {
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let tokens = generate_oauth_tokens(&test_user(), TEST_SESSION_ID, &test_grant("email"), banned_token_store, test_keyring()).await.unwrap();
+        assert!(tokens.id_token.is_none());
+    }
 }
@@ -1,14 +1,51 @@
 use dotenvy::dotenv;
+use jsonwebtoken::Algorithm;
 use lazy_static::lazy_static;
 use secrecy::Secret;
-use std::env as std_env;
+use std::{env as std_env, path::PathBuf};
+
+use super::jwt_key::{JwtKey, DEFAULT_HMAC_KEY_ID};
 
 // Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
 lazy_static! {
     pub static ref JWT_SECRET: Secret<String> = set_token();
+    // The key the JWT keyring starts with. Rotations replace it without a restart.
+    pub static ref JWT_INITIAL_KEY: JwtKey = set_jwt_initial_key();
+    pub static ref JWT_KEY_ROTATION_INTERVAL_SECONDS: u64 = set_jwt_key_rotation_interval_seconds();
+    pub static ref JWT_KEY_ENCRYPTION_KEY: Secret<String> = set_jwt_key_encryption_key();
+    pub static ref JWT_ISSUER: String = set_jwt_issuer();
+    pub static ref JWT_AUDIENCE: String = set_jwt_audience();
+    pub static ref JWT_LEEWAY_SECONDS: u64 = set_jwt_leeway_seconds();
+    pub static ref ADMIN_API_TOKEN: Option<Secret<String>> = set_admin_api_token();
     pub static ref DATABASE_URL: Secret<String> = set_database_url();
     pub static ref REDIS_HOST_NAME: String = set_redis_host();
     pub static ref POSTMARK_AUTH_TOKEN: Secret<String> = set_postmark_auth_token(); 
+    pub static ref AUTH_SERVICE_URL: String = set_auth_service_url();
+    pub static ref REQUIRE_VERIFIED_EMAIL: bool = set_require_verified_email();
+    pub static ref TOTP_ENCRYPTION_KEY: Secret<String> = set_totp_encryption_key();
+    pub static ref TWO_FA_CODE_HASH_KEY: Secret<String> = set_two_fa_code_hash_key();
+    pub static ref MAGIC_LINK_SIGNING_KEY: Secret<String> = set_magic_link_signing_key();
+    pub static ref TOTP_DRIFT_STEPS: u64 = set_totp_drift_steps();
+    pub static ref WEBAUTHN_RP_ID: String = set_webauthn_rp_id();
+    pub static ref WEBAUTHN_ORIGIN: String = set_webauthn_origin();
+    pub static ref ACCOUNT_DELETION_GRACE_PERIOD_SECONDS: u64 = set_account_deletion_grace_period_seconds();
+    pub static ref LOGIN_MAX_ATTEMPTS_PER_IP: u32 = set_login_max_attempts_per_ip();
+    pub static ref LOGIN_MAX_ATTEMPTS_PER_ACCOUNT: u32 = set_login_max_attempts_per_account();
+    pub static ref LOGIN_RATE_LIMIT_WINDOW_SECONDS: u64 = set_login_rate_limit_window_seconds();
+    pub static ref LOGIN_LOCKOUT_THRESHOLD: u32 = set_login_lockout_threshold();
+    pub static ref LOGIN_LOCKOUT_BASE_SECONDS: u64 = set_login_lockout_base_seconds();
+    pub static ref LOGIN_LOCKOUT_MAX_SECONDS: u64 = set_login_lockout_max_seconds();
+    pub static ref TWO_FA_RESEND_COOLDOWN_SECONDS: u64 = set_two_fa_resend_cooldown_seconds();
+    pub static ref TWO_FA_MAX_RESENDS: u32 = set_two_fa_max_resends();
+    pub static ref ARGON2_MEMORY_KIB: u32 = set_argon2_cost(env::ARGON2_MEMORY_KIB_ENV_VAR, DEFAULT_ARGON2_MEMORY_KIB);
+    pub static ref ARGON2_ITERATIONS: u32 = set_argon2_cost(env::ARGON2_ITERATIONS_ENV_VAR, DEFAULT_ARGON2_ITERATIONS);
+    pub static ref ARGON2_PARALLELISM: u32 = set_argon2_cost(env::ARGON2_PARALLELISM_ENV_VAR, DEFAULT_ARGON2_PARALLELISM);
+    pub static ref PASSWORD_MIN_LENGTH: usize = set_password_min_length();
+    pub static ref PASSWORD_MAX_LENGTH: usize = set_password_max_length();
+    pub static ref PASSWORD_MIN_CHARACTER_CLASSES: usize = set_password_min_character_classes();
+    pub static ref PASSWORD_MIN_STRENGTH_SCORE: u8 = set_password_min_strength_score();
+    pub static ref BREACHED_PASSWORDS_DIR: Option<PathBuf> = set_breached_passwords_dir();
+    pub static ref PASSWORD_HISTORY_DEPTH: usize = set_password_history_depth();
 }
 
 fn set_token() -> Secret<String> {
@@ -20,6 +57,82 @@ fn set_token() -> Secret<String> {
     Secret::new(secret)
 }
 
+// HS256 with JWT_SECRET unless JWT_ALGORITHM asks for an asymmetric key, in which case
+// JWT_PRIVATE_KEY_PATH must point to its PEM file and JWT_SECRET is not needed
+fn set_jwt_initial_key() -> JwtKey {
+    dotenv().ok();
+    let algorithm = match std_env::var(env::JWT_ALGORITHM_ENV_VAR) {
+        Ok(value) => match value.as_str() {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "EdDSA" => Algorithm::EdDSA,
+            _ => panic!("JWT_ALGORITHM must be one of HS256, RS256 or EdDSA."),
+        },
+        Err(_) => DEFAULT_JWT_ALGORITHM,
+    };
+    let kid = std_env::var(env::JWT_KEY_ID_ENV_VAR).ok().filter(|kid| !kid.is_empty());
+
+    if algorithm == Algorithm::HS256 {
+        return JwtKey::from_secret(kid.unwrap_or(DEFAULT_HMAC_KEY_ID.to_owned()), &JWT_SECRET);
+    }
+
+    let path = std_env::var(env::JWT_PRIVATE_KEY_PATH_ENV_VAR)
+        .expect("JWT_PRIVATE_KEY_PATH must be set when JWT_ALGORITHM is RS256 or EdDSA.");
+    let pem = std::fs::read(&path).expect("JWT_PRIVATE_KEY_PATH must point to a readable PEM file.");
+    JwtKey::from_pem(algorithm, &pem, kid).expect("JWT private key must match JWT_ALGORITHM.")
+}
+
+// Rotated keys are persisted so every instance can sign with them, encrypted with this
+fn set_jwt_key_encryption_key() -> Secret<String> {
+    dotenv().ok();
+    let key = std_env::var(env::JWT_KEY_ENCRYPTION_KEY_ENV_VAR).expect("JWT_KEY_ENCRYPTION_KEY must be set.");
+    if key.is_empty() {
+        panic!("JWT_KEY_ENCRYPTION_KEY must not be empty.");
+    }
+    Secret::new(key)
+}
+
+// Tokens name this service as their issuer, by default under the URL it is reached at
+fn set_jwt_issuer() -> String {
+    dotenv().ok();
+    std_env::var(env::JWT_ISSUER_ENV_VAR).unwrap_or(AUTH_SERVICE_URL.to_owned())
+}
+
+fn set_jwt_audience() -> String {
+    dotenv().ok();
+    std_env::var(env::JWT_AUDIENCE_ENV_VAR).unwrap_or(DEFAULT_JWT_AUDIENCE.to_owned())
+}
+
+fn set_jwt_leeway_seconds() -> u64 {
+    dotenv().ok();
+    match std_env::var(env::JWT_LEEWAY_SECONDS_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .expect("JWT_LEEWAY_SECONDS must be a non-negative integer."),
+        Err(_) => DEFAULT_JWT_LEEWAY_SECONDS,
+    }
+}
+
+// Zero, the default, leaves rotation to the admin endpoint
+fn set_jwt_key_rotation_interval_seconds() -> u64 {
+    dotenv().ok();
+    match std_env::var(env::JWT_KEY_ROTATION_INTERVAL_SECONDS_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .expect("JWT_KEY_ROTATION_INTERVAL_SECONDS must be a non-negative integer."),
+        Err(_) => DEFAULT_JWT_KEY_ROTATION_INTERVAL_SECONDS,
+    }
+}
+
+// Admin endpoints are disabled unless a token is configured for them
+fn set_admin_api_token() -> Option<Secret<String>> {
+    dotenv().ok();
+    std_env::var(env::ADMIN_API_TOKEN_ENV_VAR)
+        .ok()
+        .filter(|token| !token.is_empty())
+        .map(Secret::new)
+}
+
 fn set_database_url() -> Secret<String> {
     dotenv().ok(); // Load environment variables
     let url = std_env::var(env::DATABASE_URL_ENV_VAR).expect("DATABASE_URL must be set.");
@@ -38,15 +151,353 @@ fn set_postmark_auth_token() -> Secret<String> {
     )
 }
 
+fn set_auth_service_url() -> String {
+    dotenv().ok();
+    std_env::var(env::AUTH_SERVICE_URL_ENV_VAR).unwrap_or(DEFAULT_AUTH_SERVICE_URL.to_owned())
+}
+
+fn set_require_verified_email() -> bool {
+    dotenv().ok();
+    match std_env::var(env::REQUIRE_VERIFIED_EMAIL_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .expect("REQUIRE_VERIFIED_EMAIL must be either true or false."),
+        Err(_) => DEFAULT_REQUIRE_VERIFIED_EMAIL,
+    }
+}
+
+fn set_totp_encryption_key() -> Secret<String> {
+    dotenv().ok();
+    let key = std_env::var(env::TOTP_ENCRYPTION_KEY_ENV_VAR).expect("TOTP_ENCRYPTION_KEY must be set.");
+    if key.is_empty() {
+        panic!("TOTP_ENCRYPTION_KEY must not be empty.");
+    }
+    Secret::new(key)
+}
+
+// Pending 2FA codes are only kept as a hash keyed with this, so they can't be read back out of Redis
+fn set_two_fa_code_hash_key() -> Secret<String> {
+    dotenv().ok();
+    let key = std_env::var(env::TWO_FA_CODE_HASH_KEY_ENV_VAR).expect("TWO_FA_CODE_HASH_KEY must be set.");
+    if key.is_empty() {
+        panic!("TWO_FA_CODE_HASH_KEY must not be empty.");
+    }
+    Secret::new(key)
+}
+
+// Emailed magic login links are signed with this, so links we didn't send are turned away
+fn set_magic_link_signing_key() -> Secret<String> {
+    dotenv().ok();
+    let key = std_env::var(env::MAGIC_LINK_SIGNING_KEY_ENV_VAR).expect("MAGIC_LINK_SIGNING_KEY must be set.");
+    if key.is_empty() {
+        panic!("MAGIC_LINK_SIGNING_KEY must not be empty.");
+    }
+    Secret::new(key)
+}
+
+fn set_totp_drift_steps() -> u64 {
+    dotenv().ok();
+    match std_env::var(env::TOTP_DRIFT_STEPS_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .expect("TOTP_DRIFT_STEPS must be a non-negative integer."),
+        Err(_) => DEFAULT_TOTP_DRIFT_STEPS,
+    }
+}
+
+fn set_webauthn_rp_id() -> String {
+    dotenv().ok();
+    std_env::var(env::WEBAUTHN_RP_ID_ENV_VAR).unwrap_or(DEFAULT_WEBAUTHN_RP_ID.to_owned())
+}
+
+// Passkey ceremonies run in the browser on the pages this service serves,
+// so by default the expected origin is the service's own URL
+fn set_webauthn_origin() -> String {
+    dotenv().ok();
+    std_env::var(env::WEBAUTHN_ORIGIN_ENV_VAR).unwrap_or(AUTH_SERVICE_URL.to_owned())
+}
+
+// How long a user has to change their mind after asking for their account to be deleted
+fn set_account_deletion_grace_period_seconds() -> u64 {
+    dotenv().ok();
+    match std_env::var(env::ACCOUNT_DELETION_GRACE_PERIOD_SECONDS_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .expect("ACCOUNT_DELETION_GRACE_PERIOD_SECONDS must be a non-negative integer."),
+        Err(_) => DEFAULT_ACCOUNT_DELETION_GRACE_PERIOD_SECONDS,
+    }
+}
+
+// Login and 2FA attempts from one client address allowed per rate limit window
+fn set_login_max_attempts_per_ip() -> u32 {
+    dotenv().ok();
+    match std_env::var(env::LOGIN_MAX_ATTEMPTS_PER_IP_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value > 0)
+            .expect("LOGIN_MAX_ATTEMPTS_PER_IP must be a positive integer."),
+        Err(_) => DEFAULT_LOGIN_MAX_ATTEMPTS_PER_IP,
+    }
+}
+
+// Login and 2FA attempts against one account allowed per rate limit window
+fn set_login_max_attempts_per_account() -> u32 {
+    dotenv().ok();
+    match std_env::var(env::LOGIN_MAX_ATTEMPTS_PER_ACCOUNT_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value > 0)
+            .expect("LOGIN_MAX_ATTEMPTS_PER_ACCOUNT must be a positive integer."),
+        Err(_) => DEFAULT_LOGIN_MAX_ATTEMPTS_PER_ACCOUNT,
+    }
+}
+
+fn set_login_rate_limit_window_seconds() -> u64 {
+    dotenv().ok();
+    match std_env::var(env::LOGIN_RATE_LIMIT_WINDOW_SECONDS_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value > 0)
+            .expect("LOGIN_RATE_LIMIT_WINDOW_SECONDS must be a positive integer."),
+        Err(_) => DEFAULT_LOGIN_RATE_LIMIT_WINDOW_SECONDS,
+    }
+}
+
+// Consecutive failures before an account or login attempt is locked. Each failure past
+// it doubles the lock, starting from LOGIN_LOCKOUT_BASE_SECONDS up to LOGIN_LOCKOUT_MAX_SECONDS.
+fn set_login_lockout_threshold() -> u32 {
+    dotenv().ok();
+    match std_env::var(env::LOGIN_LOCKOUT_THRESHOLD_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value > 0)
+            .expect("LOGIN_LOCKOUT_THRESHOLD must be a positive integer."),
+        Err(_) => DEFAULT_LOGIN_LOCKOUT_THRESHOLD,
+    }
+}
+
+fn set_login_lockout_base_seconds() -> u64 {
+    dotenv().ok();
+    match std_env::var(env::LOGIN_LOCKOUT_BASE_SECONDS_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value > 0)
+            .expect("LOGIN_LOCKOUT_BASE_SECONDS must be a positive integer."),
+        Err(_) => DEFAULT_LOGIN_LOCKOUT_BASE_SECONDS,
+    }
+}
+
+fn set_login_lockout_max_seconds() -> u64 {
+    dotenv().ok();
+    match std_env::var(env::LOGIN_LOCKOUT_MAX_SECONDS_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value > 0)
+            .expect("LOGIN_LOCKOUT_MAX_SECONDS must be a positive integer."),
+        Err(_) => DEFAULT_LOGIN_LOCKOUT_MAX_SECONDS,
+    }
+}
+
+// How long a user has to wait after a 2FA code was sent before asking for it again
+fn set_two_fa_resend_cooldown_seconds() -> u64 {
+    dotenv().ok();
+    match std_env::var(env::TWO_FA_RESEND_COOLDOWN_SECONDS_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .expect("TWO_FA_RESEND_COOLDOWN_SECONDS must be a non-negative integer."),
+        Err(_) => DEFAULT_TWO_FA_RESEND_COOLDOWN_SECONDS,
+    }
+}
+
+// How many times the code for a single login attempt can be re-sent
+fn set_two_fa_max_resends() -> u32 {
+    dotenv().ok();
+    match std_env::var(env::TWO_FA_MAX_RESENDS_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .expect("TWO_FA_MAX_RESENDS must be a non-negative integer."),
+        Err(_) => DEFAULT_TWO_FA_MAX_RESENDS,
+    }
+}
+
+// Cost settings for new password hashes. Existing hashes are upgraded when their owner next logs in.
+fn set_argon2_cost(env_var: &str, default: u32) -> u32 {
+    dotenv().ok();
+    match std_env::var(env_var) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value > 0)
+            .unwrap_or_else(|| panic!("{} must be a positive integer.", env_var)),
+        Err(_) => default,
+    }
+}
+
+// Passwords can't be shorter than Password::parse allows, so the minimum starts there
+fn set_password_min_length() -> usize {
+    dotenv().ok();
+    match std_env::var(env::PASSWORD_MIN_LENGTH_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value >= DEFAULT_PASSWORD_MIN_LENGTH)
+            .expect("PASSWORD_MIN_LENGTH must be an integer of at least 8."),
+        Err(_) => DEFAULT_PASSWORD_MIN_LENGTH,
+    }
+}
+
+fn set_password_max_length() -> usize {
+    dotenv().ok();
+    match std_env::var(env::PASSWORD_MAX_LENGTH_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value >= *PASSWORD_MIN_LENGTH)
+            .expect("PASSWORD_MAX_LENGTH must be an integer no smaller than PASSWORD_MIN_LENGTH."),
+        Err(_) => DEFAULT_PASSWORD_MAX_LENGTH,
+    }
+}
+
+// How many of lowercase, uppercase, digits and symbols a password has to mix
+fn set_password_min_character_classes() -> usize {
+    dotenv().ok();
+    match std_env::var(env::PASSWORD_MIN_CHARACTER_CLASSES_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| (1..=4).contains(value))
+            .expect("PASSWORD_MIN_CHARACTER_CLASSES must be between 1 and 4."),
+        Err(_) => DEFAULT_PASSWORD_MIN_CHARACTER_CLASSES,
+    }
+}
+
+// On zxcvbn's 0 (guessable in under a thousand tries) to 4 (over ten billion) scale
+fn set_password_min_strength_score() -> u8 {
+    dotenv().ok();
+    match std_env::var(env::PASSWORD_MIN_STRENGTH_SCORE_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|value| *value <= 4)
+            .expect("PASSWORD_MIN_STRENGTH_SCORE must be between 0 and 4."),
+        Err(_) => DEFAULT_PASSWORD_MIN_STRENGTH_SCORE,
+    }
+}
+
+// Directory of k-anonymity range files, one per 5 character SHA-1 prefix. Checking for breached
+// passwords is off when it isn't set.
+fn set_breached_passwords_dir() -> Option<PathBuf> {
+    dotenv().ok();
+    std_env::var(env::BREACHED_PASSWORDS_DIR_ENV_VAR)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+// How many of a user's most recent passwords, counting the current one, a new password
+// can't be. Zero turns the check off.
+fn set_password_history_depth() -> usize {
+    dotenv().ok();
+    match std_env::var(env::PASSWORD_HISTORY_DEPTH_ENV_VAR) {
+        Ok(value) => value
+            .parse()
+            .expect("PASSWORD_HISTORY_DEPTH must be a non-negative integer."),
+        Err(_) => DEFAULT_PASSWORD_HISTORY_DEPTH,
+    }
+}
+
 pub mod env {
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
+    pub const JWT_ALGORITHM_ENV_VAR: &str = "JWT_ALGORITHM";
+    pub const JWT_PRIVATE_KEY_PATH_ENV_VAR: &str = "JWT_PRIVATE_KEY_PATH";
+    pub const JWT_KEY_ID_ENV_VAR: &str = "JWT_KEY_ID";
+    pub const JWT_KEY_ROTATION_INTERVAL_SECONDS_ENV_VAR: &str = "JWT_KEY_ROTATION_INTERVAL_SECONDS";
+    pub const JWT_KEY_ENCRYPTION_KEY_ENV_VAR: &str = "JWT_KEY_ENCRYPTION_KEY";
+    pub const JWT_ISSUER_ENV_VAR: &str = "JWT_ISSUER";
+    pub const JWT_AUDIENCE_ENV_VAR: &str = "JWT_AUDIENCE";
+    pub const JWT_LEEWAY_SECONDS_ENV_VAR: &str = "JWT_LEEWAY_SECONDS";
+    pub const ADMIN_API_TOKEN_ENV_VAR: &str = "ADMIN_API_TOKEN";
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
     pub const REDIS_HOST_NAME_ENV_VAR: &str = "REDIS_HOST_NAME";
     pub const POSTMARK_AUTH_TOKEN_ENV_VAR: &str = "POSTMARK_AUTH_TOKEN";
+    pub const AUTH_SERVICE_URL_ENV_VAR: &str = "AUTH_SERVICE_URL";
+    pub const REQUIRE_VERIFIED_EMAIL_ENV_VAR: &str = "REQUIRE_VERIFIED_EMAIL";
+    pub const TOTP_ENCRYPTION_KEY_ENV_VAR: &str = "TOTP_ENCRYPTION_KEY";
+    pub const TWO_FA_CODE_HASH_KEY_ENV_VAR: &str = "TWO_FA_CODE_HASH_KEY";
+    pub const MAGIC_LINK_SIGNING_KEY_ENV_VAR: &str = "MAGIC_LINK_SIGNING_KEY";
+    pub const TOTP_DRIFT_STEPS_ENV_VAR: &str = "TOTP_DRIFT_STEPS";
+    pub const WEBAUTHN_RP_ID_ENV_VAR: &str = "WEBAUTHN_RP_ID";
+    pub const WEBAUTHN_ORIGIN_ENV_VAR: &str = "WEBAUTHN_ORIGIN";
+    pub const ACCOUNT_DELETION_GRACE_PERIOD_SECONDS_ENV_VAR: &str = "ACCOUNT_DELETION_GRACE_PERIOD_SECONDS";
+    pub const LOGIN_MAX_ATTEMPTS_PER_IP_ENV_VAR: &str = "LOGIN_MAX_ATTEMPTS_PER_IP";
+    pub const LOGIN_MAX_ATTEMPTS_PER_ACCOUNT_ENV_VAR: &str = "LOGIN_MAX_ATTEMPTS_PER_ACCOUNT";
+    pub const LOGIN_RATE_LIMIT_WINDOW_SECONDS_ENV_VAR: &str = "LOGIN_RATE_LIMIT_WINDOW_SECONDS";
+    pub const LOGIN_LOCKOUT_THRESHOLD_ENV_VAR: &str = "LOGIN_LOCKOUT_THRESHOLD";
+    pub const LOGIN_LOCKOUT_BASE_SECONDS_ENV_VAR: &str = "LOGIN_LOCKOUT_BASE_SECONDS";
+    pub const LOGIN_LOCKOUT_MAX_SECONDS_ENV_VAR: &str = "LOGIN_LOCKOUT_MAX_SECONDS";
+    pub const TWO_FA_RESEND_COOLDOWN_SECONDS_ENV_VAR: &str = "TWO_FA_RESEND_COOLDOWN_SECONDS";
+    pub const TWO_FA_MAX_RESENDS_ENV_VAR: &str = "TWO_FA_MAX_RESENDS";
+    pub const ARGON2_MEMORY_KIB_ENV_VAR: &str = "ARGON2_MEMORY_KIB";
+    pub const ARGON2_ITERATIONS_ENV_VAR: &str = "ARGON2_ITERATIONS";
+    pub const ARGON2_PARALLELISM_ENV_VAR: &str = "ARGON2_PARALLELISM";
+    pub const PASSWORD_MIN_LENGTH_ENV_VAR: &str = "PASSWORD_MIN_LENGTH";
+    pub const PASSWORD_MAX_LENGTH_ENV_VAR: &str = "PASSWORD_MAX_LENGTH";
+    pub const PASSWORD_MIN_CHARACTER_CLASSES_ENV_VAR: &str = "PASSWORD_MIN_CHARACTER_CLASSES";
+    pub const PASSWORD_MIN_STRENGTH_SCORE_ENV_VAR: &str = "PASSWORD_MIN_STRENGTH_SCORE";
+    pub const BREACHED_PASSWORDS_DIR_ENV_VAR: &str = "BREACHED_PASSWORDS_DIR";
+    pub const PASSWORD_HISTORY_DEPTH_ENV_VAR: &str = "PASSWORD_HISTORY_DEPTH";
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
+pub const REFRESH_COOKIE_NAME: &str = "refresh_token";
+pub const DEFAULT_JWT_ALGORITHM: Algorithm = Algorithm::HS256;
+pub const DEFAULT_JWT_KEY_ROTATION_INTERVAL_SECONDS: u64 = 0;
+// How often each instance reloads the keyring, to pick up keys rotated by the others
+pub const JWT_KEYRING_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+// The app our tokens are meant for, which checks them through /verify-token
+pub const DEFAULT_JWT_AUDIENCE: &str = "app-service";
+// Allowed clock skew between us and whoever checks our tokens' exp and nbf claims
+pub const DEFAULT_JWT_LEEWAY_SECONDS: u64 = 60;
 pub const DEFAULT_REDIS_HOSTNAME: &str = "127.0.0.1"; 
+pub const DEFAULT_AUTH_SERVICE_URL: &str = "http://localhost:3000";
+pub const DEFAULT_REQUIRE_VERIFIED_EMAIL: bool = false;
+// Number of 30 second steps either side of the current one in which a TOTP code is still accepted
+pub const DEFAULT_TOTP_DRIFT_STEPS: u64 = 1;
+pub const TOTP_ISSUER: &str = "AuthService";
+// The RP ID must be the host (or a parent domain of it) the browser sees the service on
+pub const DEFAULT_WEBAUTHN_RP_ID: &str = "localhost";
+pub const WEBAUTHN_RP_NAME: &str = "AuthService";
+pub const DEFAULT_ACCOUNT_DELETION_GRACE_PERIOD_SECONDS: u64 = 604_800; // 7 days
+// How often accounts whose deletion grace period has ended are looked for and removed
+pub const ACCOUNT_DELETION_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+// How long a 2FA code can be used to finish logging in
+pub const TWO_FA_CODE_TTL_SECONDS: u64 = 600;
+// Logins a user can have waiting on 2FA at the same time, e.g. on different devices
+pub const MAX_PENDING_TWO_FA_CHALLENGES: usize = 5;
+// Wrong codes a login attempt can take before it is thrown away and the user has to log in again
+pub const MAX_TWO_FA_CODE_ATTEMPTS: u32 = 5;
+pub const DEFAULT_LOGIN_MAX_ATTEMPTS_PER_IP: u32 = 20;
+pub const DEFAULT_LOGIN_MAX_ATTEMPTS_PER_ACCOUNT: u32 = 10;
+pub const DEFAULT_LOGIN_RATE_LIMIT_WINDOW_SECONDS: u64 = 60;
+pub const DEFAULT_LOGIN_LOCKOUT_THRESHOLD: u32 = 5;
+pub const DEFAULT_LOGIN_LOCKOUT_BASE_SECONDS: u64 = 30;
+pub const DEFAULT_LOGIN_LOCKOUT_MAX_SECONDS: u64 = 3600;
+pub const DEFAULT_TWO_FA_RESEND_COOLDOWN_SECONDS: u64 = 30;
+pub const DEFAULT_TWO_FA_MAX_RESENDS: u32 = 3;
+pub const DEFAULT_ARGON2_MEMORY_KIB: u32 = 15000;
+pub const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+pub const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+pub const DEFAULT_PASSWORD_MIN_LENGTH: usize = 8;
+pub const DEFAULT_PASSWORD_MAX_LENGTH: usize = 128;
+pub const DEFAULT_PASSWORD_MIN_CHARACTER_CLASSES: usize = 1;
+pub const DEFAULT_PASSWORD_MIN_STRENGTH_SCORE: u8 = 0;
+pub const DEFAULT_PASSWORD_HISTORY_DEPTH: usize = 5;
 
 pub mod prod {
     pub const APP_ADDRESS: &str = "0.0.0.0:3000";
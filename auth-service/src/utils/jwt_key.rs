@@ -0,0 +1,269 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use color_eyre::eyre::{eyre, Context, Result};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use ring::{
+    rand::SystemRandom,
+    rsa::PublicKeyComponents,
+    signature::{Ed25519KeyPair, KeyPair, RsaKeyPair},
+};
+use rsa::{pkcs8::EncodePrivateKey, RsaPrivateKey};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+// Key ID used for the shared HS256 secret when none is configured. Symmetric keys are never
+// published, so unlike asymmetric keys there is no public thumbprint to derive one from.
+pub const DEFAULT_HMAC_KEY_ID: &str = "default";
+
+// Size of the keys generated on rotation
+const GENERATED_HMAC_SECRET_LENGTH: usize = 64;
+const GENERATED_RSA_KEY_BITS: usize = 2048;
+
+// A key JWTs are signed and verified with, named in each token's header by its `kid`
+#[derive(Clone)]
+pub struct JwtKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub encoding_key: EncodingKey,
+    pub decoding_key: DecodingKey,
+    // The public half, served at /.well-known/jwks.json so other services can verify tokens
+    // themselves. Shared HS256 secrets have no public half and are never published.
+    pub jwk: Option<Jwk>,
+    // The PEM private key, or the shared secret for HS256, kept so rotated keys can be persisted
+    pub private_key: Secret<String>,
+}
+
+impl JwtKey {
+    pub fn from_secret(kid: String, secret: &Secret<String>) -> Self {
+        let bytes = secret.expose_secret().as_bytes();
+        Self {
+            kid,
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(bytes),
+            decoding_key: DecodingKey::from_secret(bytes),
+            jwk: None,
+            private_key: secret.clone(),
+        }
+    }
+
+    // Rebuild a key from the private material it was persisted with
+    pub fn from_private_key(algorithm: Algorithm, kid: String, private_key: &Secret<String>) -> Result<Self> {
+        match algorithm {
+            Algorithm::HS256 => Ok(Self::from_secret(kid, private_key)),
+            _ => Self::from_pem(algorithm, private_key.expose_secret().as_bytes(), Some(kid)),
+        }
+    }
+
+    // Load an RS256 or EdDSA private key from PEM. Without an explicit `kid` the key is named
+    // by its RFC 7638 thumbprint, so the same key always gets the same ID.
+    pub fn from_pem(algorithm: Algorithm, pem: &[u8], kid: Option<String>) -> Result<Self> {
+        let parsed = pem::parse(pem).wrap_err("JWT private key is not valid PEM")?;
+        let private_key = String::from_utf8(pem.to_vec()).wrap_err("JWT private key is not valid PEM")?;
+
+        let (encoding_key, parameters, key_algorithm) = match algorithm {
+            Algorithm::RS256 => {
+                let key_pair = match parsed.tag() {
+                    "RSA PRIVATE KEY" => RsaKeyPair::from_der(parsed.contents()),
+                    _ => RsaKeyPair::from_pkcs8(parsed.contents()),
+                }
+                .map_err(|e| eyre!("JWT private key is not a usable RSA key: {}", e))?;
+                let components = PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+
+                let encoding_key = EncodingKey::from_rsa_pem(pem).wrap_err("failed to load RSA private key")?;
+                let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(components.n),
+                    e: URL_SAFE_NO_PAD.encode(components.e),
+                });
+                (encoding_key, parameters, KeyAlgorithm::RS256)
+            }
+            Algorithm::EdDSA => {
+                let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(parsed.contents())
+                    .map_err(|e| eyre!("JWT private key is not a usable Ed25519 key: {}", e))?;
+
+                let encoding_key = EncodingKey::from_ed_pem(pem).wrap_err("failed to load Ed25519 private key")?;
+                let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+                });
+                (encoding_key, parameters, KeyAlgorithm::EdDSA)
+            }
+            other => return Err(eyre!("{:?} keys can't be loaded from PEM", other)),
+        };
+
+        let kid = match kid {
+            Some(kid) => kid,
+            None => jwk_thumbprint(&parameters)?,
+        };
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: parameters,
+        };
+        let decoding_key = DecodingKey::from_jwk(&jwk).wrap_err("failed to build JWT decoding key")?;
+
+        Ok(Self {
+            kid,
+            algorithm,
+            encoding_key,
+            decoding_key,
+            jwk: Some(jwk),
+            private_key: Secret::new(private_key),
+        })
+    }
+
+    // Generate a fresh key for rotation. RSA key generation takes a noticeable amount of CPU,
+    // so callers on the async runtime should run this on a blocking thread.
+    pub fn generate(algorithm: Algorithm) -> Result<Self> {
+        match algorithm {
+            Algorithm::HS256 => {
+                let secret: String = rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(GENERATED_HMAC_SECRET_LENGTH)
+                    .map(char::from)
+                    .collect();
+                Ok(Self::from_secret(Uuid::new_v4().to_string(), &Secret::new(secret)))
+            }
+            Algorithm::RS256 => {
+                let key = RsaPrivateKey::new(&mut rand::thread_rng(), GENERATED_RSA_KEY_BITS)
+                    .wrap_err("failed to generate RSA key")?;
+                let pem = key
+                    .to_pkcs8_pem(rsa::pkcs8::LineEnding::LF)
+                    .wrap_err("failed to encode RSA key")?;
+                Self::from_pem(algorithm, pem.as_bytes(), None)
+            }
+            Algorithm::EdDSA => {
+                let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+                    .map_err(|e| eyre!("failed to generate Ed25519 key: {}", e))?;
+                let pem = pem::encode(&pem::Pem::new("PRIVATE KEY", document.as_ref()));
+                Self::from_pem(algorithm, pem.as_bytes(), None)
+            }
+            other => Err(eyre!("{:?} keys can't be generated", other)),
+        }
+    }
+}
+
+// RFC 7638: SHA-256 over the key's required members, serialized in lexicographic order
+fn jwk_thumbprint(parameters: &AlgorithmParameters) -> Result<String> {
+    let canonical = match parameters {
+        AlgorithmParameters::RSA(rsa) => format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, rsa.e, rsa.n),
+        AlgorithmParameters::OctetKeyPair(okp) => format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, okp.x),
+        _ => return Err(eyre!("unsupported key type for JWK thumbprint")),
+    };
+    Ok(URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    const RSA_PRIVATE_KEY: &[u8] = include_bytes!("../../tests/fixtures/jwt_rs256_private.pem");
+    const ED25519_PRIVATE_KEY: &[u8] = include_bytes!("../../tests/fixtures/jwt_eddsa_private.pem");
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn round_trip(key: &JwtKey) -> TestClaims {
+        let claims = TestClaims { sub: "test@example.com".to_owned(), exp: 4_000_000_000 };
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+        let token = encode(&header, &claims, &key.encoding_key).unwrap();
+
+        assert_eq!(decode_header(&token).unwrap().kid, Some(key.kid.clone()));
+        decode::<TestClaims>(&token, &key.decoding_key, &Validation::new(key.algorithm))
+            .unwrap()
+            .claims
+    }
+
+    #[test]
+    fn rs256_key_signs_and_verifies() {
+        let key = JwtKey::from_pem(Algorithm::RS256, RSA_PRIVATE_KEY, None).unwrap();
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+
+        // The published key alone is enough to verify tokens
+        let jwk = key.jwk.as_ref().unwrap();
+        assert_eq!(jwk.common.key_id, Some(key.kid.clone()));
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::RSA(_)));
+        assert!(DecodingKey::from_jwk(jwk).is_ok());
+    }
+
+    #[test]
+    fn eddsa_key_signs_and_verifies() {
+        let key = JwtKey::from_pem(Algorithm::EdDSA, ED25519_PRIVATE_KEY, Some("ed-1".to_owned())).unwrap();
+        assert_eq!(key.kid, "ed-1");
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+        assert!(matches!(key.jwk.unwrap().algorithm, AlgorithmParameters::OctetKeyPair(_)));
+    }
+
+    #[test]
+    fn key_must_match_algorithm() {
+        assert!(JwtKey::from_pem(Algorithm::RS256, ED25519_PRIVATE_KEY, None).is_err());
+        assert!(JwtKey::from_pem(Algorithm::EdDSA, RSA_PRIVATE_KEY, None).is_err());
+        assert!(JwtKey::from_pem(Algorithm::HS256, RSA_PRIVATE_KEY, None).is_err());
+    }
+
+    #[test]
+    fn hmac_key_is_not_published() {
+        let key = JwtKey::from_secret(DEFAULT_HMAC_KEY_ID.to_owned(), &Secret::new("secret".to_owned()));
+        assert!(key.jwk.is_none());
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+    }
+
+    #[test]
+    fn generated_keys_sign_and_verify() {
+        for algorithm in [Algorithm::HS256, Algorithm::RS256, Algorithm::EdDSA] {
+            let key = JwtKey::generate(algorithm).unwrap();
+            assert_eq!(key.algorithm, algorithm);
+            assert_eq!(key.jwk.is_some(), algorithm != Algorithm::HS256);
+            assert_eq!(round_trip(&key).sub, "test@example.com");
+        }
+
+        // Every rotation must introduce a key with a new ID
+        let first = JwtKey::generate(Algorithm::HS256).unwrap();
+        let second = JwtKey::generate(Algorithm::HS256).unwrap();
+        assert_ne!(first.kid, second.kid);
+    }
+
+    #[test]
+    fn keys_can_be_rebuilt_from_their_private_key() {
+        for algorithm in [Algorithm::HS256, Algorithm::RS256, Algorithm::EdDSA] {
+            let key = JwtKey::generate(algorithm).unwrap();
+            let rebuilt = JwtKey::from_private_key(algorithm, key.kid.clone(), &key.private_key).unwrap();
+            assert_eq!(rebuilt.kid, key.kid);
+
+            // A token signed by one verifies with the other
+            let claims = TestClaims { sub: "test@example.com".to_owned(), exp: 4_000_000_000 };
+            let token = encode(&Header::new(algorithm), &claims, &key.encoding_key).unwrap();
+            assert!(decode::<TestClaims>(&token, &rebuilt.decoding_key, &Validation::new(algorithm)).is_ok());
+        }
+    }
+
+    #[test]
+    fn thumbprint_matches_rfc_7638_example() {
+        let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw".to_owned(),
+            e: "AQAB".to_owned(),
+        });
+        assert_eq!(jwk_thumbprint(&parameters).unwrap(), "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs");
+    }
+}
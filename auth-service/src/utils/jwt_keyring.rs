@@ -0,0 +1,333 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Context, Result};
+use jsonwebtoken::jwk::JwkSet;
+
+use crate::{
+    app_state::{JwtKeyStoreType, JwtKeyringType},
+    domain::StoredJwtKey,
+};
+
+use super::{auth::TOKEN_TTL_SECONDS, jwt_key::JwtKey};
+
+// The keys JWTs are signed and verified with. New tokens are always signed with the newest key.
+// Keys it replaced stay usable for verification until they retire, which is no earlier than the
+// last token they signed expires, so rotating never logs anyone out.
+pub struct JwtKeyring {
+    // The key from configuration, which rotated keys are loaded on top of
+    configured_key: Arc<JwtKey>,
+    // Oldest first; the last entry is the signing key and the only one without a retirement time
+    keys: Vec<KeyringEntry>,
+}
+
+struct KeyringEntry {
+    key: Arc<JwtKey>,
+    // Only rotated keys have one; the configured key is always the oldest
+    created_at: Option<DateTime<Utc>>,
+    retires_at: Option<DateTime<Utc>>,
+}
+
+impl KeyringEntry {
+    fn is_retired(&self, now: DateTime<Utc>) -> bool {
+        self.retires_at.is_some_and(|retires_at| retires_at <= now)
+    }
+}
+
+impl JwtKeyring {
+    pub fn new(key: JwtKey) -> Self {
+        let key = Arc::new(key);
+        Self {
+            configured_key: key.clone(),
+            keys: vec![KeyringEntry { key, created_at: None, retires_at: None }],
+        }
+    }
+
+    pub fn signing_key(&self) -> Arc<JwtKey> {
+        self.keys.last().expect("keyring always holds a signing key").key.clone()
+    }
+
+    // When the signing key was rotated in, unless it is still the configured key
+    pub fn last_rotated_at(&self) -> Option<DateTime<Utc>> {
+        self.keys.last().and_then(|entry| entry.created_at)
+    }
+
+    // Tokens from before key IDs were added have none, and can only have come from the signing key
+    pub fn verification_key(&self, kid: Option<&str>) -> Option<Arc<JwtKey>> {
+        let Some(kid) = kid else {
+            return Some(self.signing_key());
+        };
+
+        let now = Utc::now();
+        self.keys
+            .iter()
+            .find(|entry| entry.key.kid == kid && !entry.is_retired(now))
+            .map(|entry| entry.key.clone())
+    }
+
+    // Make `key` the signing key. The keys it replaces keep verifying tokens for at most
+    // `retire_after`, so a rotation with no grace period cuts off every older key at once.
+    pub fn rotate(&mut self, key: JwtKey, retire_after: chrono::Duration) {
+        let now = Utc::now();
+        let retires_at = now + retire_after;
+        self.keys.retain(|entry| !entry.is_retired(now));
+        for entry in self.keys.iter_mut() {
+            entry.retires_at = Some(entry.retires_at.map_or(retires_at, |current| current.min(retires_at)));
+        }
+        self.keys.push(KeyringEntry { key: Arc::new(key), created_at: Some(now), retires_at: None });
+    }
+
+    // Rebuild the keyring from the configured key followed by `stored_keys`, oldest first. Each key
+    // retires `retire_after` after the one replacing it was created. Keys of another algorithm than
+    // the configured one are skipped, so changing JWT_ALGORITHM starts over from the configured key.
+    pub fn load(&mut self, stored_keys: Vec<StoredJwtKey>, retire_after: chrono::Duration) -> Result<()> {
+        let mut keys = vec![KeyringEntry { key: self.configured_key.clone(), created_at: None, retires_at: None }];
+        for stored_key in stored_keys {
+            if stored_key.algorithm != self.configured_key.algorithm {
+                continue;
+            }
+
+            // Keys already on the keyring don't need parsing again
+            let key = match self.keys.iter().find(|entry| entry.key.kid == stored_key.kid) {
+                Some(entry) => entry.key.clone(),
+                None => Arc::new(
+                    JwtKey::from_private_key(stored_key.algorithm, stored_key.kid, &stored_key.private_key)
+                        .wrap_err("failed to load stored JWT key")?,
+                ),
+            };
+
+            if let Some(replaced) = keys.last_mut() {
+                replaced.retires_at = Some(stored_key.created_at + retire_after);
+            }
+            keys.push(KeyringEntry { key, created_at: Some(stored_key.created_at), retires_at: None });
+        }
+
+        let now = Utc::now();
+        keys.retain(|entry| !entry.is_retired(now));
+        self.keys = keys;
+        Ok(())
+    }
+
+    // Every public key that can still verify a token, so services caching the set keep
+    // accepting tokens signed just before a rotation
+    pub fn jwks(&self) -> JwkSet {
+        let now = Utc::now();
+        let keys = self
+            .keys
+            .iter()
+            .filter(|entry| !entry.is_retired(now))
+            .filter_map(|entry| entry.key.jwk.clone())
+            .collect();
+        JwkSet { keys }
+    }
+}
+
+// Replace the signing key with a newly generated one of the same algorithm, returning its ID.
+// The old key is retired once every token it signed has expired. The new key is persisted, so it
+// survives restarts and the other instances start using it on their next sync.
+#[tracing::instrument(name = "Rotating JWT signing key", skip_all)]
+pub async fn rotate_jwt_key(jwt_keyring: JwtKeyringType, jwt_key_store: JwtKeyStoreType) -> Result<String> {
+    let algorithm = jwt_keyring.read().await.signing_key().algorithm;
+    let key = tokio::task::spawn_blocking(move || JwtKey::generate(algorithm))
+        .await
+        .wrap_err("JWT key generation task failed")??;
+    let kid = key.kid.clone();
+
+    let retire_after = token_lifetime()?;
+    let now = Utc::now();
+    let stored_key = StoredJwtKey {
+        kid: kid.clone(),
+        algorithm,
+        private_key: key.private_key.clone(),
+        created_at: now,
+    };
+    {
+        let mut store = jwt_key_store.write().await;
+        store.add_key(stored_key).await.wrap_err("failed to persist rotated JWT key")?;
+        // Keys replaced a full token lifetime ago can no longer verify anything
+        if let Err(e) = store.remove_keys_replaced_before(now - retire_after).await {
+            tracing::warn!("failed to remove retired JWT keys: {:?}", e);
+        }
+    }
+
+    jwt_keyring.write().await.rotate(key, retire_after);
+
+    tracing::info!(kid = %kid, "rotated JWT signing key");
+    Ok(kid)
+}
+
+// Rotate the signing key every `interval` for as long as the service runs. Every instance runs
+// this schedule, so an instance skips its turn when another one has rotated within the interval.
+pub async fn rotate_jwt_key_periodically(jwt_keyring: JwtKeyringType, jwt_key_store: JwtKeyStoreType, interval: Duration) {
+    let Ok(min_key_age) = chrono::Duration::from_std(interval) else {
+        tracing::error!("JWT key rotation interval is too long, not rotating");
+        return;
+    };
+
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately, and the key loaded at startup is still fresh
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if let Err(e) = sync_jwt_keyring(jwt_keyring.clone(), jwt_key_store.clone()).await {
+            tracing::error!("scheduled JWT key rotation failed: {:?}", e);
+            continue;
+        }
+
+        let last_rotated_at = jwt_keyring.read().await.last_rotated_at();
+        if last_rotated_at.is_some_and(|rotated_at| Utc::now() - rotated_at < min_key_age) {
+            continue;
+        }
+
+        if let Err(e) = rotate_jwt_key(jwt_keyring.clone(), jwt_key_store.clone()).await {
+            tracing::error!("scheduled JWT key rotation failed: {:?}", e);
+        }
+    }
+}
+
+// Load the keys rotated by any instance, including this one before a restart
+#[tracing::instrument(name = "Syncing JWT keyring", skip_all)]
+pub async fn sync_jwt_keyring(jwt_keyring: JwtKeyringType, jwt_key_store: JwtKeyStoreType) -> Result<()> {
+    let stored_keys = jwt_key_store
+        .read()
+        .await
+        .get_keys()
+        .await
+        .wrap_err("failed to retrieve stored JWT keys")?;
+    let retire_after = token_lifetime()?;
+    jwt_keyring.write().await.load(stored_keys, retire_after)
+}
+
+// Reload the keyring every `interval` for as long as the service runs. Until an instance has
+// synced, it rejects tokens signed with a key another instance has just rotated in.
+pub async fn sync_jwt_keyring_periodically(jwt_keyring: JwtKeyringType, jwt_key_store: JwtKeyStoreType, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately, and the keyring was loaded at startup
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if let Err(e) = sync_jwt_keyring(jwt_keyring.clone(), jwt_key_store.clone()).await {
+            tracing::error!("JWT keyring sync failed: {:?}", e);
+        }
+    }
+}
+
+fn token_lifetime() -> Result<chrono::Duration> {
+    chrono::Duration::try_seconds(TOKEN_TTL_SECONDS).ok_or(eyre!("failed to create token lifetime delta"))
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::Algorithm;
+    use tokio::sync::RwLock;
+
+    use crate::services::data_stores::HashmapJwtKeyStore;
+
+    use super::*;
+
+    fn rotated_keyring(retire_after: chrono::Duration) -> (JwtKeyring, String, String) {
+        let mut keyring = JwtKeyring::new(JwtKey::generate(Algorithm::EdDSA).unwrap());
+        let old_kid = keyring.signing_key().kid.clone();
+        keyring.rotate(JwtKey::generate(Algorithm::EdDSA).unwrap(), retire_after);
+        let new_kid = keyring.signing_key().kid.clone();
+        (keyring, old_kid, new_kid)
+    }
+
+    #[test]
+    fn rotation_changes_signing_key_but_keeps_old_key_for_verification() {
+        let (keyring, old_kid, new_kid) = rotated_keyring(chrono::Duration::minutes(10));
+        assert_ne!(old_kid, new_kid);
+        assert!(keyring.verification_key(Some(&old_kid)).is_some());
+        assert!(keyring.verification_key(Some(&new_kid)).is_some());
+        assert!(keyring.verification_key(Some("unknown")).is_none());
+
+        let published: Vec<_> = keyring.jwks().keys.into_iter().filter_map(|jwk| jwk.common.key_id).collect();
+        assert_eq!(published, vec![old_kid, new_kid]);
+    }
+
+    #[test]
+    fn retired_keys_no_longer_verify_or_get_published() {
+        let (keyring, old_kid, new_kid) = rotated_keyring(chrono::Duration::zero());
+        assert!(keyring.verification_key(Some(&old_kid)).is_none());
+        assert!(keyring.verification_key(Some(&new_kid)).is_some());
+
+        let published: Vec<_> = keyring.jwks().keys.into_iter().filter_map(|jwk| jwk.common.key_id).collect();
+        assert_eq!(published, vec![new_kid]);
+    }
+
+    #[test]
+    fn retired_keys_are_dropped_on_next_rotation() {
+        let (mut keyring, _, _) = rotated_keyring(chrono::Duration::zero());
+        keyring.rotate(JwtKey::generate(Algorithm::EdDSA).unwrap(), chrono::Duration::minutes(10));
+        assert_eq!(keyring.keys.len(), 2);
+    }
+
+    #[test]
+    fn tokens_without_key_id_verify_with_signing_key() {
+        let (keyring, _, new_kid) = rotated_keyring(chrono::Duration::minutes(10));
+        assert_eq!(keyring.verification_key(None).unwrap().kid, new_kid);
+    }
+
+    fn stored_key(algorithm: Algorithm, created_at: DateTime<Utc>) -> StoredJwtKey {
+        let key = JwtKey::generate(algorithm).unwrap();
+        StoredJwtKey { kid: key.kid, algorithm, private_key: key.private_key, created_at }
+    }
+
+    #[test]
+    fn loaded_keys_retire_a_token_lifetime_after_being_replaced() {
+        let mut keyring = JwtKeyring::new(JwtKey::generate(Algorithm::EdDSA).unwrap());
+        let configured_kid = keyring.signing_key().kid.clone();
+        let now = Utc::now();
+        let old = stored_key(Algorithm::EdDSA, now - chrono::Duration::hours(2));
+        let new = stored_key(Algorithm::EdDSA, now - chrono::Duration::minutes(5));
+
+        keyring.load(vec![old.clone(), new.clone()], chrono::Duration::minutes(10)).unwrap();
+
+        assert_eq!(keyring.signing_key().kid, new.kid);
+        assert_eq!(keyring.last_rotated_at(), Some(new.created_at));
+        assert!(keyring.verification_key(Some(&old.kid)).is_some());
+        assert!(keyring.verification_key(Some(&configured_kid)).is_none());
+    }
+
+    #[test]
+    fn loading_skips_keys_of_another_algorithm() {
+        let mut keyring = JwtKeyring::new(JwtKey::generate(Algorithm::EdDSA).unwrap());
+        let configured_kid = keyring.signing_key().kid.clone();
+
+        keyring.load(vec![stored_key(Algorithm::HS256, Utc::now())], chrono::Duration::minutes(10)).unwrap();
+
+        assert_eq!(keyring.signing_key().kid, configured_kid);
+        assert_eq!(keyring.last_rotated_at(), None);
+    }
+
+    #[tokio::test]
+    async fn rotate_jwt_key_keeps_algorithm() {
+        let jwt_keyring: JwtKeyringType = Arc::new(RwLock::new(JwtKeyring::new(JwtKey::generate(Algorithm::HS256).unwrap())));
+        let jwt_key_store: JwtKeyStoreType = Arc::new(RwLock::new(HashmapJwtKeyStore::default()));
+        let old_kid = jwt_keyring.read().await.signing_key().kid.clone();
+
+        let kid = rotate_jwt_key(jwt_keyring.clone(), jwt_key_store).await.unwrap();
+
+        let signing_key = jwt_keyring.read().await.signing_key();
+        assert_eq!(signing_key.kid, kid);
+        assert_ne!(kid, old_kid);
+        assert_eq!(signing_key.algorithm, Algorithm::HS256);
+        assert!(jwt_keyring.read().await.verification_key(Some(&old_kid)).is_some());
+    }
+
+    #[tokio::test]
+    async fn rotated_keys_are_shared_through_the_store() {
+        let configured_key = JwtKey::generate(Algorithm::EdDSA).unwrap();
+        let jwt_key_store: JwtKeyStoreType = Arc::new(RwLock::new(HashmapJwtKeyStore::default()));
+        let jwt_keyring: JwtKeyringType = Arc::new(RwLock::new(JwtKeyring::new(configured_key.clone())));
+        let kid = rotate_jwt_key(jwt_keyring, jwt_key_store.clone()).await.unwrap();
+
+        // Another instance, or this one after a restart, starts from the configured key alone
+        let other_keyring: JwtKeyringType = Arc::new(RwLock::new(JwtKeyring::new(configured_key.clone())));
+        sync_jwt_keyring(other_keyring.clone(), jwt_key_store).await.unwrap();
+
+        let other_keyring = other_keyring.read().await;
+        assert_eq!(other_keyring.signing_key().kid, kid);
+        assert!(other_keyring.verification_key(Some(&configured_key.kid)).is_some());
+    }
+}
@@ -0,0 +1,246 @@
+use std::{net::IpAddr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, LoginAttemptId, PendingTwoFAAttempt},
+    utils::constants::{
+        LOGIN_LOCKOUT_BASE_SECONDS, LOGIN_LOCKOUT_MAX_SECONDS, LOGIN_LOCKOUT_THRESHOLD, LOGIN_MAX_ATTEMPTS_PER_ACCOUNT,
+        LOGIN_MAX_ATTEMPTS_PER_IP, LOGIN_RATE_LIMIT_WINDOW_SECONDS, TWO_FA_MAX_RESENDS, TWO_FA_RESEND_COOLDOWN_SECONDS,
+    },
+};
+
+// How hard login and 2FA attempts are throttled. Attempts are limited per client address and
+// per account over a sliding window, and repeated failures lock the account (or, for 2FA, the
+// login attempt) for a time that doubles with every further failure. Emailed 2FA codes can
+// only be re-sent a few times per login attempt, and not in quick succession.
+#[derive(Debug, Clone)]
+pub struct LoginThrottlePolicy {
+    pub max_attempts_per_ip: u32,
+    pub max_attempts_per_account: u32,
+    pub window: Duration,
+    pub lockout_threshold: u32,
+    pub lockout_base: Duration,
+    pub lockout_max: Duration,
+    pub two_fa_resend_cooldown: Duration,
+    pub max_two_fa_resends: u32,
+}
+
+impl Default for LoginThrottlePolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts_per_ip: *LOGIN_MAX_ATTEMPTS_PER_IP,
+            max_attempts_per_account: *LOGIN_MAX_ATTEMPTS_PER_ACCOUNT,
+            window: Duration::from_secs(*LOGIN_RATE_LIMIT_WINDOW_SECONDS),
+            lockout_threshold: *LOGIN_LOCKOUT_THRESHOLD,
+            lockout_base: Duration::from_secs(*LOGIN_LOCKOUT_BASE_SECONDS),
+            lockout_max: Duration::from_secs(*LOGIN_LOCKOUT_MAX_SECONDS),
+            two_fa_resend_cooldown: Duration::from_secs(*TWO_FA_RESEND_COOLDOWN_SECONDS),
+            max_two_fa_resends: *TWO_FA_MAX_RESENDS,
+        }
+    }
+}
+
+impl LoginThrottlePolicy {
+    // How long to lock for after `failures` failures in a row, if at all
+    pub fn lockout_for(&self, failures: u32) -> Option<Duration> {
+        if failures < self.lockout_threshold {
+            return None;
+        }
+        let doublings = (failures - self.lockout_threshold).min(31);
+        Some(self.lockout_base.saturating_mul(1 << doublings).min(self.lockout_max))
+    }
+
+    // Whether a login attempt's 2FA code can be re-sent yet, or at all
+    pub fn check_two_fa_resend(&self, attempt: &PendingTwoFAAttempt, now: DateTime<Utc>) -> Result<(), AuthAPIError> {
+        if attempt.resends >= self.max_two_fa_resends {
+            return Err(AuthAPIError::TooManyTwoFAResends);
+        }
+        let since_last_sent = (now - attempt.last_sent_at).to_std().unwrap_or(Duration::ZERO);
+        if since_last_sent < self.two_fa_resend_cooldown {
+            return Err(AuthAPIError::TwoFAResendCooldown {
+                retry_after: self.two_fa_resend_cooldown - since_last_sent,
+            });
+        }
+        Ok(())
+    }
+
+    // Failures are remembered for longer than the longest lock, so waiting one out
+    // doesn't reset the backoff
+    fn failure_ttl(&self) -> Duration {
+        self.lockout_max.saturating_mul(2)
+    }
+}
+
+// Turn away a login while the account is locked or the client is over its limits.
+// Every call counts as an attempt, whatever its outcome.
+#[tracing::instrument(name = "Throttle login", skip_all)]
+pub async fn throttle_login(state: &AppState, client_ip: Option<IpAddr>, email: &Email) -> Result<(), AuthAPIError> {
+    ensure_not_locked(state, &[account_key(email)]).await?;
+    count_attempt(state, client_ip, email).await
+}
+
+// Same as `throttle_login`, but a login attempt that has been locked for too many wrong
+// codes is turned away too
+#[tracing::instrument(name = "Throttle 2FA", skip_all)]
+pub async fn throttle_two_fa(
+    state: &AppState,
+    client_ip: Option<IpAddr>,
+    email: &Email,
+    login_attempt_id: &LoginAttemptId,
+) -> Result<(), AuthAPIError> {
+    ensure_not_locked(state, &[account_key(email), login_attempt_key(login_attempt_id)]).await?;
+    count_attempt(state, client_ip, email).await
+}
+
+// Count a wrong password or code against the account and, for 2FA, the login attempt,
+// locking either once it has failed too many times in a row
+#[tracing::instrument(name = "Record login failure", skip_all)]
+pub async fn record_login_failure(
+    state: &AppState,
+    email: &Email,
+    login_attempt_id: Option<&LoginAttemptId>,
+) -> Result<(), AuthAPIError> {
+    let policy = &state.login_throttle_policy;
+    let mut keys = vec![account_key(email)];
+    keys.extend(login_attempt_id.map(login_attempt_key));
+
+    let mut store = state.login_throttle_store.write().await;
+    for key in keys {
+        let failures = store
+            .record_failure(&key, policy.failure_ttl())
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        if let Some(duration) = policy.lockout_for(failures) {
+            tracing::warn!(failures, "locking login after repeated failures");
+            store
+                .lock(&key, duration)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        }
+    }
+
+    Ok(())
+}
+
+// A completed login starts the account's failure count over
+#[tracing::instrument(name = "Record login success", skip_all)]
+pub async fn record_login_success(state: &AppState, email: &Email) -> Result<(), AuthAPIError> {
+    state
+        .login_throttle_store
+        .write()
+        .await
+        .reset_failures(&account_key(email))
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+async fn ensure_not_locked(state: &AppState, keys: &[String]) -> Result<(), AuthAPIError> {
+    let store = state.login_throttle_store.read().await;
+    for key in keys {
+        let lock = store
+            .get_lock(key)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        if let Some(retry_after) = lock {
+            return Err(AuthAPIError::TooManyRequests { retry_after });
+        }
+    }
+    Ok(())
+}
+
+async fn count_attempt(state: &AppState, client_ip: Option<IpAddr>, email: &Email) -> Result<(), AuthAPIError> {
+    let policy = &state.login_throttle_policy;
+    let mut limits = vec![(account_key(email), policy.max_attempts_per_account)];
+    limits.extend(client_ip.map(|ip| (ip_key(ip), policy.max_attempts_per_ip)));
+
+    let mut store = state.login_throttle_store.write().await;
+    for (key, max_attempts) in limits {
+        let attempts = store
+            .record_attempt(&key, policy.window)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        if attempts > max_attempts {
+            return Err(AuthAPIError::TooManyRequests { retry_after: policy.window });
+        }
+    }
+    Ok(())
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+fn account_key(email: &Email) -> String {
+    format!("account:{}", email.as_ref().expose_secret())
+}
+
+fn login_attempt_key(login_attempt_id: &LoginAttemptId) -> String {
+    format!("login_attempt:{}", login_attempt_id.as_ref().expose_secret())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LoginThrottlePolicy {
+        LoginThrottlePolicy {
+            max_attempts_per_ip: 20,
+            max_attempts_per_account: 10,
+            window: Duration::from_secs(60),
+            lockout_threshold: 3,
+            lockout_base: Duration::from_secs(30),
+            lockout_max: Duration::from_secs(300),
+            two_fa_resend_cooldown: Duration::from_secs(30),
+            max_two_fa_resends: 3,
+        }
+    }
+
+    #[test]
+    fn no_lockout_below_threshold() {
+        assert_eq!(policy().lockout_for(1), None);
+        assert_eq!(policy().lockout_for(2), None);
+    }
+
+    #[test]
+    fn lockout_doubles_with_each_failure_up_to_max() {
+        let policy = policy();
+        assert_eq!(policy.lockout_for(3), Some(Duration::from_secs(30)));
+        assert_eq!(policy.lockout_for(4), Some(Duration::from_secs(60)));
+        assert_eq!(policy.lockout_for(5), Some(Duration::from_secs(120)));
+        assert_eq!(policy.lockout_for(6), Some(Duration::from_secs(240)));
+        assert_eq!(policy.lockout_for(7), Some(Duration::from_secs(300)));
+        assert_eq!(policy.lockout_for(u32::MAX), Some(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn two_fa_resend_waits_for_cooldown() {
+        let now = Utc::now();
+        let attempt = PendingTwoFAAttempt {
+            last_sent_at: now - chrono::Duration::seconds(10),
+            resends: 0,
+        };
+        assert!(matches!(
+            policy().check_two_fa_resend(&attempt, now),
+            Err(AuthAPIError::TwoFAResendCooldown { retry_after }) if retry_after == Duration::from_secs(20)
+        ));
+        assert!(policy().check_two_fa_resend(&attempt, now + chrono::Duration::seconds(20)).is_ok());
+    }
+
+    #[test]
+    fn two_fa_resend_is_limited() {
+        let now = Utc::now();
+        let attempt = PendingTwoFAAttempt {
+            last_sent_at: now - chrono::Duration::seconds(60),
+            resends: 2,
+        };
+        assert!(policy().check_two_fa_resend(&attempt, now).is_ok());
+        let attempt = PendingTwoFAAttempt { resends: 3, ..attempt };
+        assert!(matches!(
+            policy().check_two_fa_resend(&attempt, now),
+            Err(AuthAPIError::TooManyTwoFAResends)
+        ));
+    }
+}
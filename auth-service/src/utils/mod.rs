@@ -1,3 +1,16 @@
+pub mod account_deletion;
 pub mod constants;
 pub mod auth;
+pub mod device;
+pub mod jwt_key;
+pub mod jwt_keyring;
+pub mod login_throttle;
+pub mod magic_link;
+pub mod oauth;
+pub mod password_hash;
+pub mod password_policy;
+pub mod password_strength;
+pub mod totp;
+pub mod user_import;
+pub mod webauthn;
 pub mod tracing;
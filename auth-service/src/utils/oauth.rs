@@ -0,0 +1,87 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::domain::constant_time_eq;
+
+// Asking for `openid` makes the request an OpenID Connect one, which gets an ID token
+pub const OPENID_SCOPE: &str = "openid";
+// Lets the client read the user's email address from the ID token and /userinfo
+pub const EMAIL_SCOPE: &str = "email";
+pub const SUPPORTED_SCOPES: [&str; 2] = [OPENID_SCOPE, EMAIL_SCOPE];
+
+// The only PKCE method we accept. With "plain" the challenge is the verifier itself, so anyone
+// who saw the authorization request could redeem the code.
+pub const PKCE_METHOD: &str = "S256";
+
+// RFC 7636 section 4.1: verifiers are 43 to 128 characters long
+const CODE_VERIFIER_MIN_LENGTH: usize = 43;
+const CODE_VERIFIER_MAX_LENGTH: usize = 128;
+// An S256 challenge is a SHA-256 digest, which base64url encodes to 43 characters
+const CODE_CHALLENGE_LENGTH: usize = 43;
+
+pub fn has_scope(scope: &str, wanted: &str) -> bool {
+    scope.split_whitespace().any(|requested| requested == wanted)
+}
+
+// A scope is supported when it asks for at least one thing, and only for things we know about
+pub fn is_supported_scope(scope: &str) -> bool {
+    let mut requested = scope.split_whitespace().peekable();
+    requested.peek().is_some() && requested.all(|scope| SUPPORTED_SCOPES.contains(&scope))
+}
+
+pub fn is_valid_code_challenge(code_challenge: &str) -> bool {
+    code_challenge.len() == CODE_CHALLENGE_LENGTH
+        && code_challenge.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// RFC 7636 section 4.6: the verifier presented at /token must hash to the challenge the
+// authorization request was made with
+pub fn verify_pkce(code_verifier: &str, code_challenge: &str) -> bool {
+    if !is_valid_code_verifier(code_verifier) {
+        return false;
+    }
+    let computed_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+    constant_time_eq(computed_challenge, code_challenge)
+}
+
+// Verifiers are made of the unreserved URI characters from RFC 3986
+fn is_valid_code_verifier(code_verifier: &str) -> bool {
+    (CODE_VERIFIER_MIN_LENGTH..=CODE_VERIFIER_MAX_LENGTH).contains(&code_verifier.len())
+        && code_verifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example from RFC 7636 appendix B
+    const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuNd-bw-cM";
+
+    #[test]
+    fn accepts_matching_verifier() {
+        assert!(is_valid_code_challenge(CODE_CHALLENGE));
+        assert!(verify_pkce(CODE_VERIFIER, CODE_CHALLENGE));
+    }
+
+    #[test]
+    fn rejects_wrong_or_malformed_verifier() {
+        assert!(!verify_pkce("aBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk", CODE_CHALLENGE));
+        // The challenge itself, as a client using "plain" would send
+        assert!(!verify_pkce(CODE_CHALLENGE, CODE_CHALLENGE));
+        assert!(!verify_pkce("too-short", CODE_CHALLENGE));
+    }
+
+    #[test]
+    fn checks_requested_scopes() {
+        assert!(is_supported_scope("openid"));
+        assert!(is_supported_scope("openid email"));
+        assert!(!is_supported_scope(""));
+        assert!(!is_supported_scope("openid profile"));
+
+        assert!(has_scope("openid email", EMAIL_SCOPE));
+        assert!(!has_scope("openid", EMAIL_SCOPE));
+    }
+}
@@ -0,0 +1,179 @@
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version,
+};
+use color_eyre::eyre::{eyre, Context, Result};
+use pbkdf2::Pbkdf2;
+use scrypt::Scrypt;
+use secrecy::{ExposeSecret, Secret};
+
+use crate::{
+    domain::is_bcrypt_hash,
+    utils::constants::{ARGON2_ITERATIONS, ARGON2_MEMORY_KIB, ARGON2_PARALLELISM},
+};
+
+lazy_static::lazy_static! {
+    // Hash of a random password, verified against when the email is unknown
+    pub static ref DUMMY_PASSWORD_HASH: Secret<String> = Secret::new(
+        hash_password(uuid::Uuid::new_v4().to_string().as_bytes())
+            .expect("Failed to compute dummy password hash"),
+    );
+}
+
+// Checks the candidate against a stored hash. New hashes are always Argon2id, but users
+// imported from older systems can still have PBKDF2, scrypt or bcrypt hashes until they log in.
+#[tracing::instrument(name = "Verify password hash", skip_all)]
+pub async fn verify_password_hash(
+    expected_password_hash: Secret<String>, // Updated!
+    password_candidate: Secret<String>, // Updated!
+) -> Result<()> { // Changed!
+    let current_span: tracing::Span = tracing::Span::current();
+    let result = tokio::task::spawn_blocking(move || {
+        current_span.in_scope(|| {
+            let expected_password_hash = expected_password_hash.expose_secret();
+            let password_candidate = password_candidate.expose_secret();
+
+            if is_bcrypt_hash(expected_password_hash) {
+                return match bcrypt::verify(password_candidate, expected_password_hash)? {
+                    true => Ok(()),
+                    false => Err(eyre!("password does not match bcrypt hash")),
+                };
+            }
+
+            let expected_password_hash: PasswordHash<'_> =
+                PasswordHash::new(expected_password_hash)?;
+
+            expected_password_hash
+                .verify_password(&[&Argon2::default(), &Pbkdf2, &Scrypt], password_candidate.as_bytes())
+                .wrap_err("failed to verify password hash")
+        })
+    })
+    .await;
+
+    result?
+}
+
+#[tracing::instrument(name = "Computing password hash", skip_all)]
+pub async fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> { // Updated!
+    let current_span: tracing::Span = tracing::Span::current();
+
+    let result = tokio::task::spawn_blocking(move || {
+        current_span.in_scope(|| {
+            let password_hash = hash_password(password.expose_secret().as_bytes())?;
+
+            Ok(Secret::new(password_hash)) // Updated!
+        })
+    })
+    .await;
+
+    result?
+}
+
+fn hash_password(password: &[u8]) -> Result<String> {
+    let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params()?)
+        .hash_password(password, &salt)?
+        .to_string();
+
+    Ok(password_hash)
+}
+
+fn argon2_params() -> Result<Params> {
+    Params::new(*ARGON2_MEMORY_KIB, *ARGON2_ITERATIONS, *ARGON2_PARALLELISM, None)
+        .map_err(|e| eyre!("invalid Argon2 parameters: {}", e))
+}
+
+// Whether a stored hash was made with anything other than the current algorithm and cost
+pub fn needs_rehash(password_hash: &str) -> bool {
+    let Ok(current) = argon2_params() else {
+        return false;
+    };
+    // bcrypt hashes aren't PHC strings, so anything that doesn't parse is a legacy hash
+    let Ok(password_hash) = PasswordHash::new(password_hash) else {
+        return true;
+    };
+    if password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    match Params::try_from(&password_hash) {
+        Ok(params) => {
+            params.m_cost() != current.m_cost()
+                || params.t_cost() != current.t_cost()
+                || params.p_cost() != current.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret(s: &str) -> Secret<String> {
+        Secret::new(s.to_owned())
+    }
+
+    fn pbkdf2_hash(password: &str) -> String {
+        let params = pbkdf2::Params { rounds: 1000, output_length: 32 };
+        Pbkdf2
+            .hash_password_customized(
+                password.as_bytes(),
+                Some(pbkdf2::Algorithm::Pbkdf2Sha256.ident()),
+                None,
+                params,
+                &SaltString::generate(&mut rand::thread_rng()),
+            )
+            .unwrap()
+            .to_string()
+    }
+
+    fn scrypt_hash(password: &str) -> String {
+        Scrypt
+            .hash_password_customized(
+                password.as_bytes(),
+                None,
+                None,
+                scrypt::Params::new(4, 8, 1, 32).unwrap(),
+                &SaltString::generate(&mut rand::thread_rng()),
+            )
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn verifies_legacy_hashes() {
+        for hash in [
+            bcrypt::hash("password123", 4).unwrap(),
+            pbkdf2_hash("password123"),
+            scrypt_hash("password123"),
+        ] {
+            assert!(verify_password_hash(secret(&hash), secret("password123")).await.is_ok());
+            assert!(verify_password_hash(secret(&hash), secret("wrong-password")).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn verifies_current_hashes() {
+        let hash = compute_password_hash(secret("password123")).await.unwrap();
+
+        assert!(verify_password_hash(hash.clone(), secret("password123")).await.is_ok());
+        assert!(verify_password_hash(hash, secret("wrong-password")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn only_current_hashes_are_up_to_date() {
+        let current = compute_password_hash(secret("password123")).await.unwrap();
+        assert!(!needs_rehash(current.expose_secret()));
+
+        let cheaper = Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::new(8192, 1, 1, None).unwrap())
+            .hash_password(b"password123", &SaltString::generate(&mut rand::thread_rng()))
+            .unwrap()
+            .to_string();
+        assert!(needs_rehash(&cheaper));
+        assert!(needs_rehash(&bcrypt::hash("password123", 4).unwrap()));
+        assert!(needs_rehash(&pbkdf2_hash("password123")));
+        assert!(needs_rehash(&scrypt_hash("password123")));
+    }
+}
@@ -0,0 +1,238 @@
+use std::{io::ErrorKind, path::{Path, PathBuf}};
+
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    domain::{AuthAPIError, Email, PasswordPolicyViolation},
+    utils::{
+        constants::{
+            BREACHED_PASSWORDS_DIR, PASSWORD_MAX_LENGTH, PASSWORD_MIN_CHARACTER_CLASSES, PASSWORD_MIN_LENGTH,
+            PASSWORD_MIN_STRENGTH_SCORE,
+        },
+        password_strength::strength_score,
+    },
+};
+
+// What a new password has to satisfy. Only passwords being set are checked, so existing
+// users can still log in after the policy is tightened.
+//
+// Breached passwords are looked up in a directory of k-anonymity range files, laid out like
+// the Pwned Passwords range API: `ABCDE.txt` holds a `SUFFIX:COUNT` line for every breached
+// password whose SHA-1 starts with ABCDE. Only the one file for the prefix is read.
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    // Of lowercase, uppercase, digits and symbols
+    pub min_character_classes: usize,
+    pub min_strength_score: u8,
+    pub breached_passwords_dir: Option<PathBuf>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: *PASSWORD_MIN_LENGTH,
+            max_length: *PASSWORD_MAX_LENGTH,
+            min_character_classes: *PASSWORD_MIN_CHARACTER_CLASSES,
+            min_strength_score: *PASSWORD_MIN_STRENGTH_SCORE,
+            breached_passwords_dir: BREACHED_PASSWORDS_DIR.clone(),
+        }
+    }
+}
+
+impl PasswordPolicy {
+    // Refuse the password with every rule it breaks
+    pub async fn enforce(&self, password: &Secret<String>, email: Option<&Email>) -> Result<(), AuthAPIError> {
+        let violations = self
+            .check(password, email)
+            .await
+            .map_err(AuthAPIError::UnexpectedError)?;
+
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(AuthAPIError::PasswordPolicyViolation(violations)),
+        }
+    }
+
+    // The rules about the email address are skipped when it isn't known
+    pub async fn check(&self, password: &Secret<String>, email: Option<&Email>) -> Result<Vec<PasswordPolicyViolation>> {
+        let password = password.expose_secret();
+        let length = password.chars().count();
+
+        if length > self.max_length {
+            // Not worth scoring or hashing any further
+            return Ok(vec![PasswordPolicyViolation::TooLong]);
+        }
+
+        let mut violations = Vec::new();
+        if length < self.min_length {
+            violations.push(PasswordPolicyViolation::TooShort);
+        }
+        if character_classes(password) < self.min_character_classes {
+            violations.push(PasswordPolicyViolation::TooFewCharacterClasses);
+        }
+
+        let email_parts = email.map(|email| email_parts(email.as_ref().expose_secret())).unwrap_or_default();
+        if email.is_some_and(|email| contains_email(password, email.as_ref().expose_secret())) {
+            violations.push(PasswordPolicyViolation::ContainsEmail);
+        }
+
+        let user_inputs: Vec<&str> = email_parts.iter().map(String::as_str).collect();
+        if strength_score(password, &user_inputs) < self.min_strength_score {
+            violations.push(PasswordPolicyViolation::TooWeak);
+        }
+
+        if let Some(dir) = &self.breached_passwords_dir {
+            if is_breached(dir, password).await? {
+                violations.push(PasswordPolicyViolation::Breached);
+            }
+        }
+
+        Ok(violations)
+    }
+}
+
+fn character_classes(password: &str) -> usize {
+    [
+        password.chars().any(|c| c.is_lowercase()),
+        password.chars().any(|c| c.is_uppercase()),
+        password.chars().any(|c| c.is_numeric()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ]
+    .into_iter()
+    .filter(|present| *present)
+    .count()
+}
+
+// The whole address, or its local part where that is long enough not to turn up by chance
+fn contains_email(password: &str, email: &str) -> bool {
+    let password = password.to_lowercase();
+    let email = email.to_lowercase();
+    let local_part = email.split('@').next().unwrap_or_default();
+
+    password.contains(&email) || (local_part.chars().count() >= 3 && password.contains(local_part))
+}
+
+// Words from the address a guesser targeting this user would try, e.g. "jane", "doe" and
+// "example" for jane.doe@example.com
+fn email_parts(email: &str) -> Vec<String> {
+    email
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| part.chars().count() >= 3)
+        .map(str::to_owned)
+        .collect()
+}
+
+async fn is_breached(dir: &Path, password: &str) -> Result<bool> {
+    let digest: String = Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+    let (prefix, suffix) = digest.split_at(5);
+
+    let range = match tokio::fs::read_to_string(dir.join(format!("{}.txt", prefix))).await {
+        Ok(range) => range,
+        // No file means no breached password has this prefix
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    // Padding entries added to hide the real number of matches have a count of 0
+    Ok(range
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .any(|(candidate, count)| candidate.eq_ignore_ascii_case(suffix) && count.trim() != "0"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 10,
+            max_length: 64,
+            min_character_classes: 3,
+            min_strength_score: 3,
+            breached_passwords_dir: None,
+        }
+    }
+
+    fn email(s: &str) -> Email {
+        Email::parse(Secret::new(s.to_owned())).unwrap()
+    }
+
+    async fn check(policy: &PasswordPolicy, password: &str, email: Option<&Email>) -> Vec<PasswordPolicyViolation> {
+        policy.check(&Secret::new(password.to_owned()), email).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn strong_password_passes() {
+        assert!(check(&policy(), "kP9#vT2$wq!Z", Some(&email("jane.doe@example.com"))).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn every_broken_rule_is_reported() {
+        assert_eq!(
+            check(&policy(), "password", None).await,
+            vec![
+                PasswordPolicyViolation::TooShort,
+                PasswordPolicyViolation::TooFewCharacterClasses,
+                PasswordPolicyViolation::TooWeak,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn overlong_password_is_rejected() {
+        assert_eq!(check(&policy(), &"aB3$".repeat(20), None).await, vec![PasswordPolicyViolation::TooLong]);
+    }
+
+    #[tokio::test]
+    async fn password_containing_email_is_rejected() {
+        let email = email("jane.doe@example.com");
+
+        assert!(check(&policy(), "Jane.Doe@Example.com!1", Some(&email)).await.contains(&PasswordPolicyViolation::ContainsEmail));
+        assert!(check(&policy(), "xX-jane.doe-Xx#42", Some(&email)).await.contains(&PasswordPolicyViolation::ContainsEmail));
+        // Without the address to compare against, the rule can't apply
+        assert!(!check(&policy(), "xX-jane.doe-Xx#42", None).await.contains(&PasswordPolicyViolation::ContainsEmail));
+    }
+
+    #[tokio::test]
+    async fn breached_password_is_rejected() {
+        let dir = std::env::temp_dir().join(format!("breached-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // SHA-1 of "kP9#vT2$wq!Z", split into prefix and suffix, next to a padding entry
+        let digest: String = Sha1::digest(b"kP9#vT2$wq!Z").iter().map(|b| format!("{:02X}", b)).collect();
+        let (prefix, suffix) = digest.split_at(5);
+        std::fs::write(
+            dir.join(format!("{}.txt", prefix)),
+            format!("0000000000000000000000000000000000A:0\r\n{}:42\r\n", suffix),
+        )
+        .unwrap();
+        let policy = PasswordPolicy { breached_passwords_dir: Some(dir.clone()), ..policy() };
+
+        assert_eq!(check(&policy, "kP9#vT2$wq!Z", None).await, vec![PasswordPolicyViolation::Breached]);
+        assert!(check(&policy, "Zq!w$2Tv#9Pk", None).await.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn padding_entries_are_not_breaches() {
+        let dir = std::env::temp_dir().join(format!("breached-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let digest: String = Sha1::digest(b"kP9#vT2$wq!Z").iter().map(|b| format!("{:02X}", b)).collect();
+        let (prefix, suffix) = digest.split_at(5);
+        std::fs::write(dir.join(format!("{}.txt", prefix)), format!("{}:0\n", suffix)).unwrap();
+        let policy = PasswordPolicy { breached_passwords_dir: Some(dir.clone()), ..policy() };
+
+        assert!(check(&policy, "kP9#vT2$wq!Z", None).await.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
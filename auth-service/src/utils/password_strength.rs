@@ -0,0 +1,224 @@
+// A small take on zxcvbn's strength estimate. The password is split into the cheapest
+// sequence of guessable patterns (common passwords, words from the user's own details,
+// keyboard walks, alphabetical or numeric sequences and repeated characters), and whatever is
+// left over is guessed by brute force. The score is zxcvbn's 0-4 scale over the total guesses.
+
+// Most common first, as a guesser would try them
+const COMMON_PASSWORDS: &[&str] = &[
+    "password", "123456", "qwerty", "letmein", "welcome", "admin", "iloveyou", "monkey",
+    "dragon", "football", "baseball", "abc123", "master", "sunshine", "princess", "shadow",
+    "superman", "trustno1", "hello", "freedom", "whatever", "login", "michael", "jordan",
+    "hunter", "ranger", "buster", "soccer", "harley", "batman", "andrew", "tigger", "charlie",
+    "robert", "thomas", "hockey", "killer", "george", "summer", "winter", "secret", "love",
+    "pass", "test", "guest", "root", "user", "changeme", "default", "computer", "internet",
+    "cheese", "pepper", "ginger", "orange", "banana", "apple", "flower", "starwars",
+    "pokemon", "matrix", "ninja", "mustang", "access", "maggie", "jessica", "daniel",
+    "jennifer", "joshua", "ashley", "nicole", "chelsea", "liverpool", "arsenal", "chocolate",
+    "purple", "silver", "golden", "diamond", "family", "friend", "money", "angel",
+    "beautiful", "lovely", "blessed", "jesus", "heaven", "samsung", "google", "facebook",
+    "company", "spring", "autumn", "october", "november", "december", "january",
+];
+
+const KEYBOARD_ROWS: &[&str] = &["qwertyuiop", "asdfghjkl", "zxcvbnm", "1234567890"];
+
+// Guesses for the user's own details, such as their email address, which an attacker who
+// targets them will try first
+const USER_INPUT_GUESSES: f64 = 1.0;
+
+// 0: too guessable, up to 4: very unguessable
+pub fn strength_score(password: &str, user_inputs: &[&str]) -> u8 {
+    let guesses_log10 = estimate_guesses_log10(password, user_inputs);
+    match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+// log10 of the number of guesses an attacker needs, taking the cheapest way to cover the
+// password with patterns
+fn estimate_guesses_log10(password: &str, user_inputs: &[&str]) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let user_inputs: Vec<Vec<char>> = user_inputs
+        .iter()
+        .filter(|input| input.chars().count() >= 3)
+        .map(|input| input.to_lowercase().chars().collect())
+        .collect();
+
+    // cheapest[i] is the fewest guesses (as log10) that cover the first i characters
+    let mut cheapest = vec![f64::INFINITY; chars.len() + 1];
+    cheapest[0] = 0.0;
+
+    for start in 0..chars.len() {
+        let base = cheapest[start];
+        if base.is_infinite() {
+            continue;
+        }
+        let mut consider = |end: usize, guesses: f64| {
+            let total = base + guesses.max(1.0).log10();
+            if total < cheapest[end] {
+                cheapest[end] = total;
+            }
+        };
+
+        consider(start + 1, brute_force_cardinality(chars[start]));
+
+        for (rank, word) in COMMON_PASSWORDS.iter().enumerate() {
+            let word: Vec<char> = word.chars().collect();
+            if let Some(variations) = dictionary_match(&chars[start..], &word) {
+                consider(start + word.len(), (rank + 1) as f64 * variations);
+            }
+        }
+        for word in &user_inputs {
+            if let Some(variations) = dictionary_match(&chars[start..], word) {
+                consider(start + word.len(), USER_INPUT_GUESSES * variations);
+            }
+        }
+
+        let repeat = repeat_length(&chars[start..]);
+        for length in 3..=repeat {
+            consider(start + length, brute_force_cardinality(chars[start]) * length as f64);
+        }
+
+        let (sequence, descending) = sequence_length(&chars[start..]);
+        for length in 3..=sequence {
+            let first = chars[start].to_ascii_lowercase();
+            // Starting from the obvious end makes a sequence easier to guess
+            let starts = if matches!(first, 'a' | 'z' | '0' | '1' | '9') { 4.0 } else { 26.0 };
+            let direction = if descending { 2.0 } else { 1.0 };
+            consider(start + length, starts * direction * length as f64);
+        }
+
+        let walk = keyboard_walk_length(&chars[start..]);
+        for length in 4..=walk {
+            consider(start + length, 20.0 * length as f64);
+        }
+    }
+
+    cheapest[chars.len()]
+}
+
+fn brute_force_cardinality(c: char) -> f64 {
+    match c {
+        'a'..='z' | 'A'..='Z' => 26.0,
+        '0'..='9' => 10.0,
+        _ => 33.0,
+    }
+}
+
+// Whether `chars` starts with `word`, allowing for capitals and common l33t substitutions.
+// Returns how many variations of the word a guesser has to try to get there.
+fn dictionary_match(chars: &[char], word: &[char]) -> Option<f64> {
+    if chars.len() < word.len() {
+        return None;
+    }
+    let candidate = &chars[..word.len()];
+    if !candidate.iter().zip(word).all(|(c, w)| c.to_ascii_lowercase() == *w || unleet(*c).contains(w)) {
+        return None;
+    }
+
+    let mut variations = 1.0;
+    if candidate.iter().any(|c| c.is_ascii_uppercase()) {
+        variations *= 2.0;
+    }
+    if candidate.iter().zip(word).any(|(c, w)| c.to_ascii_lowercase() != *w) {
+        variations *= 2.0;
+    }
+    Some(variations)
+}
+
+fn unleet(c: char) -> &'static [char] {
+    match c {
+        '4' | '@' => &['a'],
+        '3' => &['e'],
+        '1' | '!' | '|' => &['i', 'l'],
+        '0' => &['o'],
+        '$' | '5' => &['s'],
+        '7' | '+' => &['t'],
+        _ => &[],
+    }
+}
+
+fn repeat_length(chars: &[char]) -> usize {
+    let first = chars[0].to_ascii_lowercase();
+    chars.iter().take_while(|c| c.to_ascii_lowercase() == first).count()
+}
+
+// Length of the run of consecutive letters or digits, like "abcd" or "4321", and whether it
+// counts down
+fn sequence_length(chars: &[char]) -> (usize, bool) {
+    let codes: Vec<i64> = chars
+        .iter()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase() as i64)
+        .collect();
+    if codes.len() < 2 {
+        return (codes.len(), false);
+    }
+
+    let delta = codes[1] - codes[0];
+    if delta.abs() != 1 {
+        return (1, false);
+    }
+    let same_class = |a: i64, b: i64| (a as u8 as char).is_ascii_digit() == (b as u8 as char).is_ascii_digit();
+    let length = 1 + codes
+        .windows(2)
+        .take_while(|pair| pair[1] - pair[0] == delta && same_class(pair[0], pair[1]))
+        .count();
+    (length, delta < 0)
+}
+
+// Length of the run that follows a keyboard row, in either direction
+fn keyboard_walk_length(chars: &[char]) -> usize {
+    let lower: String = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    KEYBOARD_ROWS
+        .iter()
+        .flat_map(|row| [row.to_string(), row.chars().rev().collect()])
+        .map(|row| {
+            (1..=lower.len().min(row.len()))
+                .rev()
+                .find(|length| lower.is_char_boundary(*length) && row.contains(&lower[..*length]))
+                .unwrap_or(0)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_passwords_are_weak() {
+        for password in ["password", "password123", "P@ssw0rd!", "qwerty1234", "letmein2"] {
+            assert_eq!(strength_score(password, &[]), 0, "{} scored too high", password);
+        }
+    }
+
+    #[test]
+    fn patterns_are_weak() {
+        for password in ["aaaaaaaaaaaa", "abcdefghijk", "9876543210", "asdfghjkl"] {
+            assert!(strength_score(password, &[]) <= 1, "{} scored too high", password);
+        }
+    }
+
+    #[test]
+    fn the_users_own_details_are_weak() {
+        assert!(strength_score("alicesmith2024", &["alice", "smith"]) <= 1);
+        assert_eq!(strength_score("alicesmith2024", &[]), 4);
+    }
+
+    #[test]
+    fn random_and_long_passwords_are_strong() {
+        for password in ["kP9#vT2$wq!Z", "correct horse battery staple", "Tr0ub4dour&3x"] {
+            assert_eq!(strength_score(password, &[]), 4, "{} scored too low", password);
+        }
+    }
+
+    #[test]
+    fn empty_password_scores_zero() {
+        assert_eq!(strength_score("", &[]), 0);
+    }
+}
@@ -0,0 +1,148 @@
+use color_eyre::eyre::{Context, Result};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha1::Sha1;
+
+use crate::domain::{constant_time_eq, Email, TotpSecret, TwoFACode};
+
+use super::constants::TOTP_ISSUER;
+
+// RFC 6238 defaults, which is also all most authenticator apps support
+const TOTP_STEP_SECONDS: u64 = 30;
+const TOTP_DIGITS: u32 = 6;
+
+// Generate the TOTP code for the time step containing `unix_time`
+pub fn generate_code(secret: &TotpSecret, unix_time: u64) -> Result<String> {
+    generate_code_for_step(secret, unix_time / TOTP_STEP_SECONDS)
+}
+
+// Check a code against the current time step and up to `drift_steps` steps on either side,
+// to allow for clock skew between the server and the user's device. Returns the time step
+// the code matched, so the caller can refuse to accept that step again.
+#[tracing::instrument(name = "Verifying TOTP code", skip_all)]
+pub fn verify_code(
+    secret: &TotpSecret,
+    code: &TwoFACode,
+    unix_time: u64,
+    drift_steps: u64,
+) -> Result<Option<u64>> {
+    let current_step = unix_time / TOTP_STEP_SECONDS;
+    let first_step = current_step.saturating_sub(drift_steps);
+    let last_step = current_step.saturating_add(drift_steps);
+
+    // Every step in the window is checked, so the time taken doesn't depend on which one matched
+    let mut matched_step = None;
+    for step in first_step..=last_step {
+        let expected = generate_code_for_step(secret, step)?;
+        if constant_time_eq(expected, code.as_ref().expose_secret()) {
+            matched_step = Some(step);
+        }
+    }
+
+    Ok(matched_step)
+}
+
+// Build the `otpauth://` URI authenticator apps use to enroll a secret, usually via a QR code
+pub fn get_otpauth_uri(secret: &TotpSecret, email: &Email) -> String {
+    let issuer = urlencoding::encode(TOTP_ISSUER);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        urlencoding::encode(email.as_ref().expose_secret()),
+        secret.as_ref().expose_secret(),
+        issuer,
+        TOTP_DIGITS,
+        TOTP_STEP_SECONDS
+    )
+}
+
+// HOTP (RFC 4226) with the time step as the counter
+fn generate_code_for_step(secret: &TotpSecret, step: u64) -> Result<String> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(&secret.to_bytes())
+        .wrap_err("failed to create HMAC from TOTP secret")?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    Ok(format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+
+    // The SHA1 seed from RFC 6238 Appendix B, "12345678901234567890", in base32
+    fn rfc_secret() -> TotpSecret {
+        TotpSecret::parse(Secret::new("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_owned())).unwrap()
+    }
+
+    fn code(s: &str) -> TwoFACode {
+        TwoFACode::parse(s.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn test_generate_code_matches_rfc_6238_vectors() {
+        // Last six digits of the 8-digit SHA1 test vectors
+        let vectors = [
+            (59, "287082"),
+            (1_111_111_109, "081804"),
+            (1_111_111_111, "050471"),
+            (1_234_567_890, "005924"),
+            (2_000_000_000, "279037"),
+            (20_000_000_000, "353130"),
+        ];
+
+        for (time, expected) in vectors {
+            assert_eq!(generate_code(&rfc_secret(), time).unwrap(), expected, "time {}", time);
+        }
+    }
+
+    #[test]
+    fn test_verify_code_within_drift_window() {
+        let secret = rfc_secret();
+        let now = 1_234_567_890;
+        let previous = generate_code(&secret, now - TOTP_STEP_SECONDS).unwrap();
+        let next = generate_code(&secret, now + TOTP_STEP_SECONDS).unwrap();
+
+        let step = now / TOTP_STEP_SECONDS;
+
+        assert_eq!(verify_code(&secret, &code("005924"), now, 0).unwrap(), Some(step));
+        assert_eq!(verify_code(&secret, &code(&previous), now, 1).unwrap(), Some(step - 1));
+        assert_eq!(verify_code(&secret, &code(&next), now, 1).unwrap(), Some(step + 1));
+        assert_eq!(verify_code(&secret, &code(&previous), now, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_verify_code_rejects_wrong_code() {
+        let secret = rfc_secret();
+        let now = 1_234_567_890;
+        let far_away = generate_code(&secret, now + 10 * TOTP_STEP_SECONDS).unwrap();
+
+        assert_eq!(verify_code(&secret, &code(&far_away), now, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_get_otpauth_uri() {
+        let email = Email::parse(Secret::new("test+totp@example.com".to_owned())).unwrap();
+        let uri = get_otpauth_uri(&rfc_secret(), &email);
+
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains("test%2Btotp%40example.com"));
+        assert!(uri.contains("secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"));
+        assert!(uri.contains("digits=6&period=30"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use color_eyre::eyre::{eyre, Result};
+use secrecy::Secret;
+use serde::Deserialize;
+
+use crate::domain::{Email, ImportedPasswordHash, TwoFAMethod, UserStore, UserStoreError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    // Needs an `email,hash,requires_2fa` header row
+    Csv,
+    // One JSON object per line, with the same keys as the CSV header
+    JsonLines,
+}
+
+impl ImportFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => Ok(Self::Csv),
+            Some("jsonl") | Some("ndjson") => Ok(Self::JsonLines),
+            _ => Err(eyre!("{} is not a .csv or .jsonl file", path.display())),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub already_existed: usize,
+    // Line number and reason for each record that couldn't be imported
+    pub failed: Vec<(usize, String)>,
+}
+
+#[derive(Deserialize)]
+struct ImportRecord {
+    email: String,
+    hash: String,
+    requires_2fa: bool,
+}
+
+// Add every user in `reader` through `user_store`. A bad record doesn't stop the import;
+// it is reported in the summary with its line number and the rest carry on. Users that
+// already exist are left untouched, so an interrupted import can simply be run again.
+pub async fn import_users<S: UserStore + ?Sized>(
+    reader: impl Read,
+    format: ImportFormat,
+    user_store: &mut S,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+
+    for (line, record) in read_records(reader, format)? {
+        let (email, password_hash, two_fa_method) = match record.and_then(parse_record) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                summary.failed.push((line, e.to_string()));
+                continue;
+            }
+        };
+
+        match user_store.import_user(email, password_hash, two_fa_method).await {
+            Ok(()) => summary.imported += 1,
+            Err(UserStoreError::UserAlreadyExists) => summary.already_existed += 1,
+            Err(e) => summary.failed.push((line, e.to_string())),
+        }
+    }
+
+    Ok(summary)
+}
+
+fn read_records(reader: impl Read, format: ImportFormat) -> Result<Vec<(usize, Result<ImportRecord>)>> {
+    match format {
+        ImportFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(reader);
+            let headers = reader.headers()?.clone();
+            Ok(reader
+                .records()
+                .enumerate()
+                .map(|(index, record)| {
+                    // The header is line 1
+                    let line = record
+                        .as_ref()
+                        .ok()
+                        .and_then(|record| record.position())
+                        .map_or(index + 2, |position| position.line() as usize);
+                    let record = record
+                        .and_then(|record| record.deserialize(Some(&headers)))
+                        .map_err(|e| eyre!("invalid CSV record: {}", e));
+                    (line, record)
+                })
+                .collect())
+        }
+        ImportFormat::JsonLines => Ok(BufReader::new(reader)
+            .lines()
+            .enumerate()
+            // Blank lines, such as a trailing newline, aren't records
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|(index, line)| {
+                let record = line
+                    .map_err(|e| eyre!(e))
+                    .and_then(|line| serde_json::from_str(&line).map_err(|e| eyre!("invalid JSON record: {}", e)));
+                (index + 1, record)
+            })
+            .collect()),
+    }
+}
+
+fn parse_record(record: ImportRecord) -> Result<(Email, ImportedPasswordHash, TwoFAMethod)> {
+    let email = Email::parse(Secret::new(record.email))?;
+    let password_hash = ImportedPasswordHash::parse(Secret::new(record.hash))?;
+    // Like signup, importing can only opt into emailed codes
+    let two_fa_method = match record.requires_2fa {
+        true => TwoFAMethod::Email,
+        false => TwoFAMethod::None,
+    };
+
+    Ok((email, password_hash, two_fa_method))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::data_stores::HashmapUserStore;
+
+    const BCRYPT_HASH: &str = "$2b$04$jHK5W0uNx6cgr4p1RhR6G.rPx.yJLX8aFTTW/V4KDOu8HSNbRM0Ha";
+    const PBKDF2_HASH: &str =
+        "$pbkdf2-sha256$i=1000,l=32$c2FsdHNhbHQ$jJwjWz/YhMQRX9qsCPjbQwWqFHe1qBtESvc5B8FVFmQ";
+
+    fn email(s: &str) -> Email {
+        Email::parse(Secret::new(s.to_owned())).unwrap()
+    }
+
+    #[test]
+    fn format_is_picked_from_the_extension() {
+        assert_eq!(ImportFormat::from_path(Path::new("users.csv")).unwrap(), ImportFormat::Csv);
+        assert_eq!(ImportFormat::from_path(Path::new("users.jsonl")).unwrap(), ImportFormat::JsonLines);
+        assert!(ImportFormat::from_path(Path::new("users.txt")).is_err());
+    }
+
+    #[tokio::test]
+    async fn imports_csv() {
+        // PHC parameters contain commas, so those hashes have to be quoted
+        let csv = format!(
+            "email,hash,requires_2fa\nalice@example.com,{},true\nbob@example.com,\"{}\",false\n",
+            BCRYPT_HASH, PBKDF2_HASH
+        );
+        let mut store = HashmapUserStore::default();
+
+        let summary = import_users(csv.as_bytes(), ImportFormat::Csv, &mut store).await.unwrap();
+
+        assert_eq!(summary, ImportSummary { imported: 2, ..Default::default() });
+        let alice = store.get_user(&email("alice@example.com")).await.unwrap();
+        assert_eq!(alice.two_fa_method, TwoFAMethod::Email);
+        let bob = store.get_user(&email("bob@example.com")).await.unwrap();
+        assert_eq!(bob.two_fa_method, TwoFAMethod::None);
+    }
+
+    #[tokio::test]
+    async fn imports_json_lines() {
+        let jsonl = format!(
+            "{}\n\n{}\n",
+            serde_json::json!({ "email": "alice@example.com", "hash": BCRYPT_HASH, "requires_2fa": false }),
+            serde_json::json!({ "email": "bob@example.com", "hash": PBKDF2_HASH, "requires_2fa": true }),
+        );
+        let mut store = HashmapUserStore::default();
+
+        let summary = import_users(jsonl.as_bytes(), ImportFormat::JsonLines, &mut store).await.unwrap();
+
+        assert_eq!(summary, ImportSummary { imported: 2, ..Default::default() });
+        assert!(store.get_user(&email("bob@example.com")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn reports_bad_records_and_carries_on() {
+        let csv = format!(
+            "email,hash,requires_2fa\nnot-an-email,{hash},false\ncarol@example.com,password123,false\ndave@example.com,{hash},maybe\nerin@example.com,{hash},false\n",
+            hash = BCRYPT_HASH
+        );
+        let mut store = HashmapUserStore::default();
+
+        let summary = import_users(csv.as_bytes(), ImportFormat::Csv, &mut store).await.unwrap();
+
+        assert_eq!(summary.imported, 1);
+        assert_eq!(
+            summary.failed.iter().map(|(line, _)| *line).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        assert!(store.get_user(&email("erin@example.com")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn existing_users_are_skipped() {
+        let csv = format!("email,hash,requires_2fa\nalice@example.com,{},false\n", BCRYPT_HASH);
+        let mut store = HashmapUserStore::default();
+
+        import_users(csv.as_bytes(), ImportFormat::Csv, &mut store).await.unwrap();
+        let summary = import_users(csv.as_bytes(), ImportFormat::Csv, &mut store).await.unwrap();
+
+        assert_eq!(summary, ImportSummary { already_existed: 1, ..Default::default() });
+    }
+}
@@ -0,0 +1,354 @@
+use std::io::Cursor;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::value::Value;
+use color_eyre::eyre::{eyre, Context, Result};
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::domain::{Email, PasskeyChallenge, PasskeyCredential};
+
+use super::constants::TWO_FA_CODE_HASH_KEY;
+
+// How long the browser and the challenge store give the user to complete a ceremony
+pub const PASSKEY_CHALLENGE_TTL_SECONDS: u64 = 300;
+
+// COSE algorithm identifier for ECDSA with P-256 and SHA-256, the only one we accept
+pub const COSE_ALGORITHM_ES256: i64 = -7;
+
+// Authenticator data flags (WebAuthn §6.1)
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+// rpIdHash (32) + flags (1) + signCount (4)
+const AUTHENTICATOR_DATA_MIN_LENGTH: usize = 37;
+const AAGUID_LENGTH: usize = 16;
+
+// The relying party the ceremonies are bound to: the RP ID is hashed into the authenticator
+// data and the origin is echoed back by the browser in clientDataJSON
+pub struct RelyingParty<'a> {
+    pub id: &'a str,
+    pub origin: &'a str,
+}
+
+// The raw fields of an `AuthenticatorAssertionResponse`, already base64url decoded
+pub struct PasskeyAssertion {
+    pub client_data_json: Vec<u8>,
+    pub authenticator_data: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony_type: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+// Check a `navigator.credentials.create()` response against the registration challenge and
+// return the new credential. We ask for "none" attestation, so only the self-reported
+// authenticator data is checked, not who made the authenticator.
+#[tracing::instrument(name = "Verifying passkey registration", skip_all)]
+pub fn verify_registration(
+    rp: &RelyingParty,
+    challenge: &PasskeyChallenge,
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+) -> Result<PasskeyCredential> {
+    verify_client_data(rp, challenge, client_data_json, "webauthn.create")?;
+
+    let attestation: Value = ciborium::de::from_reader(attestation_object)
+        .wrap_err("Attestation object is not valid CBOR")?;
+    let format = map_get(&attestation, &Value::Text("fmt".to_owned()))
+        .and_then(Value::as_text)
+        .ok_or_else(|| eyre!("Attestation object has no format"))?;
+    if format != "none" {
+        return Err(eyre!("Unsupported attestation format {}", format));
+    }
+    let authenticator_data = map_get(&attestation, &Value::Text("authData".to_owned()))
+        .and_then(Value::as_bytes)
+        .ok_or_else(|| eyre!("Attestation object has no authenticator data"))?;
+
+    let authenticator_data = parse_authenticator_data(authenticator_data)?;
+    verify_authenticator_data(rp, &authenticator_data, false)?;
+
+    let (credential_id, public_key) = authenticator_data
+        .attested_credential
+        .ok_or_else(|| eyre!("Authenticator data has no attested credential"))?;
+
+    Ok(PasskeyCredential {
+        credential_id: URL_SAFE_NO_PAD.encode(credential_id),
+        public_key,
+        sign_count: authenticator_data.sign_count,
+    })
+}
+
+// Check a `navigator.credentials.get()` response against the login challenge and the stored
+// credential, returning the authenticator's new signature counter
+#[tracing::instrument(name = "Verifying passkey assertion", skip_all)]
+pub fn verify_assertion(
+    rp: &RelyingParty,
+    challenge: &PasskeyChallenge,
+    credential: &PasskeyCredential,
+    assertion: &PasskeyAssertion,
+    require_user_verification: bool,
+) -> Result<u32> {
+    verify_client_data(rp, challenge, &assertion.client_data_json, "webauthn.get")?;
+
+    let authenticator_data = parse_authenticator_data(&assertion.authenticator_data)?;
+    verify_authenticator_data(rp, &authenticator_data, require_user_verification)?;
+
+    // The authenticator signs its data followed by the SHA-256 hash of clientDataJSON
+    let mut signed_data = assertion.authenticator_data.clone();
+    signed_data.extend_from_slice(&Sha256::digest(&assertion.client_data_json));
+
+    let verifying_key = VerifyingKey::from_sec1_bytes(&credential.public_key)
+        .wrap_err("Stored passkey public key is invalid")?;
+    let signature = Signature::from_der(&assertion.signature).wrap_err("Malformed assertion signature")?;
+    verifying_key
+        .verify(&signed_data, &signature)
+        .wrap_err("Assertion signature does not match")?;
+
+    // Authenticators that keep a counter must increase it on every use. Seeing it stand still
+    // or go backwards means two copies of the credential exist, so the login is refused.
+    // A counter that is always zero means the authenticator doesn't keep one.
+    let sign_count = authenticator_data.sign_count;
+    if (sign_count != 0 || credential.sign_count != 0) && sign_count <= credential.sign_count {
+        return Err(eyre!("Passkey signature counter did not increase, the credential may have been cloned"));
+    }
+
+    Ok(sign_count)
+}
+
+lazy_static! {
+    // Decoy credential IDs are sent to anyone who asks, so they get their own key rather than
+    // the one the 2FA code hashes are made with
+    static ref PASSKEY_DECOY_KEY: [u8; 32] =
+        derive_subkey(TWO_FA_CODE_HASH_KEY.expose_secret().as_bytes(), b"passkey-decoy");
+}
+
+// HKDF-SHA256 (RFC 5869) with a single output block, which is all a 32 byte key needs
+fn derive_subkey(secret: &[u8], label: &[u8]) -> [u8; 32] {
+    let mut extract = Hmac::<Sha256>::new_from_slice(&[0; 32]).expect("HMAC accepts keys of any length");
+    extract.update(secret);
+    let pseudorandom_key = extract.finalize().into_bytes();
+
+    let mut expand = Hmac::<Sha256>::new_from_slice(&pseudorandom_key).expect("HMAC accepts keys of any length");
+    expand.update(label);
+    expand.update(&[1]);
+    expand.finalize().into_bytes().into()
+}
+
+// A made-up credential ID to offer in place of real ones when the user has no passkeys, so the
+// login options don't give away which emails have an account or a passkey. It is derived from
+// the email rather than random so asking twice gives the same answer, like it would for a real user.
+pub fn decoy_credential_id(email: &Email) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(PASSKEY_DECOY_KEY.as_slice()).expect("HMAC accepts keys of any length");
+    mac.update(email.as_ref().expose_secret().as_bytes());
+    URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+fn verify_client_data(
+    rp: &RelyingParty,
+    challenge: &PasskeyChallenge,
+    client_data_json: &[u8],
+    expected_type: &str,
+) -> Result<()> {
+    let client_data: ClientData =
+        serde_json::from_slice(client_data_json).wrap_err("clientDataJSON is malformed")?;
+
+    if client_data.ceremony_type != expected_type {
+        return Err(eyre!("Unexpected ceremony type {}", client_data.ceremony_type));
+    }
+    if client_data.challenge != challenge.challenge {
+        return Err(eyre!("Challenge does not match"));
+    }
+    if client_data.origin != rp.origin {
+        return Err(eyre!("Unexpected origin {}", client_data.origin));
+    }
+
+    Ok(())
+}
+
+fn verify_authenticator_data(
+    rp: &RelyingParty,
+    authenticator_data: &AuthenticatorData,
+    require_user_verification: bool,
+) -> Result<()> {
+    if authenticator_data.rp_id_hash[..] != Sha256::digest(rp.id.as_bytes())[..] {
+        return Err(eyre!("Authenticator data is for a different relying party"));
+    }
+    if authenticator_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err(eyre!("User was not present"));
+    }
+    if require_user_verification && authenticator_data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err(eyre!("User was not verified"));
+    }
+
+    Ok(())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData> {
+    if data.len() < AUTHENTICATOR_DATA_MIN_LENGTH {
+        return Err(eyre!("Authenticator data is too short"));
+    }
+
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+        let rest = &data[AUTHENTICATOR_DATA_MIN_LENGTH..];
+        let id_start = AAGUID_LENGTH + 2;
+        if rest.len() < id_start {
+            return Err(eyre!("Attested credential data is too short"));
+        }
+        let id_length = u16::from_be_bytes([rest[AAGUID_LENGTH], rest[AAGUID_LENGTH + 1]]) as usize;
+        let credential_id = rest
+            .get(id_start..id_start + id_length)
+            .ok_or_else(|| eyre!("Attested credential data is too short"))?
+            .to_vec();
+
+        // The COSE key is followed by extension data if the ED flag is set, so only the first
+        // CBOR item is read
+        let cose_key: Value = ciborium::de::from_reader(Cursor::new(&rest[id_start + id_length..]))
+            .wrap_err("Credential public key is not valid CBOR")?;
+
+        Some((credential_id, parse_cose_key(&cose_key)?))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+// Convert an ES256 COSE_Key (RFC 9053 §7.1.1) to an uncompressed SEC1 point
+fn parse_cose_key(key: &Value) -> Result<Vec<u8>> {
+    let int_param = |label: i64| {
+        map_get(key, &Value::Integer(label.into()))
+            .and_then(Value::as_integer)
+            .and_then(|value| i64::try_from(value).ok())
+    };
+    let bytes_param = |label: i64| map_get(key, &Value::Integer(label.into())).and_then(Value::as_bytes);
+
+    // kty = EC2, alg = ES256, crv = P-256
+    if int_param(1) != Some(2) || int_param(3) != Some(COSE_ALGORITHM_ES256) || int_param(-1) != Some(1) {
+        return Err(eyre!("Only ES256 passkeys are supported"));
+    }
+
+    let (x, y) = match (bytes_param(-2), bytes_param(-3)) {
+        (Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => (x, y),
+        _ => return Err(eyre!("Credential public key has invalid coordinates")),
+    };
+
+    let mut public_key = Vec::with_capacity(65);
+    public_key.push(0x04);
+    public_key.extend_from_slice(x);
+    public_key.extend_from_slice(y);
+
+    // Make sure the point is actually on the curve before we store it
+    VerifyingKey::from_sec1_bytes(&public_key).wrap_err("Credential public key is not a valid P-256 point")?;
+
+    Ok(public_key)
+}
+
+fn map_get<'a>(map: &'a Value, key: &Value) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(entry_key, _)| entry_key == key)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::PasskeyCeremony;
+
+    const RP: RelyingParty = RelyingParty {
+        id: "localhost",
+        origin: "http://localhost:3000",
+    };
+
+    fn client_data(ceremony_type: &str, challenge: &PasskeyChallenge, origin: &str) -> Vec<u8> {
+        serde_json::json!({
+            "type": ceremony_type,
+            "challenge": challenge.challenge,
+            "origin": origin,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn client_data_must_match_ceremony() {
+        let challenge = PasskeyChallenge::new(PasskeyCeremony::Login);
+        let other_challenge = PasskeyChallenge::new(PasskeyCeremony::Login);
+
+        let valid = client_data("webauthn.get", &challenge, RP.origin);
+        assert!(verify_client_data(&RP, &challenge, &valid, "webauthn.get").is_ok());
+        assert!(verify_client_data(&RP, &challenge, &valid, "webauthn.create").is_err());
+        assert!(verify_client_data(&RP, &other_challenge, &valid, "webauthn.get").is_err());
+
+        let phishing = client_data("webauthn.get", &challenge, "https://evil.example");
+        assert!(verify_client_data(&RP, &challenge, &phishing, "webauthn.get").is_err());
+    }
+
+    #[test]
+    fn authenticator_data_must_match_relying_party_and_flags() {
+        let data = parse_authenticator_data(&authenticator_data("localhost", FLAG_USER_PRESENT, 3)).unwrap();
+        assert_eq!(data.sign_count, 3);
+        assert!(data.attested_credential.is_none());
+        assert!(verify_authenticator_data(&RP, &data, false).is_ok());
+        assert!(verify_authenticator_data(&RP, &data, true).is_err());
+
+        let data = parse_authenticator_data(&authenticator_data("evil.example", FLAG_USER_PRESENT, 0)).unwrap();
+        assert!(verify_authenticator_data(&RP, &data, false).is_err());
+
+        let data = parse_authenticator_data(&authenticator_data("localhost", 0, 0)).unwrap();
+        assert!(verify_authenticator_data(&RP, &data, false).is_err());
+
+        assert!(parse_authenticator_data(&[0; 36]).is_err());
+    }
+
+    #[test]
+    fn cose_key_must_be_es256() {
+        let key = |alg: i64| {
+            Value::Map(vec![
+                (Value::Integer(1.into()), Value::Integer(2.into())),
+                (Value::Integer(3.into()), Value::Integer(alg.into())),
+                (Value::Integer((-1).into()), Value::Integer(1.into())),
+                (Value::Integer((-2).into()), Value::Bytes(vec![0; 32])),
+                (Value::Integer((-3).into()), Value::Bytes(vec![0; 32])),
+            ])
+        };
+
+        // RS256 keys are turned away before the coordinates are looked at
+        assert!(parse_cose_key(&key(-257)).is_err());
+        // The all-zero point is not on the curve
+        assert!(parse_cose_key(&key(COSE_ALGORITHM_ES256)).is_err());
+    }
+}
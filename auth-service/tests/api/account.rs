@@ -0,0 +1,190 @@
+use auth_service::{
+    routes::{AccountDeletionScheduledResponse, CancelAccountDeletionResponse, TwoFactorAuthResponse},
+    ErrorResponse,
+};
+use test_macros::auto_cleanup;
+
+use crate::helpers::{get_link_param, get_random_email, TestApp};
+
+// Sign up a user and log them in on the app's own client, returning their email
+async fn sign_up_and_log_in(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    email
+}
+
+// The login attempt from a 2FA challenge response, along with the code that was sent for it
+async fn get_login_attempt(app: &TestApp, response: reqwest::Response) -> (String, String) {
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    (login_attempt_id, app.get_last_two_fa_code().await)
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.delete_account(&serde_json::json!({ "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_password_is_wrong() {
+    let mut app = TestApp::new().await;
+    let email = sign_up_and_log_in(&app).await;
+
+    let response = app.delete_account(&serde_json::json!({ "password": "wrongpassword" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The account is untouched
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_sign_out_and_refuse_login_until_deletion_is_cancelled() {
+    let mut app = TestApp::new().await;
+    let email = sign_up_and_log_in(&app).await;
+    let other_client = reqwest::Client::builder().cookie_store(true).build().unwrap();
+    let response = other_client
+        .post(format!("{}/login", &app.address))
+        .json(&serde_json::json!({ "email": email, "password": "password123" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.delete_account(&serde_json::json!({ "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 202);
+    response
+        .json::<AccountDeletionScheduledResponse>()
+        .await
+        .expect("Could not deserialize response body to AccountDeletionScheduledResponse");
+
+    // Every device is signed out straight away
+    let response = other_client
+        .get(format!("{}/sessions", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+    let response = other_client
+        .post(format!("{}/refresh", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let login_body = serde_json::json!({ "email": email, "password": "password123" });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Account pending deletion".to_owned()
+    );
+
+    // Following the emailed link restores the account
+    let token = get_link_param(&app.get_last_email_body().await, "token");
+    let response = app.get_cancel_account_deletion(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<CancelAccountDeletionResponse>()
+        .await
+        .expect("Could not deserialize response body to CancelAccountDeletionResponse");
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The link only works once
+    let response = app.get_cancel_account_deletion(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_for_unknown_cancel_token() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_cancel_account_deletion("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_require_2fa_code_when_enabled() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    let (login_attempt_id, code) = get_login_attempt(&app, response).await;
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The password alone only gets a fresh 2FA challenge
+    let response = app.delete_account(&serde_json::json!({ "password": "password123" })).await;
+    let (login_attempt_id, code) = get_login_attempt(&app, response).await;
+
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+    let response = app
+        .delete_account(&serde_json::json!({
+            "password": "password123",
+            "loginAttemptId": login_attempt_id,
+            "2FACode": wrong_code
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .delete_account(&serde_json::json!({
+            "password": "password123",
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+}
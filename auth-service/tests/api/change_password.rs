@@ -0,0 +1,159 @@
+use auth_service::{routes::ChangePasswordResponse, utils::{auth::validate_token, constants::JWT_COOKIE_NAME}, ErrorResponse};
+use test_macros::auto_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up and log in, returning the user's email and JWT
+async fn sign_up_and_log_in(app: &TestApp) -> (String, String) {
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    (email, token)
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_current_password_is_wrong() {
+    let mut app = TestApp::new().await;
+    let (email, _) = sign_up_and_log_in(&app).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "wrongpassword",
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The password is unchanged
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_new_password_is_invalid() {
+    let mut app = TestApp::new().await;
+    sign_up_and_log_in(&app).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "short"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse")
+        .reasons,
+        vec!["too_short"]);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_change_password_and_replace_tokens() {
+    let mut app = TestApp::new().await;
+    let (email, old_token) = sign_up_and_log_in(&app).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let new_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    assert_eq!(
+        response.json::<ChangePasswordResponse>().await.unwrap().message,
+        "Password changed successfully!"
+    );
+
+    let result = validate_token(&old_token, app.banned_token_store.clone(), app.session_store.clone(), app.jwt_keyring.clone()).await;
+    assert!(result.is_err());
+    let result = validate_token(&new_token, app.banned_token_store.clone(), app.session_store.clone(), app.jwt_keyring.clone()).await;
+    assert!(result.is_ok());
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "newpassword123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_new_password_was_used_recently() {
+    let mut app = TestApp::new().await;
+    sign_up_and_log_in(&app).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Both the current password and the one it replaced are refused
+    for (current_password, new_password) in [("newpassword123", "newpassword123"), ("newpassword123", "password123")] {
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "currentPassword": current_password,
+                "newPassword": new_password
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+        assert_eq!(response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .reasons,
+            vec!["reused"]);
+    }
+}
@@ -0,0 +1,79 @@
+use auth_service::ErrorResponse;
+use test_macros::auto_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn sign_up(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_admin_token_is_wrong() {
+    let mut app = TestApp::new().await;
+    let email = sign_up(&app).await;
+
+    let response = app
+        .post_disable_user("not-the-admin-token", &serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_404_if_user_does_not_exist() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_disable_user(&app.admin_api_token, &serde_json::json!({ "email": get_random_email() }))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_sign_out_disabled_user_and_refuse_login_until_enabled() {
+    let mut app = TestApp::new().await;
+    let email = sign_up(&app).await;
+    let login_body = serde_json::json!({ "email": email, "password": "password123" });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_disable_user(&app.admin_api_token, &serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The tokens the user already holds stop working
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse")
+        .error,
+        "Account disabled".to_owned());
+
+    let response = app
+        .post_enable_user(&app.admin_api_token, &serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
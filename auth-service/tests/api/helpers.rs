@@ -1,20 +1,24 @@
-use std::{str::FromStr, sync::Arc};
+use std::{future::Future, str::FromStr, sync::Arc, time::{Duration, Instant}};
 use secrecy::{Secret, ExposeSecret};
 use sqlx::{Connection, Executor, PgConnection, PgPool, postgres::{PgConnectOptions, PgPoolOptions}};
 use tokio::sync::RwLock;
-use auth_service::{Application, app_state::{AppState, BannedTokenStoreType, TwoFACodeStoreType}, domain::Email, get_postgres_pool, get_redis_client, services::data_stores::{PostgresUserStore, PostmarkEmailClient, RedisBannedTokenStore, RedisTwoFACodeStore }, utils::constants::{DATABASE_URL, REDIS_HOST_NAME, test}};
+use auth_service::{Application, app_state::{AppState, BannedTokenStoreType, JwtKeyringType, SessionStoreType, TwoFACodeStoreType}, domain::Email, get_postgres_pool, get_redis_client, services::data_stores::{HashmapLoginThrottleStore, PostgresJwtKeyStore, PostgresOAuthClientStore, PostgresPasskeyStore, PostgresRecoveryCodeStore, PostgresUserStore, PostmarkEmailClient, RedisAuthorizationCodeStore, RedisBannedTokenStore, RedisEmailVerificationTokenStore, RedisMagicLinkTokenStore, RedisPasskeyChallengeStore, RedisPasswordResetTokenStore, RedisRefreshTokenStore, RedisSessionStore, RedisTwoFACodeStore }, utils::{constants::{DATABASE_URL, JWT_INITIAL_KEY, REDIS_HOST_NAME, test}, jwt_key::JwtKey, jwt_keyring::JwtKeyring, login_throttle::LoginThrottlePolicy, password_policy::PasswordPolicy}};
 use uuid::Uuid;
 use reqwest::{Client, cookie::Jar};
-use wiremock::MockServer;
+use wiremock::{matchers::{method, path}, Mock, MockServer, ResponseTemplate};
 
 pub struct TestApp {
     pub address: String,
     pub cookie_jar: Arc<Jar>,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub session_store: SessionStoreType,
+    pub jwt_keyring: JwtKeyringType,
+    pub admin_api_token: String,
     pub http_client: reqwest::Client,
     pub email_server: MockServer, // New!
     pub db_name: String,
+    pub pg_pool: PgPool,
     pub clean_up_called: bool,
 }
 
@@ -28,16 +32,67 @@ impl Drop for TestApp {
 
 impl TestApp {
     pub async fn new() -> Self {
+        Self::build(false, LoginThrottlePolicy::default(), PasswordPolicy::default(), JWT_INITIAL_KEY.clone()).await
+    }
+
+    // Same as `new`, but login is refused until the user's email address is verified
+    pub async fn new_requiring_verified_email() -> Self {
+        Self::build(true, LoginThrottlePolicy::default(), PasswordPolicy::default(), JWT_INITIAL_KEY.clone()).await
+    }
+
+    // Same as `new`, but login attempts are throttled by the given policy
+    pub async fn new_with_login_throttle_policy(login_throttle_policy: LoginThrottlePolicy) -> Self {
+        Self::build(false, login_throttle_policy, PasswordPolicy::default(), JWT_INITIAL_KEY.clone()).await
+    }
+
+    // Same as `new`, but new passwords have to meet the given policy
+    pub async fn new_with_password_policy(password_policy: PasswordPolicy) -> Self {
+        Self::build(false, LoginThrottlePolicy::default(), password_policy, JWT_INITIAL_KEY.clone()).await
+    }
+
+    // Same as `new`, but tokens are signed with the given key instead of the configured one
+    pub async fn new_with_jwt_key(jwt_key: JwtKey) -> Self {
+        Self::build(false, LoginThrottlePolicy::default(), PasswordPolicy::default(), jwt_key).await
+    }
+
+    async fn build(require_verified_email: bool, login_throttle_policy: LoginThrottlePolicy, password_policy: PasswordPolicy, jwt_key: JwtKey) -> Self {
         let pg_pool = configure_postgresql().await;
         let db_name = pg_pool.connect_options().get_database().unwrap().to_string();
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool.clone())));
+        let recovery_code_store = Arc::new(RwLock::new(PostgresRecoveryCodeStore::new(pg_pool.clone())));
+        let passkey_store = Arc::new(RwLock::new(PostgresPasskeyStore::new(pg_pool.clone())));
+        let oauth_client_store = Arc::new(RwLock::new(PostgresOAuthClientStore::new(pg_pool.clone())));
+        let jwt_key_store = Arc::new(RwLock::new(PostgresJwtKeyStore::new(pg_pool.clone())));
         let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
         let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(Arc::new(RwLock::new(configure_redis())))));
+        let refresh_token_store = Arc::new(RwLock::new(RedisRefreshTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
+        let session_store = Arc::new(RwLock::new(RedisSessionStore::new(Arc::new(RwLock::new(configure_redis())))));
+        let password_reset_token_store = Arc::new(RwLock::new(RedisPasswordResetTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
+        let email_verification_token_store = Arc::new(RwLock::new(RedisEmailVerificationTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
+        let magic_link_token_store = Arc::new(RwLock::new(RedisMagicLinkTokenStore::new(Arc::new(RwLock::new(configure_redis())))));
+        let passkey_challenge_store = Arc::new(RwLock::new(RedisPasskeyChallengeStore::new(Arc::new(RwLock::new(configure_redis())))));
+        let authorization_code_store = Arc::new(RwLock::new(RedisAuthorizationCodeStore::new(Arc::new(RwLock::new(configure_redis())))));
+        // Every test connects from 127.0.0.1, so sharing Redis counts between apps would
+        // let one test's logins eat into another's per-address limit
+        let login_throttle_store = Arc::new(RwLock::new(HashmapLoginThrottleStore::default()));
         // Set up a mock email server
         let email_server = MockServer::start().await; // New!
+
+        // Every signup sends a verification email. Accept any email by default with the lowest
+        // priority, so mocks mounted by individual tests still take precedence.
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .with_priority(u8::MAX)
+            .mount(&email_server)
+            .await;
+
         let base_url = email_server.uri(); // New!
         let email_client = Arc::new(configure_postmark_email_client(base_url)); // Updated!
-        let app_state = AppState::new(user_store, banned_token_store.clone(), two_fa_code_store.clone(), email_client.clone());
+        // Each app gets its own keyring, so rotating keys in one test can't affect another
+        let jwt_keyring = Arc::new(RwLock::new(JwtKeyring::new(jwt_key)));
+        let admin_api_token = Uuid::new_v4().to_string();
+        let app_state = AppState::new(user_store, banned_token_store.clone(), two_fa_code_store.clone(), refresh_token_store, session_store.clone(), password_reset_token_store, email_verification_token_store, magic_link_token_store, recovery_code_store, passkey_store, passkey_challenge_store, oauth_client_store, authorization_code_store, login_throttle_store, email_client.clone(), jwt_keyring.clone(), jwt_key_store, require_verified_email, login_throttle_policy, password_policy, Some(Secret::new(admin_api_token.clone())));
 
         let app = Application::build(app_state.clone(), test::APP_ADDRESS)
             .await
@@ -62,9 +117,13 @@ impl TestApp {
             cookie_jar,
             banned_token_store,
             two_fa_code_store,
+            session_store,
+            jwt_keyring,
+            admin_api_token,
             http_client,
             email_server,
             db_name,
+            pg_pool,
             clean_up_called: false,
         }
     }
@@ -77,6 +136,143 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
     
+    pub async fn get_jwks(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/.well-known/jwks.json", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_rotate_jwt_keys(&self, admin_api_token: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/admin/jwt-keys/rotate", &self.address))
+            .bearer_auth(admin_api_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_register_oauth_client<Body>(&self, admin_api_token: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/clients", &self.address))
+            .bearer_auth(admin_api_token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Client credentials go in a Basic auth header when given, so tests can also send them in the form
+    pub async fn post_disable_user<Body>(&self, admin_api_token: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/users/disable", &self.address))
+            .bearer_auth(admin_api_token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_enable_user<Body>(&self, admin_api_token: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/users/enable", &self.address))
+            .bearer_auth(admin_api_token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_introspect<Body>(&self, client_credentials: Option<(&str, &str)>, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut request = self
+            .http_client
+            .post(format!("{}/introspect", &self.address))
+            .form(body);
+        if let Some((client_id, client_secret)) = client_credentials {
+            request = request.basic_auth(client_id, Some(client_secret));
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_openid_configuration(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/.well-known/openid-configuration", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Redirects aren't followed, as they lead to the client's redirect URI or to pages of ours
+    // the tests would rather inspect the address of
+    pub async fn get_authorize(&self, params: &[(&str, &str)]) -> reqwest::Response {
+        reqwest::Client::builder()
+            .cookie_provider(self.cookie_jar.clone())
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+            .get(format!("{}/authorize", &self.address))
+            .query(params)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_authorize<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/authorize", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_oauth_client(&self, client_id: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/clients/{}", &self.address, client_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_token<Body>(&self, client_credentials: Option<(&str, &str)>, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut request = self
+            .http_client
+            .post(format!("{}/token", &self.address))
+            .form(body);
+        if let Some((client_id, client_secret)) = client_credentials {
+            request = request.basic_auth(client_id, Some(client_secret));
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_userinfo(&self, access_token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/userinfo", &self.address))
+            .bearer_auth(access_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_signup<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -101,6 +297,28 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_magic_link_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/login/magic-link", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Follows the emailed link, which sends its token, expiry and signature as query parameters
+    pub async fn get_magic_link_callback(&self, params: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/login/magic-link/callback", &self.address))
+            .query(params)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.http_client
             .post(&format!("{}/logout", &self.address))
@@ -109,6 +327,95 @@ impl TestApp {
             .expect("Failed to log out.")
     }
 
+    pub async fn post_logout_all(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/logout-all", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_refresh(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/refresh", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_session(&self, session_id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/sessions/{}", &self.address, session_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/change-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_account<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .delete(format!("{}/account", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_cancel_account_deletion(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/account/cancel-deletion", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/request", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_2fa<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -121,6 +428,110 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
     
+    pub async fn post_resend_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/resend-2fa", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_enroll_totp<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/totp/enroll", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_confirm_totp<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/totp/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_regenerate_recovery_codes<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/recovery-codes", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_recovery_code<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-recovery-code", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_passkey_register_start(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/passkeys/register/start", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_passkey_register_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/passkeys/register/finish", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_passkey_login_start<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/passkeys/login/start", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_passkey_login_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/passkeys/login/finish", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -133,6 +544,59 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_verify_email(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/verify-email", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Returns the plain-text body of the most recent email sent through the mock Postmark server
+    pub async fn get_last_email_body(&self) -> String {
+        let requests = self
+            .email_server
+            .received_requests()
+            .await
+            .expect("Request recording is disabled on the mock email server");
+        let request = requests.last().expect("No email was sent");
+        let body: serde_json::Value =
+            serde_json::from_slice(&request.body).expect("Email request body is not valid JSON");
+        body["TextBody"].as_str().expect("Email has no TextBody").to_owned()
+    }
+
+    pub async fn get_email_count(&self) -> usize {
+        self.email_server
+            .received_requests()
+            .await
+            .expect("Request recording is disabled on the mock email server")
+            .len()
+    }
+
+    // Password reset and magic link emails are sent after the response, so wait for them to
+    // arrive before reading them
+    pub async fn wait_for_email_count(&self, count: usize) {
+        for _ in 0..100 {
+            if self.get_email_count().await >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("Expected {} emails to have been sent", count);
+    }
+
+    // The code from the last 2FA email sent. The service only keeps a hash of it, so this
+    // is the only place to read it from.
+    pub async fn get_last_two_fa_code(&self) -> String {
+        let body = self.get_last_email_body().await;
+        body.split("Here is your 2FA code: ")
+            .nth(1)
+            .and_then(|rest| rest.split(',').next())
+            .expect("Last email is not a 2FA code")
+            .to_owned()
+    }
+
 // Implement a clean_up method on the TestApp struct which calls the delete_database helper function.
 // NOTE: You will have to update TestApp to store the test database name.
 
@@ -147,6 +611,51 @@ pub fn get_random_email() -> String {
     format!("{}@example.com", Uuid::new_v4())
 }
 
+// Times `request` for a known and an unknown email and returns the median of each. The two
+// are interleaved so load from other tests affects both alike, and the first round is a
+// warm-up that isn't counted.
+pub async fn median_latencies<F, Fut>(known_email: &str, request: F) -> (Duration, Duration)
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    const SAMPLES: usize = 7;
+    let mut known = Vec::with_capacity(SAMPLES);
+    let mut unknown = Vec::with_capacity(SAMPLES);
+
+    for round in 0..=SAMPLES {
+        let start = Instant::now();
+        request(known_email.to_owned()).await;
+        let known_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        request(get_random_email()).await;
+        let unknown_elapsed = start.elapsed();
+
+        if round > 0 {
+            known.push(known_elapsed);
+            unknown.push(unknown_elapsed);
+        }
+    }
+
+    (median(known), median(unknown))
+}
+
+fn median(mut samples: Vec<Duration>) -> Duration {
+    samples.sort();
+    samples[samples.len() / 2]
+}
+
+// Pulls the value of a `name=value` query parameter out of a link embedded in an email body
+pub fn get_link_param(email_body: &str, name: &str) -> String {
+    let prefix = format!("{}=", name);
+    let start = email_body.find(&prefix).expect("Link parameter not found in email") + prefix.len();
+    email_body[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect()
+}
+
 async fn configure_postgresql() -> PgPool {
     let postgresql_conn_url = DATABASE_URL.expose_secret().to_owned();
 
@@ -0,0 +1,166 @@
+use auth_service::{
+    routes::{IntrospectResponse, RegisterOAuthClientResponse},
+    utils::constants::JWT_COOKIE_NAME,
+};
+use test_macros::auto_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn register_client(app: &TestApp) -> RegisterOAuthClientResponse {
+    let response = app
+        .post_register_oauth_client(&app.admin_api_token, &serde_json::json!({ "name": "resource-server" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    response
+        .json::<RegisterOAuthClientResponse>()
+        .await
+        .expect("Could not deserialize response body to RegisterOAuthClientResponse")
+}
+
+async fn login_for_token(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    auth_cookie.value().to_owned()
+}
+
+async fn introspect(app: &TestApp, client: &RegisterOAuthClientResponse, token: &str) -> IntrospectResponse {
+    let response = app
+        .post_introspect(
+            Some((&client.client_id, &client.client_secret)),
+            &[("token", token)],
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("cache-control").and_then(|value| value.to_str().ok()),
+        Some("no-store")
+    );
+    response
+        .json::<IntrospectResponse>()
+        .await
+        .expect("Could not deserialize response body to IntrospectResponse")
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_claims_for_active_token() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app).await;
+    let email = get_random_email();
+    let token = login_for_token(&app, &email).await;
+
+    let response = introspect(&app, &client, &token).await;
+    assert!(response.active);
+    assert_eq!(response.sub.as_deref(), Some(email.as_str()));
+    assert_eq!(response.username.as_deref(), Some(email.as_str()));
+    assert!(response.exp > response.iat);
+    assert!(response.jti.is_some_and(|jti| !jti.is_empty()));
+    assert_eq!(response.scope.as_deref(), Some("user"));
+    assert_eq!(response.token_type.as_deref(), Some("Bearer"));
+    // Tokens from our own login flows weren't issued to any OAuth client
+    assert_eq!(response.client_id, None);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_accept_client_credentials_in_form_body() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app).await;
+    let token = login_for_token(&app, &get_random_email()).await;
+
+    let response = app
+        .post_introspect(
+            None,
+            &[
+                ("token", token.as_str()),
+                ("client_id", client.client_id.as_str()),
+                ("client_secret", client.client_secret.as_str()),
+            ],
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = response.json::<IntrospectResponse>().await.unwrap();
+    assert!(response.active);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_report_banned_token_as_inactive() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app).await;
+    let token = login_for_token(&app, &get_random_email()).await;
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Only `active` is returned for inactive tokens, so nothing leaks about them
+    let response = introspect(&app, &client, &token).await;
+    assert_eq!(response, IntrospectResponse::default());
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_report_invalid_token_as_inactive() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app).await;
+
+    let response = introspect(&app, &client, "invalid_token").await;
+    assert!(!response.active);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_without_client_credentials() {
+    let mut app = TestApp::new().await;
+    let token = login_for_token(&app, &get_random_email()).await;
+
+    let response = app.post_introspect(None, &[("token", token.as_str())]).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_with_wrong_client_secret() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app).await;
+    let token = login_for_token(&app, &get_random_email()).await;
+    let wrong_secret = "x".repeat(client.client_secret.len());
+
+    let response = app
+        .post_introspect(Some((&client.client_id, &wrong_secret)), &[("token", token.as_str())])
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_introspect(Some(("unknown-client", &client.client_secret)), &[("token", token.as_str())])
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_422_if_token_is_missing() {
+    let mut app = TestApp::new().await;
+    let client = register_client(&app).await;
+
+    let response = app
+        .post_introspect(Some((&client.client_id, &client.client_secret)), &[("token_type_hint", "access_token")])
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+}
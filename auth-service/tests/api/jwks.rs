@@ -0,0 +1,82 @@
+use auth_service::{routes::RotateJwtKeysResponse, utils::constants::{JWT_COOKIE_NAME, JWT_INITIAL_KEY}};
+use jsonwebtoken::{decode_header, jwk::JwkSet};
+use test_macros::auto_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_published_signing_keys() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_jwks().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("cache-control").is_some());
+
+    let jwks = response
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+
+    // Only asymmetric keys are published; a shared HS256 secret never leaves the service
+    match &JWT_INITIAL_KEY.jwk {
+        Some(jwk) => assert_eq!(jwks.keys, vec![jwk.clone()]),
+        None => assert!(jwks.keys.is_empty()),
+    }
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_name_signing_key_in_issued_tokens() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    let header = decode_header(auth_cookie.value()).expect("Auth cookie is not a JWT");
+    assert_eq!(header.kid, Some(JWT_INITIAL_KEY.kid.clone()));
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_publish_rotated_key_alongside_retiring_one() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_rotate_jwt_keys(&app.admin_api_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let kid = response
+        .json::<RotateJwtKeysResponse>()
+        .await
+        .expect("Could not deserialize response body to RotateJwtKeysResponse")
+        .kid;
+
+    let jwks = app
+        .get_jwks()
+        .await
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+    let published: Vec<_> = jwks.keys.into_iter().filter_map(|jwk| jwk.common.key_id).collect();
+
+    if JWT_INITIAL_KEY.jwk.is_some() {
+        assert_eq!(published, vec![JWT_INITIAL_KEY.kid.clone(), kid]);
+    } else {
+        assert!(published.is_empty());
+    }
+}
@@ -1,10 +1,20 @@
-use crate::helpers::{get_random_email, TestApp};
+use std::time::Duration;
+
+use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+use pbkdf2::Pbkdf2;
+
+use crate::helpers::{get_random_email, median_latencies, TestApp};
 use auth_service::{ErrorResponse};
 use secrecy::{Secret, ExposeSecret};
 use serde_json;
 use auth_service::{
-    domain::{Email},
+    domain::{Email, ImportedPasswordHash, LoginAttemptId, TwoFAMethod, UserStore},
     routes::TwoFactorAuthResponse,
+    services::data_stores::PostgresUserStore,
+    utils::{
+        constants::{ARGON2_ITERATIONS, ARGON2_MEMORY_KIB, ARGON2_PARALLELISM},
+        login_throttle::LoginThrottlePolicy,
+    },
 };
 use test_macros::auto_cleanup;
 use wiremock::{Mock, ResponseTemplate, matchers::{method, path}};
@@ -129,6 +139,163 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
 
     assert_eq!(json_body.message, "2FA required".to_owned());
 
-    let (login_attempt_id, _) = app.two_fa_code_store.read().await.get_code(&random_email).await.unwrap();
-    assert_eq!(login_attempt_id.as_ref().expose_secret(), &json_body.login_attempt_id);
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id.clone()).unwrap();
+    assert!(app.two_fa_code_store.read().await.get_attempt(&random_email, &login_attempt_id).await.is_ok());
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_take_as_long_for_unknown_email_as_for_wrong_password() {
+    // Keep the throttle out of the way so every attempt reaches the password check
+    let mut app = TestApp::new_with_login_throttle_policy(LoginThrottlePolicy {
+        max_attempts_per_ip: 1000,
+        max_attempts_per_account: 1000,
+        lockout_threshold: 1000,
+        ..LoginThrottlePolicy::default()
+    })
+    .await;
+
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let (known, unknown) = median_latencies(&email, |email| {
+        let app = &app;
+        async move {
+            let response = app
+                .post_login(&serde_json::json!({ "email": email, "password": "wrong-password" }))
+                .await;
+            assert_eq!(response.status().as_u16(), 401);
+        }
+    })
+    .await;
+
+    let known = known.as_secs_f64();
+    let unknown = unknown.as_secs_f64();
+    assert!(
+        unknown >= known * 0.5 && unknown <= known * 2.0,
+        "unknown email took {:.1}ms, wrong password took {:.1}ms",
+        unknown * 1000.0,
+        known * 1000.0
+    );
+}
+
+async fn stored_password_hash(app: &TestApp, email: &str) -> String {
+    sqlx::query_scalar("SELECT password_hash FROM users WHERE email = $1")
+        .bind(email)
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to read password hash")
+}
+
+// The upgrade happens in the background after the login response, so wait for it
+async fn assert_password_hash_upgraded(app: &TestApp, email: &str, old_hash: &str) {
+    let expected_params = format!(
+        "m={},t={},p={}",
+        *ARGON2_MEMORY_KIB, *ARGON2_ITERATIONS, *ARGON2_PARALLELISM
+    );
+    let mut new_hash = old_hash.to_owned();
+    for _ in 0..50 {
+        new_hash = stored_password_hash(app, email).await;
+        if new_hash != old_hash {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(new_hash.starts_with("$argon2id$v=19$"), "hash was not upgraded: {}", new_hash);
+    assert!(new_hash.contains(&expected_params), "hash was not upgraded: {}", new_hash);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_upgrade_outdated_password_hash_after_login() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    // Swap in a hash made with cheaper settings, as if the cost had since been raised
+    let old_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(8192, 1, 1, None).unwrap(),
+    )
+    .hash_password(b"password123", &SaltString::generate(&mut rand::thread_rng()))
+    .unwrap()
+    .to_string();
+    sqlx::query("UPDATE users SET password_hash = $1 WHERE email = $2")
+        .bind(&old_hash)
+        .bind(&email)
+        .execute(&app.pg_pool)
+        .await
+        .expect("Failed to replace password hash");
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_password_hash_upgraded(&app, &email, &old_hash).await;
+
+    // The password still works against the upgraded hash
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_log_in_imported_users_and_upgrade_their_hashes() {
+    let mut app = TestApp::new().await;
+    let mut user_store = PostgresUserStore::new(app.pg_pool.clone());
+
+    let pbkdf2_hash = Pbkdf2
+        .hash_password_customized(
+            b"password123",
+            Some(pbkdf2::Algorithm::Pbkdf2Sha256.ident()),
+            None,
+            pbkdf2::Params { rounds: 1000, output_length: 32 },
+            &SaltString::generate(&mut rand::thread_rng()),
+        )
+        .unwrap()
+        .to_string();
+    let legacy_hashes = [bcrypt::hash("password123", 4).unwrap(), pbkdf2_hash];
+
+    for legacy_hash in legacy_hashes {
+        let email = get_random_email();
+        user_store
+            .import_user(
+                Email::parse(Secret::new(email.clone())).unwrap(),
+                ImportedPasswordHash::parse(Secret::new(legacy_hash.clone())).unwrap(),
+                TwoFAMethod::None,
+            )
+            .await
+            .expect("Failed to import user");
+
+        let response = app
+            .post_login(&serde_json::json!({ "email": email, "password": "wrong-password" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+
+        let response = app
+            .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+
+        assert_password_hash_upgraded(&app, &email, &legacy_hash).await;
+    }
 }
@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use auth_service::{
+    domain::RecoveryCode,
+    routes::{SignupResponse, TwoFactorAuthResponse},
+    utils::login_throttle::LoginThrottlePolicy,
+    ErrorResponse,
+};
+use reqwest::header::RETRY_AFTER;
+use secrecy::ExposeSecret;
+use test_macros::auto_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+fn policy() -> LoginThrottlePolicy {
+    LoginThrottlePolicy {
+        max_attempts_per_ip: 100,
+        max_attempts_per_account: 100,
+        window: Duration::from_secs(60),
+        lockout_threshold: 3,
+        lockout_base: Duration::from_secs(30),
+        lockout_max: Duration::from_secs(300),
+        two_fa_resend_cooldown: Duration::from_secs(30),
+        max_two_fa_resends: 3,
+    }
+}
+
+async fn sign_up(app: &TestApp, requires_2fa: bool) -> String {
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": requires_2fa
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+async fn assert_too_many_requests(response: reqwest::Response, retry_after: u64) {
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(
+        response.headers().get(RETRY_AFTER).unwrap().to_str().unwrap(),
+        retry_after.to_string()
+    );
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Too many requests".to_owned()
+    );
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_lock_account_after_repeated_wrong_passwords() {
+    let mut app = TestApp::new_with_login_throttle_policy(policy()).await;
+    let email = sign_up(&app, false).await;
+
+    for _ in 0..3 {
+        let response = app
+            .post_login(&serde_json::json!({ "email": email, "password": "wrongpassword" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Even the right password is refused while the account is locked
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_too_many_requests(response, 30).await;
+
+    // Other accounts are unaffected
+    let other_email = sign_up(&app, false).await;
+    let response = app
+        .post_login(&serde_json::json!({ "email": other_email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_reset_failures_after_successful_login() {
+    let mut app = TestApp::new_with_login_throttle_policy(policy()).await;
+    let email = sign_up(&app, false).await;
+
+    for _ in 0..2 {
+        let response = app
+            .post_login(&serde_json::json!({ "email": email, "password": "wrongpassword" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The count starts over, so two more mistakes don't lock the account
+    for _ in 0..2 {
+        let response = app
+            .post_login(&serde_json::json!({ "email": email, "password": "wrongpassword" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_limit_attempts_per_client_address() {
+    let mut app = TestApp::new_with_login_throttle_policy(LoginThrottlePolicy {
+        max_attempts_per_ip: 3,
+        ..policy()
+    })
+    .await;
+
+    // Spreading attempts over several accounts doesn't get around the limit
+    for _ in 0..3 {
+        let response = app
+            .post_login(&serde_json::json!({ "email": get_random_email(), "password": "password123" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": get_random_email(), "password": "password123" }))
+        .await;
+    assert_too_many_requests(response, 60).await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_limit_attempts_per_account() {
+    let mut app = TestApp::new_with_login_throttle_policy(LoginThrottlePolicy {
+        max_attempts_per_account: 2,
+        ..policy()
+    })
+    .await;
+    let email = sign_up(&app, false).await;
+
+    for _ in 0..2 {
+        let response = app
+            .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_too_many_requests(response, 60).await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_lock_login_attempt_after_repeated_wrong_2fa_codes() {
+    let mut app = TestApp::new_with_login_throttle_policy(policy()).await;
+    let email = sign_up(&app, true).await;
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let code = app.get_last_two_fa_code().await;
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+
+    for _ in 0..3 {
+        let response = app
+            .post_verify_2fa(&serde_json::json!({
+                "email": email,
+                "loginAttemptId": login_attempt_id,
+                "2FACode": wrong_code
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Once locked, not even the right code gets through
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code
+        }))
+        .await;
+    assert_too_many_requests(response, 30).await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_lock_login_attempt_after_repeated_wrong_recovery_codes() {
+    let mut app = TestApp::new_with_login_throttle_policy(policy()).await;
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let recovery_codes = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse")
+        .recovery_codes
+        .expect("Signup with 2FA returned no recovery codes");
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    for _ in 0..3 {
+        let response = app
+            .post_verify_recovery_code(&serde_json::json!({
+                "email": email,
+                "loginAttemptId": login_attempt_id,
+                "recoveryCode": RecoveryCode::default().as_ref().expose_secret()
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Once locked, not even a real recovery code gets through
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": recovery_codes[0]
+        }))
+        .await;
+    assert_too_many_requests(response, 30).await;
+}
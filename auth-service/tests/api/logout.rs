@@ -1,6 +1,5 @@
-use auth_service::{utils::constants::JWT_COOKIE_NAME, ErrorResponse};
+use auth_service::{utils::{auth::validate_token, constants::JWT_COOKIE_NAME}, ErrorResponse};
 use reqwest::Url;
-use secrecy::Secret;
 use test_macros::auto_cleanup;
 use crate::helpers::TestApp;
 
@@ -63,12 +62,16 @@ async fn should_return_200_if_valid_jwt_cookie() {
         .find(|c| c.name() == JWT_COOKIE_NAME)
         .unwrap()
         .value().to_string();
+    let jti = validate_token(&token, app.banned_token_store.clone(), app.session_store.clone(), app.jwt_keyring.clone())
+        .await
+        .expect("Failed to validate JWT")
+        .jti;
 
     // Now, log out
     let response = app.post_logout().await;
     assert_eq!(response.status().as_u16(), 200);
 
-    let is_banned = app.banned_token_store.read().await.is_token_banned(&Secret::new(token)).await.unwrap();
+    let is_banned = app.banned_token_store.read().await.is_token_banned(&jti).await.unwrap();
     assert!(is_banned);
 }
 
@@ -98,4 +101,57 @@ async fn should_return_400_if_logout_called_twice_in_a_row() {
     // Try to log out again with the same cookie
     let response = app.post_logout().await;
     assert_eq!(response.status().as_u16(), 400);
-}
\ No newline at end of file
+}
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_logout_all_called_without_jwt_cookie() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_logout_all().await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_invalidate_every_token_on_logout_all() {
+    let mut app = TestApp::new().await;
+    let signup_body = serde_json::json!({
+        "email": "user3@example.com",
+        "password": "securepassword",
+        "requires2FA": false
+    });
+    let signup_response = app.post_signup(&signup_body).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": "user3@example.com",
+        "password": "securepassword"
+    });
+
+    // Log in on another device first, keeping hold of its token
+    let other_login_response = app.post_login(&login_body).await;
+    assert_eq!(other_login_response.status().as_u16(), 200);
+    let other_token = other_login_response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .unwrap()
+        .value()
+        .to_string();
+
+    let login_response = app.post_login(&login_body).await;
+    assert_eq!(login_response.status().as_u16(), 200);
+
+    let response = app.post_logout_all().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let result = validate_token(&other_token, app.banned_token_store.clone(), app.session_store.clone(), app.jwt_keyring.clone()).await;
+    assert!(result.is_err());
+
+    // This device's cookies are cleared too
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // Logging in again works as normal
+    let login_response = app.post_login(&login_body).await;
+    assert_eq!(login_response.status().as_u16(), 200);
+}
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use auth_service::{domain::TwoFAMethod, routes::{MagicLinkResponse, TwoFactorAuthResponse}, utils::constants::JWT_COOKIE_NAME, ErrorResponse};
+use test_macros::auto_cleanup;
+use wiremock::{Mock, ResponseTemplate, matchers::{method, path}};
+use crate::helpers::{get_link_param, get_random_email, median_latencies, TestApp};
+
+async fn signup(app: &TestApp, email: &str, requires_2fa: bool) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": requires_2fa
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+// Asks for a magic link and returns the callback parameters the login page would pass on
+async fn request_magic_link(app: &TestApp, email: &str) -> Vec<(&'static str, String)> {
+    let sent = app.get_email_count().await;
+    let response = app
+        .post_magic_link_request(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.wait_for_email_count(sent + 1).await;
+    let body = app.get_last_email_body().await;
+    assert!(body.contains("/?magic_link_token="));
+    [("token", "magic_link_token"), ("expires", "expires"), ("signature", "signature")]
+        .into_iter()
+        .map(|(name, link_name)| (name, get_link_param(&body, link_name)))
+        .collect()
+}
+
+async fn follow_magic_link(app: &TestApp, params: &[(&'static str, String)]) -> reqwest::Response {
+    let params: Vec<(&str, &str)> = params.iter().map(|(name, value)| (*name, value.as_str())).collect();
+    app.get_magic_link_callback(&params).await
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_same_response_whether_or_not_user_exists() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+
+    let mut messages = Vec::new();
+    for email in [email, get_random_email()] {
+        let response = app
+            .post_magic_link_request(&serde_json::json!({ "email": email }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+        messages.push(response.json::<MagicLinkResponse>().await.unwrap().message);
+    }
+    assert_eq!(messages[0], messages[1]);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_take_as_long_for_unknown_email_as_for_existing_one() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+
+    // A slow email provider would show up in the response time if the link were sent before responding
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(150)))
+        .mount(&app.email_server)
+        .await;
+
+    let (existing, unknown) = median_latencies(&email, |email| {
+        let app = &app;
+        async move {
+            let response = app
+                .post_magic_link_request(&serde_json::json!({ "email": email }))
+                .await;
+            assert_eq!(response.status().as_u16(), 200);
+        }
+    })
+    .await;
+
+    assert!(
+        existing.abs_diff(unknown) < Duration::from_millis(100),
+        "unknown email took {:?}, existing email took {:?}",
+        unknown,
+        existing
+    );
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_log_in_with_emailed_link() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+
+    let params = request_magic_link(&app, &email).await;
+    let response = follow_magic_link(&app, &params).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_link_used_twice() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+
+    let params = request_magic_link(&app, &email).await;
+    let response = follow_magic_link(&app, &params).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = follow_magic_link(&app, &params).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Invalid token".to_owned()
+    );
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_link_tampered_with() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+
+    let mut params = request_magic_link(&app, &email).await;
+    let expires: i64 = params[1].1.parse().unwrap();
+    params[1].1 = (expires + 3600).to_string();
+
+    let response = follow_magic_link(&app, &params).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_ask_for_2fa_if_enabled() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, true).await;
+
+    let params = request_magic_link(&app, &email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = follow_magic_link(&app, &params).await;
+    assert_eq!(response.status().as_u16(), 206);
+    assert!(response.cookies().all(|cookie| cookie.name() != JWT_COOKIE_NAME));
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    assert_eq!(json_body.message, "2FA required".to_owned());
+    assert_eq!(json_body.two_fa_method, TwoFAMethod::Email);
+    assert_eq!(json_body.email, Some(email));
+}
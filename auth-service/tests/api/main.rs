@@ -1,7 +1,25 @@
+mod account;
+mod change_password;
+mod disable_user;
 mod helpers;
+mod introspect;
+mod jwks;
 mod login;
+mod login_throttle;
 mod logout;
+mod magic_link;
+mod oauth;
+mod oauth_clients;
+mod passkeys;
+mod password_reset;
+mod recovery_codes;
+mod refresh;
+mod resend_2fa;
 mod root;
+mod rotate_jwt_keys;
+mod sessions;
 mod signup;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
\ No newline at end of file
@@ -0,0 +1,491 @@
+use auth_service::{
+    routes::{ConsentResponse, IntrospectResponse, OAuthClientResponse, OpenIdConfiguration, RegisterOAuthClientResponse, TokenResponse, UserInfoResponse},
+    utils::{auth::Claims, constants::{JWT_COOKIE_NAME, JWT_ISSUER}, jwt_key::JwtKey},
+    ErrorResponse,
+};
+use jsonwebtoken::{decode, Algorithm, Validation};
+use reqwest::Url;
+use test_macros::auto_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const REDIRECT_URI: &str = "https://app.example.com/callback";
+// The example from RFC 7636 appendix B
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuNd-bw-cM";
+
+// OAuth is only served when tokens are signed with a key that can be published
+async fn spawn_app() -> TestApp {
+    TestApp::new_with_jwt_key(JwtKey::generate(Algorithm::EdDSA).expect("Failed to generate EdDSA key")).await
+}
+
+async fn register_client(app: &TestApp) -> RegisterOAuthClientResponse {
+    let response = app
+        .post_register_oauth_client(
+            &app.admin_api_token,
+            &serde_json::json!({ "name": "web-app", "redirectUris": [REDIRECT_URI] }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    response
+        .json::<RegisterOAuthClientResponse>()
+        .await
+        .expect("Could not deserialize response body to RegisterOAuthClientResponse")
+}
+
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+fn authorization_request(client_id: &str) -> serde_json::Value {
+    serde_json::json!({
+        "response_type": "code",
+        "client_id": client_id,
+        "redirect_uri": REDIRECT_URI,
+        "scope": "openid email",
+        "state": "af0ifjsldkj",
+        "nonce": "n-0S6_WzA2Mj",
+        "code_challenge": CODE_CHALLENGE,
+        "code_challenge_method": "S256"
+    })
+}
+
+fn query_param(url: &str, name: &str) -> Option<String> {
+    Url::parse(url)
+        .expect("Redirect is not a valid URL")
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+// Consent to the authorization request, returning the code the client is sent back with
+async fn authorize(app: &TestApp, client_id: &str) -> String {
+    let mut consent = authorization_request(client_id);
+    consent["approved"] = serde_json::json!(true);
+
+    let response = app.post_authorize(&consent).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let redirect_uri = response
+        .json::<ConsentResponse>()
+        .await
+        .expect("Could not deserialize response body to ConsentResponse")
+        .redirect_uri;
+    assert!(redirect_uri.starts_with(REDIRECT_URI));
+    assert_eq!(query_param(&redirect_uri, "state").as_deref(), Some("af0ifjsldkj"));
+    query_param(&redirect_uri, "code").expect("No code in redirect")
+}
+
+async fn redeem_code(app: &TestApp, client: &RegisterOAuthClientResponse, code: &str, code_verifier: &str) -> reqwest::Response {
+    app.post_token(
+        Some((&client.client_id, &client.client_secret)),
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", REDIRECT_URI),
+            ("code_verifier", code_verifier),
+        ],
+    )
+    .await
+}
+
+async fn assert_oauth_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error
+    );
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_serve_openid_configuration() {
+    let mut app = spawn_app().await;
+
+    let response = app.get_openid_configuration().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let configuration = response
+        .json::<OpenIdConfiguration>()
+        .await
+        .expect("Could not deserialize response body to OpenIdConfiguration");
+    assert_eq!(configuration.issuer, *JWT_ISSUER);
+    assert!(configuration.authorization_endpoint.ends_with("/authorize"));
+    assert!(configuration.token_endpoint.ends_with("/token"));
+    assert!(configuration.jwks_uri.ends_with("/.well-known/jwks.json"));
+    assert_eq!(configuration.code_challenge_methods_supported, vec!["S256".to_owned()]);
+    assert_eq!(configuration.id_token_signing_alg_values_supported, vec![Algorithm::EdDSA]);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_404_for_oauth_endpoints_if_tokens_are_signed_with_hs256() {
+    let mut app = TestApp::new_with_jwt_key(JwtKey::generate(Algorithm::HS256).expect("Failed to generate HS256 key")).await;
+
+    // ID tokens could only be verified with JWT_SECRET, which clients must never get
+    assert_eq!(app.get_openid_configuration().await.status().as_u16(), 404);
+    assert_eq!(app.get_authorize(&[("client_id", "web-app")]).await.status().as_u16(), 404);
+    assert_eq!(app.get_userinfo("token").await.status().as_u16(), 404);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_issue_tokens_for_authorization_code_with_pkce() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    let response = redeem_code(&app, &client, &code, CODE_VERIFIER).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("cache-control").and_then(|value| value.to_str().ok()),
+        Some("no-store")
+    );
+
+    let tokens = response
+        .json::<TokenResponse>()
+        .await
+        .expect("Could not deserialize response body to TokenResponse");
+    assert_eq!(tokens.token_type, "Bearer");
+    assert_eq!(tokens.scope, "openid email");
+
+    let id_token = tokens.id_token.expect("No ID token issued");
+    let signing_key = app.jwt_keyring.read().await.signing_key();
+    let mut validation = Validation::new(signing_key.algorithm);
+    validation.set_audience(&[client.client_id.as_str()]);
+    validation.set_issuer(&[JWT_ISSUER.as_str()]);
+    let claims = decode::<Claims>(&id_token, &signing_key.decoding_key, &validation)
+        .expect("ID token does not verify")
+        .claims;
+    assert_eq!(claims.sub, email);
+    assert_eq!(claims.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+
+    let response = app.get_userinfo(&tokens.access_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let userinfo = response
+        .json::<UserInfoResponse>()
+        .await
+        .expect("Could not deserialize response body to UserInfoResponse");
+    assert_eq!(userinfo.sub, email);
+    assert_eq!(userinfo.email, Some(email));
+    assert_eq!(userinfo.email_verified, Some(false));
+
+    // ID tokens are for the client, not for calling us with
+    assert_eq!(app.get_userinfo(&id_token).await.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_send_user_to_consent_page_if_logged_in() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+
+    let request = authorization_request(&client.client_id);
+    let params: Vec<(&str, &str)> = request
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str().unwrap()))
+        .collect();
+
+    let response = app.get_authorize(&params).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response.headers().get("location").unwrap().to_str().unwrap();
+    assert!(location.starts_with("/?return_to=%2Fauthorize%3F"));
+
+    signup_and_login(&app, &get_random_email()).await;
+
+    let response = app.get_authorize(&params).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response.headers().get("location").unwrap().to_str().unwrap();
+    assert!(location.starts_with("/consent.html?"));
+    assert!(location.contains(CODE_CHALLENGE));
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_client_name_for_consent_page() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+
+    let response = app.get_oauth_client(&client.client_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<OAuthClientResponse>()
+            .await
+            .expect("Could not deserialize response body to OAuthClientResponse"),
+        OAuthClientResponse {
+            client_id: client.client_id,
+            name: "web-app".to_owned(),
+        }
+    );
+
+    let response = app.get_oauth_client("unknown-client").await;
+    assert_oauth_error(response, 400, "invalid_request").await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_redirect_uri_is_not_registered() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+
+    let response = app
+        .get_authorize(&[
+            ("response_type", "code"),
+            ("client_id", &client.client_id),
+            ("redirect_uri", "https://attacker.example.com/callback"),
+            ("scope", "openid"),
+            ("code_challenge", CODE_CHALLENGE),
+            ("code_challenge_method", "S256"),
+        ])
+        .await;
+    assert_oauth_error(response, 400, "invalid_request").await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_redirect_with_error_if_pkce_is_missing() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+
+    let response = app
+        .get_authorize(&[
+            ("response_type", "code"),
+            ("client_id", &client.client_id),
+            ("redirect_uri", REDIRECT_URI),
+            ("scope", "openid"),
+            ("state", "af0ifjsldkj"),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let location = response.headers().get("location").unwrap().to_str().unwrap();
+    assert!(location.starts_with(REDIRECT_URI));
+    assert_eq!(query_param(location, "error").as_deref(), Some("invalid_request"));
+    assert_eq!(query_param(location, "state").as_deref(), Some("af0ifjsldkj"));
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_redirect_with_access_denied_if_user_declines() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let mut consent = authorization_request(&client.client_id);
+    consent["approved"] = serde_json::json!(false);
+
+    let response = app.post_authorize(&consent).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let redirect_uri = response
+        .json::<ConsentResponse>()
+        .await
+        .expect("Could not deserialize response body to ConsentResponse")
+        .redirect_uri;
+    assert_eq!(query_param(&redirect_uri, "error").as_deref(), Some("access_denied"));
+    assert_eq!(query_param(&redirect_uri, "code"), None);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_consent_is_given_without_logging_in() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+
+    let mut consent = authorization_request(&client.client_id);
+    consent["approved"] = serde_json::json!(true);
+
+    let response = app.post_authorize(&consent).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_reject_wrong_code_verifier() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    let response = redeem_code(&app, &client, &code, "aBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk").await;
+    assert_oauth_error(response, 400, "invalid_grant").await;
+
+    // A failed attempt spends the code
+    let response = redeem_code(&app, &client, &code, CODE_VERIFIER).await;
+    assert_oauth_error(response, 400, "invalid_grant").await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_reject_code_redeemed_twice() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    assert_eq!(redeem_code(&app, &client, &code, CODE_VERIFIER).await.status().as_u16(), 200);
+
+    let response = redeem_code(&app, &client, &code, CODE_VERIFIER).await;
+    assert_oauth_error(response, 400, "invalid_grant").await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_reject_code_issued_to_another_client() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+    let other_client = register_client(&app).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    let response = redeem_code(&app, &other_client, &code, CODE_VERIFIER).await;
+    assert_oauth_error(response, 400, "invalid_grant").await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_client_secret_is_wrong() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    let response = app
+        .post_token(
+            Some((&client.client_id, "wrong-secret")),
+            &[
+                ("grant_type", "authorization_code"),
+                ("code", code.as_str()),
+                ("redirect_uri", REDIRECT_URI),
+                ("code_verifier", CODE_VERIFIER),
+            ],
+        )
+        .await;
+    assert_oauth_error(response, 401, "invalid_client").await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_userinfo_is_called_with_our_own_token() {
+    let mut app = spawn_app().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    assert_eq!(app.get_userinfo(auth_cookie.value()).await.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_access_token_is_used_as_auth_cookie() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    let tokens = redeem_code(&app, &client, &code, CODE_VERIFIER)
+        .await
+        .json::<TokenResponse>()
+        .await
+        .expect("Could not deserialize response body to TokenResponse");
+
+    // A client holding the access token must not be able to manage the user's account with it
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; HttpOnly; SameSite=Lax; Path=/", JWT_COOKIE_NAME, tokens.access_token),
+        &Url::parse(&app.address).expect("Failed to parse URL"),
+    );
+
+    assert_eq!(app.get_sessions().await.status().as_u16(), 401);
+    assert_eq!(
+        app.post_enroll_totp(&serde_json::json!({ "password": "password123" }))
+            .await
+            .status()
+            .as_u16(),
+        401
+    );
+    assert_eq!(
+        app.post_regenerate_recovery_codes(&serde_json::json!({ "password": "password123" }))
+            .await
+            .status()
+            .as_u16(),
+        401
+    );
+    assert_eq!(app.post_passkey_register_start().await.status().as_u16(), 401);
+    assert_eq!(
+        app.delete_account(&serde_json::json!({ "password": "password123" }))
+            .await
+            .status()
+            .as_u16(),
+        401
+    );
+    assert_eq!(app.post_logout_all().await.status().as_u16(), 401);
+    assert_eq!(app.post_logout().await.status().as_u16(), 401);
+
+    // It still works where it is meant to
+    assert_eq!(app.get_userinfo(&tokens.access_token).await.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_access_token_is_passed_to_verify_token() {
+    let mut app = spawn_app().await;
+    let client = register_client(&app).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let code = authorize(&app, &client.client_id).await;
+    let tokens = redeem_code(&app, &client, &code, CODE_VERIFIER)
+        .await
+        .json::<TokenResponse>()
+        .await
+        .expect("Could not deserialize response body to TokenResponse");
+
+    // Our own apps check their jwt cookie through /verify-token, so it must not pass there
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": tokens.access_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Resource servers still see it as active through introspection, along with the client
+    let response = app
+        .post_introspect(
+            Some((&client.client_id, &client.client_secret)),
+            &[("token", tokens.access_token.as_str())],
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let introspection = response
+        .json::<IntrospectResponse>()
+        .await
+        .expect("Could not deserialize response body to IntrospectResponse");
+    assert!(introspection.active);
+    assert_eq!(introspection.client_id, Some(client.client_id.clone()));
+    assert_eq!(introspection.scope.as_deref(), Some("openid email"));
+}
@@ -0,0 +1,93 @@
+use auth_service::routes::RegisterOAuthClientResponse;
+use test_macros::auto_cleanup;
+
+use crate::helpers::TestApp;
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_201_with_client_credentials() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_register_oauth_client(&app.admin_api_token, &serde_json::json!({ "name": "resource-server" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let client = response
+        .json::<RegisterOAuthClientResponse>()
+        .await
+        .expect("Could not deserialize response body to RegisterOAuthClientResponse");
+    assert_eq!(client.name, "resource-server");
+    assert!(!client.client_id.is_empty());
+    assert!(!client.client_secret.is_empty());
+    assert!(client.redirect_uris.is_empty());
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_register_redirect_uris() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_register_oauth_client(
+            &app.admin_api_token,
+            &serde_json::json!({ "name": "web-app", "redirectUris": ["https://app.example.com/callback"] }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let client = response
+        .json::<RegisterOAuthClientResponse>()
+        .await
+        .expect("Could not deserialize response body to RegisterOAuthClientResponse");
+    assert_eq!(client.redirect_uris, vec!["https://app.example.com/callback".to_owned()]);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_redirect_uri_is_invalid() {
+    let mut app = TestApp::new().await;
+
+    for redirect_uri in ["/callback", "javascript:alert(1)", "https://app.example.com/callback#fragment"] {
+        let response = app
+            .post_register_oauth_client(
+                &app.admin_api_token,
+                &serde_json::json!({ "name": "web-app", "redirectUris": [redirect_uri] }),
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for redirect URI: {}", redirect_uri);
+    }
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_admin_token_is_wrong() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_register_oauth_client("not-the-admin-token", &serde_json::json!({ "name": "resource-server" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_name_is_empty() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_register_oauth_client(&app.admin_api_token, &serde_json::json!({ "name": "  " }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_register_oauth_client(&app.admin_api_token, &serde_json::json!({ "title": "resource-server" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+}
@@ -0,0 +1,498 @@
+use std::time::Duration;
+
+use auth_service::{
+    routes::{PasskeyLoginOptions, PasskeyRegistrationOptions, SignupResponse, TwoFactorAuthResponse},
+    utils::{
+        constants::{JWT_COOKIE_NAME, WEBAUTHN_ORIGIN, WEBAUTHN_RP_ID},
+        login_throttle::LoginThrottlePolicy,
+    },
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::value::Value;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use test_macros::auto_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+// Plays the part of a platform authenticator and the browser in front of it,
+// producing the same JSON a page would post after `navigator.credentials`
+#[derive(Clone)]
+struct SoftwareAuthenticator {
+    signing_key: SigningKey,
+    credential_id: Vec<u8>,
+    sign_count: u32,
+    user_verification: bool,
+    origin: String,
+}
+
+impl SoftwareAuthenticator {
+    fn new() -> Self {
+        let mut credential_id = vec![0u8; 16];
+        OsRng.fill_bytes(&mut credential_id);
+        Self {
+            signing_key: SigningKey::random(&mut OsRng),
+            credential_id,
+            sign_count: 0,
+            user_verification: true,
+            origin: WEBAUTHN_ORIGIN.to_owned(),
+        }
+    }
+
+    fn id(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.credential_id)
+    }
+
+    fn client_data_json(&self, ceremony_type: &str, challenge: &str) -> Vec<u8> {
+        serde_json::json!({
+            "type": ceremony_type,
+            "challenge": challenge,
+            "origin": self.origin,
+            "crossOrigin": false,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn authenticator_data(&self, extra_flags: u8) -> Vec<u8> {
+        let mut flags = FLAG_USER_PRESENT | extra_flags;
+        if self.user_verification {
+            flags |= FLAG_USER_VERIFIED;
+        }
+        let mut data = Sha256::digest(WEBAUTHN_RP_ID.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        data
+    }
+
+    fn create(&self, options: &PasskeyRegistrationOptions) -> serde_json::Value {
+        let point = self.signing_key.verifying_key().to_encoded_point(false);
+        let cose_key = Value::Map(vec![
+            (Value::Integer(1.into()), Value::Integer(2.into())),
+            (Value::Integer(3.into()), Value::Integer((-7).into())),
+            (Value::Integer((-1).into()), Value::Integer(1.into())),
+            (Value::Integer((-2).into()), Value::Bytes(point.x().unwrap().to_vec())),
+            (Value::Integer((-3).into()), Value::Bytes(point.y().unwrap().to_vec())),
+        ]);
+
+        let mut authenticator_data = self.authenticator_data(FLAG_ATTESTED_CREDENTIAL_DATA);
+        authenticator_data.extend_from_slice(&[0u8; 16]);
+        authenticator_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        authenticator_data.extend_from_slice(&self.credential_id);
+        ciborium::ser::into_writer(&cose_key, &mut authenticator_data).unwrap();
+
+        let attestation_object = Value::Map(vec![
+            (Value::Text("fmt".to_owned()), Value::Text("none".to_owned())),
+            (Value::Text("attStmt".to_owned()), Value::Map(vec![])),
+            (Value::Text("authData".to_owned()), Value::Bytes(authenticator_data)),
+        ]);
+        let mut attestation_object_bytes = Vec::new();
+        ciborium::ser::into_writer(&attestation_object, &mut attestation_object_bytes).unwrap();
+
+        serde_json::json!({
+            "ceremonyId": options.ceremony_id,
+            "id": self.id(),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(self.client_data_json("webauthn.create", &options.challenge)),
+                "attestationObject": URL_SAFE_NO_PAD.encode(attestation_object_bytes),
+            }
+        })
+    }
+
+    fn get(&mut self, options: &PasskeyLoginOptions) -> serde_json::Value {
+        self.sign_count += 1;
+        let client_data_json = self.client_data_json("webauthn.get", &options.challenge);
+        let authenticator_data = self.authenticator_data(0);
+
+        let mut signed_data = authenticator_data.clone();
+        signed_data.extend_from_slice(&Sha256::digest(&client_data_json));
+        let signature: Signature = self.signing_key.sign(&signed_data);
+
+        serde_json::json!({
+            "ceremonyId": options.ceremony_id,
+            "id": self.id(),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data_json),
+                "authenticatorData": URL_SAFE_NO_PAD.encode(authenticator_data),
+                "signature": URL_SAFE_NO_PAD.encode(signature.to_der().as_bytes()),
+            }
+        })
+    }
+}
+
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn register(app: &TestApp, authenticator: &SoftwareAuthenticator) {
+    let response = app.post_passkey_register_start().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let options = response
+        .json::<PasskeyRegistrationOptions>()
+        .await
+        .expect("Could not deserialize response body to PasskeyRegistrationOptions");
+    assert_eq!(options.rp.id, WEBAUTHN_RP_ID.to_owned());
+
+    let response = app
+        .post_passkey_register_finish(&authenticator.create(&options))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+// Runs a passkey login and returns the status of the finishing request
+async fn login_with_passkey(
+    app: &TestApp,
+    authenticator: &mut SoftwareAuthenticator,
+    email: &str,
+    login_attempt_id: Option<&str>,
+) -> reqwest::Response {
+    let response = app
+        .post_passkey_login_start(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let options = response
+        .json::<PasskeyLoginOptions>()
+        .await
+        .expect("Could not deserialize response body to PasskeyLoginOptions");
+    assert_eq!(options.allow_credentials[0].id, authenticator.id());
+
+    let mut body = authenticator.get(&options);
+    body["email"] = serde_json::json!(email);
+    body["loginAttemptId"] = serde_json::json!(login_attempt_id);
+    app.post_passkey_login_finish(&body).await
+}
+
+// Starts a passkey login and returns the options handed to the browser
+async fn start_passkey_login(app: &TestApp, email: &str) -> PasskeyLoginOptions {
+    let response = app
+        .post_passkey_login_start(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<PasskeyLoginOptions>()
+        .await
+        .expect("Could not deserialize response body to PasskeyLoginOptions")
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_registering_without_jwt_cookie() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_passkey_register_start().await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_not_reveal_whether_user_has_passkeys() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    // Users without passkeys and emails without an account get the same kind of options
+    // as users with one, and the same options each time they ask
+    for email in [email, get_random_email()] {
+        let options = start_passkey_login(&app, &email).await;
+        assert_eq!(options.allow_credentials.len(), 1);
+        let repeated_options = start_passkey_login(&app, &email).await;
+        assert_eq!(repeated_options.allow_credentials[0].id, options.allow_credentials[0].id);
+        assert_ne!(repeated_options.ceremony_id, options.ceremony_id);
+
+        // ...but no passkey can answer them
+        let mut body = SoftwareAuthenticator::new().get(&options);
+        body["email"] = serde_json::json!(email);
+        let response = app.post_passkey_login_finish(&body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_not_let_new_ceremony_replace_one_in_flight() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+
+    let options = start_passkey_login(&app, &email).await;
+
+    // Anyone can start a login for the user's email while theirs is in flight
+    start_passkey_login(&app, &email).await;
+
+    let mut body = authenticator.get(&options);
+    body["email"] = serde_json::json!(email);
+    let response = app.post_passkey_login_finish(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_ceremony_was_started_for_another_user() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+
+    let options = start_passkey_login(&app, &get_random_email()).await;
+
+    let mut body = authenticator.get(&options);
+    body["email"] = serde_json::json!(email);
+    let response = app.post_passkey_login_finish(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_lock_account_after_repeated_failed_passkey_logins() {
+    let mut app = TestApp::new_with_login_throttle_policy(LoginThrottlePolicy {
+        max_attempts_per_ip: 100,
+        max_attempts_per_account: 100,
+        window: Duration::from_secs(60),
+        lockout_threshold: 3,
+        lockout_base: Duration::from_secs(30),
+        lockout_max: Duration::from_secs(300),
+        two_fa_resend_cooldown: Duration::from_secs(30),
+        max_two_fa_resends: 3,
+    })
+    .await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+
+    // A passkey that was never registered fails like a wrong password
+    let mut unknown_authenticator = SoftwareAuthenticator::new();
+    for _ in 0..3 {
+        let options = start_passkey_login(&app, &email).await;
+        let mut body = unknown_authenticator.get(&options);
+        body["email"] = serde_json::json!(email);
+        let response = app.post_passkey_login_finish(&body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Once locked, not even the right passkey gets through
+    let options = start_passkey_login(&app, &email).await;
+    let mut body = authenticator.get(&options);
+    body["email"] = serde_json::json!(email);
+    let response = app.post_passkey_login_finish(&body).await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_login_without_password_using_passkey() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+
+    let response = login_with_passkey(&app, &mut authenticator, &email, None).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.cookies().any(|c| c.name() == JWT_COOKIE_NAME && !c.value().is_empty()));
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_exclude_registered_passkeys_and_reject_duplicates() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+
+    let response = app.post_passkey_register_start().await;
+    let options = response
+        .json::<PasskeyRegistrationOptions>()
+        .await
+        .expect("Could not deserialize response body to PasskeyRegistrationOptions");
+    assert_eq!(options.exclude_credentials.len(), 1);
+    assert_eq!(options.exclude_credentials[0].id, authenticator.id());
+
+    let response = app
+        .post_passkey_register_finish(&authenticator.create(&options))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_registration_origin_does_not_match() {
+    let mut app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    authenticator.origin = "https://phishing.example".to_owned();
+
+    let response = app.post_passkey_register_start().await;
+    let options = response
+        .json::<PasskeyRegistrationOptions>()
+        .await
+        .expect("Could not deserialize response body to PasskeyRegistrationOptions");
+
+    let response = app
+        .post_passkey_register_finish(&authenticator.create(&options))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_passwordless_login_lacks_user_verification() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+
+    authenticator.user_verification = false;
+    let response = login_with_passkey(&app, &mut authenticator, &email, None).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_signature_counter_does_not_increase() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+    let mut cloned_authenticator = authenticator.clone();
+
+    let response = login_with_passkey(&app, &mut authenticator, &email, None).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // A copy of the key starting from the same counter gives itself away
+    let response = login_with_passkey(&app, &mut cloned_authenticator, &email, None).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_assertion_is_replayed() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+
+    let options = start_passkey_login(&app, &email).await;
+
+    let mut body = authenticator.get(&options);
+    body["email"] = serde_json::json!(email);
+    let response = app.post_passkey_login_finish(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_passkey_login_finish(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_complete_2fa_login_with_passkey() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let recovery_codes = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse")
+        .recovery_codes
+        .expect("Signup with 2FA returned no recovery codes");
+
+    // Log in once with a recovery code to get the JWT needed to register a passkey
+    let login_attempt_id = start_2fa_login(&app, &email).await;
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": recovery_codes[0]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+
+    // As a second factor the passkey doesn't need to verify the user
+    authenticator.user_verification = false;
+    let login_attempt_id = start_2fa_login(&app, &email).await;
+    let response = login_with_passkey(&app, &mut authenticator, &email, Some(&login_attempt_id)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.cookies().any(|c| c.name() == JWT_COOKIE_NAME && !c.value().is_empty()));
+
+    // The login attempt is used up
+    let response = login_with_passkey(&app, &mut authenticator, &email, Some(&login_attempt_id)).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_login_attempt_id_does_not_match() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &authenticator).await;
+
+    // No password login is pending for this attempt
+    let login_attempt_id = uuid::Uuid::new_v4().to_string();
+    let response = login_with_passkey(&app, &mut authenticator, &email, Some(&login_attempt_id)).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+async fn start_2fa_login(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id
+}
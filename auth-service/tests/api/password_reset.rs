@@ -0,0 +1,390 @@
+use std::time::Duration;
+
+use auth_service::{routes::PasswordResetResponse, utils::{auth::PASSWORD_RESET_MAX_ATTEMPTS, constants::JWT_COOKIE_NAME}, ErrorResponse};
+use test_macros::auto_cleanup;
+use wiremock::{Mock, ResponseTemplate, matchers::{method, path}};
+use crate::helpers::{get_link_param, get_random_email, median_latencies, TestApp};
+
+async fn signup(app: &TestApp, email: &str, password: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": password,
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn request_reset_token(app: &TestApp, email: &str) -> String {
+    let sent = app.get_email_count().await;
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.wait_for_email_count(sent + 1).await;
+    get_link_param(&app.get_last_email_body().await, "password_reset_token")
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "mail": get_random_email() }))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({ "token": "some-token" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_invalid_email() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": "not-an-email" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_same_response_whether_or_not_user_exists() {
+    let mut app = TestApp::new().await;
+    let existing_email = get_random_email();
+    signup(&app, &existing_email, "password123").await;
+
+    // Only the existing account should receive an email
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let sent = app.get_email_count().await;
+    let existing_response = app
+        .post_password_reset_request(&serde_json::json!({ "email": existing_email }))
+        .await;
+    let unknown_response = app
+        .post_password_reset_request(&serde_json::json!({ "email": get_random_email() }))
+        .await;
+    app.wait_for_email_count(sent + 1).await;
+
+    assert_eq!(existing_response.status().as_u16(), 200);
+    assert_eq!(unknown_response.status().as_u16(), 200);
+    assert_eq!(
+        existing_response.json::<PasswordResetResponse>().await.unwrap(),
+        unknown_response.json::<PasswordResetResponse>().await.unwrap()
+    );
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_take_as_long_for_unknown_email_as_for_existing_one() {
+    let mut app = TestApp::new().await;
+    let existing_email = get_random_email();
+    signup(&app, &existing_email, "password123").await;
+
+    // A slow email provider would show up in the response time if the email were sent before responding
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(150)))
+        .mount(&app.email_server)
+        .await;
+
+    let (existing, unknown) = median_latencies(&existing_email, |email| {
+        let app = &app;
+        async move {
+            let response = app
+                .post_password_reset_request(&serde_json::json!({ "email": email }))
+                .await;
+            assert_eq!(response.status().as_u16(), 200);
+        }
+    })
+    .await;
+
+    assert!(
+        existing.abs_diff(unknown) < Duration::from_millis(100),
+        "unknown email took {:?}, existing email took {:?}",
+        unknown,
+        existing
+    );
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_reset_password_with_emailed_token() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, "password123").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let token = request_reset_token(&app, &email).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "newpassword123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_token_used_twice() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, "password123").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let token = request_reset_token(&app, &email).await;
+    let confirm_body = serde_json::json!({
+        "token": token,
+        "newPassword": "newpassword123"
+    });
+
+    let response = app.post_password_reset_confirm(&confirm_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_password_reset_confirm(&confirm_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse")
+        .error,
+        "Invalid token".to_owned());
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_unknown_token() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": "a".repeat(64),
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_keep_token_if_new_password_invalid() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, "password123").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let token = request_reset_token(&app, &email).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "short"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_keep_token_if_new_password_contains_email() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, "password123").await;
+
+    let token = request_reset_token(&app, &email).await;
+
+    // The email is only known once the token is looked up, so this is the last check made
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": format!("my {}", email)
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .reasons,
+        vec!["contains_email"]
+    );
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_keep_token_if_new_password_was_used_recently() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, "password123").await;
+
+    let token = request_reset_token(&app, &email).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "password123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    // Unlike at /change-password, the link holder isn't told the password was used before
+    let error = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error.error, "Invalid credentials");
+    assert!(error.reasons.is_empty());
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_invalidate_token_after_too_many_refused_passwords() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, "password123").await;
+
+    let token = request_reset_token(&app, &email).await;
+
+    for _ in 0..PASSWORD_RESET_MAX_ATTEMPTS {
+        let response = app
+            .post_password_reset_confirm(&serde_json::json!({
+                "token": token,
+                "newPassword": "password123"
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_invalidate_existing_tokens_after_reset() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email, "password123").await;
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let old_token = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("JWT cookie not found in login response")
+        .value()
+        .to_string();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let token = request_reset_token(&app, &email).await;
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "newpassword123"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": old_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The refresh token issued with the old session is gone as well
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // A fresh login right after the reset works
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "newpassword123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_token = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("JWT cookie not found in login response")
+        .value()
+        .to_string();
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": new_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
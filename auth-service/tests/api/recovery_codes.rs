@@ -0,0 +1,283 @@
+use auth_service::routes::{RecoveryCodesResponse, SignupResponse, TwoFactorAuthResponse};
+use test_macros::auto_cleanup;
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up a user with emailed 2FA codes and returns their recovery codes
+async fn signup_with_2fa(app: &TestApp, email: &str) -> Vec<String> {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse")
+        .recovery_codes
+        .expect("Signup with 2FA returned no recovery codes")
+}
+
+// Starts a login for a 2FA user and returns the login attempt ID
+async fn start_login(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id
+}
+
+// Regenerates the logged in user's recovery codes, answering the emailed 2FA challenge
+async fn regenerate(app: &TestApp) -> Vec<String> {
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({
+            "password": "password123",
+            "loginAttemptId": login_attempt_id,
+            "2FACode": app.get_last_two_fa_code().await
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .recovery_codes
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_not_issue_recovery_codes_without_2fa() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let body = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse");
+    assert_eq!(body.recovery_codes, None);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": get_random_email(),
+            "loginAttemptId": "some-id"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_invalid_recovery_code() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_with_2fa(&app, &email).await;
+    let login_attempt_id = start_login(&app, &email).await;
+
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": "not-a-code!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_login_with_recovery_code_only_once() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let recovery_codes = signup_with_2fa(&app, &email).await;
+
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": recovery_codes[0]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The same code can't complete a second login
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": recovery_codes[0]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // ...but the rest of the set still works
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": recovery_codes[1]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_login_attempt_id_does_not_match() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let recovery_codes = signup_with_2fa(&app, &email).await;
+    start_login(&app, &email).await;
+
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": uuid::Uuid::new_v4().to_string(),
+            "recoveryCode": recovery_codes[0]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_replace_codes_when_regenerated() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let old_codes = signup_with_2fa(&app, &email).await;
+
+    // Log in with a recovery code to get a JWT cookie
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": old_codes[0]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let new_codes = regenerate(&app).await;
+    assert_eq!(new_codes.len(), 10);
+
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": old_codes[1]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": new_codes[0]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_regenerating_without_2fa() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_regenerating_without_reauthenticating() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let codes = signup_with_2fa(&app, &email).await;
+
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": codes[0]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The JWT cookie alone must not be enough to mint codes that get past 2FA
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({ "password": "wrongpassword" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({
+            "password": "password123",
+            "loginAttemptId": uuid::Uuid::new_v4().to_string(),
+            "2FACode": "123456"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The user's own codes still work
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = app
+        .post_verify_recovery_code(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": codes[1]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
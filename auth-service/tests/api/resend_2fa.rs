@@ -0,0 +1,249 @@
+use std::time::Duration;
+
+use auth_service::{
+    domain::LoginAttemptId,
+    routes::{Resend2FAResponse, TwoFactorAuthResponse},
+    utils::login_throttle::LoginThrottlePolicy,
+    ErrorResponse,
+};
+use reqwest::header::RETRY_AFTER;
+use secrecy::ExposeSecret;
+use test_macros::auto_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+// No waiting between resends, so they can be made back to back
+fn policy() -> LoginThrottlePolicy {
+    LoginThrottlePolicy {
+        two_fa_resend_cooldown: Duration::ZERO,
+        max_two_fa_resends: 2,
+        ..LoginThrottlePolicy::default()
+    }
+}
+
+// Sign up a user with emailed 2FA and start logging them in, returning their email and login attempt ID
+async fn start_login(app: &TestApp) -> (String, String) {
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    (email, login_attempt_id)
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_resend_2fa(&serde_json::json!({ "email": get_random_email() })).await;
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_resend_2fa(&serde_json::json!({
+            "email": get_random_email(),
+            "loginAttemptId": "not-a-login-attempt-id"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_for_unknown_login_attempt() {
+    let mut app = TestApp::new_with_login_throttle_policy(policy()).await;
+    let (email, _) = start_login(&app).await;
+
+    let response = app
+        .post_resend_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": LoginAttemptId::default().as_ref().expose_secret()
+        }))
+        .await;
+    assert_error(response, 401, "Incorrect credentials").await;
+
+    // Nor can someone else's login attempt be resent
+    let (_, other_login_attempt_id) = start_login(&app).await;
+    let response = app
+        .post_resend_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": other_login_attempt_id
+        }))
+        .await;
+    assert_error(response, 401, "Incorrect credentials").await;
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_not_reveal_whether_account_exists_or_uses_email_2fa() {
+    let mut app = TestApp::new_with_login_throttle_policy(policy()).await;
+    let no_2fa_email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": no_2fa_email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let (email_2fa_email, _) = start_login(&app).await;
+
+    // Without a pending emailed attempt, every account looks like an unknown one
+    for email in [get_random_email(), no_2fa_email, email_2fa_email] {
+        let response = app
+            .post_resend_2fa(&serde_json::json!({
+                "email": email,
+                "loginAttemptId": LoginAttemptId::default().as_ref().expose_secret()
+            }))
+            .await;
+        assert_error(response, 401, "Incorrect credentials").await;
+    }
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_send_fresh_code_for_same_login_attempt() {
+    let mut app = TestApp::new_with_login_throttle_policy(policy()).await;
+    let (email, login_attempt_id) = start_login(&app).await;
+    let old_code = app.get_last_two_fa_code().await;
+
+    let response = app
+        .post_resend_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<Resend2FAResponse>()
+            .await
+            .expect("Could not deserialize response body to Resend2FAResponse")
+            .message,
+        "2FA code resent".to_owned()
+    );
+
+    let new_code = app.get_last_two_fa_code().await;
+
+    // The code from the first email no longer works, the one just sent does
+    if old_code != new_code {
+        let response = app
+            .post_verify_2fa(&serde_json::json!({
+                "email": email,
+                "loginAttemptId": login_attempt_id,
+                "2FACode": old_code
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": new_code
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_429_during_cooldown() {
+    let mut app = TestApp::new_with_login_throttle_policy(LoginThrottlePolicy {
+        two_fa_resend_cooldown: Duration::from_secs(30),
+        ..policy()
+    })
+    .await;
+    let (email, login_attempt_id) = start_login(&app).await;
+    let code = app.get_last_two_fa_code().await;
+
+    let response = app
+        .post_resend_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id
+        }))
+        .await;
+    let retry_after: u64 = response.headers().get(RETRY_AFTER).unwrap().to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= 30);
+    assert_error(response, 429, "2FA code sent too recently").await;
+
+    // The code that was already sent is left alone
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_429_after_max_resends() {
+    let mut app = TestApp::new_with_login_throttle_policy(policy()).await;
+    let (email, login_attempt_id) = start_login(&app).await;
+
+    for _ in 0..2 {
+        let response = app
+            .post_resend_2fa(&serde_json::json!({
+                "email": email,
+                "loginAttemptId": login_attempt_id
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let response = app
+        .post_resend_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id
+        }))
+        .await;
+    assert!(response.headers().get(RETRY_AFTER).is_none());
+    assert_error(response, 429, "2FA code resend limit reached").await;
+
+    // The last code sent can still be used to finish logging in
+    let code = app.get_last_two_fa_code().await;
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use auth_service::{routes::RotateJwtKeysResponse, services::data_stores::PostgresJwtKeyStore, utils::{constants::{JWT_COOKIE_NAME, JWT_INITIAL_KEY}, jwt_keyring::{sync_jwt_keyring, JwtKeyring}}};
+use jsonwebtoken::decode_header;
+use test_macros::auto_cleanup;
+use tokio::sync::RwLock;
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn login_for_token(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    auth_cookie.value().to_owned()
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_admin_token_is_wrong() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_rotate_jwt_keys("not-the-admin-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_admin_token_is_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/admin/jwt-keys/rotate", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_sign_new_tokens_with_rotated_key_without_logging_anyone_out() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let old_token = login_for_token(&app, &email).await;
+
+    let response = app.post_rotate_jwt_keys(&app.admin_api_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let kid = response
+        .json::<RotateJwtKeysResponse>()
+        .await
+        .expect("Could not deserialize response body to RotateJwtKeysResponse")
+        .kid;
+    assert_ne!(decode_header(&old_token).unwrap().kid, Some(kid.clone()));
+
+    let new_token = login_for_token(&app, &email).await;
+    assert_eq!(decode_header(&new_token).unwrap().kid, Some(kid));
+
+    for token in [old_token, new_token] {
+        let response = app.post_verify_token(&serde_json::json!({ "token": token })).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_keep_rotated_key_across_restarts() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_rotate_jwt_keys(&app.admin_api_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let kid = response
+        .json::<RotateJwtKeysResponse>()
+        .await
+        .expect("Could not deserialize response body to RotateJwtKeysResponse")
+        .kid;
+
+    // A restarted or second instance starts from the configured key and loads the rest
+    let jwt_keyring = Arc::new(RwLock::new(JwtKeyring::new(JWT_INITIAL_KEY.clone())));
+    let jwt_key_store = Arc::new(RwLock::new(PostgresJwtKeyStore::new(app.pg_pool.clone())));
+    sync_jwt_keyring(jwt_keyring.clone(), jwt_key_store)
+        .await
+        .expect("Failed to load rotated JWT keys");
+
+    let jwt_keyring = jwt_keyring.read().await;
+    assert_eq!(jwt_keyring.signing_key().kid, kid);
+    assert!(jwt_keyring.verification_key(Some(&JWT_INITIAL_KEY.kid)).is_some());
+}
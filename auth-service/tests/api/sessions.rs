@@ -0,0 +1,182 @@
+use auth_service::{routes::SessionsResponse, ErrorResponse};
+use reqwest::header::USER_AGENT;
+use test_macros::auto_cleanup;
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up a user and log them in on the app's own client, returning their email
+async fn sign_up_and_log_in(app: &TestApp) -> String {
+    let random_email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    random_email
+}
+
+// Log the user in again from a different browser with its own cookies
+async fn log_in_from_other_device(app: &TestApp, email: &str) -> reqwest::Client {
+    let client = reqwest::Client::builder().cookie_store(true).build().unwrap();
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .header(USER_AGENT, "Other Device")
+        .json(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    client
+}
+
+async fn get_sessions(app: &TestApp) -> SessionsResponse {
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<SessionsResponse>()
+        .await
+        .expect("Could not deserialize response body to SessionsResponse")
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.delete_session("some-session").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_list_one_session_per_login() {
+    let mut app = TestApp::new().await;
+    let email = sign_up_and_log_in(&app).await;
+    log_in_from_other_device(&app, &email).await;
+
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions.iter().filter(|session| session.current).count(), 1);
+
+    let other_session = sessions.iter().find(|session| !session.current).unwrap();
+    assert_eq!(other_session.user_agent.as_deref(), Some("Other Device"));
+    assert_eq!(other_session.ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_sign_out_other_device_when_its_session_is_revoked() {
+    let mut app = TestApp::new().await;
+    let email = sign_up_and_log_in(&app).await;
+    let other_client = log_in_from_other_device(&app, &email).await;
+
+    let sessions = get_sessions(&app).await.sessions;
+    let other_session = sessions.iter().find(|session| !session.current).unwrap();
+
+    let response = app.delete_session(&other_session.id).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The other device's access token and refresh token both stop working
+    let response = other_client
+        .get(format!("{}/sessions", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = other_client
+        .post(format!("{}/refresh", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+
+    // While this device stays signed in
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_sign_out_when_current_session_is_revoked() {
+    let mut app = TestApp::new().await;
+    sign_up_and_log_in(&app).await;
+
+    let sessions = get_sessions(&app).await.sessions;
+    let response = app.delete_session(&sessions[0].id).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The cookies are gone, so there is nothing left to authenticate with
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_404_for_another_users_session() {
+    let mut app = TestApp::new().await;
+    sign_up_and_log_in(&app).await;
+
+    // A different user signed in elsewhere
+    let other_email = get_random_email();
+    let response = app.post_signup(&serde_json::json!({
+        "email": other_email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let other_client = log_in_from_other_device(&app, &other_email).await;
+    let other_sessions = other_client
+        .get(format!("{}/sessions", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<SessionsResponse>()
+        .await
+        .expect("Could not deserialize response body to SessionsResponse");
+
+    let response = app.delete_session(&other_sessions.sessions[0].id).await;
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse")
+        .error,
+        "Session not found".to_owned());
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_end_session_on_logout() {
+    let mut app = TestApp::new().await;
+    let email = sign_up_and_log_in(&app).await;
+    let other_client = log_in_from_other_device(&app, &email).await;
+
+    let response = other_client
+        .post(format!("{}/logout", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+}
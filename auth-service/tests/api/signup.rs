@@ -1,4 +1,5 @@
-use auth_service::{routes::SignupResponse, ErrorResponse};
+use auth_service::{routes::SignupResponse, utils::password_policy::PasswordPolicy, ErrorResponse};
+use sha1::{Digest, Sha1};
 use test_macros::auto_cleanup;
 
 use crate::helpers::{get_random_email, TestApp};
@@ -52,18 +53,16 @@ async fn should_return_201_if_valid_input() {
 
     assert_eq!(response.status().as_u16(), 201);
 
-    let expected_response = SignupResponse {
-        message: "User created successfully!".to_owned(),
-    };
+    let json_body = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to UserBody");
 
     // Assert that we are getting the correct response body!
-    assert_eq!(
-        response
-            .json::<SignupResponse>()
-            .await
-            .expect("Could not deserialize response body to UserBody"),
-        expected_response
-    );
+    assert_eq!(json_body.message, "User created successfully!".to_owned());
+
+    // Signing up with 2FA hands out the recovery codes
+    assert_eq!(json_body.recovery_codes.map(|codes| codes.len()), Some(10));
 }
 
 #[auto_cleanup]
@@ -76,11 +75,6 @@ async fn should_return_400_if_invalid_input() {
             "password": "password123",
             "requires2FA": true
         }),
-        serde_json::json!({
-            "email": get_random_email(),
-            "password": "short",
-            "requires2FA": true
-        }),
         serde_json::json!({
             "email": "random_email.es",
             "password": "pass123654",
@@ -109,7 +103,7 @@ async fn should_return_400_if_invalid_input() {
 
 #[auto_cleanup]
 #[tokio::test]
-async fn should_return_409_if_email_already_exists() {
+async fn should_return_201_and_notify_owner_if_email_already_exists() {
     let mut app = TestApp::new().await;
     let random_email = get_random_email();
     let signup_body = serde_json::json!({
@@ -119,16 +113,112 @@ async fn should_return_409_if_email_already_exists() {
     });
     let first_response = app.post_signup(&signup_body).await;
     assert_eq!(first_response.status().as_u16(), 201);
-    
+    let first_body = first_response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse");
+
     let second_response = app.post_signup(&signup_body).await;
-    assert_eq!(second_response.status().as_u16(), 409);
+    assert_eq!(second_response.status().as_u16(), 201);
+    let second_body = second_response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse");
 
+    // Same shape as a fresh signup, so the response doesn't reveal the account exists
+    assert_eq!(second_body.message, first_body.message);
     assert_eq!(
-        second_response
+        second_body.recovery_codes.map(|codes| codes.len()),
+        first_body.recovery_codes.map(|codes| codes.len())
+    );
+
+    let body = app.get_last_email_body().await;
+    assert!(body.contains("already has an account"));
+    assert!(!body.contains("/verify-email?token="));
+}
+
+fn strict_password_policy() -> PasswordPolicy {
+    PasswordPolicy {
+        min_length: 10,
+        max_length: 64,
+        min_character_classes: 3,
+        min_strength_score: 3,
+        breached_passwords_dir: None,
+    }
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_with_reasons_if_password_breaks_policy() {
+    let mut app = TestApp::new_with_password_policy(strict_password_policy()).await;
+
+    let test_cases = [
+        ("short", vec!["too_short", "too_few_character_classes", "too_weak"]),
+        ("password123", vec!["too_few_character_classes", "too_weak"]),
+        ("Password123!", vec!["too_weak"]),
+        ("prefix-jane.doe-Xx#42", vec!["contains_email"]),
+    ];
+
+    for (password, reasons) in test_cases {
+        let response = app
+            .post_signup(&serde_json::json!({
+                "email": "jane.doe@example.com",
+                "password": password,
+                "requires2FA": false
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for password: {}", password);
+
+        let body = response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse");
+        assert_eq!(body.error, "Password does not meet the password policy");
+        assert_eq!(body.reasons, reasons, "Failed for password: {}", password);
+    }
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": "jane.doe@example.com",
+            "password": "kP9#vT2$wq!Z",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_password_is_breached() {
+    // A range file for the prefix of the breached password, as the policy expects on disk
+    let breached_dir = std::env::temp_dir().join(format!("breached-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&breached_dir).unwrap();
+    let digest: String = Sha1::digest(b"kP9#vT2$wq!Z").iter().map(|b| format!("{:02X}", b)).collect();
+    let (prefix, suffix) = digest.split_at(5);
+    std::fs::write(breached_dir.join(format!("{}.txt", prefix)), format!("{}:3\n", suffix)).unwrap();
+
+    let mut app = TestApp::new_with_password_policy(PasswordPolicy {
+        breached_passwords_dir: Some(breached_dir.clone()),
+        ..strict_password_policy()
+    })
+    .await;
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "kP9#vT2$wq!Z",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response
             .json::<ErrorResponse>()
             .await
             .expect("Could not deserialize response body to ErrorResponse")
-            .error,
-        "User already exists".to_owned()
+            .reasons,
+        vec!["breached"]
     );
-}
\ No newline at end of file
+
+    std::fs::remove_dir_all(breached_dir).unwrap();
+}
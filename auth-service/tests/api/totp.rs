@@ -0,0 +1,311 @@
+use auth_service::{
+    domain::{TotpSecret, TwoFAMethod},
+    routes::{ConfirmTotpResponse, TotpEnrollmentResponse, TwoFactorAuthResponse},
+    utils::{constants::JWT_COOKIE_NAME, totp::generate_code},
+};
+use chrono::Utc;
+use secrecy::Secret;
+use test_macros::auto_cleanup;
+use wiremock::{Mock, ResponseTemplate, matchers::{method, path}};
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up and logs in a user without 2FA, leaving their JWT in the app's cookie jar
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn enroll(app: &TestApp) -> TotpSecret {
+    let response = app.post_enroll_totp(&serde_json::json!({ "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<TotpEnrollmentResponse>()
+        .await
+        .expect("Could not deserialize response body to TotpEnrollmentResponse");
+    TotpSecret::parse(Secret::new(body.secret)).expect("Invalid TOTP secret")
+}
+
+fn current_code(secret: &TotpSecret) -> String {
+    generate_code(secret, Utc::now().timestamp() as u64).unwrap()
+}
+
+fn previous_code(secret: &TotpSecret) -> String {
+    generate_code(secret, Utc::now().timestamp() as u64 - 30).unwrap()
+}
+
+// Returns a valid six digit code that is not accepted for `secret` right now
+fn wrong_code(secret: &TotpSecret) -> String {
+    let code = current_code(secret);
+    let wrong: u32 = (code.parse::<u32>().unwrap() + 500_000) % 1_000_000;
+    format!("{:06}", wrong)
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_enroll_totp(&serde_json::json!({ "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_confirm_totp(&serde_json::json!({ "2FACode": "123456" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_otpauth_uri_on_enrollment() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let response = app.post_enroll_totp(&serde_json::json!({ "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<TotpEnrollmentResponse>()
+        .await
+        .expect("Could not deserialize response body to TotpEnrollmentResponse");
+    assert!(body.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(body.otpauth_uri.contains(&format!("secret={}", body.secret)));
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_enrolling_with_wrong_password() {
+    let mut app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    // The JWT cookie alone is not enough to get a secret that produces the user's second factor
+    let response = app.post_enroll_totp(&serde_json::json!({ "password": "wrongpassword" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_require_emailed_code_to_enroll_if_2fa_enabled() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": app.get_last_two_fa_code().await
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The password alone only starts the second round trip
+    let response = app.post_enroll_totp(&serde_json::json!({ "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let response = app
+        .post_enroll_totp(&serde_json::json!({
+            "password": "password123",
+            "loginAttemptId": uuid::Uuid::new_v4().to_string(),
+            "2FACode": "123456"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_enroll_totp(&serde_json::json!({
+            "password": "password123",
+            "loginAttemptId": login_attempt_id,
+            "2FACode": app.get_last_two_fa_code().await
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_confirmation_code_incorrect() {
+    let mut app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+    let secret = enroll(&app).await;
+
+    let response = app
+        .post_confirm_totp(&serde_json::json!({ "2FACode": wrong_code(&secret) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_confirming_without_enrollment() {
+    let mut app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let response = app.post_confirm_totp(&serde_json::json!({ "2FACode": "123456" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_409_if_totp_already_enabled() {
+    let mut app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+    let secret = enroll(&app).await;
+
+    let response = app
+        .post_confirm_totp(&serde_json::json!({ "2FACode": current_code(&secret) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_enroll_totp(&serde_json::json!({ "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_login_with_totp_code_after_confirmation() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let secret = enroll(&app).await;
+
+    // Each code only works once, so confirm with an earlier one to leave the current code for logging in
+    let response = app
+        .post_confirm_totp(&serde_json::json!({ "2FACode": previous_code(&secret) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Confirming hands out a fresh set of recovery codes
+    let body = response
+        .json::<ConfirmTotpResponse>()
+        .await
+        .expect("Could not deserialize response body to ConfirmTotpResponse");
+    assert_eq!(body.recovery_codes.len(), 10);
+
+    // No 2FA email is sent to TOTP users
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    assert_eq!(body.two_fa_method, TwoFAMethod::Totp);
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": body.login_attempt_id,
+            "2FACode": wrong_code(&secret)
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": body.login_attempt_id,
+            "2FACode": current_code(&secret)
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.cookies().any(|c| c.name() == JWT_COOKIE_NAME && !c.value().is_empty()));
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_accept_code_from_previous_time_step() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let secret = enroll(&app).await;
+
+    let response = app
+        .post_confirm_totp(&serde_json::json!({ "2FACode": previous_code(&secret) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_reject_replayed_totp_code() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let secret = enroll(&app).await;
+
+    let confirmation_code = previous_code(&secret);
+    let response = app
+        .post_confirm_totp(&serde_json::json!({ "2FACode": confirmation_code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let login_code = current_code(&secret);
+    let cases = [
+        // The code that confirmed enrollment can't also be used to log in
+        (&confirmation_code, 401),
+        (&login_code, 200),
+        // Nor can a code that has already logged the user in
+        (&login_code, 401),
+    ];
+
+    for (code, expected_status) in cases {
+        let response = app
+            .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 206);
+
+        let body = response
+            .json::<TwoFactorAuthResponse>()
+            .await
+            .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+        let response = app
+            .post_verify_2fa(&serde_json::json!({
+                "email": email,
+                "loginAttemptId": body.login_attempt_id,
+                "2FACode": code
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+}
@@ -1,4 +1,15 @@
-use auth_service::{domain::{Email}, routes::TwoFactorAuthResponse, utils::constants::JWT_COOKIE_NAME,};
+use std::time::Duration;
+
+use auth_service::{
+    domain::{Email, LoginAttemptId},
+    get_redis_client,
+    routes::TwoFactorAuthResponse,
+    utils::{
+        constants::{JWT_COOKIE_NAME, MAX_TWO_FA_CODE_ATTEMPTS, REDIS_HOST_NAME},
+        login_throttle::LoginThrottlePolicy,
+    },
+};
+use redis::Commands;
 use test_macros::auto_cleanup;
 use wiremock::{Mock, ResponseTemplate, matchers::{method, path}};
 use crate::helpers::{get_random_email, TestApp};
@@ -44,8 +55,8 @@ async fn should_return_422_if_malformed_input() {
 
     assert_eq!(json_body.message, "2FA required".to_owned());
 
-    let (login_attempt_id, _) = app.two_fa_code_store.read().await.get_code(&random_email).await.unwrap();
-    assert_eq!(login_attempt_id.as_ref().expose_secret(), &json_body.login_attempt_id);
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id.clone()).unwrap();
+    assert!(app.two_fa_code_store.read().await.get_attempt(&random_email, &login_attempt_id).await.is_ok());
 
     let verify_2fa_body = serde_json::json!({
         "email": random_email.as_ref().expose_secret(),
@@ -96,8 +107,8 @@ async fn should_return_400_if_invalid_input() {
 
     assert_eq!(json_body.message, "2FA required".to_owned());
 
-    let (login_attempt_id, _) = app.two_fa_code_store.read().await.get_code(&random_email).await.unwrap();
-    assert_eq!(login_attempt_id.as_ref().expose_secret(), &json_body.login_attempt_id);
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id.clone()).unwrap();
+    assert!(app.two_fa_code_store.read().await.get_attempt(&random_email, &login_attempt_id).await.is_ok());
 
     let verify_2fa_body = serde_json::json!({
         "email": random_email.as_ref().expose_secret(),
@@ -150,8 +161,8 @@ async fn should_return_401_if_incorrect_credentials() {
 
     assert_eq!(json_body.message, "2FA required".to_owned());
 
-    let (login_attempt_id, _) = app.two_fa_code_store.read().await.get_code(&random_email).await.unwrap();
-    assert_eq!(login_attempt_id.as_ref().expose_secret(), &json_body.login_attempt_id);
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id.clone()).unwrap();
+    assert!(app.two_fa_code_store.read().await.get_attempt(&random_email, &login_attempt_id).await.is_ok());
 
     let verify_2fa_body = serde_json::json!({
         "email": random_email.as_ref().expose_secret(),
@@ -205,13 +216,13 @@ async fn should_return_401_if_old_code() {
 
     assert_eq!(json_body.message, "2FA required".to_owned());
 
-    let (login_attempt_id, two_fa_code) = app.two_fa_code_store.read().await.get_code(&random_email).await.unwrap();
-    assert_eq!(login_attempt_id.as_ref().expose_secret(), &json_body.login_attempt_id);
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id.clone()).unwrap();
+    let two_fa_code = app.get_last_two_fa_code().await;
 
     let verify_2fa_body = serde_json::json!({
         "email": random_email.as_ref().expose_secret(),
         "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
-        "2FACode": two_fa_code.as_ref().expose_secret()
+        "2FACode": two_fa_code
     });
 
     let response = app.post_verify_2fa(&verify_2fa_body).await;
@@ -255,12 +266,12 @@ async fn should_return_200_if_correct_code() {
         .await
         .expect("Could not deserialize response body to TwoFactorAuthResponse");
     assert_eq!(json_body.message, "2FA required".to_owned());
-    let (login_attempt_id, two_fa_code) = app.two_fa_code_store.read().await.get_code(&random_email).await.unwrap();
-    assert_eq!(login_attempt_id.as_ref().expose_secret(), &json_body.login_attempt_id);
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id.clone()).unwrap();
+    let two_fa_code = app.get_last_two_fa_code().await;
     let verify_2fa_body = serde_json::json!({
         "email": random_email.as_ref().expose_secret(),
         "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
-        "2FACode": two_fa_code.as_ref().expose_secret()
+        "2FACode": two_fa_code
     });
     let response = app.post_verify_2fa(&verify_2fa_body).await;
     assert_eq!(response.status().as_u16(), 200);
@@ -303,15 +314,187 @@ async fn should_return_401_if_same_code_twice() {
         .await
         .expect("Could not deserialize response body to TwoFactorAuthResponse");
     assert_eq!(json_body.message, "2FA required".to_owned());
-    let (login_attempt_id, two_fa_code) = app.two_fa_code_store.read().await.get_code(&random_email).await.unwrap();
-    assert_eq!(login_attempt_id.as_ref().expose_secret(), &json_body.login_attempt_id);
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id.clone()).unwrap();
+    let two_fa_code = app.get_last_two_fa_code().await;
     let verify_2fa_body = serde_json::json!({
         "email": random_email.as_ref().expose_secret(),
         "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
-        "2FACode": two_fa_code.as_ref().expose_secret()
+        "2FACode": two_fa_code
     });
     let response = app.post_verify_2fa(&verify_2fa_body).await;
     assert_eq!(response.status().as_u16(), 200);
     let response = app.post_verify_2fa(&verify_2fa_body).await;
     assert_eq!(response.status().as_u16(), 401);
-}
\ No newline at end of file
+}
+async fn log_in_with_2fa(app: &TestApp, email: &Email) -> (LoginAttemptId, String) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email.as_ref().expose_secret(),
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id).unwrap();
+    (login_attempt_id, app.get_last_two_fa_code().await)
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_complete_concurrent_logins_independently() {
+    let mut app = TestApp::new().await;
+    let random_email = Email::parse(Secret::new(get_random_email())).unwrap();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email.as_ref().expose_secret(),
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    // Say, one login from a laptop and one from a phone, before either has been verified
+    let (first_attempt_id, first_code) = log_in_with_2fa(&app, &random_email).await;
+    let (second_attempt_id, second_code) = log_in_with_2fa(&app, &random_email).await;
+
+    // A code only works for the attempt it was sent for
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email.as_ref().expose_secret(),
+            "loginAttemptId": first_attempt_id.as_ref().expose_secret(),
+            "2FACode": second_code
+        }))
+        .await;
+    if first_code != second_code {
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    for (login_attempt_id, code) in [(second_attempt_id, second_code), (first_attempt_id, first_code)] {
+        let response = app
+            .post_verify_2fa(&serde_json::json!({
+                "email": random_email.as_ref().expose_secret(),
+                "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+                "2FACode": code
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_discard_login_attempt_after_too_many_wrong_codes() {
+    // Keep the login throttle's own lockout out of the way
+    let mut app = TestApp::new_with_login_throttle_policy(LoginThrottlePolicy {
+        lockout_threshold: 100,
+        ..LoginThrottlePolicy::default()
+    })
+    .await;
+    let random_email = Email::parse(Secret::new(get_random_email())).unwrap();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email.as_ref().expose_secret(),
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let (login_attempt_id, code) = log_in_with_2fa(&app, &random_email).await;
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+
+    for _ in 0..MAX_TWO_FA_CODE_ATTEMPTS {
+        let response = app
+            .post_verify_2fa(&serde_json::json!({
+                "email": random_email.as_ref().expose_secret(),
+                "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+                "2FACode": wrong_code
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The attempt is gone, so the user has to log in again
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email.as_ref().expose_secret(),
+            "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+            "2FACode": code
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_attempt(&random_email, &login_attempt_id)
+        .await
+        .is_err());
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_not_store_plaintext_code_or_login_attempt_id_in_redis() {
+    let mut app = TestApp::new_with_login_throttle_policy(LoginThrottlePolicy {
+        two_fa_resend_cooldown: Duration::ZERO,
+        ..LoginThrottlePolicy::default()
+    })
+    .await;
+    let random_email = Email::parse(Secret::new(get_random_email())).unwrap();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email.as_ref().expose_secret(),
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let (login_attempt_id, code) = log_in_with_2fa(&app, &random_email).await;
+    let login_attempt_id = login_attempt_id.as_ref().expose_secret().to_owned();
+    let mut conn = get_redis_client(REDIS_HOST_NAME.to_owned())
+        .expect("Failed to get Redis client")
+        .get_connection()
+        .expect("Failed to get Redis connection");
+
+    let mut assert_nothing_in_plaintext = |code: &str| {
+        // No key is named after the login attempt
+        let keys: Vec<String> = conn.keys("*").unwrap();
+        assert!(keys.iter().all(|key| !key.contains(&login_attempt_id)));
+
+        let attempt_hashes: Vec<String> = conn
+            .zrange(format!("user_login_attempts:{}", random_email.as_ref().expose_secret()), 0, -1)
+            .unwrap();
+        assert_eq!(attempt_hashes.len(), 1);
+        let record: String = conn.get(format!("two_fa_code:{}", attempt_hashes[0])).unwrap();
+        assert!(!record.contains(code));
+        assert!(!record.contains(&login_attempt_id));
+    };
+
+    assert_nothing_in_plaintext(&code);
+
+    // Nor does a resent code end up there
+    let response = app
+        .post_resend_2fa(&serde_json::json!({
+            "email": random_email.as_ref().expose_secret(),
+            "loginAttemptId": login_attempt_id
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_code = app.get_last_two_fa_code().await;
+    assert_nothing_in_plaintext(&new_code);
+
+    // The hash is still enough to check the code against
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email.as_ref().expose_secret(),
+            "loginAttemptId": login_attempt_id,
+            "2FACode": new_code
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,149 @@
+use auth_service::{routes::VerifyEmailResponse, utils::{auth::validate_token, constants::JWT_COOKIE_NAME}, ErrorResponse};
+use test_macros::auto_cleanup;
+use wiremock::{Mock, ResponseTemplate, matchers::{method, path}};
+use crate::helpers::{get_link_param, get_random_email, TestApp};
+
+// Signs up a new user without 2FA and returns the token from their verification email
+async fn signup(app: &TestApp, email: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    get_link_param(&app.get_last_email_body().await, "token")
+}
+
+async fn login(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await
+}
+
+fn get_jwt(response: &reqwest::Response) -> String {
+    response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("JWT cookie not found in login response")
+        .value()
+        .to_string()
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_send_verification_email_on_signup() {
+    let mut app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let token = signup(&app, &get_random_email()).await;
+    assert_eq!(token.len(), 64);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_400_if_token_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/verify-email", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let mut app = TestApp::new().await;
+
+    for token in ["not-a-token".to_owned(), "a".repeat(64)] {
+        let response = app.get_verify_email(&token).await;
+        assert_eq!(response.status().as_u16(), 401, "Failed for token: {}", token);
+    }
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_verify_email_with_emailed_token() {
+    let mut app = TestApp::new().await;
+    let token = signup(&app, &get_random_email()).await;
+
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<VerifyEmailResponse>()
+            .await
+            .expect("Could not deserialize response body to VerifyEmailResponse"),
+        VerifyEmailResponse {
+            message: "Email verified successfully!".to_owned(),
+        }
+    );
+
+    // The link only works once
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse")
+        .error,
+        "Invalid token".to_owned());
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_flag_unverified_email_in_claims_by_default() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let token = signup(&app, &email).await;
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let claims = validate_token(&get_jwt(&response), app.banned_token_store.clone(), app.session_store.clone(), app.jwt_keyring.clone())
+        .await
+        .expect("Failed to validate JWT");
+    assert!(!claims.email_verified);
+
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let claims = validate_token(&get_jwt(&response), app.banned_token_store.clone(), app.session_store.clone(), app.jwt_keyring.clone())
+        .await
+        .expect("Failed to validate JWT");
+    assert!(claims.email_verified);
+}
+
+#[auto_cleanup]
+#[tokio::test]
+async fn should_refuse_login_until_verified_when_required() {
+    let mut app = TestApp::new_requiring_verified_email().await;
+    let email = get_random_email();
+    let token = signup(&app, &email).await;
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse")
+        .error,
+        "Email not verified".to_owned());
+
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status().as_u16(), 200);
+}